@@ -0,0 +1,36 @@
+/*
+ * C ABI of uni-price-polling, built with `cargo build --release --features cdylib`.
+ * See src/ffi.rs for the details.
+ */
+#ifndef UNI_PRICE_POLLING_H
+#define UNI_PRICE_POLLING_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct upp_subscription upp_subscription;
+
+/* Called from the polling thread with every quote, json is only valid during the call */
+typedef void (*upp_callback)(void *user_data, double price, uint64_t block, const char *json);
+
+/* Message of the last failure on the calling thread, empty without one */
+const char *upp_last_error(void);
+
+/* Quotes a TOKEN/WETH pool once in USDT, 0 on success and -1 on failure */
+int upp_fetch_price(const char *rpc_url, const char *pool, double *price);
+
+/* Quotes a pool every interval_ms on a thread of its own, NULL on failure,
+   also when callback is NULL */
+upp_subscription *upp_subscribe(const char *rpc_url, const char *pool, uint64_t interval_ms, upp_callback callback, void *user_data);
+
+/* Stops a subscription, waits for a running callback to return */
+void upp_unsubscribe(upp_subscription *subscription);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,190 @@
+/*!
+ *
+ * DEX aggregator comparison. Assets mapped to their token get a quote from
+ * the 0x or 1inch API for selling `size` USDT worth of the token with every
+ * quote, next to what the same trade gets through the route's own pools:
+ *
+ * [aggregator]
+ * api = "0x"           # or "1inch"
+ * key = "..."
+ * size = 10000
+ * tokens = { "LINK/USDT" = "0x514910771af9ca656af840dff83e8264ecf986ca" }
+ *
+ * A positive difference means the aggregator pays more, routing the trade
+ * there would be better. Only Uniswap V2 routes can be compared, both APIs
+ * quote mainnet.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::types::Address;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::amm;
+use crate::source::{Asset, PriceSource, Quote};
+use crate::uniswap_v2;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Api {
+    #[serde(rename = "0x")]
+    ZeroEx,
+    #[serde(rename = "1inch")]
+    OneInch,
+}
+
+impl Api {
+    fn default_url(self) -> &'static str {
+        match self {
+            Api::ZeroEx => "https://api.0x.org",
+            Api::OneInch => "https://api.1inch.dev",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AggregatorConfig {
+    pub api: Api,
+    /// API key, both APIs require one
+    pub key: String,
+    /// USDT value of the compared trade
+    #[serde(default = "default_size")]
+    pub size: f64,
+    /// Token addresses by asset name
+    pub tokens: HashMap<String, Address>,
+    /// API base url, defaults to the public API
+    pub url: Option<String>,
+}
+
+fn default_size() -> f64 {
+    10_000.0
+}
+
+pub struct Aggregator {
+    client: Client,
+    config: AggregatorConfig,
+    usdt: Address,
+}
+
+impl Aggregator {
+    /**
+     * @gist creates the client
+     * @param client -- HTTP client used for the API
+     * @param config -- api, key, size and tokens
+    **/
+    pub fn new(client: Client, config: AggregatorConfig) -> eyre::Result<Self> {
+        Ok(Self { client, config, usdt: uniswap_v2::USDT.parse()? })
+    }
+
+    /**
+     * @gist whether the asset is mapped to a token
+    **/
+    pub fn lists(&self, asset: &str) -> bool {
+        self.config.tokens.contains_key(asset)
+    }
+
+    /**
+     * @gist how much USDT the aggregator pays for an amount of a token
+     * @param asset -- asset name, mapped to the token
+     * @param amount -- unscaled amount of the token
+    **/
+    pub async fn sell(&self, asset: &str, amount: u128) -> eyre::Result<f64> {
+        let token = self.config.tokens.get(asset)
+            .ok_or_else(|| eyre::eyre!("no token for {asset}"))?;
+        let base = self.config.url.as_deref().unwrap_or(self.config.api.default_url());
+        let (token, usdt, amount) = (format!("{token:?}"), format!("{:?}", self.usdt), amount.to_string());
+
+        let request = match self.config.api {
+            Api::ZeroEx => self.client.get(format!("{base}/swap/permit2/price"))
+                .query(&[("chainId", "1"), ("sellToken", &token), ("buyToken", &usdt), ("sellAmount", &amount)])
+                .header("0x-api-key", &self.config.key)
+                .header("0x-version", "v2"),
+            Api::OneInch => self.client.get(format!("{base}/swap/v6.0/1/quote"))
+                .query(&[("src", &token), ("dst", &usdt), ("amount", &amount)])
+                .bearer_auth(&self.config.key),
+        };
+        let res: Value = request.send().await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| eyre::eyre!("{:?}: {}", self.config.api, e.without_url()))?
+            .json().await?;
+
+        // Both APIs return the amount as a decimal string
+        let field = match self.config.api {
+            Api::ZeroEx => "buyAmount",
+            Api::OneInch => "dstAmount",
+        };
+        let out: u128 = res[field].as_str().and_then(|a| a.parse().ok())
+            .ok_or_else(|| eyre::eyre!("{:?} has no quote for {asset}", self.config.api))?;
+        Ok(uniswap_v2::reformat_usd(out))
+    }
+}
+
+/**
+ * @gist the USDT a route of a TOKEN/ETH and an ETH/USDT pool pays for an
+ * amount of TOKEN, from the raw reserves of a quote
+ * @param quote -- a Uniswap V2 route quote
+ * @param amount -- TOKEN sold, in whole tokens
+**/
+pub fn route_out(quote: &Quote, amount: f64) -> Option<f64> {
+    let raw = quote.raw.as_ref()?;
+    let [(token, eth), (eth_2, usdt)] = raw.reserves[..] else {
+        return None;
+    };
+    let [(token_decimals, _), _] = raw.decimals[..] else {
+        return None;
+    };
+    let token = token as f64 / 10_f64.powi(token_decimals as i32);
+    let eth = amm::amount_out(amount, token, uniswap_v2::reformat_wei(eth), amm::V2_FEE);
+    Some(amm::amount_out(eth, uniswap_v2::reformat_wei(eth_2), uniswap_v2::reformat_usd(usdt), amm::V2_FEE))
+}
+
+/// Adds the aggregator's and the route's price of a trade to the quotes
+pub struct WithAggregator {
+    inner: Arc<dyn PriceSource>,
+    aggregator: Arc<Aggregator>,
+}
+
+impl WithAggregator {
+    /**
+     * @gist wraps a source
+    **/
+    pub fn new(inner: Arc<dyn PriceSource>, aggregator: Arc<Aggregator>) -> Self {
+        Self { inner, aggregator }
+    }
+}
+
+#[async_trait]
+impl PriceSource for WithAggregator {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let mut quote = self.inner.quote(asset).await?;
+        let Some(&(token_decimals, _)) = quote.raw.as_ref().and_then(|raw| raw.decimals.first()) else {
+            return Ok(quote);
+        };
+        if quote.price <= 0.0 {
+            return Ok(quote);
+        }
+        let amount = self.aggregator.config.size / quote.price;
+        let Some(own) = route_out(&quote, amount) else {
+            return Ok(quote);
+        };
+        let unscaled = (amount * 10_f64.powi(token_decimals as i32)) as u128;
+        match self.aggregator.sell(&asset.name, unscaled).await {
+            Ok(out) => {
+                quote.sized_price = Some(own / amount);
+                quote.aggregator_price = Some(out / amount);
+                quote.aggregator_bps = Some((out - own) / own * 10_000.0);
+            }
+            Err(e) => eprintln!("no aggregator quote for {}: {e}", asset.name),
+        }
+        Ok(quote)
+    }
+}
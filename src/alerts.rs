@@ -0,0 +1,398 @@
+/*!
+ *
+ * Threshold alerts. Rules are evaluated against every quote in watch mode:
+ *
+ * [[alert]]
+ * name = "link-high"
+ * asset = "TOKEN/USDT"
+ * above = 20.0
+ * persist = 3      # condition has to hold for 3 consecutive polls
+ * cooldown = 600   # seconds before the rule may fire again
+ *
+ * A rule fires once when its condition starts holding and re-arms when the
+ * condition clears, so a price sitting above a threshold doesn't fire every
+ * cycle. `persist` and `cooldown` stop a price wicking back and forth across
+ * the threshold from firing every time it crosses.
+ *
+ * `field` compares another value of the quote than its price, e.g. a
+ * momentum alert on the rate of change:
+ *
+ * [[alert]]
+ * name = "link-pump"
+ * asset = "LINK"
+ * field = "change_per_min"   # price, change_24h, change_per_min, accel_per_min2 or rsi
+ * above = 0.05
+ *
+ * An RSI rule with `above = 70` fires when the asset gets overbought, one
+ * with `below = 30` when it gets oversold.
+ *
+ * `band` fires when the price closes outside its Bollinger bands instead,
+ * "upper" above the upper band and "lower" below the lower one.
+ *
+ * `swap_above` fires for every single swap of the pool worth more than that
+ * many USDT, with its direction and transaction:
+ *
+ * [[alert]]
+ * name = "link-whale"
+ * asset = "LINK"
+ * swap_above = 100000
+ *
+ * `liquidity_change_above` does the same for liquidity added to or removed
+ * from the pool, a large withdrawal is often the first sign of a rug pull.
+ * `sandwich_above` for every sandwich of the pool whose victims traded more
+ * than that many USDT, 0 for all of them, see `--sandwiches`.
+ *
+ * `silent_for` is a deadman switch: it fires when the asset had no quote
+ * for that many seconds, because the RPC is dead or the feed keeps failing,
+ * and re-arms with the next quote:
+ *
+ * [[alert]]
+ * name = "link-silent"
+ * asset = "LINK"
+ * silent_for = 600
+ *
+**/
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::clock::unix_now;
+use crate::source::Quote;
+use crate::sandwich::Sandwich;
+use crate::swaps::{LiquidityEvent, Trade};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    pub name: String,
+    /// Name of the asset the rule watches
+    pub asset: String,
+    /// Value of the quote the thresholds apply to
+    #[serde(default)]
+    pub field: Field,
+    /// Fire when the value is above this
+    pub above: Option<f64>,
+    /// Fire when the value is below this
+    pub below: Option<f64>,
+    /// Fire when the price closes outside this Bollinger band
+    pub band: Option<Band>,
+    /// Fire for every swap worth more USDT than this
+    pub swap_above: Option<f64>,
+    /// Fire for every Mint or Burn worth more USDT than this
+    pub liquidity_change_above: Option<f64>,
+    /// Fire for every sandwich around swaps worth more USDT than this
+    pub sandwich_above: Option<f64>,
+    /// Fire when the asset had no quote for this many seconds
+    pub silent_for: Option<u64>,
+    /// Number of consecutive polls the condition has to hold
+    #[serde(default = "default_persist")]
+    pub persist: u32,
+    /// Minimum number of seconds between two notifications of this rule
+    #[serde(default)]
+    pub cooldown: u64,
+}
+
+fn default_persist() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Field {
+    #[default]
+    Price,
+    Change24h,
+    ChangePerMin,
+    AccelPerMin2,
+    Rsi,
+}
+
+impl Field {
+    /**
+     * @gist the value of a quote, None while watch mode has not computed it
+    **/
+    pub fn of(self, quote: &Quote) -> Option<f64> {
+        match self {
+            Field::Price => Some(quote.price),
+            Field::Change24h => quote.change_24h,
+            Field::ChangePerMin => quote.change_per_min,
+            Field::AccelPerMin2 => quote.accel_per_min2,
+            Field::Rsi => quote.rsi,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Field::Price => "price",
+            Field::Change24h => "change_24h",
+            Field::ChangePerMin => "change_per_min",
+            Field::AccelPerMin2 => "accel_per_min2",
+            Field::Rsi => "rsi",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Band {
+    Upper,
+    Lower,
+}
+
+impl AlertRule {
+    /**
+     * @gist whether the value breaches the rule
+    **/
+    pub fn breached(&self, value: f64) -> bool {
+        self.above.is_some_and(|above| value > above) || self.below.is_some_and(|below| value < below)
+    }
+
+    /**
+     * @gist the alert the rule sends for a made-up value of its field, to
+     * test the notifiers. persist and cooldown are left out, rules firing on
+     * swaps, liquidity, sandwiches, bands or silence send a plain test alert
+     * whatever the value.
+     * @param value -- the price, or the value of the rule's field
+     * @output None when the rule does not fire at the value
+    **/
+    pub fn test(&self, value: f64) -> Option<Alert> {
+        let mut quote = Quote::new("test", &self.asset, value);
+        match self.field {
+            Field::Price => {}
+            Field::Change24h => quote.change_24h = Some(value),
+            Field::ChangePerMin => quote.change_per_min = Some(value),
+            Field::AccelPerMin2 => quote.accel_per_min2 = Some(value),
+            Field::Rsi => quote.rsi = Some(value),
+        }
+
+        let on_value = self.band.is_none() && self.swap_above.is_none() && self.liquidity_change_above.is_none()
+            && self.sandwich_above.is_none() && self.silent_for.is_none();
+        let message = match on_value {
+            true if !self.breached_by(&quote) => return None,
+            true => describe(self, &quote),
+            false => format!("[{}] {} test alert", self.name, self.asset),
+        };
+        Some(Alert { rule: self.name.clone(), asset: self.asset.clone(), price: value, message: format!("TEST {message}") })
+    }
+
+    /**
+     * @gist whether the quote breaches the rule, a rule on a value watch
+     * mode has not computed yet never does
+    **/
+    pub fn breached_by(&self, quote: &Quote) -> bool {
+        match (self.band, quote.bollinger) {
+            (Some(Band::Upper), Some(bands)) => quote.price > bands.upper,
+            (Some(Band::Lower), Some(bands)) => quote.price < bands.lower,
+            (Some(_), None) => false,
+            (None, _) => self.field.of(quote).is_some_and(|v| self.breached(v)),
+        }
+    }
+}
+
+/// Evaluation state of a rule
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RuleState {
+    /// Consecutive polls the condition has held
+    pub consecutive: u32,
+    /// The rule fired and the condition hasn't cleared since
+    pub firing: bool,
+    /// Unix time of the last notification
+    pub last_fired: Option<u64>,
+    /// Unix time and price of the last quote, for silent_for rules
+    #[serde(default)]
+    pub last_quote: Option<(u64, f64)>,
+}
+
+/// A rule that fired
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub rule: String,
+    pub asset: String,
+    pub price: f64,
+    pub message: String,
+}
+
+pub struct AlertEngine {
+    rules: Vec<(AlertRule, RuleState)>,
+    /// Unix time the engine was created, silence counts from here until the
+    /// first quote
+    started: u64,
+}
+
+impl AlertEngine {
+    /**
+     * @gist creates the engine with fresh state for every rule
+    **/
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        Self { rules: rules.into_iter().map(|r| (r, RuleState::default())).collect(), started: unix_now() }
+    }
+
+    /**
+     * @gist creates the engine and restores the state of rules by name, e.g.
+     * from the state file
+    **/
+    pub fn with_states(rules: Vec<AlertRule>, states: &HashMap<String, RuleState>) -> Self {
+        let rules = rules.into_iter()
+            .map(|r| {
+                let state = states.get(&r.name).cloned().unwrap_or_default();
+                (r, state)
+            })
+            .collect();
+        Self { rules, started: unix_now() }
+    }
+
+    /**
+     * @gist every rule with its current state, in config order
+    **/
+    pub fn rules(&self) -> impl Iterator<Item = (&AlertRule, &RuleState)> {
+        self.rules.iter().map(|(r, s)| (r, s))
+    }
+
+    /**
+     * @gist the current state of every rule by name
+    **/
+    pub fn states(&self) -> HashMap<String, RuleState> {
+        self.rules.iter().map(|(r, s)| (r.name.clone(), s.clone())).collect()
+    }
+
+    /**
+     * @gist evaluates every rule watching the quote's asset
+     * @param quote -- the latest quote
+     * @output the alerts that fired
+    **/
+    pub fn observe(&mut self, quote: &Quote) -> Vec<Alert> {
+        self.observe_at(quote, unix_now())
+    }
+
+    /**
+     * @gist like observe with an explicit clock, in unix seconds
+    **/
+    pub fn observe_at(&mut self, quote: &Quote, now: u64) -> Vec<Alert> {
+        let mut fired = Vec::new();
+
+        for (rule, state) in self.rules.iter_mut().filter(|(r, _)| r.asset == quote.asset) {
+            if rule.silent_for.is_some() {
+                state.last_quote = Some((now, quote.price));
+                state.firing = false;
+                continue;
+            }
+            // Every large swap or LP change is an event of its own, there is
+            // no condition to clear
+            let events: Vec<String> = match (rule.swap_above, rule.liquidity_change_above, rule.sandwich_above) {
+                (Some(size), _, _) => quote.trades.iter().filter(|t| t.usd > size).map(|t| describe_trade(rule, t)).collect(),
+                (_, Some(size), _) => quote.liquidity_events.iter().filter(|e| e.usd > size).map(|e| describe_liquidity(rule, e)).collect(),
+                (_, _, Some(size)) => quote.sandwiches.iter().filter(|s| s.victim_usd >= size).map(|s| describe_sandwich(rule, s)).collect(),
+                (None, None, None) => {
+                    if let Some(alert) = observe_level(rule, state, quote, now) {
+                        fired.push(alert);
+                    }
+                    continue;
+                }
+            };
+            for message in events {
+                if state.last_fired.is_some_and(|last| now < last + rule.cooldown) {
+                    break;
+                }
+                state.last_fired = Some(now);
+                fired.push(Alert { rule: rule.name.clone(), asset: quote.asset.clone(), price: quote.price, message });
+            }
+        }
+
+        fired
+    }
+
+    /**
+     * @gist fires the silent_for rules whose asset had no quote for too
+     * long, to be called every poll cycle
+     * @param now -- unix seconds
+    **/
+    pub fn silent(&mut self, now: u64) -> Vec<Alert> {
+        let mut fired = Vec::new();
+        for (rule, state) in &mut self.rules {
+            let Some(silent_for) = rule.silent_for else {
+                continue;
+            };
+            let since = state.last_quote.map_or(self.started, |(t, _)| t);
+            let cooled_down = state.last_fired.is_none_or(|last| now >= last + rule.cooldown);
+            if state.firing || now < since + silent_for || !cooled_down {
+                continue;
+            }
+            state.firing = true;
+            state.last_fired = Some(now);
+            let (message, price) = match state.last_quote {
+                Some((_, price)) => (format!("[{}] {} had no quote for {}s, last ${price}", rule.name, rule.asset, now - since), price),
+                None => (format!("[{}] {} had no quote since the start {}s ago", rule.name, rule.asset, now - since), f64::NAN),
+            };
+            fired.push(Alert { rule: rule.name.clone(), asset: rule.asset.clone(), price, message });
+        }
+        fired
+    }
+}
+
+/**
+ * @gist evaluates a rule on a value of the quote
+**/
+fn observe_level(rule: &AlertRule, state: &mut RuleState, quote: &Quote, now: u64) -> Option<Alert> {
+    if !rule.breached_by(quote) {
+        state.consecutive = 0;
+        state.firing = false;
+        return None;
+    }
+
+    state.consecutive = state.consecutive.saturating_add(1);
+    if state.firing || state.consecutive < rule.persist {
+        return None;
+    }
+
+    let cooled_down = state.last_fired.is_none_or(|last| now >= last + rule.cooldown);
+    if !cooled_down {
+        return None;
+    }
+
+    state.firing = true;
+    state.last_fired = Some(now);
+    Some(Alert {
+        rule: rule.name.clone(),
+        asset: quote.asset.clone(),
+        price: quote.price,
+        message: describe(rule, quote),
+    })
+}
+
+fn describe_trade(rule: &AlertRule, trade: &Trade) -> String {
+    let side = if trade.buy { "buy" } else { "sell" };
+    format!("[{}] {} {side} of ${:.0}: {} at ${} in block {}, tx {:?}",
+        rule.name, rule.asset, trade.usd, trade.token, trade.price(), trade.block, trade.tx)
+}
+
+fn describe_liquidity(rule: &AlertRule, event: &LiquidityEvent) -> String {
+    let change = if event.added { "added" } else { "removed" };
+    format!("[{}] {} liquidity of ${:.0} {change}: {} tokens and {} ETH in block {}, tx {:?}",
+        rule.name, rule.asset, event.usd, event.token, event.eth, event.block, event.tx)
+}
+
+fn describe_sandwich(rule: &AlertRule, sandwich: &Sandwich) -> String {
+    let side = if sandwich.buy { "bought" } else { "sold" };
+    format!("[{}] {} sandwich in block {}: victims {side} ${:.0} between tx {:?} and {:?}, about ${:.0} to {:?}",
+        rule.name, rule.asset, sandwich.block, sandwich.victim_usd, sandwich.front, sandwich.back, sandwich.profit_usd, sandwich.attacker)
+}
+
+fn describe(rule: &AlertRule, quote: &Quote) -> String {
+    let price = quote.price;
+    match (rule.band, quote.bollinger) {
+        (Some(Band::Upper), Some(bands)) => return format!("[{}] {} closed above the upper band {}: ${price}", rule.name, rule.asset, bands.upper),
+        (Some(Band::Lower), Some(bands)) => return format!("[{}] {} closed below the lower band {}: ${price}", rule.name, rule.asset, bands.lower),
+        _ => {}
+    }
+
+    let value = rule.field.of(quote).unwrap_or(price);
+    let threshold = match (rule.above, rule.below) {
+        (Some(above), _) if value > above => format!("above {above}"),
+        (_, Some(below)) => format!("below {below}"),
+        _ => String::new(),
+    };
+    match rule.field {
+        Field::Price => format!("[{}] {} is {threshold}: ${price}", rule.name, rule.asset),
+        field => format!("[{}] {} {} is {threshold}: {value} (${price})", rule.name, rule.asset, field.name()),
+    }
+}
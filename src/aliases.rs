@@ -0,0 +1,134 @@
+/*!
+ *
+ * The address book: short names for pools and tokens, kept per chain in a
+ * TOML file (by default ~/.config/uni-price-polling/aliases.toml). Commands
+ * taking an address also take a name from the book, and outputs show the
+ * name instead of the raw address.
+ *
+**/
+
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::checksum;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AddressBook {
+    /// Names by chain id, then address by name
+    #[serde(default)]
+    pub chain: BTreeMap<String, BTreeMap<String, Address>>,
+}
+
+impl AddressBook {
+    /**
+     * @gist the names of a chain, sorted
+    **/
+    pub fn entries(&self, chain: u64) -> impl Iterator<Item = (&str, &Address)> {
+        self.chain.get(&chain.to_string())
+            .into_iter()
+            .flatten()
+            .map(|(name, address)| (name.as_str(), address))
+    }
+
+    /**
+     * @gist adds or replaces a name
+     * @param chain -- the chain id the address lives on
+     * @param name -- the short name, anything that is not an address
+     * @param address -- what the name stands for
+     * @output the address the name stood for before, if any
+    **/
+    pub fn add(&mut self, chain: u64, name: &str, address: Address) -> eyre::Result<Option<Address>> {
+        if name.is_empty() || name.starts_with("0x") || name.contains(char::is_whitespace) {
+            eyre::bail!("invalid alias {name:?}, names can't be empty, contain spaces or start with 0x");
+        }
+        Ok(self.chain.entry(chain.to_string()).or_default().insert(name.to_string(), address))
+    }
+
+    /**
+     * @gist removes a name
+     * @output the address the name stood for, None if there was no such name
+    **/
+    pub fn remove(&mut self, chain: u64, name: &str) -> Option<Address> {
+        let names = self.chain.get_mut(&chain.to_string())?;
+        let address = names.remove(name);
+        if names.is_empty() {
+            self.chain.remove(&chain.to_string());
+        }
+        address
+    }
+
+    /**
+     * @gist turns a command line argument into an address
+     * @param chain -- the chain id to look names up on
+     * @param name_or_address -- a hex address or a name from the book
+     * @output the address
+    **/
+    pub fn resolve(&self, chain: u64, name_or_address: &str) -> eyre::Result<Address> {
+        if name_or_address.starts_with("0x") {
+            return checksum::parse(name_or_address);
+        }
+        self.chain.get(&chain.to_string())
+            .and_then(|names| names.get(name_or_address))
+            .copied()
+            .ok_or_else(|| eyre::eyre!("unknown alias {name_or_address:?} on chain {chain}"))
+    }
+
+    /**
+     * @gist reverse lookup, the first name of an address
+     * @param chain -- the chain id the address lives on
+     * @param address -- the address to name
+    **/
+    pub fn name_of(&self, chain: u64, address: &Address) -> Option<&str> {
+        self.entries(chain).find(|(_, a)| *a == address).map(|(name, _)| name)
+    }
+}
+
+/**
+ * @gist where the book lives when --aliases is not given
+ * @output $XDG_CONFIG_HOME/uni-price-polling/aliases.toml, falling back to
+ * ~/.config
+**/
+pub fn default_path() -> PathBuf {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_default();
+    base.join("uni-price-polling").join("aliases.toml")
+}
+
+/**
+ * @gist reads the address book, a missing file is an empty book
+ * @param path -- location of the book
+ * @output the stored names
+**/
+pub fn load(path: &Path) -> eyre::Result<AddressBook> {
+    if !path.exists() {
+        return Ok(AddressBook::default());
+    }
+    let text = std::fs::read_to_string(path)?;
+    let mut value: toml::Value = toml::from_str(&text)
+        .map_err(|e| eyre::eyre!("invalid address book {}: {e}", path.display()))?;
+    checksum::normalize(&mut value)
+        .map_err(|e| eyre::eyre!("invalid address book {}: {e}", path.display()))?;
+    value.try_into()
+        .map_err(|e| eyre::eyre!("invalid address book {}: {e}", path.display()))
+}
+
+/**
+ * @gist writes the address book, creating its directory if needed
+ * @param path -- location of the book
+ * @param book -- the names to store
+**/
+pub fn save(path: &Path, book: &AddressBook) -> eyre::Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, toml::to_string(book)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,23 @@
+/*!
+ *
+ * Constant-product (x * y = k) math used by Uniswap V2 and its forks. All
+ * amounts are decimal numbers, i.e. already divided by 10**decimals.
+ *
+**/
+
+/// Swap fee charged by Uniswap V2 pools, 0.3%
+pub const V2_FEE: f64 = 0.003;
+
+/**
+ * @gist output of a swap through a constant-product pool, same formula as
+ * UniswapV2Library.getAmountOut
+ * @param amount_in -- amount sent to the pool
+ * @param reserve_in -- pool reserve of the token sent in
+ * @param reserve_out -- pool reserve of the token received
+ * @param fee -- the pool fee, e.g. 0.003
+ * @output the amount received
+**/
+pub fn amount_out(amount_in: f64, reserve_in: f64, reserve_out: f64, fee: f64) -> f64 {
+    let amount_in = amount_in * (1.0 - fee);
+    amount_in * reserve_out / (reserve_in + amount_in)
+}
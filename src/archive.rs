@@ -0,0 +1,345 @@
+/*!
+ *
+ * Price history of long ranges kept small. Most blocks don't touch the
+ * pools of a route, and the ones that do move the reserves by a trade, so
+ * `history archive` doesn't write a row per block: it writes the reserves
+ * of both pools as a full snapshot every `--snapshot-every` blocks and, in
+ * between, only how much each pool's reserves changed in the blocks with a
+ * Sync event:
+ *
+ * uni-price-polling history archive --pool LINK --from 2024-01-01 --to 2024-07-01 -o link.archive.zst
+ * uni-price-polling history at link.archive.zst --block 19000000 --block 19100000
+ *
+ * `history at` rebuilds the reserves and the price at any block of the
+ * range from the snapshot before it and the deltas up to it. The archive is
+ * text, one record per line, compressed when its name ends in .gz or .zst:
+ *
+ * # uni-price-polling archive 1
+ * P <TOKEN/ETH pool> <ETH/USDT pool>
+ * S <block> <timestamp> <reserve0> <reserve1> <reserve0> <reserve1>
+ * D <block> <0 for TOKEN/ETH, 1 for ETH/USDT> <change of reserve0> <change of reserve1>
+ * E <last block> <timestamp>
+ *
+ * Every record holds the reserves at the end of its block. The times of
+ * blocks between two snapshots are interpolated.
+ *
+**/
+
+use ethers::{providers::Middleware, types::Address};
+use eyre::{bail, eyre};
+
+use std::fs::File;
+use std::io::BufRead;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::backfill;
+use crate::export::{Compression, Writer};
+use crate::history;
+use crate::uniswap_v2::route_from_reserves;
+
+/// First line of every archive, with the version of the format
+const HEADER: &str = "# uni-price-polling archive 1";
+
+/// Blocks between two snapshots when --snapshot-every is not given, about
+/// a day
+pub const DEFAULT_SNAPSHOT_EVERY: u64 = 7200;
+
+/// Blocks whose events are held in memory at once while archiving
+const WINDOW: u64 = 20_000;
+
+/// What `history archive` wrote
+#[derive(Debug, Clone, Copy)]
+pub struct Summary {
+    pub snapshots: usize,
+    pub deltas: usize,
+    /// Blocks the archive covers
+    pub blocks: u64,
+}
+
+/// The route at one block, rebuilt from an archive
+#[derive(Debug, Clone, Copy)]
+pub struct Point {
+    pub block: u64,
+    /// Interpolated between the snapshots around the block
+    pub timestamp: u64,
+    /// Reserves of the TOKEN/ETH pool
+    pub start: (u128, u128),
+    /// Reserves of the ETH/USDT pool
+    pub end: (u128, u128),
+    pub price: f64,
+}
+
+/**
+ * @gist writes an archive of a route over a range of blocks
+ * @param provider -- used for getReserves, eth_getLogs and the block times
+ * @param start_a -- the TOKEN/ETH pair address
+ * @param end_a -- the ETH/USDT pair address
+ * @param from -- first block of the range, also the first snapshot
+ * @param to -- last block of the range
+ * @param every -- blocks between two snapshots
+ * @param path -- the archive, replaced if it exists
+**/
+pub async fn build<M: Middleware + 'static>(provider: Arc<M>, start_a: &Address, end_a: &Address, from: u64, to: u64, every: u64, path: &Path) -> eyre::Result<Summary> {
+    let stamp = async |block: u64| -> eyre::Result<u64> {
+        let header = provider.get_block(block).await
+            .map_err(|e| eyre!("could not read block {block}: {e}"))?
+            .ok_or_else(|| eyre!("the node has no block {block}"))?;
+        Ok(header.timestamp.as_u64())
+    };
+    let events = async |lo: u64, hi: u64| backfill::sync_events(provider.clone(), start_a, end_a, lo - 1, hi).await;
+
+    // The reserves at the end of the block before the range
+    let start = backfill::reserves_at(provider.clone(), start_a, from.saturating_sub(1)).await?;
+    let end = backfill::reserves_at(provider.clone(), end_a, from.saturating_sub(1)).await?;
+    encode(path, (start_a, end_a), (start, end), (from, to), every, events, stamp).await
+}
+
+/**
+ * @gist writes the records of an archive
+ * @param pools -- the TOKEN/ETH and ETH/USDT pair addresses
+ * @param reserves -- of both pools at the end of the block before the range
+ * @param range -- its first and last block
+ * @param events -- the Sync events of the blocks from lo to hi, oldest first
+ * @param stamp -- the time of a block
+**/
+async fn encode<E, S>(path: &Path, pools: (&Address, &Address), reserves: ((u128, u128), (u128, u128)), range: (u64, u64), every: u64, mut events: E, stamp: S) -> eyre::Result<Summary>
+where
+    E: AsyncFnMut(u64, u64) -> eyre::Result<Vec<backfill::SyncEvent>>,
+    S: AsyncFn(u64) -> eyre::Result<u64>,
+{
+    let file = File::create(path).map_err(|e| eyre!("could not create {}: {e}", path.display()))?;
+    let mut writer = Writer::new(file, Compression::for_path(path))?;
+    writer.write_line(HEADER)?;
+    writer.write_line(&format!("P {:?} {:?}", pools.0, pools.1))?;
+
+    let (from, to) = range;
+    let (mut start, mut end) = reserves;
+    let mut summary = Summary { snapshots: 0, deltas: 0, blocks: to - from + 1 };
+    let mut next = from;
+
+    let mut lo = from;
+    while lo <= to {
+        let hi = (lo + WINDOW - 1).min(to);
+        let mut events = events(lo, hi).await?.into_iter().peekable();
+        while let Some(first) = events.next() {
+            let block = first.block;
+            // Snapshots up to the block before are the reserves as they are now
+            while next < block {
+                writer.write_line(&format!("S {next} {} {} {} {} {}", stamp(next).await?, start.0, start.1, end.0, end.1))?;
+                summary.snapshots += 1;
+                next += every;
+            }
+
+            let (old_start, old_end) = (start, end);
+            let mut apply = |event: backfill::SyncEvent| match event.is_start {
+                true => start = event.reserves,
+                false => end = event.reserves,
+            };
+            apply(first);
+            while let Some(event) = events.next_if(|e| e.block == block) {
+                apply(event);
+            }
+            // The events of a snapshot's own block go into the snapshot
+            if block == next {
+                continue;
+            }
+            for (pool, old, new) in [(0, old_start, start), (1, old_end, end)] {
+                if old != new {
+                    writer.write_line(&format!("D {block} {pool} {} {}", new.0 as i128 - old.0 as i128, new.1 as i128 - old.1 as i128))?;
+                    summary.deltas += 1;
+                }
+            }
+        }
+        lo = hi + 1;
+    }
+    while next <= to {
+        writer.write_line(&format!("S {next} {} {} {} {} {}", stamp(next).await?, start.0, start.1, end.0, end.1))?;
+        summary.snapshots += 1;
+        next += every;
+    }
+    writer.write_line(&format!("E {to} {}", stamp(to).await?))?;
+    writer.finish()?;
+    Ok(summary)
+}
+
+/**
+ * @gist rebuilds the route at some blocks in one pass over an archive
+ * @param path -- written by `history archive`
+ * @param blocks -- in any order, each within the range of the archive
+ * @output a point per block, oldest first
+**/
+pub fn at(path: &Path, blocks: &[u64]) -> eyre::Result<Vec<Point>> {
+    let mut wanted = blocks.to_vec();
+    wanted.sort_unstable();
+    wanted.dedup();
+    let mut wanted = wanted.into_iter().peekable();
+
+    let mut lines = history::open(path)?.lines();
+    if lines.next().transpose()?.as_deref() != Some(HEADER) {
+        bail!("{} is not an archive of this version, expected {HEADER:?} on its first line", path.display());
+    }
+
+    // The reserves after the records read so far, None before the first snapshot
+    let mut reserves: Option<((u128, u128), (u128, u128))> = None;
+    // Block and time of the last snapshot
+    let mut stamped = (0, 0);
+    // Points waiting for the time of the next snapshot
+    let mut pending: Vec<Point> = Vec::new();
+    let mut points = Vec::with_capacity(blocks.len());
+
+    for (n, line) in lines.enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let bad = || eyre!("{} line {}: invalid record {line:?}", path.display(), n + 2);
+        let kind = fields.first().copied().unwrap_or_default();
+        if kind == "P" {
+            continue;
+        }
+        let record: u64 = field(&fields, 1).ok_or_else(bad)?;
+        let last = kind == "E";
+
+        // Wanted blocks before this record have the reserves as they are now
+        while let Some(block) = wanted.next_if(|w| *w < record || last && *w == record) {
+            let Some((start, end)) = reserves else {
+                bail!("block {block} is before the archive, which starts at block {record}");
+            };
+            pending.push(Point { block, timestamp: 0, start, end, price: route_from_reserves(start, end).mid_price() });
+        }
+
+        match kind {
+            "S" | "E" => {
+                let time: u64 = field(&fields, 2).ok_or_else(bad)?;
+                for mut point in pending.drain(..) {
+                    point.timestamp = match record - stamped.0 {
+                        0 => time,
+                        span => stamped.1 + time.saturating_sub(stamped.1) * (point.block - stamped.0) / span,
+                    };
+                    points.push(point);
+                }
+                stamped = (record, time);
+                if last {
+                    if let Some(block) = wanted.next() {
+                        bail!("block {block} is after the archive, which ends at block {record}");
+                    }
+                    return Ok(points);
+                }
+                let reserve = |i: usize| field::<u128>(&fields, i).ok_or_else(bad);
+                reserves = Some(((reserve(3)?, reserve(4)?), (reserve(5)?, reserve(6)?)));
+            }
+            "D" => {
+                let Some((start, end)) = reserves.as_mut() else {
+                    return Err(bad());
+                };
+                let pool = match field::<u8>(&fields, 2) {
+                    Some(0) => start,
+                    Some(1) => end,
+                    _ => return Err(bad()),
+                };
+                let moved = |i: usize, reserve: u128| field::<i128>(&fields, i)
+                    .and_then(|change| reserve.checked_add_signed(change))
+                    .ok_or_else(bad);
+                *pool = (moved(3, pool.0)?, moved(4, pool.1)?);
+            }
+            _ => return Err(bad()),
+        }
+    }
+    bail!("{} has no end record, it may still be being written", path.display())
+}
+
+/**
+ * @gist parses a field of a record
+**/
+fn field<T: FromStr>(fields: &[&str], i: usize) -> Option<T> {
+    fields.get(i)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backfill::SyncEvent;
+
+    const START: ((u128, u128), (u128, u128)) = ((200_000, 1_000), (10_000, 20_000_000));
+
+    fn sync(block: u64, is_start: bool, reserves: (u128, u128)) -> SyncEvent {
+        SyncEvent { block, is_start, reserves }
+    }
+
+    fn events() -> Vec<SyncEvent> {
+        vec![
+            // In the block of the first snapshot
+            sync(100, true, (199_000, 1_005)),
+            sync(105, true, (198_000, 1_010)),
+            sync(105, false, (10_010, 19_980_000)),
+            sync(110, false, (10_020, 19_960_000)),
+            sync(117, true, (201_000, 995)),
+            // In the block of the last snapshot
+            sync(130, false, (10_000, 20_000_000)),
+        ]
+    }
+
+    fn time(block: u64) -> u64 {
+        1_700_000_000 + 12 * (block - 100)
+    }
+
+    /// Writes the events from block 100 to 130 with a snapshot every 10
+    async fn write(name: &str) -> (std::path::PathBuf, Summary) {
+        let path = std::env::temp_dir().join(format!("uni-price-polling-{}-{name}", std::process::id()));
+        let (start_a, end_a) = (Address::repeat_byte(1), Address::repeat_byte(2));
+        let all = events();
+        let events = async |lo: u64, hi: u64| Ok(all.iter().filter(|e| (lo..=hi).contains(&e.block)).copied().collect());
+        let stamp = async |block: u64| Ok(time(block));
+        let summary = encode(&path, (&start_a, &end_a), START, (100, 130), 10, events, stamp).await.unwrap();
+        (path, summary)
+    }
+
+    /// The reserves at the end of a block, from the events themselves
+    fn replayed(block: u64) -> ((u128, u128), (u128, u128)) {
+        let (mut start, mut end) = START;
+        for e in events().into_iter().filter(|e| e.block <= block) {
+            match e.is_start {
+                true => start = e.reserves,
+                false => end = e.reserves,
+            }
+        }
+        (start, end)
+    }
+
+    #[tokio::test]
+    async fn writes_snapshots_and_deltas() {
+        let (path, summary) = write("records.archive").await;
+        assert_eq!((summary.snapshots, summary.deltas, summary.blocks), (4, 3, 31));
+        let text = std::fs::read_to_string(&path).unwrap();
+        let records: Vec<&str> = text.lines().map(|l| &l[..1]).collect();
+        assert_eq!(records, ["#", "P", "S", "D", "D", "S", "D", "S", "S", "E"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn rebuilds_every_block() {
+        for name in ["blocks.archive", "blocks.archive.gz", "blocks.archive.zst"] {
+            let (path, _) = write(name).await;
+            let blocks: Vec<u64> = (100..=130).rev().chain([105, 117]).collect();
+            let points = at(&path, &blocks).unwrap();
+            assert_eq!(points.len(), 31, "{name}");
+            for (point, block) in points.iter().zip(100..=130) {
+                let (start, end) = replayed(block);
+                assert_eq!((point.block, point.timestamp), (block, time(block)), "{name}");
+                assert_eq!((point.start, point.end), (start, end), "{name} block {block}");
+                assert_eq!(point.price, route_from_reserves(start, end).mid_price());
+            }
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_blocks_outside_the_range() {
+        let (path, _) = write("range.archive").await;
+        let before = at(&path, &[99]).unwrap_err().to_string();
+        assert!(before.contains("before the archive"), "{before}");
+        let after = at(&path, &[131]).unwrap_err().to_string();
+        assert!(after.contains("after the archive"), "{after}");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+/*!
+ *
+ * API keys and rate limits of the `--dashboard` server, for when it is
+ * reachable from more than localhost. With keys configured every request
+ * but the one for the page itself needs a key, as a bearer token, an
+ * X-Api-Key header or an api_key query parameter (the page passes its own
+ * on to the endpoints):
+ *
+ * [server]
+ * api_keys = ["3f9c...", "b71e..."]
+ * rate_limit = 60   # requests per minute and key, 0 for no limit
+ *
+ * curl -H "Authorization: Bearer 3f9c..." 127.0.0.1:8080/api/quotes
+ * open http://127.0.0.1:8080/?api_key=3f9c...
+ *
+ * A key over its limit gets 429 with a Retry-After. Without keys the limit
+ * applies per client address.
+ *
+**/
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::dashboard::ServerConfig;
+
+/// Window the rate limit is counted over
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Why a request is turned away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Denied {
+    /// No key or an unknown one
+    Unauthorized,
+    /// Over the rate limit, try again after this long
+    Limited(Duration),
+}
+
+/// Checks the keys and counts the requests of every key
+pub struct Gate {
+    keys: Vec<String>,
+    limit: u32,
+    /// Start of the current window and the requests in it, by key or client
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl Gate {
+    /**
+     * @gist creates the gate
+     * @param config -- the [server] table
+    **/
+    pub fn new(config: &ServerConfig) -> Self {
+        Self { keys: config.api_keys.clone(), limit: config.rate_limit, windows: Mutex::new(HashMap::new()) }
+    }
+
+    /**
+     * @gist lets a request through or tells why not
+     * @param key -- the key the request came with
+     * @param client -- where it came from, counted when no keys are configured
+    **/
+    pub fn check(&self, key: Option<&str>, client: IpAddr) -> Result<(), Denied> {
+        self.check_with(key, client, &[])
+    }
+
+    /**
+     * @gist check for an endpoint that more keys open, e.g. those of a
+     * namespace
+     * @param scoped -- accepted next to the keys of [server], a key is needed
+     * once either has some
+    **/
+    pub fn check_with(&self, key: Option<&str>, client: IpAddr, scoped: &[String]) -> Result<(), Denied> {
+        let counted = match self.keys.is_empty() && scoped.is_empty() {
+            true => client.to_string(),
+            false => match key {
+                // Every key is compared, and all of each, so the time taken
+                // doesn't tell how much of a key was right
+                Some(key) if self.keys.iter().chain(scoped).fold(false, |found, k| found | same(k, key)) => key.to_string(),
+                _ => return Err(Denied::Unauthorized),
+            },
+        };
+        if self.limit == 0 {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        // Forget the clients whose window ended, they would start over anyway
+        windows.retain(|_, (start, _)| now.duration_since(*start) < WINDOW);
+        let (start, count) = windows.entry(counted).or_insert((now, 0));
+        if *count >= self.limit {
+            return Err(Denied::Limited(WINDOW.saturating_sub(now.duration_since(*start))));
+        }
+        *count += 1;
+        Ok(())
+    }
+}
+
+/**
+ * @gist compares two keys in a time that only depends on their length
+**/
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/**
+ * @gist finds the key of a request
+ * @param headers -- names in lower case
+ * @param query -- the part of the path after the ?
+**/
+pub fn key<'a>(headers: &'a [(String, String)], query: &'a str) -> Option<&'a str> {
+    let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+    header("authorization").and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| header("x-api-key"))
+        .or_else(|| query.split('&').find_map(|pair| pair.strip_prefix("api_key=")))
+        .map(str::trim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gate(keys: &[&str], limit: u32) -> Gate {
+        Gate::new(&ServerConfig { api_keys: keys.iter().map(|k| k.to_string()).collect(), rate_limit: limit, ..ServerConfig::default() })
+    }
+
+    fn headers(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect()
+    }
+
+    const CLIENT: IpAddr = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+
+    #[test]
+    fn finds_the_key_of_a_request() {
+        assert_eq!(key(&headers(&[("authorization", "Bearer abc ")]), ""), Some("abc"));
+        assert_eq!(key(&headers(&[("x-api-key", "abc")]), ""), Some("abc"));
+        assert_eq!(key(&[], "from=1&api_key=abc&to=2"), Some("abc"));
+        // The header wins over the query
+        assert_eq!(key(&headers(&[("x-api-key", "abc")]), "api_key=def"), Some("abc"));
+        // Only bearer tokens count
+        assert_eq!(key(&headers(&[("authorization", "Basic abc")]), ""), None);
+        assert_eq!(key(&[], "my_api_key=abc"), None);
+    }
+
+    #[test]
+    fn compares_whole_keys() {
+        assert!(same("3f9c", "3f9c"));
+        assert!(!same("3f9c", "3f9d"));
+        assert!(!same("3f9c", "3f9"));
+        assert!(!same("3f9", "3f9c"));
+        assert!(same("", ""));
+    }
+
+    #[test]
+    fn needs_a_known_key_once_there_are_keys() {
+        let open = gate(&[], 0);
+        assert_eq!(open.check(None, CLIENT), Ok(()));
+
+        let closed = gate(&["3f9c", "b71e"], 0);
+        assert_eq!(closed.check(Some("b71e"), CLIENT), Ok(()));
+        assert_eq!(closed.check(Some("b71"), CLIENT), Err(Denied::Unauthorized));
+        assert_eq!(closed.check(None, CLIENT), Err(Denied::Unauthorized));
+    }
+
+    #[test]
+    fn scoped_keys_open_their_endpoints_only() {
+        let scoped = ["5d2a".to_string()];
+        assert_eq!(gate(&[], 0).check_with(Some("5d2a"), CLIENT, &scoped), Ok(()));
+        assert_eq!(gate(&[], 0).check_with(None, CLIENT, &scoped), Err(Denied::Unauthorized));
+        assert_eq!(gate(&["3f9c"], 0).check_with(Some("3f9c"), CLIENT, &scoped), Ok(()));
+        assert_eq!(gate(&["3f9c"], 0).check(Some("5d2a"), CLIENT), Err(Denied::Unauthorized));
+    }
+
+    #[test]
+    fn limits_every_key_on_its_own() {
+        let gate = gate(&["3f9c", "b71e"], 2);
+        assert_eq!(gate.check(Some("3f9c"), CLIENT), Ok(()));
+        assert_eq!(gate.check(Some("3f9c"), CLIENT), Ok(()));
+        let Err(Denied::Limited(wait)) = gate.check(Some("3f9c"), CLIENT) else {
+            panic!("the third request of a minute is over the limit");
+        };
+        assert!(wait <= WINDOW && wait > WINDOW - Duration::from_secs(5));
+        assert_eq!(gate.check(Some("b71e"), CLIENT), Ok(()));
+    }
+
+    #[test]
+    fn limits_clients_without_keys() {
+        let gate = gate(&[], 1);
+        let other = IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(gate.check(None, CLIENT), Ok(()));
+        assert!(matches!(gate.check(None, CLIENT), Err(Denied::Limited(_))));
+        assert_eq!(gate.check(None, other), Ok(()));
+    }
+}
@@ -0,0 +1,180 @@
+/*!
+ *
+ * Replays the Sync events of a TOKEN/ETH and ETH/USDT pool pair to recover
+ * the prices of blocks that weren't polled, e.g. while the poller was down.
+ * Every Sync carries the full reserves of its pool, so starting from the
+ * reserves at the beginning of the gap the route can be recomputed after
+ * every event.
+ *
+ * `history backfill` does the same for any range, given as blocks or as
+ * times. Times are turned into blocks by bisecting the block timestamps, so
+ * no block-by-date service is needed:
+ *
+ * uni-price-polling history backfill --pool LINK --from 2024-01-01 --to 2024-02-01 --format csv
+ *
+**/
+
+use ethers::{
+    providers::Middleware,
+    types::{Address, ValueOrArray},
+};
+use std::sync::Arc;
+
+use crate::uniswap_v2::{get_reserves, get_reserves_at, route_from_reserves, IUniswapV2Pair};
+
+/// Gaps are only backfilled for about one day of blocks, older prices fall
+/// out of the rolling window anyway
+pub const MAX_BLOCKS: u64 = 7200;
+
+/// Block range of a single eth_getLogs request
+const CHUNK: u64 = 2000;
+
+/// A Sync event of one of the pools of a route
+#[derive(Debug, Clone, Copy)]
+pub struct SyncEvent {
+    pub block: u64,
+    /// Of the TOKEN/ETH pool, the ETH/USDT pool otherwise
+    pub is_start: bool,
+    /// The reserves after the event
+    pub reserves: (u128, u128),
+}
+
+/**
+ * @gist recomputes the route price after every Sync event in (from, to]
+ * @param provider -- used for getReserves and eth_getLogs
+ * @param start_a -- the TOKEN/ETH pair address
+ * @param end_a -- the ETH/USDT pair address
+ * @param from -- the last block that is already known
+ * @param to -- the last block to backfill
+ * @output (block, price) pairs, one per block with an event, oldest first
+**/
+pub async fn sync_prices<M: Middleware + 'static>(provider: Arc<M>, start_a: &Address, end_a: &Address, from: u64, to: u64) -> eyre::Result<Vec<(u64, f64)>> {
+    let mut start = reserves_at(provider.clone(), start_a, from).await?;
+    let mut end = reserves_at(provider.clone(), end_a, from).await?;
+
+    let mut prices: Vec<(u64, f64)> = Vec::new();
+    for event in sync_events(provider, start_a, end_a, from, to).await? {
+        if event.is_start {
+            start = event.reserves;
+        } else {
+            end = event.reserves;
+        }
+
+        let price = route_from_reserves(start, end).mid_price();
+        match prices.last_mut() {
+            Some(last) if last.0 == event.block => last.1 = price,
+            _ => prices.push((event.block, price)),
+        }
+    }
+
+    Ok(prices)
+}
+
+/**
+ * @gist the Sync events of both pools of a route in (from, to]
+ * @output oldest first, in the order they were emitted
+**/
+pub async fn sync_events<M: Middleware + 'static>(provider: Arc<M>, start_a: &Address, end_a: &Address, from: u64, to: u64) -> eyre::Result<Vec<SyncEvent>> {
+    let mut events = Vec::new();
+    for (pool, is_start) in [(start_a, true), (end_a, false)] {
+        let pair = IUniswapV2Pair::new(*pool, provider.clone());
+        let mut lo = from + 1;
+        while lo <= to {
+            let hi = (lo + CHUNK - 1).min(to);
+            // The generated filter matches the event of every contract, not just this one
+            let filter = pair.sync_filter().address(ValueOrArray::Value(*pool)).from_block(lo).to_block(hi);
+            for (sync, meta) in filter.query_with_meta().await? {
+                let event = SyncEvent { block: meta.block_number.as_u64(), is_start, reserves: (sync.reserve_0, sync.reserve_1) };
+                events.push((meta.log_index.as_u64(), event));
+            }
+            lo = hi + 1;
+        }
+    }
+    events.sort_by_key(|(index, event)| (event.block, *index));
+    Ok(events.into_iter().map(|(_, event)| event).collect())
+}
+
+/**
+ * @gist reserves of a pool at a block, nodes that don't keep that much
+ * history answer with the latest reserves instead
+**/
+pub async fn reserves_at<M: Middleware + 'static>(provider: Arc<M>, pool: &Address, block: u64) -> eyre::Result<(u128, u128)> {
+    let (r0, r1, _) = match get_reserves_at(provider.clone(), pool, Some(block.into())).await {
+        Ok(reserves) => reserves,
+        Err(_) => get_reserves(provider, pool).await?,
+    };
+    Ok((r0, r1))
+}
+
+/**
+ * @gist the first block mined at or after a time, found by bisecting the
+ * timestamps of the blocks up to the head
+ * @param provider -- used for eth_getBlockByNumber
+ * @param time -- unix time
+ * @output the block, the head when the time is after it
+**/
+pub async fn block_at<M: Middleware + 'static>(provider: &M, time: u64) -> eyre::Result<u64> {
+    let timestamp = async |block: u64| block_time(provider, block).await;
+
+    let head = provider.get_block_number().await?.as_u64();
+    if timestamp(head).await? < time {
+        return Ok(head);
+    }
+    let (mut lo, mut hi) = (0, head);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if timestamp(mid).await? < time {
+            lo = mid + 1;
+        } else {
+            hi = mid;
+        }
+    }
+    Ok(lo)
+}
+
+/**
+ * @gist the unix time of a block
+ * @param provider -- used for eth_getBlockByNumber
+**/
+pub async fn block_time<M: Middleware>(provider: &M, block: u64) -> eyre::Result<u64> {
+    let header = provider.get_block(block).await
+        .map_err(|e| eyre::eyre!("could not read block {block}: {e}"))?
+        .ok_or_else(|| eyre::eyre!("the node has no block {block}"))?;
+    Ok(header.timestamp.as_u64())
+}
+
+/**
+ * @gist the time of a block between two blocks of known times, as if the
+ * blocks in between came at an even pace
+ * @param block -- in from..=to
+ * @param from -- block and unix time of the start
+ * @param to -- block and unix time of the end
+**/
+pub fn interpolate(block: u64, from: (u64, u64), to: (u64, u64)) -> u64 {
+    match to.0.saturating_sub(from.0) {
+        0 => from.1,
+        span => from.1 + to.1.saturating_sub(from.1) * (block - from.0) / span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn interpolates_block_times() {
+        let (from, to) = ((100, 1_000), (110, 1_120));
+        assert_eq!(interpolate(100, from, to), 1_000);
+        assert_eq!(interpolate(105, from, to), 1_060);
+        assert_eq!(interpolate(110, from, to), 1_120);
+        // Rounded down to the second
+        assert_eq!(interpolate(101, (100, 1_000), (103, 1_010)), 1_003);
+    }
+
+    #[test]
+    fn interpolates_degenerate_ranges() {
+        assert_eq!(interpolate(100, (100, 1_000), (100, 1_000)), 1_000);
+        // A clock behind the start doesn't go back in time
+        assert_eq!(interpolate(105, (100, 1_000), (110, 900)), 1_000);
+    }
+}
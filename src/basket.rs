@@ -0,0 +1,119 @@
+/*!
+ *
+ * Basket/index pricing. A basket is a weighted set of pools, the index value
+ * is the weighted mean of the component prices:
+ *
+ * [[basket]]
+ * name = "DEFI"
+ *
+ * [[basket.component]]
+ * label = "LINK"
+ * pool = "0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974"
+ * weight = 0.5
+ * inverted = false   # layout of the pool, as in [[pool]]
+ * decimals = 18      # of TOKEN
+ *
+ * Weights don't have to add up to one, they are normalized.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::types::Address;
+use eyre::bail;
+use serde::Deserialize;
+
+use crate::composite::weighted_mean;
+use crate::poller::{self, Feed};
+use crate::source::{Asset, PriceSource, Quote};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BasketConfig {
+    /// Label used in the output
+    pub name: String,
+    pub component: Vec<ComponentConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ComponentConfig {
+    /// Label of the token in the component list
+    pub label: String,
+    /// The TOKEN/ETH pool used to price it
+    pub pool: Address,
+    pub weight: f64,
+    /// WETH is token0 of the pool and TOKEN is token1
+    #[serde(default)]
+    pub inverted: bool,
+    /// Decimals of TOKEN
+    #[serde(default = "default_decimals")]
+    pub decimals: u8,
+}
+
+fn default_decimals() -> u8 {
+    18
+}
+
+impl BasketConfig {
+    /**
+     * @gist the asset the basket prices, it has no address of its own
+    **/
+    pub fn asset(&self) -> Asset {
+        Asset { name: self.name.clone(), address: Address::zero() }
+    }
+}
+
+/// Weighted index over several feeds
+pub struct Basket {
+    components: Vec<(Feed, f64)>,
+}
+
+impl Basket {
+    /**
+     * @gist creates the source
+     * @param components -- the feed of every token and its weight
+    **/
+    pub fn new(components: Vec<(Feed, f64)>) -> Self {
+        Self { components }
+    }
+}
+
+#[async_trait]
+impl PriceSource for Basket {
+    fn name(&self) -> &str {
+        "INDEX"
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let feeds: Vec<Feed> = self.components.iter().map(|(feed, _)| feed.clone()).collect();
+        let mut components = Vec::with_capacity(feeds.len());
+        let mut weighted = Vec::with_capacity(feeds.len());
+
+        // Unlike a composite, an index with a missing component is wrong
+        // rather than less precise, so any failure fails the whole quote
+        for ((_, weight), quote) in self.components.iter().zip(poller::poll(&feeds).await) {
+            let quote = quote?;
+            weighted.push((quote.price, *weight));
+            components.push(quote);
+        }
+
+        let Some(price) = weighted_mean(&weighted) else {
+            bail!("{}: basket weights add up to zero", asset.name);
+        };
+
+        let mut quote = Quote::new(self.name(), &asset.name, price);
+        // Stamped like the most recent component
+        if let Some(latest) = components.iter().max_by_key(|q| q.block) {
+            quote.block = latest.block;
+            quote.timestamp = latest.timestamp;
+        }
+        // The components price different tokens, they don't corroborate
+        // each other like the venues of a composite
+        let scores: Vec<(f64, f64)> = components.iter().zip(&self.components)
+            .filter_map(|(q, (_, weight))| Some((f64::from(q.confidence?), *weight)))
+            .collect();
+        quote.confidence = weighted_mean(&scores).map(|s| s.round() as u8);
+        quote.components = components;
+        Ok(quote)
+    }
+}
@@ -0,0 +1,176 @@
+/*!
+ *
+ * Batched reserve reads for large watchlists. Instead of a getReserves call
+ * per pool and hop, the Uniswap V2 sources sharing a batch read the
+ * reserves of every pool of the watchlist with multicalls of `chunk`
+ * pools each, all at the block the first chunk was read at. The snapshot
+ * serves one quote per pool and cycle: it is read again in every cycle of
+ * watch mode, see `next_cycle`, and within one once a pool asks a second
+ * time, e.g. to quote a reorged block again. A pool that sits out cycles
+ * thus never gets the reserves of an older one.
+ *
+ * --multicall-chunk picks the chunk size, larger chunks mean fewer but
+ * heavier eth_calls.
+ *
+**/
+
+use ethers::{
+    abi::Token,
+    prelude::Multicall,
+    providers::Middleware,
+    types::Address,
+};
+use tokio::sync::{Mutex, OnceCell};
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::source::Head;
+use crate::uniswap_v2::IUniswapV2Pair;
+
+/// The reserves of every pool at one block
+struct Snapshot {
+    /// The cycle it was read in
+    cycle: u64,
+    head: Head,
+    reserves: HashMap<Address, Result<(u128, u128, u32), String>>,
+    /// Pools quoted from this snapshot
+    served: HashSet<Address>,
+}
+
+/// Reads the reserves of many pools with chunked multicalls
+pub struct ReserveBatch<M> {
+    provider: Arc<M>,
+    chunk: usize,
+    pools: std::sync::Mutex<Vec<Address>>,
+    multicall: OnceCell<Multicall<M>>,
+    snapshot: Mutex<Option<Snapshot>>,
+    cycle: AtomicU64,
+}
+
+impl<M: Middleware + 'static> ReserveBatch<M> {
+    /**
+     * @gist creates a batch
+     * @param provider -- this is used to send request to the multicall SC
+     * @param chunk -- number of pools read by one multicall
+     * @param pools -- the pools read on every refresh, others are added
+     * when first asked for
+    **/
+    pub fn new(provider: Arc<M>, chunk: usize, pools: Vec<Address>) -> Self {
+        let mut unique = Vec::with_capacity(pools.len());
+        for pool in pools {
+            if !unique.contains(&pool) {
+                unique.push(pool);
+            }
+        }
+        Self {
+            provider,
+            chunk: chunk.max(1),
+            pools: std::sync::Mutex::new(unique),
+            multicall: OnceCell::new(),
+            snapshot: Mutex::new(None),
+            cycle: AtomicU64::new(0),
+        }
+    }
+
+    /**
+     * @gist starts a new poll cycle, the next pool asking reads the reserves
+     * again
+    **/
+    pub fn next_cycle(&self) {
+        self.cycle.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /**
+     * @gist reserves of a pool and the ETH/USDT pool at the same block
+     * @param pool -- the TOKEN/ETH pair
+     * @param end_a -- the ETH/USDT pair
+     * @output the block read at and the (reserve0, reserve1, blockTimestampLast)
+     * of both pairs
+    **/
+    pub async fn reserves(&self, pool: &Address, end_a: &Address) -> eyre::Result<(Head, (u128, u128, u32), (u128, u128, u32))> {
+        {
+            let mut pools = self.pools.lock().unwrap();
+            for p in [pool, end_a] {
+                if !pools.contains(p) {
+                    pools.push(*p);
+                }
+            }
+        }
+
+        let mut snapshot = self.snapshot.lock().await;
+        let cycle = self.cycle.load(Ordering::Relaxed);
+        let fresh = snapshot.as_ref()
+            .is_some_and(|s| s.cycle == cycle && !s.served.contains(pool) && s.reserves.contains_key(pool) && s.reserves.contains_key(end_a));
+        if !fresh {
+            *snapshot = Some(self.read(cycle).await?);
+        }
+        let snapshot = snapshot.as_mut().expect("the snapshot was just read");
+        snapshot.served.insert(*pool);
+
+        let get = |p: &Address| -> eyre::Result<(u128, u128, u32)> {
+            match snapshot.reserves.get(p) {
+                Some(Ok(reserves)) => Ok(*reserves),
+                Some(Err(e)) => Err(eyre::eyre!("could not read the reserves of {p:?}: {e}")),
+                None => Err(eyre::eyre!("{p:?} is missing from the batch")),
+            }
+        };
+        Ok((snapshot.head, get(pool)?, get(end_a)?))
+    }
+
+    /**
+     * @gist reads the reserves of every pool, the first chunk also reads the
+     * block number and time the other chunks are pinned to
+    **/
+    async fn read(&self, cycle: u64) -> eyre::Result<Snapshot> {
+        let pools = self.pools.lock().unwrap().clone();
+        let base = self.multicall.get_or_try_init(|| Multicall::new(self.provider.clone(), None)).await
+            .map_err(|e| eyre::eyre!("could not set up the multicall: {e}"))?;
+
+        let mut head: Option<Head> = None;
+        let mut reserves = HashMap::with_capacity(pools.len());
+        for chunk in pools.chunks(self.chunk) {
+            let mut multicall = base.clone();
+            multicall.clear_calls();
+            match head {
+                Some(head) => multicall = multicall.block(head.number),
+                None => {
+                    multicall.add_get_block_number();
+                    multicall.add_get_current_block_timestamp();
+                }
+            }
+            // A bad pool must not fail the others
+            for pool in chunk {
+                multicall.add_call(IUniswapV2Pair::new(*pool, self.provider.clone()).get_reserves(), true);
+            }
+
+            let mut results = multicall.call_raw().await.map_err(|e| eyre::eyre!("multicall failed: {e}"))?;
+            if head.is_none() {
+                let mut uint = || match results.remove(0) {
+                    Ok(Token::Uint(v)) => Ok(v.as_u64()),
+                    _ => Err(eyre::eyre!("the multicall returned no block")),
+                };
+                head = Some(Head { number: uint()?, timestamp: uint()? });
+            }
+            for (pool, result) in chunk.iter().zip(results) {
+                reserves.insert(*pool, decode(result));
+            }
+        }
+
+        let head = head.ok_or_else(|| eyre::eyre!("no pools to read"))?;
+        Ok(Snapshot { cycle, head, reserves, served: HashSet::new() })
+    }
+}
+
+fn decode(result: Result<Token, ethers::types::Bytes>) -> Result<(u128, u128, u32), String> {
+    match result {
+        Ok(Token::Tuple(values)) => match values.as_slice() {
+            [Token::Uint(r0), Token::Uint(r1), Token::Uint(last)] => Ok((r0.as_u128(), r1.as_u128(), last.as_u32())),
+            _ => Err("unexpected getReserves() output".to_string()),
+        },
+        Ok(_) => Err("unexpected getReserves() output".to_string()),
+        Err(data) if data.is_empty() => Err("getReserves() reverted or the address has no code".to_string()),
+        Err(data) => Err(format!("getReserves() reverted with {data}")),
+    }
+}
@@ -0,0 +1,270 @@
+/*!
+ *
+ * Load test of an RPC, to size a watchlist and compare providers before
+ * running them. `bench` quotes the pools of the watchlist (or --pool) for
+ * a number of rounds in each mode and reports the latency of a quote and
+ * the pools per second the RPC sustains:
+ *
+ * uni-price-polling --config watch.toml bench --rounds 10
+ *
+ * sequential  one quote at a time, the getReserves calls of a pool follow
+ *             each other
+ * concurrent  every pool of a round at once, like watch mode, up to
+ *             --max-concurrent-requests in flight
+ * multicall   like concurrent with the reserves of a round read by
+ *             multicalls of --multicall-chunk pools
+ * event       subscribes to the Sync events of the pools over --ws, a
+ *             round is a block that moved one of them and every event is
+ *             quoted as it arrives
+ *
+ * The latency of a quote is taken from the start of its round, so in the
+ * batched modes it includes the wait for the others; in event mode it is
+ * taken from the arrival of the event, and the report adds how long after
+ * its block's timestamp the first event of a block arrived. The first
+ * round of every mode only warms up the connection and is not counted.
+ * Event mode ends early when the pools see no event for EVENT_WAIT.
+ *
+**/
+
+use ethers::{
+    contract::EthEvent,
+    providers::{Middleware, Provider, StreamExt, Ws},
+    types::{Address, Filter, ValueOrArray},
+};
+use eyre::eyre;
+use serde::Serialize;
+use tokio::time::Instant;
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::batch::ReserveBatch;
+use crate::source::{Asset, PriceSource};
+use crate::uniswap_v2::{SyncFilter, UniswapV2};
+
+/// Chunk size of the multicall mode when --multicall-chunk is off
+pub const DEFAULT_CHUNK: usize = 100;
+
+/// Longest wait for the next Sync event in event mode
+pub const EVENT_WAIT: Duration = Duration::from_secs(60);
+
+/// How the pools of a round are quoted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Sequential,
+    Concurrent,
+    Multicall,
+    Event,
+}
+
+impl Mode {
+    /**
+     * @gist the name of the mode as --mode takes it
+    **/
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Sequential => "sequential",
+            Mode::Concurrent => "concurrent",
+            Mode::Multicall => "multicall",
+            Mode::Event => "event",
+        }
+    }
+}
+
+/// The result of one mode
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub mode: Mode,
+    pub pools: usize,
+    /// Counted rounds, without the warm-up
+    pub rounds: usize,
+    /// Quotes that succeeded
+    pub quotes: usize,
+    pub errors: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+    /// Quotes per second over all counted rounds
+    pub pools_per_sec: f64,
+    /// Event mode: median time from the timestamp of a block to its first
+    /// event, the timestamps are whole seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay_p50_ms: Option<f64>,
+}
+
+/**
+ * @gist quotes every pool for a number of rounds in one mode
+ * @param provider -- the RPC under test
+ * @param pools -- TOKEN/ETH pairs
+ * @param end_a -- the ETH/USDT pair
+ * @param mode -- how the pools of a round are quoted, the event mode is
+ * run_events
+ * @param rounds -- counted rounds, one more is run to warm up
+ * @param chunk -- pools per multicall in multicall mode
+**/
+pub async fn run<M: Middleware + 'static>(provider: Arc<M>, pools: &[Address], end_a: Address, mode: Mode, rounds: usize, chunk: usize) -> Report {
+    let mut route = UniswapV2::new(provider.clone(), end_a);
+    if mode == Mode::Multicall {
+        let watched = pools.iter().copied().chain([end_a]).collect();
+        route = route.with_batch(Arc::new(ReserveBatch::new(provider, chunk, watched)));
+    }
+    let source: Arc<dyn PriceSource> = Arc::new(route);
+    let assets: Vec<Asset> = pools.iter().map(|pool| Asset { name: format!("{pool:?}"), address: *pool }).collect();
+
+    let mut latencies = Vec::with_capacity(rounds * pools.len());
+    let (mut errors, mut elapsed) = (0, Duration::ZERO);
+    for round in 0..=rounds {
+        let start = Instant::now();
+        let results = match mode {
+            Mode::Sequential => {
+                let mut results = Vec::with_capacity(assets.len());
+                for asset in &assets {
+                    let quoted = Instant::now();
+                    let ok = source.quote(asset).await.is_ok();
+                    results.push((ok, quoted.elapsed()));
+                }
+                results
+            }
+            Mode::Concurrent | Mode::Multicall => {
+                let tasks: Vec<_> = assets.iter().cloned()
+                    .map(|asset| {
+                        let source = source.clone();
+                        tokio::spawn(async move {
+                            let ok = source.quote(&asset).await.is_ok();
+                            (ok, start.elapsed())
+                        })
+                    })
+                    .collect();
+                let mut results = Vec::with_capacity(tasks.len());
+                for task in tasks {
+                    results.push(task.await.unwrap_or((false, start.elapsed())));
+                }
+                results
+            }
+            Mode::Event => unreachable!("the event mode is run_events"),
+        };
+        if round == 0 {
+            continue;
+        }
+        elapsed += start.elapsed();
+        for (ok, latency) in results {
+            match ok {
+                true => latencies.push(latency.as_secs_f64() * 1000.0),
+                false => errors += 1,
+            }
+        }
+    }
+
+    report(mode, pools.len(), rounds, latencies, errors, elapsed)
+}
+
+/**
+ * @gist follows the Sync events of the pools for a number of blocks and
+ * quotes the pool of every event as it arrives
+ * @param url -- the ws:// or wss:// endpoint under test
+ * @param pools -- TOKEN/ETH pairs
+ * @param end_a -- the ETH/USDT pair
+ * @param rounds -- counted blocks, the first block with an event warms up
+**/
+pub async fn run_events(url: &str, pools: &[Address], end_a: Address, rounds: usize) -> eyre::Result<Report> {
+    let provider = Arc::new(Provider::<Ws>::connect(url).await.map_err(|e| eyre!("could not connect to {url}: {e}"))?);
+    let source: Arc<dyn PriceSource> = Arc::new(UniswapV2::new(provider.clone(), end_a));
+    let filter = Filter::new()
+        .address(ValueOrArray::Array(pools.to_vec()))
+        .topic0(SyncFilter::signature());
+    let mut logs = provider.subscribe_logs(&filter).await.map_err(|e| eyre!("could not subscribe to {url}: {e}"))?;
+
+    // Every block with an event and the unix time in ms its first one came
+    let mut blocks: Vec<(u64, f64)> = Vec::new();
+    let mut tasks = Vec::new();
+    let mut started = None;
+    let ended = loop {
+        let log = match tokio::time::timeout(EVENT_WAIT, logs.next()).await {
+            Ok(Some(log)) => log,
+            Ok(None) => {
+                eprintln!("the WebSocket stream of {url} ended");
+                break Instant::now();
+            }
+            Err(_) => {
+                eprintln!("no Sync event for {}s, ending the event mode", EVENT_WAIT.as_secs());
+                break Instant::now();
+            }
+        };
+        let arrived = Instant::now();
+        let Some(block) = log.block_number.map(|b| b.as_u64()).filter(|_| log.removed != Some(true) && pools.contains(&log.address)) else {
+            continue;
+        };
+        if blocks.last().is_none_or(|(last, _)| *last < block) {
+            // The block after the last counted one ends the last round
+            if blocks.len() == rounds + 1 {
+                break arrived;
+            }
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            blocks.push((block, now.as_secs_f64() * 1000.0));
+            if blocks.len() == 2 {
+                started = Some(arrived);
+            }
+        }
+        if blocks.len() == 1 {
+            continue;
+        }
+        let (source, asset) = (source.clone(), Asset { name: format!("{:?}", log.address), address: log.address });
+        tasks.push(tokio::spawn(async move {
+            let ok = source.quote(&asset).await.is_ok();
+            (ok, arrived.elapsed())
+        }));
+    };
+
+    let (mut latencies, mut errors) = (Vec::with_capacity(tasks.len()), 0);
+    for task in tasks {
+        match task.await {
+            Ok((true, latency)) => latencies.push(latency.as_secs_f64() * 1000.0),
+            _ => errors += 1,
+        }
+    }
+    let mut delays = Vec::with_capacity(blocks.len());
+    for (block, arrived) in blocks.iter().skip(1) {
+        if let Ok(Some(header)) = provider.get_block(*block).await {
+            delays.push(arrived - header.timestamp.as_u64() as f64 * 1000.0);
+        }
+    }
+
+    let counted = blocks.len().saturating_sub(1);
+    let elapsed = started.map_or(Duration::ZERO, |started| ended - started);
+    let mut report = report(Mode::Event, pools.len(), counted, latencies, errors, elapsed);
+    report.delay_p50_ms = (!delays.is_empty()).then(|| percentile(&mut delays, 0.5));
+    Ok(report)
+}
+
+/**
+ * @gist the p-th percentile of a sample, 0 for none
+ * @param sample -- sorted in place
+**/
+fn percentile(sample: &mut [f64], p: f64) -> f64 {
+    sample.sort_by(f64::total_cmp);
+    match sample.len() {
+        0 => 0.0,
+        n => sample[((n as f64 * p).ceil() as usize).clamp(1, n) - 1],
+    }
+}
+
+/**
+ * @gist sums up the quotes of a mode
+ * @param latencies -- of the successful quotes, in ms
+ * @param elapsed -- time the counted rounds took
+**/
+fn report(mode: Mode, pools: usize, rounds: usize, mut latencies: Vec<f64>, errors: usize, elapsed: Duration) -> Report {
+    Report {
+        mode,
+        pools,
+        rounds,
+        quotes: latencies.len(),
+        errors,
+        p50_ms: percentile(&mut latencies, 0.5),
+        p95_ms: percentile(&mut latencies, 0.95),
+        max_ms: latencies.last().copied().unwrap_or_default(),
+        pools_per_sec: if elapsed.is_zero() { 0.0 } else { latencies.len() as f64 / elapsed.as_secs_f64() },
+        delay_p50_ms: None,
+    }
+}
@@ -0,0 +1,120 @@
+/*!
+ *
+ * Per feed circuit breaker. A feed that fails `failures` polls in a row,
+ * e.g. a selfdestructed pair or a wrong address, is not polled again for
+ * `cooldown` seconds, so one bad entry does not cost every cycle its
+ * requests and error lines. After the cooldown a single poll is let
+ * through, success closes the breaker and failure opens it again:
+ *
+ * [breaker]
+ * failures = 5      # consecutive failures that trip it, 0 disables it
+ * cooldown = 300    # seconds
+ *
+**/
+
+use async_trait::async_trait;
+use serde::Deserialize;
+
+use std::sync::{Arc, Mutex};
+
+use crate::clock::{rfc3339, unix_now};
+use crate::source::{Asset, PriceSource, Quote};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BreakerConfig {
+    #[serde(default = "default_failures")]
+    pub failures: u32,
+    #[serde(default = "default_cooldown")]
+    pub cooldown: u64,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self { failures: default_failures(), cooldown: default_cooldown() }
+    }
+}
+
+fn default_failures() -> u32 {
+    5
+}
+
+fn default_cooldown() -> u64 {
+    300
+}
+
+/// Returned instead of polling while the breaker is open
+#[derive(Debug)]
+pub struct Open {
+    pub asset: String,
+    /// Unix time the next poll is let through
+    pub until: u64,
+}
+
+impl std::fmt::Display for Open {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: circuit open until {}", self.asset, rfc3339(self.until))
+    }
+}
+
+impl std::error::Error for Open {}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Failures in a row
+    failures: u32,
+    /// Unix time the open breaker lets a poll through again
+    open_until: Option<u64>,
+}
+
+/// Stops polling a source that keeps failing
+pub struct Breaker {
+    inner: Arc<dyn PriceSource>,
+    config: BreakerConfig,
+    state: Mutex<State>,
+}
+
+impl Breaker {
+    /**
+     * @gist wraps a source
+     * @param inner -- the source of one feed
+     * @param config -- when to trip and for how long
+    **/
+    pub fn new(inner: Arc<dyn PriceSource>, config: BreakerConfig) -> Self {
+        Self { inner, config, state: Mutex::new(State::default()) }
+    }
+}
+
+#[async_trait]
+impl PriceSource for Breaker {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        if let Some(until) = self.state.lock().unwrap().open_until.filter(|until| unix_now() < *until) {
+            return Err(Open { asset: asset.name.clone(), until }.into());
+        }
+
+        let result = self.inner.quote(asset).await;
+        let mut state = self.state.lock().unwrap();
+        match &result {
+            Ok(_) => {
+                if state.open_until.take().is_some() {
+                    eprintln!("{}: recovered, circuit closed", asset.name);
+                }
+                state.failures = 0;
+            }
+            Err(e) => {
+                state.failures += 1;
+                // A failed trial poll after the cooldown opens it right away
+                if self.config.failures > 0 && (state.failures >= self.config.failures || state.open_until.is_some()) {
+                    let until = unix_now() + self.config.cooldown;
+                    state.open_until = Some(until);
+                    eprintln!("{}: {} failures in a row, last: {e}; not polled until {}", asset.name, state.failures, rfc3339(until));
+                }
+            }
+        }
+        result
+    }
+}
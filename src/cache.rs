@@ -0,0 +1,163 @@
+/*!
+ *
+ * The poller as a JSON-RPC endpoint for other tools, so they read the
+ * reserves of the watched pools without spending the RPC's rate limit on
+ * them. With `--cache-rpc ADDR` watch mode answers `eth_call`s of
+ * `getReserves()` on every V2 pool it quotes from the reserves of its last
+ * cycle and passes every other request on to its own RPC:
+ *
+ * uni-price-polling --config watch.toml --interval 12 --cache-rpc 127.0.0.1:8546
+ * uni-price-polling --config watch.toml cache-serve 127.0.0.1:8546
+ * cast call 0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974 "getReserves()" --rpc-url http://127.0.0.1:8546
+ *
+ * `cache-serve` is the same, polling every 12 seconds unless --interval or
+ * --schedule is given.
+ *
+ * Calls at `latest` get the reserves of the newest block the poller read,
+ * which is up to one interval old; calls at that block number get them as
+ * well, calls at any other block go to the RPC. The requests passed on
+ * read the blocks they name, not the one the poller may be pinned to.
+ * Batches are answered one call at a time.
+ *
+**/
+
+use ethers::{
+    abi::{self, Token},
+    providers::{Middleware, RpcError},
+    types::{Address, U256},
+};
+use serde_json::{json, Value};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpListener;
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use crate::dashboard;
+use crate::provider;
+
+/// Calldata of getReserves()
+const GET_RESERVES: &str = "0x0902f1ac";
+
+/// What getReserves() returns, (reserve0, reserve1, blockTimestampLast)
+pub type Reserves = (u128, u128, u32);
+
+/// The reserves of the watched pools as the poller last read them
+#[derive(Debug, Default)]
+pub struct ReserveCache {
+    /// Block read at and the reserves, by pool
+    pools: Mutex<HashMap<Address, (u64, Reserves)>>,
+}
+
+impl ReserveCache {
+    /**
+     * @gist records the reserves of a pool, older blocks than the one
+     * recorded are ignored
+     * @param pool -- the pair
+     * @param block -- the block they were read at
+     * @param reserves -- as getReserves() returns them
+    **/
+    pub fn store(&self, pool: Address, block: u64, reserves: Reserves) {
+        let mut pools = self.pools.lock().unwrap();
+        if pools.get(&pool).is_none_or(|(recorded, _)| *recorded <= block) {
+            pools.insert(pool, (block, reserves));
+        }
+    }
+
+    /**
+     * @gist the answer to an eth_call when it is a getReserves() of a
+     * cached pool at a block the cache has
+     * @param params -- the params of the eth_call
+     * @output the return data, None when the RPC has to answer
+    **/
+    fn call(&self, params: &Value) -> Option<String> {
+        let call = params.get(0)?;
+        let data = call.get("data").or_else(|| call.get("input"))?.as_str()?;
+        if !data.eq_ignore_ascii_case(GET_RESERVES) {
+            return None;
+        }
+        let pool: Address = call.get("to")?.as_str()?.parse().ok()?;
+        let (block, (reserve0, reserve1, last)) = *self.pools.lock().unwrap().get(&pool)?;
+        let at_block = match params.get(1).map(|b| b.as_str()) {
+            None | Some(Some("latest" | "pending")) => true,
+            Some(Some(hex)) => hex.strip_prefix("0x").and_then(|h| u64::from_str_radix(h, 16).ok()) == Some(block),
+            Some(None) => false,
+        };
+        if !at_block {
+            return None;
+        }
+        let encoded = abi::encode(&[Token::Uint(U256::from(reserve0)), Token::Uint(U256::from(reserve1)), Token::Uint(U256::from(last))]);
+        Some(format!("0x{}", ethers::utils::hex::encode(encoded)))
+    }
+}
+
+/**
+ * @gist answers one JSON-RPC request from the cache or the RPC
+**/
+async fn answer<M: Middleware>(cache: &ReserveCache, provider: &M, request: &Value) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        return json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32600, "message": "invalid request" } });
+    };
+    let params = request.get("params").cloned().unwrap_or_else(|| json!([]));
+    if method == "eth_call" {
+        if let Some(result) = cache.call(&params) {
+            return json!({ "jsonrpc": "2.0", "id": id, "result": result });
+        }
+    }
+    // Passed on as asked, not at the block --at or a reorg pins the poller to
+    match provider::unpinned(provider.provider().request::<Value, Value>(method, params)).await {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(e) => match e.as_error_response() {
+            // Passed on as the RPC sent it, e.g. a revert with its data
+            Some(error) => {
+                let mut passed = json!({ "code": error.code, "message": error.message });
+                if let Some(data) = &error.data {
+                    passed["data"] = data.clone();
+                }
+                json!({ "jsonrpc": "2.0", "id": id, "error": passed })
+            }
+            None => json!({ "jsonrpc": "2.0", "id": id, "error": { "code": -32603, "message": e.to_string() } }),
+        },
+    }
+}
+
+/**
+ * @gist serves the cache in the background
+ * @param addr -- where to listen
+ * @param cache -- filled by the V2 sources of the watch loop
+ * @param provider -- answers what the cache can't
+**/
+pub async fn serve<M: Middleware + 'static>(addr: SocketAddr, cache: Arc<ReserveCache>, provider: Arc<M>) -> eyre::Result<()> {
+    let listener = TcpListener::bind(addr).await
+        .map_err(|e| eyre::eyre!("could not listen on {addr}: {e}"))?;
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                continue;
+            };
+            let (cache, provider) = (cache.clone(), provider.clone());
+            tokio::spawn(async move {
+                let request = dashboard::read_request(&mut stream).await
+                    .filter(|r| r.method == "POST")
+                    .and_then(|r| serde_json::from_slice::<Value>(&r.body).ok());
+                let body = match request {
+                    Some(Value::Array(batch)) => {
+                        let mut answers = Vec::with_capacity(batch.len());
+                        for request in &batch {
+                            answers.push(answer(&cache, provider.as_ref(), request).await);
+                        }
+                        Value::Array(answers)
+                    }
+                    Some(request) => answer(&cache, provider.as_ref(), &request).await,
+                    None => json!({ "jsonrpc": "2.0", "id": null, "error": { "code": -32700, "message": "expected a JSON-RPC request in a POST body" } }),
+                }.to_string();
+                let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len());
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+    Ok(())
+}
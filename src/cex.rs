@@ -0,0 +1,139 @@
+/*!
+ *
+ * Centralized exchange comparison. Assets mapped to an exchange symbol get
+ * the exchange's last price looked up with every quote, and the DEX-CEX
+ * basis in basis points is added to the quote:
+ *
+ * [cex]
+ * exchange = "binance"
+ * symbols = { "LINK/USDT" = "LINKUSDT" }
+ *
+**/
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::source::{Asset, PriceSource, Quote};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Exchange {
+    /// Symbols like "LINKUSDT"
+    Binance,
+    /// Products like "LINK-USD"
+    Coinbase,
+}
+
+impl Exchange {
+    fn default_url(self) -> &'static str {
+        match self {
+            Exchange::Binance => "https://api.binance.com",
+            Exchange::Coinbase => "https://api.exchange.coinbase.com",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CexConfig {
+    pub exchange: Exchange,
+    /// Exchange symbols by asset name
+    pub symbols: HashMap<String, String>,
+    /// API base url, defaults to the exchange's public API
+    pub url: Option<String>,
+}
+
+pub struct Cex {
+    client: Client,
+    config: CexConfig,
+}
+
+impl Cex {
+    /**
+     * @gist creates the client
+     * @param client -- HTTP client used for the API
+     * @param config -- exchange and symbols
+    **/
+    pub fn new(client: Client, config: CexConfig) -> Self {
+        Self { client, config }
+    }
+
+    /**
+     * @gist whether the asset has a symbol on the exchange
+    **/
+    pub fn lists(&self, asset: &str) -> bool {
+        self.config.symbols.contains_key(asset)
+    }
+
+    /**
+     * @gist the last traded price of an asset on the exchange
+     * @param asset -- asset name, mapped to the exchange symbol
+    **/
+    pub async fn price(&self, asset: &str) -> eyre::Result<f64> {
+        let symbol = self.config.symbols.get(asset)
+            .ok_or_else(|| eyre::eyre!("no {:?} symbol for {asset}", self.config.exchange))?;
+        let base = self.config.url.as_deref().unwrap_or(self.config.exchange.default_url());
+
+        let request = match self.config.exchange {
+            Exchange::Binance => self.client.get(format!("{base}/api/v3/ticker/price")).query(&[("symbol", symbol)]),
+            Exchange::Coinbase => self.client.get(format!("{base}/products/{symbol}/ticker")),
+        };
+        let res: Value = request.send().await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| eyre::eyre!("{:?}: {}", self.config.exchange, e.without_url()))?
+            .json().await?;
+
+        // Both exchanges return the price as a decimal string
+        res["price"].as_str().and_then(|p| p.parse().ok())
+            .ok_or_else(|| eyre::eyre!("{:?} has no price for {symbol}", self.config.exchange))
+    }
+}
+
+/**
+ * @gist the premium of the DEX price over the CEX price
+ * @output basis points, positive when the DEX is more expensive
+**/
+pub fn basis_bps(dex: f64, cex: f64) -> f64 {
+    (dex - cex) / cex * 10_000.0
+}
+
+/// Adds the exchange price and the DEX-CEX basis to the quotes of a source
+pub struct WithCex {
+    inner: Arc<dyn PriceSource>,
+    cex: Arc<Cex>,
+}
+
+impl WithCex {
+    /**
+     * @gist wraps a source
+    **/
+    pub fn new(inner: Arc<dyn PriceSource>, cex: Arc<Cex>) -> Self {
+        Self { inner, cex }
+    }
+}
+
+#[async_trait]
+impl PriceSource for WithCex {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        // Both prices are fetched together so they are as close in time as possible
+        let (quote, cex) = tokio::join!(self.inner.quote(asset), self.cex.price(&asset.name));
+        let mut quote = quote?;
+        match cex {
+            Ok(cex) => {
+                quote.cex_price = Some(cex);
+                quote.basis_bps = Some(basis_bps(quote.price, cex));
+            }
+            Err(e) => eprintln!("no cex price for {}: {e}", asset.name),
+        }
+        Ok(quote)
+    }
+}
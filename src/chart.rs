@@ -0,0 +1,78 @@
+/*!
+ *
+ * Static price charts, PNG or SVG depending on the file extension.
+ *
+**/
+
+use chrono::DateTime;
+use eyre::{bail, eyre};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use std::path::Path;
+
+/**
+ * @gist draws a line chart of a price series
+ * @param series -- (unix time, price) pairs, oldest first
+ * @param title -- caption above the chart
+ * @param output -- .svg for SVG, anything else is written as PNG
+ * @param size -- width and height in pixels
+**/
+pub fn render(series: &[(u64, f64)], title: &str, output: &Path, size: (u32, u32)) -> eyre::Result<()> {
+    if series.is_empty() {
+        bail!("nothing to chart, no prices in the selected range");
+    }
+
+    let svg = output.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("svg"));
+    if svg {
+        draw(SVGBackend::new(output, size).into_drawing_area(), series, title)
+    } else {
+        draw(BitMapBackend::new(output, size).into_drawing_area(), series, title)
+    }
+}
+
+fn draw<DB: DrawingBackend>(root: DrawingArea<DB, Shift>, series: &[(u64, f64)], title: &str) -> eyre::Result<()> {
+    let err = |e: DrawingAreaErrorKind<DB::ErrorType>| eyre!("could not draw chart: {e}");
+
+    let t0 = series[0].0;
+    let t1 = series[series.len() - 1].0.max(t0 + 1);
+    let min = series.iter().map(|(_, p)| *p).fold(f64::INFINITY, f64::min);
+    let max = series.iter().map(|(_, p)| *p).fold(f64::NEG_INFINITY, f64::max);
+    let pad = ((max - min) * 0.05).max(max.abs() * 1e-6).max(f64::MIN_POSITIVE);
+
+    root.fill(&WHITE).map_err(err)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 24))
+        .margin(15)
+        .x_label_area_size(40)
+        .y_label_area_size(80)
+        .build_cartesian_2d(t0..t1, (min - pad)..(max + pad))
+        .map_err(err)?;
+
+    let span = t1 - t0;
+    chart.configure_mesh()
+        .x_labels(6)
+        .x_label_formatter(&|t| time_label(*t, span))
+        .y_label_formatter(&|p| format!("{p:.4}"))
+        .draw()
+        .map_err(err)?;
+
+    chart.draw_series(LineSeries::new(series.iter().copied(), &BLUE)).map_err(err)?;
+    root.present().map_err(err)?;
+    Ok(())
+}
+
+/**
+ * @gist axis label for a unix time in UTC, with the date once the chart
+ * spans more than a day
+**/
+fn time_label(t: u64, span: u64) -> String {
+    let Some(time) = DateTime::from_timestamp(t as i64, 0) else {
+        return t.to_string();
+    };
+    if span > 24 * 60 * 60 {
+        time.format("%m-%d %H:%M").to_string()
+    } else {
+        time.format("%H:%M").to_string()
+    }
+}
@@ -0,0 +1,167 @@
+/*!
+ *
+ * Dry run of a config. `config check` parses the config and reads every
+ * address in it on-chain without starting the poller: pools must be V2
+ * pairs of TOKEN and WETH with the configured orientation and decimals,
+ * contracts must have code on the chain of the provider, and alert rules
+ * must name an asset that is polled:
+ *
+ * uni-price-polling --config watch.toml config check
+ *
+**/
+
+use ethers::{providers::Middleware, types::Address};
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::uniswap_v2::{self, get_pair_info};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// The outcome of checking one entry of the config
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub level: Level,
+    /// The table of the entry, e.g. [[pool]]
+    pub table: &'static str,
+    /// Name or address of the entry
+    pub name: String,
+    pub message: String,
+}
+
+/// How a pool of a table is laid out, None where the table doesn't say
+struct Layout {
+    inverted: Option<bool>,
+    decimals: Option<u8>,
+}
+
+/**
+ * @gist checks that a pool is a non-empty TOKEN/WETH pair laid out as
+ * configured
+ * @output the pair as TOKEN/WETH for the report
+**/
+async fn check_pool<M: Middleware + 'static>(provider: Arc<M>, pool: &Address, layout: Layout) -> Result<String, String> {
+    let weth: Address = uniswap_v2::WETH.parse().expect("WETH is a valid address");
+    let info = get_pair_info(provider, pool).await.map_err(|e| e.to_string())?;
+
+    let weth_is_token0 = match (info.token0.address == weth, info.token1.address == weth) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => return Err("neither token is WETH".to_string()),
+    };
+    if let Some(inverted) = layout.inverted.filter(|i| *i != weth_is_token0) {
+        return Err(format!("WETH is token{}, set inverted = {}", if weth_is_token0 { 0 } else { 1 }, !inverted));
+    }
+    let token = if weth_is_token0 { &info.token1 } else { &info.token0 };
+    let symbol = token.symbol.clone().unwrap_or_else(|| format!("{:?}", token.address));
+    if let Some(decimals) = layout.decimals.filter(|d| *d != token.decimals) {
+        return Err(format!("{symbol} has {} decimals, the config says {decimals}", token.decimals));
+    }
+    if info.reserve0 == 0 || info.reserve1 == 0 {
+        return Err(format!("the {symbol}/WETH pool is empty"));
+    }
+    Ok(format!("{symbol}/WETH, {} decimals", token.decimals))
+}
+
+/**
+ * @gist checks that a contract is deployed on the chain of the provider
+**/
+async fn check_code<M: Middleware + 'static>(provider: Arc<M>, address: &Address) -> Result<String, String> {
+    let code = provider.get_code(*address, None).await.map_err(|e| e.to_string())?;
+    match code.is_empty() {
+        true => Err(format!("{address:?} has no code on this chain")),
+        false => Ok(format!("{address:?} is a contract")),
+    }
+}
+
+/**
+ * @gist checks every entry of a config on-chain
+ * @param provider -- any ethers middleware, http or ipc
+ * @param config -- the parsed config
+ * @output one finding per pool, contract and alert rule, in config order
+**/
+pub async fn check<M: Middleware + 'static>(provider: Arc<M>, config: &Config) -> eyre::Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    let mut push = |table: &'static str, name: String, result: Result<String, String>| {
+        let (level, message) = match result {
+            Ok(message) => (Level::Ok, message),
+            Err(message) => (Level::Error, message),
+        };
+        findings.push(Finding { level, table, name, message });
+    };
+
+    // Every route prices its WETH on the mainnet ETH/USDT pool
+    let chain = provider.get_chainid().await?.as_u64();
+    let end_a: Address = uniswap_v2::ETH_USDT_POOL.parse()?;
+    push("chain", chain.to_string(), match chain {
+        1 => check_code(provider.clone(), &end_a).await.map(|_| "mainnet, the ETH/USDT pool is deployed".to_string()),
+        _ => Err("the routes price WETH on the mainnet ETH/USDT pool, the provider is on another chain".to_string()),
+    });
+
+    for pool in &config.pool {
+        let layout = Layout { inverted: Some(pool.inverted), decimals: Some(pool.decimals) };
+        push("[[pool]]", pool.asset().name, check_pool(provider.clone(), &pool.address, layout).await);
+    }
+    for oracle in &config.oracle {
+        push("[[oracle]]", oracle.name.clone(), check_code(provider.clone(), &oracle.address).await);
+    }
+    for composite in &config.composite {
+        if let Err(e) = composite.validate() {
+            push("[[composite]]", composite.name.clone(), Err(e.to_string()));
+        }
+        for member in &composite.source {
+            let layout = Layout { inverted: Some(member.inverted), decimals: Some(member.decimals) };
+            push("[[composite]]", format!("{} {}", composite.name, member.label), check_pool(provider.clone(), &member.pool, layout).await);
+        }
+    }
+    for basket in &config.basket {
+        for component in &basket.component {
+            let layout = Layout { inverted: Some(component.inverted), decimals: Some(component.decimals) };
+            push("[[basket]]", format!("{} {}", basket.name, component.label), check_pool(provider.clone(), &component.pool, layout).await);
+        }
+    }
+    for vault in &config.vault {
+        let layout = Layout { inverted: Some(vault.inverted), decimals: Some(vault.decimals) };
+        let result = match check_code(provider.clone(), &vault.address).await {
+            Ok(_) => check_pool(provider.clone(), &vault.pool, layout).await,
+            Err(e) => Err(e),
+        };
+        push("[[vault]]", vault.name.clone(), result);
+    }
+    for rate in &config.rate {
+        let layout = Layout { inverted: Some(rate.inverted), decimals: Some(rate.decimals) };
+        let result = match check_code(provider.clone(), &rate.address).await {
+            Ok(_) => check_pool(provider.clone(), &rate.pool, layout).await,
+            Err(e) => Err(e),
+        };
+        push("[[rate]]", rate.name.clone(), result);
+    }
+    for v3 in &config.v3 {
+        push("[[v3]]", v3.name.clone(), v3.validate().map(|_| format!("{} hops", v3.fees.len())).map_err(|e| e.to_string()));
+    }
+
+    // Rules on assets nobody quotes never fire
+    let assets: HashSet<String> = config.pool.iter().map(|p| p.asset().name)
+        .chain(config.oracle.iter().map(|o| o.name.clone()))
+        .chain(config.composite.iter().map(|c| c.name.clone()))
+        .chain(config.basket.iter().map(|b| b.name.clone()))
+        .chain(config.vault.iter().map(|v| v.name.clone()))
+        .chain(config.rate.iter().map(|r| r.name.clone()))
+        .chain(config.v3.iter().map(|v| v.name.clone()))
+        .collect();
+    for rule in &config.alert {
+        let (level, message) = match assets.contains(&rule.asset) {
+            true => (Level::Ok, format!("on {}", rule.asset)),
+            false => (Level::Warning, format!("no feed is named {:?}, the rule can only fire on --pool", rule.asset)),
+        };
+        findings.push(Finding { level, table: "[[alert]]", name: rule.name.clone(), message });
+    }
+    Ok(findings)
+}
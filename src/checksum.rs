@@ -0,0 +1,126 @@
+/*!
+ *
+ * EIP-55 checksums of the addresses users type. An address in mixed case
+ * carries a checksum in the case of its letters and has to match it, so a
+ * mistyped digit fails instead of pricing some other contract; an address
+ * in all lower or all upper case has no checksum and is taken as it is:
+ *
+ * uni-price-polling --pool 0xa2107FA5B38d9bbd2C461D6EDf11B11A50F6b974
+ *
+ * This holds for the command line, the config, the address book, piped
+ * pools and POST /prices. Addresses in the config and the book are
+ * stored in lower case once checked, which is also how every output shows
+ * them, so a pool is the same name and key however it was written.
+ *
+**/
+
+use ethers::{types::Address, utils::to_checksum};
+use toml::Value;
+
+/**
+ * @gist whether a string is written like an address, 0x and 40 hex digits
+**/
+pub fn is_address(s: &str) -> bool {
+    s.len() == 42 && s.starts_with("0x") && s[2..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/**
+ * @gist parses an address the user wrote, checking its checksum when it is
+ * in mixed case
+ * @param s -- e.g. 0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974
+**/
+pub fn parse(s: &str) -> eyre::Result<Address> {
+    if !is_address(s) {
+        eyre::bail!("invalid address {s:?}, expected 0x and 40 hex digits");
+    }
+    let address: Address = s.parse().map_err(|e| eyre::eyre!("invalid address {s:?}: {e}"))?;
+    let digits = &s[2..];
+    let mixed = digits.chars().any(|c| c.is_ascii_lowercase()) && digits.chars().any(|c| c.is_ascii_uppercase());
+    if mixed && to_checksum(&address, None) != s {
+        eyre::bail!("{s} fails its EIP-55 checksum, check it for typos or write it in lower case to skip the check");
+    }
+    Ok(address)
+}
+
+/**
+ * @gist parse for clap's value_parser
+**/
+pub fn parse_arg(s: &str) -> Result<Address, String> {
+    parse(s).map_err(|e| e.to_string())
+}
+
+/**
+ * @gist checks the addresses of a parsed TOML file and writes them in lower
+ * case
+ * @param value -- the whole file, changed in place
+**/
+pub fn normalize(value: &mut Value) -> eyre::Result<()> {
+    normalize_at(value, "")
+}
+
+fn normalize_at(value: &mut Value, at: &str) -> eyre::Result<()> {
+    match value {
+        Value::String(s) if is_address(s) => {
+            let address = parse(s).map_err(|e| eyre::eyre!("{at}: {e}"))?;
+            *s = format!("{address:?}");
+        }
+        Value::Array(items) => {
+            for item in items {
+                normalize_at(item, at)?;
+            }
+        }
+        Value::Table(table) => {
+            for (key, item) in table.iter_mut() {
+                let path = if at.is_empty() { key.clone() } else { format!("{at}.{key}") };
+                normalize_at(item, &path)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOWER: &str = "0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974";
+    const CHECKSUMMED: &str = "0xa2107FA5B38d9bbd2C461D6EDf11B11A50F6b974";
+
+    #[test]
+    fn takes_one_case_as_it_is() {
+        let address: Address = LOWER.parse().unwrap();
+        assert_eq!(parse(LOWER).unwrap(), address);
+        assert_eq!(parse(&format!("0x{}", LOWER[2..].to_uppercase())).unwrap(), address);
+    }
+
+    #[test]
+    fn checks_mixed_case() {
+        assert_eq!(parse(CHECKSUMMED).unwrap(), LOWER.parse::<Address>().unwrap());
+        // One letter in the wrong case
+        let typo = CHECKSUMMED.replacen("FA5", "Fa5", 1);
+        let e = parse(&typo).unwrap_err().to_string();
+        assert!(e.contains("fails its EIP-55 checksum"), "{e}");
+    }
+
+    #[test]
+    fn rejects_what_is_not_an_address() {
+        assert!(parse("a2107fa5b38d9bbd2c461d6edf11b11a50f6b974").is_err());
+        assert!(parse(&LOWER[..41]).is_err());
+        assert!(parse(&LOWER.replace('a', "g")).is_err());
+        assert!(parse("LINK").is_err());
+    }
+
+    #[test]
+    fn normalizes_the_addresses_of_a_file() {
+        let mut value: Value = toml::from_str(&format!("[[pool]]\naddress = \"{CHECKSUMMED}\"\nlabel = \"LINK\"\n")).unwrap();
+        normalize(&mut value).unwrap();
+        assert_eq!(value["pool"][0]["address"].as_str(), Some(LOWER));
+        assert_eq!(value["pool"][0]["label"].as_str(), Some("LINK"));
+
+        let typo = CHECKSUMMED.replacen("FA5", "Fa5", 1);
+        let mut value: Value = toml::from_str(&format!("[[pool]]\naddress = \"{typo}\"\n")).unwrap();
+        let e = normalize(&mut value).unwrap_err().to_string();
+        assert!(e.starts_with("pool.address: "), "{e}");
+    }
+}
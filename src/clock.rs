@@ -0,0 +1,151 @@
+/*!
+ *
+ * Local wall clock helpers, and how times are shown to humans. Machine
+ * formats (csv, json) always carry UTC RFC 3339, text output follows
+ * --timezone and --time-format.
+ *
+**/
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, FixedOffset, Local, NaiveDate, SecondsFormat, TimeZone,
+};
+use chrono_tz::Tz;
+use eyre::eyre;
+
+use std::fmt::Display;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/**
+ * @gist current unix time in seconds
+**/
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/**
+ * @gist parses a duration like "90s", "15m", "24h" or "7d"
+ * @output the duration in seconds
+**/
+pub fn parse_duration(s: &str) -> eyre::Result<u64> {
+    let (number, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let number: u64 = number.parse().map_err(|_| eyre!("invalid duration {s:?}"))?;
+    let scale = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(eyre!("invalid duration {s:?}, use s, m, h or d")),
+    };
+    Ok(number * scale)
+}
+
+/**
+ * @gist parses a point in time given as unix seconds, RFC 3339
+ * ("2024-01-01T00:00:00Z"), a date for its midnight in UTC ("2024-01-01")
+ * or a duration ago ("24h")
+ * @param s -- the user input
+ * @param now -- unix time relative durations are counted back from
+ * @output unix time in seconds
+**/
+pub fn parse_time(s: &str, now: u64) -> eyre::Result<u64> {
+    if let Ok(unix) = s.parse::<u64>() {
+        return Ok(unix);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return u64::try_from(time.timestamp()).map_err(|_| eyre!("{s} is before 1970"));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc();
+        return u64::try_from(midnight.timestamp()).map_err(|_| eyre!("{s} is before 1970"));
+    }
+    Ok(now.saturating_sub(parse_duration(s)?))
+}
+
+/**
+ * @gist formats unix seconds as RFC 3339 in UTC, e.g. "2024-01-01T00:00:00Z"
+**/
+pub fn rfc3339(unix: u64) -> String {
+    DateTime::from_timestamp(unix as i64, 0)
+        .map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_else(|| unix.to_string())
+}
+
+/// Time zone of the human readable output
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Zone {
+    #[default]
+    Utc,
+    /// The zone of the machine running the poller
+    Local,
+    /// A fixed offset like +02:00
+    Offset(FixedOffset),
+    /// An IANA zone like Europe/Berlin, follows daylight saving time
+    Named(Tz),
+}
+
+impl FromStr for Zone {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("utc") || s == "Z" {
+            return Ok(Zone::Utc);
+        }
+        if s.eq_ignore_ascii_case("local") {
+            return Ok(Zone::Local);
+        }
+        if let Ok(offset) = s.parse::<FixedOffset>() {
+            return Ok(Zone::Offset(offset));
+        }
+        s.parse::<Tz>().map(Zone::Named)
+            .map_err(|_| format!("unknown time zone {s:?}, use UTC, local, an offset like +02:00 or a name like Europe/Berlin"))
+    }
+}
+
+/// How times are shown in text output
+#[derive(Debug, Clone, Default)]
+pub struct TimeStyle {
+    zone: Zone,
+    /// strftime format, RFC 3339 when None
+    format: Option<String>,
+}
+
+impl TimeStyle {
+    /**
+     * @gist checks the format once so rendering cannot fail later
+     * @param zone -- the time zone times are converted to
+     * @param format -- strftime format like "%Y-%m-%d %H:%M %Z", None for
+     * RFC 3339
+    **/
+    pub fn new(zone: Zone, format: Option<String>) -> eyre::Result<Self> {
+        if let Some(f) = &format {
+            if StrftimeItems::new(f).any(|item| matches!(item, Item::Error)) {
+                return Err(eyre!("invalid time format {f:?}"));
+            }
+        }
+        Ok(Self { zone, format })
+    }
+
+    /**
+     * @gist formats unix seconds in the zone and format of the style
+    **/
+    pub fn render(&self, unix: u64) -> String {
+        let Some(utc) = DateTime::from_timestamp(unix as i64, 0) else {
+            return unix.to_string();
+        };
+        match self.zone {
+            Zone::Utc => self.show(utc),
+            Zone::Local => self.show(utc.with_timezone(&Local)),
+            Zone::Offset(offset) => self.show(utc.with_timezone(&offset)),
+            Zone::Named(tz) => self.show(utc.with_timezone(&tz)),
+        }
+    }
+
+    fn show<Z: TimeZone>(&self, time: DateTime<Z>) -> String where Z::Offset: Display {
+        match &self.format {
+            Some(f) => time.format(f).to_string(),
+            None => time.to_rfc3339_opts(SecondsFormat::Secs, true),
+        }
+    }
+}
@@ -0,0 +1,291 @@
+/*!
+ *
+ * Composite prices. The same asset is priced on several pools (Uniswap,
+ * Sushiswap and other V2 forks) and the individual quotes are folded into
+ * one number that a single manipulated pool can't move on its own.
+ *
+ * [[composite]]
+ * name = "LINK/USDT"
+ * method = "trimmed-mean"   # "median" (default), "trimmed-mean" or "liquidity-weighted"
+ * trim = 0.25               # share of quotes cut from each end
+ *
+ * [[composite.source]]
+ * label = "uniswap"
+ * pool = "0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974"
+ * inverted = false          # layout of the pool, as in [[pool]]
+ * decimals = 18             # of TOKEN
+ *
+ * The liquidity-weighted mean weighs every pool by the liquidity of its
+ * quote, read with the `inverted` and `decimals` of its source like the
+ * price, a source laid out wrong skews the weights too.
+ *
+ * With `half_life` the liquidity-weighted mean also weighs each pool by how
+ * recently it traded: a pool whose reserves last changed that many seconds
+ * before the block counts half, twice as long a quarter, and so on. A deep
+ * pool nobody trades in then can't hold the composite at an old price.
+ *
+ * [[composite]]
+ * name = "LINK/USDT"
+ * method = "liquidity-weighted"
+ * half_life = 3600
+ *
+**/
+
+use async_trait::async_trait;
+use eyre::bail;
+use ethers::types::Address;
+use serde::Deserialize;
+
+use crate::poller::{self, Feed};
+use crate::source::{Asset, PriceSource, Quote};
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Method {
+    #[default]
+    Median,
+    TrimmedMean,
+    /// Mean weighted by the liquidity of each pool, so a dust pool can't
+    /// pull the price as hard as a deep one
+    LiquidityWeighted,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CompositeConfig {
+    /// Label used in the output
+    pub name: String,
+    #[serde(default)]
+    pub method: Method,
+    /// Share of quotes cut from each end for the trimmed mean
+    #[serde(default = "default_trim")]
+    pub trim: f64,
+    /// Seconds without a trade that halve the weight of a pool, for the
+    /// liquidity-weighted mean
+    pub half_life: Option<u64>,
+    pub source: Vec<MemberConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct MemberConfig {
+    /// Label of this venue in the per-source values
+    pub label: String,
+    /// The TOKEN/ETH pair on this venue
+    pub pool: Address,
+    /// WETH is token0 of the pool and TOKEN is token1
+    #[serde(default)]
+    pub inverted: bool,
+    /// Decimals of TOKEN
+    #[serde(default = "default_decimals")]
+    pub decimals: u8,
+}
+
+fn default_trim() -> f64 {
+    0.25
+}
+
+fn default_decimals() -> u8 {
+    18
+}
+
+impl CompositeConfig {
+    /**
+     * @gist the asset the composite prices, it has no address of its own
+    **/
+    pub fn asset(&self) -> Asset {
+        Asset { name: self.name.clone(), address: Address::zero() }
+    }
+
+    /**
+     * @gist checks the options fit the method
+    **/
+    pub fn validate(&self) -> eyre::Result<()> {
+        match self.half_life {
+            Some(_) if self.method != Method::LiquidityWeighted => bail!("[[composite]] {}: half_life needs method = \"liquidity-weighted\"", self.name),
+            Some(0) => bail!("[[composite]] {}: half_life has to be at least 1 second", self.name),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Folds the quotes of several feeds into one
+pub struct Composite {
+    members: Vec<Feed>,
+    method: Method,
+    trim: f64,
+    half_life: Option<u64>,
+}
+
+impl Composite {
+    /**
+     * @gist creates the source
+     * @param members -- the per venue feeds
+     * @param method -- how the quotes are combined
+     * @param trim -- share cut from each end when method is the trimmed mean
+    **/
+    pub fn new(members: Vec<Feed>, method: Method, trim: f64) -> Self {
+        Self { members, method, trim, half_life: None }
+    }
+
+    /**
+     * @gist weighs the pools by the time since their last trade as well
+     * @param half_life -- seconds without a trade that halve the weight
+    **/
+    pub fn with_half_life(mut self, half_life: Option<u64>) -> Self {
+        self.half_life = half_life;
+        self
+    }
+}
+
+#[async_trait]
+impl PriceSource for Composite {
+    fn name(&self) -> &str {
+        "COMPOSITE"
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let mut components = Vec::with_capacity(self.members.len());
+
+        // A venue that fails is left out rather than failing the composite
+        for (feed, quote) in self.members.iter().zip(poller::poll(&self.members).await) {
+            match quote {
+                Ok(quote) => components.push(quote),
+                Err(e) => eprintln!("{}: {} failed: {e}", asset.name, feed.asset.name),
+            }
+        }
+
+        if components.is_empty() {
+            bail!("{}: no source returned a quote", asset.name);
+        }
+
+        let mut prices: Vec<f64> = components.iter().map(|q| q.price).collect();
+        let price = match self.method {
+            Method::Median => median(&mut prices),
+            Method::TrimmedMean => trimmed_mean(&mut prices, self.trim),
+            Method::LiquidityWeighted => {
+                let weighted: Vec<(f64, f64)> = components.iter()
+                    .map(|q| (q.price, q.liquidity.unwrap_or(0.0) * freshness(q, self.half_life)))
+                    .collect();
+                match weighted_mean(&weighted) {
+                    Some(price) => price,
+                    None => bail!("{}: no source reported any liquidity", asset.name),
+                }
+            }
+        };
+
+        let mut quote = Quote::new(self.name(), &asset.name, price);
+        // Stamped like the most recent component
+        if let Some(latest) = components.iter().max_by_key(|q| q.block) {
+            quote.block = latest.block;
+            quote.timestamp = latest.timestamp;
+        }
+        quote.components = components;
+        Ok(quote)
+    }
+}
+
+/**
+ * @gist share of its weight a quote keeps for the time since its pool last
+ * traded, 1 without a half life or a known last trade
+ * @param quote -- a component of the composite
+ * @param half_life -- seconds that halve the weight
+**/
+fn freshness(quote: &Quote, half_life: Option<u64>) -> f64 {
+    match (half_life, quote.timestamp, quote.updated) {
+        (Some(half_life), Some(now), Some(updated)) => 0.5_f64.powf(now.saturating_sub(updated) as f64 / half_life as f64),
+        _ => 1.0,
+    }
+}
+
+/**
+ * @gist median of a non empty list, the mean of the two middle values for
+ * even lengths
+ * @param values -- the list, gets sorted in place
+ * @output the median
+**/
+pub fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        (values[mid - 1] + values[mid]) / 2.0
+    } else {
+        values[mid]
+    }
+}
+
+/**
+ * @gist mean of a non empty list after cutting `trim` of the values from
+ * each end, never cuts so much that nothing is left
+ * @param values -- the list, gets sorted in place
+ * @param trim -- share cut from each end, between 0 and 0.5
+ * @output the trimmed mean
+**/
+pub fn trimmed_mean(values: &mut [f64], trim: f64) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let cut = ((values.len() as f64 * trim.clamp(0.0, 0.5)) as usize).min((values.len() - 1) / 2);
+    let kept = &values[cut..values.len() - cut];
+    kept.iter().sum::<f64>() / kept.len() as f64
+}
+
+/**
+ * @gist weighted mean of (value, weight) pairs
+ * @param values -- the pairs, weights must not be negative
+ * @output the mean, None if all weights are zero
+**/
+pub fn weighted_mean(values: &[(f64, f64)]) -> Option<f64> {
+    let total: f64 = values.iter().map(|(_, w)| w).sum();
+    if total <= 0.0 {
+        return None;
+    }
+    Some(values.iter().map(|(v, w)| v * w).sum::<f64>() / total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn median_of_odd_and_even_lengths() {
+        assert_eq!(median(&mut [3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&mut [4.0, 1.0, 3.0, 2.0]), 2.5);
+        assert_eq!(median(&mut [7.0]), 7.0);
+    }
+
+    #[test]
+    fn trimmed_mean_cuts_both_ends() {
+        // A quarter of 8 is 2 from each end, the outliers go
+        assert_eq!(trimmed_mean(&mut [100.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, -50.0], 0.25), 3.5);
+        assert_eq!(trimmed_mean(&mut [1.0, 2.0, 6.0], 0.0), 3.0);
+    }
+
+    #[test]
+    fn trimmed_mean_keeps_the_middle() {
+        // Half cut from each end would leave nothing
+        assert_eq!(trimmed_mean(&mut [1.0, 2.0, 9.0], 0.5), 2.0);
+        assert_eq!(trimmed_mean(&mut [1.0, 2.0, 4.0, 9.0], 0.5), 3.0);
+        assert_eq!(trimmed_mean(&mut [5.0], 0.5), 5.0);
+    }
+
+    #[test]
+    fn weighted_mean_follows_the_weights() {
+        assert_eq!(weighted_mean(&[(10.0, 3.0), (20.0, 1.0)]), Some(12.5));
+        // A dust pool barely moves it
+        let mean = weighted_mean(&[(10.0, 1e6), (1000.0, 1.0)]).unwrap();
+        assert!((mean - 10.000990).abs() < 1e-6);
+        assert_eq!(weighted_mean(&[(10.0, 0.0), (20.0, 0.0)]), None);
+        assert_eq!(weighted_mean(&[]), None);
+    }
+
+    #[test]
+    fn freshness_halves_per_half_life() {
+        let mut quote = Quote::new("UNI V2", "LINK", 10.0);
+        assert_eq!(freshness(&quote, Some(3600)), 1.0);
+        quote.timestamp = Some(10_000 + 7200);
+        quote.updated = Some(10_000);
+        assert_eq!(freshness(&quote, None), 1.0);
+        assert_eq!(freshness(&quote, Some(3600)), 0.25);
+        quote.updated = Some(10_000 + 7200);
+        assert_eq!(freshness(&quote, Some(3600)), 1.0);
+    }
+}
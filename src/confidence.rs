@@ -0,0 +1,130 @@
+/*!
+ *
+ * How much a quote can be trusted, as a score from 0 to 100 in the json
+ * output and the metrics. Four things go into it:
+ *
+ * staleness   age of the block the quote was read at, full marks up to a
+ *             minute, none after an hour
+ * liquidity   value of the pool, none at $10k or less and full marks from
+ *             $10M, on a log scale; half marks when the source doesn't know
+ * sources     number of independent prices behind the quote, components of
+ *             a composite and the cex, aggregator and router prices, full
+ *             marks from three
+ * deviation   distance of the price from the median of those other prices,
+ *             none at 5% or more
+ *
+ * weighted 30/30/20/20. A basket gets the weighted mean of the scores of
+ * its components instead. A consumer can drop everything under, say, 50:
+ *
+ * uni-price-polling --config watch.toml --interval 60 --format json | jq 'select(.confidence >= 50)'
+ *
+**/
+
+use crate::composite::median;
+use crate::source::Quote;
+
+const FRESH_SECS: f64 = 60.0;
+const STALE_SECS: f64 = 3600.0;
+const THIN_USD: f64 = 10_000.0;
+const DEEP_USD: f64 = 10_000_000.0;
+const FULL_SOURCES: f64 = 3.0;
+const MAX_DEVIATION: f64 = 0.05;
+
+/**
+ * @gist scores a quote
+ * @param quote -- with its timestamp set
+ * @param now -- unix time the score is for
+ * @output 0 to 100
+**/
+pub fn score(quote: &Quote, now: u64) -> u8 {
+    let age = now.saturating_sub(quote.timestamp.unwrap_or(now)) as f64;
+    let staleness = 1.0 - ((age - FRESH_SECS) / (STALE_SECS - FRESH_SECS)).clamp(0.0, 1.0);
+
+    let liquidity = match quote.liquidity {
+        Some(l) if l > 0.0 => ((l.log10() - THIN_USD.log10()) / (DEEP_USD.log10() - THIN_USD.log10())).clamp(0.0, 1.0),
+        Some(_) => 0.0,
+        None => 0.5,
+    };
+
+    let mut others: Vec<f64> = quote.components.iter().map(|c| c.price)
+        .chain([quote.cex_price, quote.aggregator_price, quote.simulated_price].into_iter().flatten())
+        .filter(|p| p.is_finite() && *p > 0.0)
+        .collect();
+    // A composite is priced from its components, anything else adds itself
+    let count = if quote.components.is_empty() { others.len() + 1 } else { others.len() };
+    let sources = (count as f64 / FULL_SOURCES).min(1.0);
+
+    let deviation = match others.is_empty() {
+        true => 1.0,
+        false => {
+            let median = median(&mut others);
+            1.0 - ((quote.price - median).abs() / median / MAX_DEVIATION).min(1.0)
+        }
+    };
+
+    let score = 0.3 * staleness + 0.3 * liquidity + 0.2 * sources + 0.2 * deviation;
+    (score * 100.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn quote(age: u64, liquidity: Option<f64>) -> Quote {
+        let mut quote = Quote::new("UNI V2", "LINK", 10.0);
+        quote.timestamp = Some(NOW - age);
+        quote.liquidity = liquidity;
+        quote
+    }
+
+    #[test]
+    fn a_lone_fresh_deep_pool() {
+        // 0.3 + 0.3 + 0.2 / 3 + 0.2
+        assert_eq!(score(&quote(12, Some(10_000_000.0)), NOW), 87);
+        assert_eq!(score(&quote(60, Some(50_000_000.0)), NOW), 87);
+    }
+
+    #[test]
+    fn staleness_goes_from_a_minute_to_an_hour() {
+        assert_eq!(score(&quote(60 + (3600 - 60) / 2, Some(10_000_000.0)), NOW), 72);
+        assert_eq!(score(&quote(3600, Some(10_000_000.0)), NOW), 57);
+        assert_eq!(score(&quote(86_400, Some(10_000_000.0)), NOW), 57);
+    }
+
+    #[test]
+    fn liquidity_on_a_log_scale() {
+        // $100k is a third of the way from $10k to $10M
+        assert_eq!(score(&quote(0, Some(100_000.0)), NOW), 67);
+        assert_eq!(score(&quote(0, Some(10_000.0)), NOW), 57);
+        assert_eq!(score(&quote(0, Some(0.0)), NOW), 57);
+        assert_eq!(score(&quote(0, None), NOW), 72);
+    }
+
+    #[test]
+    fn other_prices_add_sources_and_deviation() {
+        let mut agreed = quote(0, Some(10_000_000.0));
+        agreed.cex_price = Some(10.0);
+        agreed.aggregator_price = Some(10.0);
+        assert_eq!(score(&agreed, NOW), 100);
+
+        // 2.4% off the median is about half of the 5% that scores nothing
+        let mut off = agreed.clone();
+        off.cex_price = Some(10.25);
+        off.aggregator_price = Some(10.25);
+        assert_eq!(score(&off, NOW), 90);
+        off.cex_price = Some(11.0);
+        off.aggregator_price = Some(f64::NAN);
+        assert_eq!(score(&off, NOW), 73);
+    }
+
+    #[test]
+    fn a_composite_counts_its_components() {
+        let mut composite = quote(0, Some(10_000_000.0));
+        composite.components = vec![quote(0, None), quote(0, None)];
+        assert_eq!(score(&composite, NOW), 93);
+        composite.components.push(quote(0, None));
+        assert_eq!(score(&composite, NOW), 100);
+    }
+}
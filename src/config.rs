@@ -0,0 +1,132 @@
+/*!
+ *
+ * The optional TOML config file passed with --config. Everything that does
+ * not fit on a command line lives here. Secrets can stay out of it, see
+ * [secrets].
+ *
+**/
+
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::aggregator::AggregatorConfig;
+use crate::alerts::AlertRule;
+use crate::basket::BasketConfig;
+use crate::breaker::BreakerConfig;
+use crate::cex::CexConfig;
+use crate::checksum;
+use crate::composite::CompositeConfig;
+use crate::dashboard::ServerConfig;
+use crate::etherscan::EtherscanConfig;
+use crate::fallback::FallbackConfig;
+use crate::filter::TokenFilter;
+use crate::namespace::{self, NamespaceConfig};
+use crate::notify::NotifyConfig;
+use crate::oracle::OracleConfig;
+use crate::pools::PoolConfig;
+use crate::rate::RateConfig;
+use crate::secrets;
+use crate::simulate::SimulateConfig;
+use crate::sink::SinkConfig;
+use crate::subgraph::SubgraphConfig;
+use crate::v3::V3Config;
+use crate::vault::VaultConfig;
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// TOKEN/ETH pools priced like --pool
+    #[serde(default)]
+    pub pool: Vec<PoolConfig>,
+
+    /// Tokens kept out of the watchlist, discovery and new pairs
+    #[serde(default)]
+    pub tokens: TokenFilter,
+
+    /// Arbitrary view functions polled as price sources
+    #[serde(default)]
+    pub oracle: Vec<OracleConfig>,
+
+    /// Assets priced on several pools and combined into one quote
+    #[serde(default)]
+    pub composite: Vec<CompositeConfig>,
+
+    /// Weighted baskets of tokens priced as one index
+    #[serde(default)]
+    pub basket: Vec<BasketConfig>,
+
+    /// ERC-4626 vault shares priced through their underlying
+    #[serde(default)]
+    pub vault: Vec<VaultConfig>,
+
+    /// Pool prices converted by an on-chain exchange rate
+    #[serde(default)]
+    pub rate: Vec<RateConfig>,
+
+    /// Tokens priced along a path of Uniswap V3 pools
+    #[serde(default)]
+    pub v3: Vec<V3Config>,
+
+    /// When a failing feed stops being polled for a while
+    #[serde(default)]
+    pub breaker: BreakerConfig,
+
+    /// Threshold rules evaluated in watch mode
+    #[serde(default)]
+    pub alert: Vec<AlertRule>,
+
+    /// Where fired alerts are sent
+    #[serde(default)]
+    pub notify: NotifyConfig,
+
+    /// Extra destinations for the quotes of watch mode
+    #[serde(default)]
+    pub sink: Vec<SinkConfig>,
+
+    /// Uniswap subgraph for volume and fees and faster discovery
+    pub subgraph: Option<SubgraphConfig>,
+
+    /// Etherscan API used to check user supplied pools
+    pub etherscan: Option<EtherscanConfig>,
+
+    /// Off-chain prices for when the RPC is down
+    pub fallback: Option<FallbackConfig>,
+
+    /// Exchange prices to compare the quotes against
+    pub cex: Option<CexConfig>,
+
+    /// DEX aggregator quotes to compare a sized trade against
+    pub aggregator: Option<AggregatorConfig>,
+
+    /// Entry and exit rules of the paper-trading simulator
+    pub simulate: Option<SimulateConfig>,
+
+    /// API keys, rate limits and TLS of the --dashboard server
+    #[serde(default)]
+    pub server: ServerConfig,
+
+    /// Watchlists of their own, with their own sinks and alerts
+    #[serde(default)]
+    pub namespace: Vec<NamespaceConfig>,
+}
+
+/**
+ * @gist reads and parses the config file
+ * @param path -- location of the TOML file
+ * @output the parsed config
+**/
+pub fn load(path: &Path) -> eyre::Result<Config> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| eyre::eyre!("could not read {}: {e}", path.display()))?;
+    let mut value: toml::Value = toml::from_str(&text)
+        .map_err(|e| eyre::eyre!("invalid config {}: {e}", path.display()))?;
+    secrets::resolve(&mut value)
+        .map_err(|e| eyre::eyre!("invalid config {}: {e}", path.display()))?;
+    checksum::normalize(&mut value)
+        .map_err(|e| eyre::eyre!("invalid config {}: {e}", path.display()))?;
+    let config: Config = value.try_into()
+        .map_err(|e| eyre::eyre!("invalid config {}: {e}", path.display()))?;
+    namespace::validate(&config.namespace, &config.pool)
+        .map_err(|e| eyre::eyre!("invalid config {}: {e}", path.display()))?;
+    Ok(config)
+}
@@ -0,0 +1,234 @@
+/*!
+ *
+ * Depth curves: how the execution price of the TOKEN -> ETH -> USDT route
+ * degrades with trade size. Each point is a swap of `size` pushed through
+ * both pools with the constant-product math, fees included.
+ *
+ * With `--bid-ask 1000` every route quote also gets the two points of the
+ * curve at 1000 USDT, what selling and buying that much TOKEN would pay:
+ *
+ * uni-price-polling --pool LINK --bid-ask 1000
+ *
+**/
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+use std::sync::Arc;
+
+use crate::amm::{amount_out, V2_FEE};
+use crate::source::{Asset, PriceSource, Quote, Raw};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Side {
+    /// Sell TOKEN for USDT, sizes are in TOKEN
+    Sell,
+    /// Buy TOKEN with USDT, sizes are in USDT
+    Buy,
+}
+
+/// Decimal reserves of the two pools in the route
+#[derive(Debug, Clone, Copy)]
+pub struct Route {
+    pub token: f64,
+    pub eth: f64,
+    pub eth2: f64,
+    pub usdt: f64,
+}
+
+impl Route {
+    /**
+     * @gist the route a V2 quote was priced from
+     * @param raw -- the unscaled reserves of both pools
+     * @output None unless there are two hops
+    **/
+    pub fn from_raw(raw: &Raw) -> Option<Self> {
+        let (&(token, eth), &(eth2, usdt)) = (raw.reserves.first()?, raw.reserves.get(1)?);
+        let (&(token_decimals, eth_decimals), &(eth2_decimals, usdt_decimals)) = (raw.decimals.first()?, raw.decimals.get(1)?);
+        let scaled = |amount: u128, decimals: u8| amount as f64 / 10_f64.powi(decimals as i32);
+        Some(Self {
+            token: scaled(token, token_decimals),
+            eth: scaled(eth, eth_decimals),
+            eth2: scaled(eth2, eth2_decimals),
+            usdt: scaled(usdt, usdt_decimals),
+        })
+    }
+
+    /**
+     * @gist the reserve ratio price of TOKEN in USDT
+    **/
+    pub fn mid_price(&self) -> f64 {
+        (self.eth / self.token) * (self.usdt / self.eth2)
+    }
+
+    /**
+     * @gist USDT value of the TOKEN/ETH pool, twice the value of its ETH side
+    **/
+    pub fn liquidity(&self) -> f64 {
+        2.0 * self.eth * (self.usdt / self.eth2)
+    }
+
+    /**
+     * @gist execution price in USDT per TOKEN for a swap of the given size
+     * @param side -- sell or buy TOKEN
+     * @param size -- TOKEN sold or USDT spent
+     * @output the realized price
+    **/
+    pub fn execution_price(&self, side: Side, size: f64) -> f64 {
+        match side {
+            Side::Sell => {
+                let eth = amount_out(size, self.token, self.eth, V2_FEE);
+                amount_out(eth, self.eth2, self.usdt, V2_FEE) / size
+            }
+            Side::Buy => {
+                let eth = amount_out(size, self.usdt, self.eth2, V2_FEE);
+                size / amount_out(eth, self.eth, self.token, V2_FEE)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CurvePoint {
+    /// TOKEN sold or USDT spent
+    pub size: f64,
+    /// USDT per TOKEN actually received or paid
+    pub price: f64,
+    /// Distance of the execution price from the mid price in percent
+    pub impact: f64,
+}
+
+/**
+ * @gist sweeps `steps` evenly spaced sizes from max/steps up to max
+ * @param route -- reserves of both pools
+ * @param side -- sell or buy TOKEN
+ * @param max -- the largest size
+ * @param steps -- number of points
+ * @output one point per size
+**/
+pub fn depth_curve(route: &Route, side: Side, max: f64, steps: usize) -> Vec<CurvePoint> {
+    let mid = route.mid_price();
+    (1..=steps)
+        .map(|i| {
+            let size = max * i as f64 / steps as f64;
+            let price = route.execution_price(side, size);
+            CurvePoint { size, price, impact: (price - mid).abs() / mid * 100.0 }
+        })
+        .collect()
+}
+
+/// Adds the execution prices of a trade either way to the route quotes
+pub struct WithBidAsk {
+    inner: Arc<dyn PriceSource>,
+    /// USDT value of the trade
+    size: f64,
+}
+
+impl WithBidAsk {
+    /**
+     * @gist wraps a source, its quotes need the raw values
+     * @param size -- USDT value of the trade
+    **/
+    pub fn new(inner: Arc<dyn PriceSource>, size: f64) -> Self {
+        Self { inner, size }
+    }
+}
+
+#[async_trait]
+impl PriceSource for WithBidAsk {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let mut quote = self.inner.quote(asset).await?;
+        let Some(route) = quote.raw.as_ref().and_then(Route::from_raw) else {
+            return Ok(quote);
+        };
+        if quote.price <= 0.0 {
+            return Ok(quote);
+        }
+        quote.bid = Some(route.execution_price(Side::Sell, self.size / quote.price));
+        quote.ask = Some(route.execution_price(Side::Buy, self.size));
+        Ok(quote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 200 TOKEN per ETH and 2000 USDT per ETH, so TOKEN is at 10 USDT
+    fn raw() -> Raw {
+        Raw {
+            reserves: vec![(200_000 * 10_u128.pow(18), 1_000 * 10_u128.pow(18)), (10_000 * 10_u128.pow(18), 20_000_000 * 10_u128.pow(6))],
+            decimals: vec![(18, 18), (18, 6)],
+            ratios: vec![0.005, 2000.0],
+        }
+    }
+
+    struct Fixed(Option<Raw>);
+
+    #[async_trait]
+    impl PriceSource for Fixed {
+        fn name(&self) -> &str {
+            "FIXED"
+        }
+
+        async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+            let mut quote = Quote::new(self.name(), &asset.name, 10.0);
+            quote.raw = self.0.clone();
+            Ok(quote)
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9 * b.abs()
+    }
+
+    #[test]
+    fn route_of_the_raw_reserves() {
+        let route = Route::from_raw(&raw()).unwrap();
+        assert!(close(route.token, 200_000.0) && close(route.usdt, 20_000_000.0));
+        assert!(close(route.mid_price(), 10.0));
+        assert!(close(route.liquidity(), 4_000_000.0));
+
+        let mut one_hop = raw();
+        one_hop.reserves.pop();
+        assert!(Route::from_raw(&one_hop).is_none());
+    }
+
+    #[test]
+    fn execution_prices_pay_fees_and_impact() {
+        let route = Route::from_raw(&raw()).unwrap();
+        assert!(close(route.execution_price(Side::Sell, 100.0), 9.93464382378521));
+        assert!(close(route.execution_price(Side::Buy, 1000.0), 10.065772588578172));
+        // A tiny trade only pays the two fees
+        assert!(close(route.execution_price(Side::Sell, 1e-6), 10.0 * 0.997 * 0.997));
+        assert!(route.execution_price(Side::Sell, 10_000.0) < route.execution_price(Side::Sell, 100.0));
+        assert!(route.execution_price(Side::Buy, 100_000.0) > route.execution_price(Side::Buy, 1000.0));
+    }
+
+    #[test]
+    fn depth_curve_sweeps_up_to_max() {
+        let route = Route::from_raw(&raw()).unwrap();
+        let curve = depth_curve(&route, Side::Buy, 4000.0, 4);
+        let sizes: Vec<f64> = curve.iter().map(|p| p.size).collect();
+        assert_eq!(sizes, [1000.0, 2000.0, 3000.0, 4000.0]);
+        assert!(curve.windows(2).all(|w| w[0].impact < w[1].impact));
+        assert!(close(curve[0].impact, 0.65772588578172));
+    }
+
+    #[tokio::test]
+    async fn bid_ask_of_the_reference_size() {
+        let asset = Asset { name: "LINK".to_string(), address: Default::default() };
+        let quote = WithBidAsk::new(Arc::new(Fixed(Some(raw()))), 1000.0).quote(&asset).await.unwrap();
+        // The sale is of 1000 USDT worth of TOKEN at the mid price
+        let route = Route::from_raw(&raw()).unwrap();
+        assert_eq!(quote.bid, Some(route.execution_price(Side::Sell, 100.0)));
+        assert_eq!(quote.ask, Some(route.execution_price(Side::Buy, 1000.0)));
+
+        let quote = WithBidAsk::new(Arc::new(Fixed(None)), 1000.0).quote(&asset).await.unwrap();
+        assert_eq!((quote.bid, quote.ask), (None, None));
+    }
+}
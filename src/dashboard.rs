@@ -0,0 +1,569 @@
+/*!
+ *
+ * A small web dashboard for watch mode, for when Grafana is too much.
+ * `--dashboard 127.0.0.1:8080` serves a page with the live prices, a
+ * sparkline of the recent prices of every asset and the alerts that fired.
+ * The page is embedded in the binary and polls two JSON endpoints:
+ *
+ * GET /api/quotes   the last quote of every asset with its recent prices
+ * GET /api/alerts   the alerts that fired, newest first
+ *
+ * Any TOKEN/WETH pool can be looked up too, watched or not. The body is a
+ * JSON array of pool addresses, the answer one quote or error per pool in
+ * the same order, all read with one multicall at the same block:
+ *
+ * POST /prices  ["0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974", ...]
+ *
+ * POST /api/prices is the same endpoint, next to the other /api ones.
+ *
+ * The tokens of pools not priced before are read first, batched as well
+ * and at most MAX_UNKNOWN per request, and kept for the next ones.
+ *
+ * The /grafana endpoints chart the stored history in Grafana, see [grafana].
+ * Every [[namespace]] of the config has its endpoints under /ns/{name}, see
+ * [namespace].
+ *
+ * The [server] table of the config puts the endpoints behind API keys and
+ * rate limits, see [auth], and serves them over HTTPS, see [tls].
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{providers::Middleware, types::Address};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::alerts::Alert;
+use crate::auth::{self, Denied, Gate};
+use crate::batch::ReserveBatch;
+use crate::checksum;
+use crate::format::{self, Format};
+use crate::grafana::{self, QueryRequest};
+use crate::poller::{self, Feed};
+use crate::schema;
+use crate::source::{Asset, Quote};
+use crate::tls;
+use crate::uniswap_v2::{pair_layouts, UniswapV2};
+
+const PAGE: &str = include_str!("dashboard.html");
+
+/// Prices kept per asset for the sparklines
+const HISTORY: usize = 120;
+
+/// Alerts kept for the page
+const ALERTS: usize = 100;
+
+/// Largest request body read, a few thousand addresses
+const MAX_BODY: usize = 256 * 1024;
+
+/// Most pools priced by one POST /prices
+const MAX_POOLS: usize = 1000;
+
+/// Most pools of one POST /prices whose layout isn't kept yet, each
+/// costs a token and a decimals read
+const MAX_UNKNOWN: usize = 100;
+
+/// Layouts of pools kept between the requests
+const LAYOUTS: usize = 10_000;
+
+/// Time a client has to send its whole request, so slow ones can't hold
+/// connections open
+pub(crate) const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize, Debug, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Keys accepted by the API, none leaves it open
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    /// Requests per minute of every key, 0 for no limit
+    #[serde(default)]
+    pub rate_limit: u32,
+    /// PEM certificate chain to serve HTTPS with
+    pub tls_cert: Option<PathBuf>,
+    /// PEM private key of the certificate
+    pub tls_key: Option<PathBuf>,
+    /// History file of the /grafana endpoints, the first json or csv file
+    /// sink if unset
+    pub history: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    #[serde(serialize_with = "schema::versioned")]
+    quote: Quote,
+    /// (unix time, price), oldest first
+    history: VecDeque<(u64, f64)>,
+}
+
+#[derive(Serialize)]
+struct Fired {
+    time: u64,
+    #[serde(flatten)]
+    alert: Alert,
+}
+
+/// A namespace as the server sees it
+pub struct Tenant {
+    pub board: Arc<Board>,
+    /// Keys that open its endpoints, next to those of [server]
+    pub api_keys: Vec<String>,
+}
+
+/// What the dashboard shows, fed by the watch loop
+#[derive(Default)]
+pub struct Board {
+    quotes: Mutex<BTreeMap<String, Entry>>,
+    alerts: Mutex<VecDeque<Fired>>,
+}
+
+impl Board {
+    /**
+     * @gist records the latest quote of an asset
+     * @param quote -- the quote as it went to the sinks
+     * @param now -- unix time of the quote
+    **/
+    pub fn observe_quote(&self, quote: &Quote, now: u64) {
+        let mut quotes = self.quotes.lock().unwrap();
+        let entry = quotes.entry(quote.asset.clone())
+            .or_insert_with(|| Entry { quote: quote.clone(), history: VecDeque::with_capacity(HISTORY) });
+        entry.quote = quote.clone();
+        entry.history.push_back((now, quote.price));
+        if entry.history.len() > HISTORY {
+            entry.history.pop_front();
+        }
+    }
+
+    /**
+     * @gist records an alert that fired
+    **/
+    pub fn observe_alert(&self, alert: &Alert, now: u64) {
+        let mut alerts = self.alerts.lock().unwrap();
+        alerts.push_front(Fired { time: now, alert: alert.clone() });
+        alerts.truncate(ALERTS);
+    }
+
+    fn quotes_json(&self) -> String {
+        let quotes = self.quotes.lock().unwrap();
+        serde_json::to_string(&quotes.values().collect::<Vec<_>>()).unwrap_or_default()
+    }
+
+    fn prices_json(&self) -> String {
+        let quotes = self.quotes.lock().unwrap();
+        serde_json::to_string(&quotes.values().map(|e| schema::Versioned::new(&e.quote)).collect::<Vec<_>>()).unwrap_or_default()
+    }
+
+    fn alerts_json(&self) -> String {
+        serde_json::to_string(&*self.alerts.lock().unwrap()).unwrap_or_default()
+    }
+}
+
+/// Prices pools that are not necessarily on the watchlist
+#[async_trait]
+pub trait Lookup: Send + Sync {
+    /**
+     * @gist quotes TOKEN/WETH pools
+     * @output one result per pool, in the same order
+    **/
+    async fn prices(&self, pools: &[Address]) -> Vec<eyre::Result<Quote>>;
+}
+
+/// Looks pools up on chain, their layout is kept after the first lookup
+pub struct AdHoc<M> {
+    provider: Arc<M>,
+    end_a: Address,
+    layouts: Mutex<Layouts>,
+}
+
+/// Whether WETH is token0 and the decimals of TOKEN, by pool, the least
+/// recently used dropped beyond LAYOUTS
+#[derive(Default)]
+struct Layouts {
+    by_pool: HashMap<Address, ((bool, u8), u64)>,
+    /// Counts the uses, the one of a layout is its last
+    uses: u64,
+}
+
+impl Layouts {
+    fn get(&mut self, pool: &Address) -> Option<(bool, u8)> {
+        self.uses += 1;
+        let (layout, used) = self.by_pool.get_mut(pool)?;
+        *used = self.uses;
+        Some(*layout)
+    }
+
+    fn insert(&mut self, pool: Address, layout: (bool, u8)) {
+        if self.by_pool.len() >= LAYOUTS && !self.by_pool.contains_key(&pool) {
+            let oldest = self.by_pool.iter().min_by_key(|(_, (_, used))| *used).map(|(p, _)| *p);
+            if let Some(oldest) = oldest {
+                self.by_pool.remove(&oldest);
+            }
+        }
+        self.uses += 1;
+        self.by_pool.insert(pool, (layout, self.uses));
+    }
+}
+
+impl<M: Middleware + 'static> AdHoc<M> {
+    /**
+     * @gist creates the lookup
+     * @param provider -- any ethers middleware
+     * @param end_a -- the ETH/USDT pool
+    **/
+    pub fn new(provider: Arc<M>, end_a: Address) -> Self {
+        Self { provider, end_a, layouts: Mutex::new(Layouts::default()) }
+    }
+
+    /**
+     * @gist the layouts of some pools, those not kept yet read with one
+     * batch and kept, at most MAX_UNKNOWN of them
+    **/
+    async fn layouts(&self, pools: &[Address]) -> Vec<eyre::Result<(bool, u8)>> {
+        let mut layouts: Vec<Option<eyre::Result<(bool, u8)>>> = {
+            let mut kept = self.layouts.lock().unwrap();
+            pools.iter().map(|pool| kept.get(pool).map(Ok)).collect()
+        };
+        let mut unknown: Vec<Address> = Vec::new();
+        for (pool, layout) in pools.iter().zip(&layouts) {
+            if layout.is_none() && !unknown.contains(pool) {
+                unknown.push(*pool);
+            }
+        }
+        unknown.truncate(MAX_UNKNOWN);
+        // By pool, errors as text to hand them to every request of the pool
+        let read: HashMap<Address, Result<(bool, u8), String>> = match pair_layouts(self.provider.clone(), &unknown).await {
+            Ok(read) => unknown.iter().zip(read).map(|(pool, layout)| (*pool, layout.map_err(|e| e.to_string()))).collect(),
+            Err(e) => unknown.iter().map(|pool| (*pool, Err(e.to_string()))).collect(),
+        };
+        let mut kept = self.layouts.lock().unwrap();
+        for (pool, layout) in &read {
+            if let Ok(layout) = layout {
+                kept.insert(*pool, *layout);
+            }
+        }
+        for (pool, layout) in pools.iter().zip(layouts.iter_mut()) {
+            if layout.is_none() {
+                *layout = Some(match read.get(pool) {
+                    Some(read) => read.clone().map_err(|e| eyre::eyre!(e)),
+                    None => Err(eyre::eyre!("not looked up, at most {MAX_UNKNOWN} pools not priced before per request")),
+                });
+            }
+        }
+        layouts.into_iter().map(|layout| layout.expect("every layout was filled in")).collect()
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> Lookup for AdHoc<M> {
+    async fn prices(&self, pools: &[Address]) -> Vec<eyre::Result<Quote>> {
+        let layouts = self.layouts(pools).await;
+        let batch = Arc::new(ReserveBatch::new(self.provider.clone(), pools.len() + 1, pools.iter().copied().chain([self.end_a]).collect()));
+        let mut feeds = Vec::with_capacity(pools.len());
+        for (pool, layout) in pools.iter().zip(&layouts) {
+            if let Ok((inverted, decimals)) = layout {
+                let source = UniswapV2::new(self.provider.clone(), self.end_a).with_layout(*inverted, *decimals).with_batch(batch.clone());
+                feeds.push(Feed::new(Arc::new(source), Asset { name: format!("{pool:?}"), address: *pool }));
+            }
+        }
+
+        let mut quotes = poller::poll(&feeds).await.into_iter();
+        layouts.into_iter()
+            .map(|layout| match layout {
+                Ok(_) => quotes.next().unwrap_or_else(|| Err(eyre::eyre!("not polled"))),
+                Err(e) => Err(e),
+            })
+            .collect()
+    }
+}
+
+/// A request read off the socket
+pub(crate) struct Request {
+    pub(crate) method: String,
+    pub(crate) path: String,
+    /// What follows the ? of the path
+    pub(crate) query: String,
+    /// Names in lower case
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) body: Vec<u8>,
+}
+
+/**
+ * @gist reads the request line, the headers and a body of Content-Length
+ * @output None for a bad request or one not read within READ_TIMEOUT
+**/
+pub(crate) async fn read_request<S: AsyncRead + Unpin>(stream: &mut S) -> Option<Request> {
+    tokio::time::timeout(READ_TIMEOUT, read(stream)).await.ok()?
+}
+
+/**
+ * @gist read_request without the timeout
+**/
+async fn read<S: AsyncRead + Unpin>(stream: &mut S) -> Option<Request> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0; 4096];
+    let head_end = loop {
+        let n = stream.read(&mut chunk).await.ok().filter(|n| *n > 0)?;
+        buf.extend_from_slice(&chunk[..n]);
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break i + 4;
+        }
+        if buf.len() > MAX_BODY {
+            return None;
+        }
+    };
+    let head = String::from_utf8_lossy(&buf[..head_end]).to_string();
+    let mut line = head.split_whitespace();
+    let method = line.next()?.to_string();
+    let target = line.next()?;
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let headers: Vec<(String, String)> = head.lines()
+        .skip(1)
+        .filter_map(|l| l.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+    let length = headers.iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    if length > MAX_BODY {
+        return None;
+    }
+    while buf.len() < head_end + length {
+        let n = stream.read(&mut chunk).await.ok().filter(|n| *n > 0)?;
+        buf.extend_from_slice(&chunk[..n]);
+    }
+    Some(Request { method, path: path.to_string(), query: query.to_string(), headers, body: buf[head_end..head_end + length].to_vec() })
+}
+
+/**
+ * @gist answers POST /prices
+ * @output the status line and the JSON body
+**/
+async fn prices(lookup: &dyn Lookup, body: &[u8]) -> (&'static str, String) {
+    let pools: Vec<String> = match serde_json::from_slice(body) {
+        Ok(pools) => pools,
+        Err(e) => return ("400 Bad Request", serde_json::json!({ "error": format!("expected an array of addresses: {e}") }).to_string()),
+    };
+    let pools: Vec<Address> = match pools.iter().map(|p| checksum::parse(p)).collect() {
+        Ok(pools) => pools,
+        Err(e) => return ("400 Bad Request", serde_json::json!({ "error": e.to_string() }).to_string()),
+    };
+    if pools.len() > MAX_POOLS {
+        return ("400 Bad Request", serde_json::json!({ "error": format!("at most {MAX_POOLS} pools per request") }).to_string());
+    }
+    let results = lookup.prices(&pools).await;
+    let rendered: Vec<String> = pools.iter().zip(results)
+        .map(|(pool, result)| match result {
+            Ok(quote) => format::render(&quote.without_raw(), Format::Json),
+            Err(e) => format::render_error(&format!("{pool:?}"), &format!("{e:#}"), Format::Json),
+        })
+        .collect();
+    ("200 OK", format!("[{}]", rendered.join(",")))
+}
+
+/**
+ * @gist answers POST /grafana/search and /grafana/query
+ * @output the status line and the JSON body
+**/
+async fn grafana(history: Option<&Path>, path: &str, body: &[u8]) -> (&'static str, String) {
+    let error = |e: String| serde_json::json!({ "error": e }).to_string();
+    let Some(history) = history.map(Path::to_path_buf) else {
+        return ("404 Not Found", error("no history, add a file sink or set history in [server]".to_string()));
+    };
+    let request = match path {
+        "/grafana/query" => match serde_json::from_slice::<QueryRequest>(body) {
+            Ok(request) => Some(request),
+            Err(e) => return ("400 Bad Request", error(format!("invalid query: {e}"))),
+        },
+        _ => None,
+    };
+    let name = history.display().to_string();
+    // The whole file is read, away from the tasks serving the other requests
+    let answer = tokio::task::spawn_blocking(move || -> eyre::Result<String> {
+        Ok(match request {
+            Some(request) => serde_json::to_string(&grafana::query(&history, &request)?)?,
+            None => serde_json::to_string(&grafana::search(&history)?)?,
+        })
+    }).await;
+    match answer {
+        Ok(Ok(body)) => ("200 OK", body),
+        Ok(Err(e)) => ("500 Internal Server Error", error(format!("{name}: {e:#}"))),
+        Err(e) => ("500 Internal Server Error", error(e.to_string())),
+    }
+}
+
+/**
+ * @gist answers GET /ns/{name}/...
+ * @param rest -- the path after /ns/
+ * @output None for paths that aren't there
+**/
+fn namespace(tenants: &BTreeMap<String, Tenant>, rest: &str) -> Option<String> {
+    let (name, endpoint) = rest.split_once('/')?;
+    let board = &tenants.get(name)?.board;
+    match endpoint {
+        "prices" => Some(board.prices_json()),
+        "quotes" => Some(board.quotes_json()),
+        "alerts" => Some(board.alerts_json()),
+        _ => None,
+    }
+}
+
+/**
+ * @gist answers one request
+ * @param stream -- plain TCP or TLS
+ * @param client -- address of the peer, for the rate limit
+ * @param tenants -- the namespaces, by name
+ * @param history -- the file the /grafana endpoints read
+**/
+async fn handle<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, client: IpAddr, board: &Board, tenants: &BTreeMap<String, Tenant>, lookup: &dyn Lookup, gate: &Gate, history: Option<&Path>) {
+    let Some(request) = read_request(&mut stream).await else {
+        let _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await;
+        return;
+    };
+    if request.path != "/" {
+        // The keys of a namespace open its own endpoints
+        let scoped = request.path.strip_prefix("/ns/")
+            .and_then(|rest| tenants.get(rest.split('/').next().unwrap_or_default()))
+            .map_or(&[][..], |tenant| tenant.api_keys.as_slice());
+        let denied = match gate.check_with(auth::key(&request.headers, &request.query), client, scoped) {
+            Ok(()) => None,
+            Err(Denied::Unauthorized) => Some("HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Bearer\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()),
+            Err(Denied::Limited(wait)) => Some(format!("HTTP/1.1 429 Too Many Requests\r\nRetry-After: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", wait.as_secs().max(1))),
+        };
+        if let Some(response) = denied {
+            let _ = stream.write_all(response.as_bytes()).await;
+            return;
+        }
+    }
+    let (status, content_type, body) = match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/") => ("200 OK", "text/html; charset=utf-8", PAGE.to_string()),
+        ("GET", "/api/quotes") => ("200 OK", "application/json", board.quotes_json()),
+        ("GET", "/api/alerts") => ("200 OK", "application/json", board.alerts_json()),
+        ("POST", "/prices" | "/api/prices") => {
+            let (status, body) = prices(lookup, &request.body).await;
+            (status, "application/json", body)
+        }
+        ("GET", "/ns") => ("200 OK", "application/json", serde_json::to_string(&tenants.keys().collect::<Vec<_>>()).unwrap_or_default()),
+        ("GET", path) if path.starts_with("/ns/") => match namespace(tenants, &path["/ns/".len()..]) {
+            Some(body) => ("200 OK", "application/json", body),
+            None => {
+                let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await;
+                return;
+            }
+        },
+        ("GET" | "POST", "/grafana" | "/grafana/") => ("200 OK", "text/plain", "OK".to_string()),
+        ("POST", "/grafana/search" | "/grafana/query") => {
+            let (status, body) = grafana(history, &request.path, &request.body).await;
+            (status, "application/json", body)
+        }
+        _ => {
+            let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await;
+            return;
+        }
+    };
+    let response = format!("HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}", body.len());
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+/**
+ * @gist serves the page and its endpoints in the background
+ * @param addr -- where to listen
+ * @param board -- shared with the watch loop
+ * @param tenants -- the namespaces of the config, by name
+ * @param lookup -- prices the pools of POST /prices
+ * @param config -- the API keys, rate limits and TLS of the [server] table
+**/
+pub async fn serve(addr: SocketAddr, board: Arc<Board>, tenants: BTreeMap<String, Tenant>, lookup: Arc<dyn Lookup>, config: &ServerConfig) -> eyre::Result<()> {
+    let tls = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(tls::acceptor(cert, key)?),
+        (None, None) => None,
+        _ => eyre::bail!("[server] needs both tls_cert and tls_key"),
+    };
+    // An empty key would let requests without one through
+    if config.api_keys.iter().any(|k| k.trim().is_empty()) {
+        eyre::bail!("[server] api_keys holds an empty key");
+    }
+    // Without keys of its own the top level, e.g. /ns, would be open to all
+    if config.api_keys.is_empty() && tenants.values().any(|t| !t.api_keys.is_empty()) {
+        eyre::bail!("[server] needs api_keys too when a [[namespace]] has some");
+    }
+    let gate = Arc::new(Gate::new(config));
+    let history = Arc::new(config.history.clone());
+    let tenants = Arc::new(tenants);
+    let listener = TcpListener::bind(addr).await
+        .map_err(|e| eyre::eyre!("could not listen on {addr}: {e}"))?;
+    tokio::spawn(async move {
+        loop {
+            let Ok((stream, client)) = listener.accept().await else {
+                continue;
+            };
+            let (board, tenants, lookup, gate, tls, history) = (board.clone(), tenants.clone(), lookup.clone(), gate.clone(), tls.clone(), history.clone());
+            tokio::spawn(async move {
+                match tls {
+                    // A failed or stalled handshake is the client's problem, e.g. plain HTTP
+                    Some(tls) => if let Ok(Ok(stream)) = tokio::time::timeout(READ_TIMEOUT, tls.accept(stream)).await {
+                        handle(stream, client.ip(), &board, &tenants, lookup.as_ref(), &gate, history.as_deref()).await;
+                    },
+                    None => handle(stream, client.ip(), &board, &tenants, lookup.as_ref(), &gate, history.as_deref()).await,
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn reads_the_line_headers_and_query() {
+        let mut stream: &[u8] = b"GET /ns/treasury/prices?api_key=3f9c&x=1 HTTP/1.1\r\nHost: localhost\r\nX-Api-Key:  b71e \r\n\r\n";
+        let request = read_request(&mut stream).await.unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str(), request.query.as_str()), ("GET", "/ns/treasury/prices", "api_key=3f9c&x=1"));
+        assert_eq!(request.headers, [("host".to_string(), "localhost".to_string()), ("x-api-key".to_string(), "b71e".to_string())]);
+        assert!(request.body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn reads_a_body_sent_in_parts() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let body = br#"["0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974"]"#;
+        let writing = tokio::spawn(async move {
+            client.write_all(format!("POST /prices HTTP/1.1\r\nContent-Length: {}\r\n\r\n", body.len()).as_bytes()).await.unwrap();
+            for part in body.chunks(10) {
+                client.write_all(part).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+            client
+        });
+        let request = read_request(&mut server).await.unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/prices"));
+        assert_eq!(request.body, body);
+        drop(writing.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn refuses_bad_requests() {
+        // The connection closes before the headers end
+        let mut stream: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n";
+        assert!(read_request(&mut stream).await.is_none());
+        // Or before the body does
+        let mut stream: &[u8] = b"POST /prices HTTP/1.1\r\nContent-Length: 10\r\n\r\n[]";
+        assert!(read_request(&mut stream).await.is_none());
+        // No target
+        let mut stream: &[u8] = b"GET\r\n\r\n";
+        assert!(read_request(&mut stream).await.is_none());
+        let too_long = format!("POST /prices HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        let mut stream = too_long.as_bytes();
+        assert!(read_request(&mut stream).await.is_none());
+    }
+}
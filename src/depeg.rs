@@ -0,0 +1,98 @@
+/*!
+ *
+ * Stablecoin depeg monitoring, the `depeg` preset. USDT, USDC and DAI are
+ * priced two ways every interval:
+ *
+ * - against each other on the Uniswap V2 USDC/USDT, DAI/USDC and DAI/USDT
+ *   pools, USDC/USDT at 0.998 means 1 USDC buys 0.998 USDT
+ * - against a USD composite: ETH is priced on the USDT, USDC and DAI pools
+ *   of WETH, and the median of the three is taken as its dollar price, so
+ *   one stable losing its peg doesn't move the reference
+ *
+ * uni-price-polling depeg --max-bps 50 --notify
+ *
+ * Prices are an alert once they are more than `--max-bps` basis points away
+ * from 1.00, and again after they returned within it.
+ *
+**/
+
+use ethers::{providers::Middleware, types::Address};
+use serde::Serialize;
+
+use std::sync::Arc;
+
+use crate::pairs::STABLES;
+use crate::uniswap_v2::{self, get_reserves};
+
+/// Stable pairs on Uniswap V2 as (pair, token0, token1)
+pub const CROSS_POOLS: [(&str, &str, &str); 3] = [
+    ("0x3041CbD36888bECc7bbCBc0045E3B1f144466f5f", "USDC", "USDT"),
+    ("0xAE461cA67B15dc8dc81CE7615e0320dA1A9aB8D5", "DAI", "USDC"),
+    ("0xB20bd5D04BE54f870D5C0d3cA85d82b34B836405", "DAI", "USDT"),
+];
+
+/// The WETH pair of every stable as (stable, pair, WETH is token0)
+pub const ETH_POOLS: [(&str, &str, bool); 3] = [
+    ("USDT", uniswap_v2::ETH_USDT_POOL, true),
+    ("USDC", "0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc", false),
+    ("DAI", "0xA478c2975Ab1Ea89e8196811F51A7B7Ade33eB11", false),
+];
+
+/// A price that should be 1.00
+#[derive(Debug, Clone, Serialize)]
+pub struct Peg {
+    /// USDC for the composite, USDC/USDT for a pool
+    pub asset: String,
+    pub price: f64,
+    /// Distance from 1.00 in basis points, negative below
+    pub bps: f64,
+}
+
+impl Peg {
+    fn new(asset: String, price: f64) -> Self {
+        Self { asset, price, bps: (price - 1.0) * 10_000.0 }
+    }
+}
+
+/**
+ * @gist reads the stable and WETH pools once
+ * @param provider -- any ethers middleware
+ * @output the composite price of every stable, then the pool rates
+**/
+pub async fn check<M: Middleware + 'static>(provider: Arc<M>) -> eyre::Result<Vec<Peg>> {
+    let mut eth = Vec::with_capacity(ETH_POOLS.len());
+    for (symbol, pool, weth_first) in ETH_POOLS {
+        let decimals = decimals(symbol);
+        let (r0, r1, _) = get_reserves(provider.clone(), &pool.parse::<Address>()?).await?;
+        let (weth, stable) = if weth_first { (r0, r1) } else { (r1, r0) };
+        if weth == 0 {
+            eyre::bail!("the {symbol}/WETH pool is empty");
+        }
+        // ETH in units of the stable
+        eth.push((symbol, amount(stable, decimals) / amount(weth, 18)));
+    }
+    let mut sorted: Vec<f64> = eth.iter().map(|(_, price)| *price).collect();
+    sorted.sort_by(f64::total_cmp);
+    let usd = sorted[sorted.len() / 2];
+
+    let mut pegs: Vec<Peg> = eth.into_iter()
+        .map(|(symbol, price)| Peg::new(symbol.to_string(), usd / price))
+        .collect();
+    for (pool, token0, token1) in CROSS_POOLS {
+        let (r0, r1, _) = get_reserves(provider.clone(), &pool.parse::<Address>()?).await?;
+        if r0 == 0 {
+            eyre::bail!("the {token0}/{token1} pool is empty");
+        }
+        let rate = amount(r1, decimals(token1)) / amount(r0, decimals(token0));
+        pegs.push(Peg::new(format!("{token0}/{token1}"), rate));
+    }
+    Ok(pegs)
+}
+
+fn decimals(symbol: &str) -> u8 {
+    STABLES.iter().find(|(s, _, _)| *s == symbol).map_or(18, |(_, _, decimals)| *decimals)
+}
+
+fn amount(raw: u128, decimals: u8) -> f64 {
+    raw as f64 / 10_f64.powi(decimals as i32)
+}
@@ -0,0 +1,188 @@
+/*!
+ *
+ * Optional Etherscan checks of user supplied pools. When an API key is
+ * configured, the pair and both of its tokens are looked up before they are
+ * trusted: unverified contracts, proxies (whose code can change under us)
+ * and contracts that are not a UniswapV2Pair or an ERC20 are reported as
+ * warnings.
+ *
+ * [etherscan]
+ * api_key = "..."
+ *
+**/
+
+use ethers::{providers::Middleware, types::Address};
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::uniswap_v2::IUniswapV2Pair;
+
+/// The free API tier allows five calls per second
+const MIN_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Functions every ERC20 has
+const ERC20_FUNCTIONS: [&str; 6] = ["totalSupply", "balanceOf", "transfer", "transferFrom", "approve", "allowance"];
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct EtherscanConfig {
+    pub api_key: String,
+    /// API endpoint, any Etherscan V2 compatible explorer works
+    #[serde(default = "default_url")]
+    pub url: String,
+}
+
+fn default_url() -> String {
+    "https://api.etherscan.io/v2/api".to_string()
+}
+
+/// What a contract is expected to be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expect {
+    Pair,
+    Erc20,
+}
+
+/// The verified source of a contract, as far as the checks need it
+#[derive(Debug, Clone)]
+pub struct Contract {
+    pub name: String,
+    /// None when the source is not verified
+    pub abi: Option<Value>,
+    /// Where a proxy forwards to
+    pub implementation: Option<Address>,
+}
+
+pub struct Etherscan {
+    client: Client,
+    config: EtherscanConfig,
+    chain: u64,
+    last: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl Etherscan {
+    /**
+     * @gist creates the client
+     * @param client -- HTTP client used for the API
+     * @param config -- key and endpoint
+     * @param chain -- chain id the contracts live on
+    **/
+    pub fn new(client: Client, config: EtherscanConfig, chain: u64) -> Self {
+        Self { client, config, chain, last: tokio::sync::Mutex::new(None) }
+    }
+
+    /**
+     * @gist fetches the verified source of a contract, calls are spaced out
+     * to stay within the rate limit
+     * @param address -- the contract
+    **/
+    pub async fn contract(&self, address: &Address) -> eyre::Result<Contract> {
+        {
+            let mut last = self.last.lock().await;
+            if let Some(at) = *last {
+                tokio::time::sleep(MIN_INTERVAL.saturating_sub(at.elapsed())).await;
+            }
+            *last = Some(Instant::now());
+        }
+
+        let res: Value = self.client.get(&self.config.url)
+            .query(&[
+                ("chainid", self.chain.to_string()),
+                ("module", "contract".to_string()),
+                ("action", "getsourcecode".to_string()),
+                ("address", format!("{address:?}")),
+                ("apikey", self.config.api_key.clone()),
+            ])
+            .send().await
+            .and_then(|r| r.error_for_status())
+            // The url carries the api key
+            .map_err(|e| eyre::eyre!("etherscan unavailable: {}", e.without_url()))?
+            .json().await
+            .map_err(|e| eyre::eyre!("invalid etherscan response: {}", e.without_url()))?;
+
+        let Some(result) = res["result"].get(0) else {
+            eyre::bail!("etherscan error: {}", res["result"].as_str().unwrap_or("unknown"));
+        };
+        let abi = serde_json::from_str(result["ABI"].as_str().unwrap_or_default()).ok();
+        let implementation = match result["Proxy"].as_str() {
+            Some("1") => result["Implementation"].as_str().and_then(|a| a.parse().ok()),
+            _ => None,
+        };
+
+        Ok(Contract {
+            name: result["ContractName"].as_str().unwrap_or_default().to_string(),
+            abi,
+            implementation,
+        })
+    }
+
+    /**
+     * @gist checks a contract against what it is expected to be, proxies are
+     * judged by their implementation
+     * @param address -- the contract
+     * @param expect -- a pair or a token
+     * @output the warnings, empty if the contract looks right
+    **/
+    pub async fn check(&self, address: &Address, expect: Expect) -> eyre::Result<Vec<String>> {
+        let mut warnings = Vec::new();
+        let mut contract = self.contract(address).await?;
+
+        if let Some(implementation) = contract.implementation {
+            warnings.push(format!("{address:?} is a proxy for {implementation:?}, its code can change"));
+            contract = self.contract(&implementation).await?;
+        }
+        let Some(abi) = &contract.abi else {
+            warnings.push(format!("{address:?} is not verified on etherscan"));
+            return Ok(warnings);
+        };
+
+        match expect {
+            Expect::Pair if contract.name != "UniswapV2Pair" => {
+                warnings.push(format!("{address:?} is {}, not a UniswapV2Pair", contract.name));
+            }
+            Expect::Erc20 => {
+                let functions: Vec<&str> = abi.as_array().into_iter().flatten()
+                    .filter(|item| item["type"] == "function")
+                    .filter_map(|item| item["name"].as_str())
+                    .collect();
+                let missing: Vec<&str> = ERC20_FUNCTIONS.into_iter().filter(|f| !functions.contains(f)).collect();
+                if !missing.is_empty() {
+                    warnings.push(format!("{address:?} ({}) is missing ERC20 functions: {}", contract.name, missing.join(", ")));
+                }
+            }
+            Expect::Pair => {}
+        }
+        Ok(warnings)
+    }
+}
+
+/**
+ * @gist checks a pool and both of its tokens
+ * @param etherscan -- the API client
+ * @param provider -- reads the tokens of the pair
+ * @param pool -- the pair
+ * @output the warnings, a failed lookup is a warning too
+**/
+pub async fn check_pool<M: Middleware + 'static>(etherscan: &Etherscan, provider: Arc<M>, pool: &Address) -> Vec<String> {
+    let mut warnings = match etherscan.check(pool, Expect::Pair).await {
+        Ok(warnings) => warnings,
+        Err(e) => return vec![format!("could not check {pool:?}: {e}")],
+    };
+
+    let pair = IUniswapV2Pair::new(*pool, provider);
+    for token in [pair.token_0().call().await, pair.token_1().call().await] {
+        let result = match token {
+            Ok(token) => etherscan.check(&token, Expect::Erc20).await,
+            Err(e) => Err(eyre::eyre!("could not read the tokens of {pool:?}: {e}")),
+        };
+        match result {
+            Ok(w) => warnings.extend(w),
+            Err(e) => warnings.push(e.to_string()),
+        }
+    }
+    warnings
+}
@@ -0,0 +1,119 @@
+/*!
+ *
+ * Exit codes, so scripts and monitoring wrappers can branch on why a run
+ * failed:
+ *
+ * 0  success
+ * 1  any other error
+ * 2  bad command line
+ * 3  bad address: not an address, an unknown alias or not a pool
+ * 4  RPC failure: the node could not be reached or failed the request
+ * 5  stale pool: the reserves were not updated within --max-age
+ * 6  thin pool: the pool holds less than --min-liquidity-usd
+ *
+ * uni-price-polling --pool LINK --quiet --max-age 3600 || echo "failed with $?"
+ *
+**/
+
+use ethers::{
+    contract::ContractError,
+    providers::{Middleware, ProviderError},
+};
+
+use std::fmt;
+use std::process::ExitCode;
+
+/// The failure classes with their own exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    BadAddress,
+    Rpc,
+    Stale,
+    Thin,
+}
+
+impl Kind {
+    pub fn code(self) -> u8 {
+        match self {
+            Kind::BadAddress => 3,
+            Kind::Rpc => 4,
+            Kind::Stale => 5,
+            Kind::Thin => 6,
+        }
+    }
+}
+
+/// An error with a known class
+pub struct Failure {
+    pub kind: Kind,
+    message: String,
+}
+
+impl Failure {
+    pub fn new(kind: Kind, message: impl fmt::Display) -> Self {
+        Self { kind, message: message.to_string() }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+// main prints errors with Debug, only the message is of use here
+impl fmt::Debug for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/**
+ * @gist classifies a quote error by its cause
+ * @param error -- the error of a source
+ * @output None when it's neither an RPC nor an address problem
+**/
+pub fn classify<M: Middleware + 'static>(error: &eyre::Report) -> Option<Kind> {
+    for cause in error.chain() {
+        if let Some(failure) = cause.downcast_ref::<Failure>() {
+            return Some(failure.kind);
+        }
+        if cause.is::<ProviderError>() {
+            return Some(Kind::Rpc);
+        }
+        if let Some(e) = cause.downcast_ref::<ContractError<M>>() {
+            return match e {
+                ContractError::MiddlewareError { .. } | ContractError::ProviderError { .. } => Some(Kind::Rpc),
+                // An address without the contract returns nothing to decode
+                ContractError::DecodingError(_) | ContractError::AbiError(_)
+                | ContractError::DetokenizationError(_) | ContractError::Revert(_) => Some(Kind::BadAddress),
+                _ => None,
+            };
+        }
+    }
+    None
+}
+
+/**
+ * @gist turns a quote error into the error main returns, a Failure when
+ * its class is known
+**/
+pub fn classified<M: Middleware + 'static>(error: eyre::Report) -> Box<dyn std::error::Error> {
+    match classify::<M>(&error) {
+        Some(kind) => Box::new(Failure::new(kind, format!("{error:#}"))),
+        None => error.into(),
+    }
+}
+
+/**
+ * @gist the exit code of an error returned from main
+ * @output the code of its class, 1 when it has none
+**/
+pub fn code(error: &(dyn std::error::Error + 'static)) -> ExitCode {
+    match error.downcast_ref::<Failure>() {
+        Some(failure) => ExitCode::from(failure.kind.code()),
+        None => ExitCode::FAILURE,
+    }
+}
@@ -0,0 +1,249 @@
+/*!
+ *
+ * Archiving the quote history. `history export` reads the files written by
+ * the file sinks, rotated and gzipped ones included, and writes the rows
+ * unchanged into one file per UTC day, e.g. 2024-01-01.csv.zst, ready to be
+ * copied to object storage:
+ *
+ * uni-price-polling history export quotes.csv quotes.*.csv.gz -o archive --compress zstd
+ *
+ * Rows of a day spread over several inputs end up in the same file.
+ *
+**/
+
+use eyre::{bail, eyre};
+use flate2::write::GzEncoder;
+use serde_json::Value;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::history;
+
+/// Compression of the exported files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /**
+     * @gist the compression a file name asks for, by its .gz or .zst
+     * extension
+    **/
+    pub fn for_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+}
+
+/// Which rows are exported
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Only this asset, all when unset
+    pub asset: Option<String>,
+    /// Unix time of the first row kept
+    pub from: Option<u64>,
+    /// Unix time of the last row kept
+    pub to: Option<u64>,
+}
+
+impl Filter {
+    fn keeps(&self, asset: &str, timestamp: u64) -> bool {
+        self.asset.as_deref().is_none_or(|a| a == asset)
+            && self.from.is_none_or(|from| timestamp >= from)
+            && self.to.is_none_or(|to| timestamp <= to)
+    }
+}
+
+/// One exported file
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    pub path: PathBuf,
+    pub rows: usize,
+}
+
+/// A file written line by line, compressed or not
+pub(crate) enum Writer {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Writer {
+    pub(crate) fn new(file: File, compression: Compression) -> eyre::Result<Self> {
+        let file = BufWriter::new(file);
+        Ok(match compression {
+            Compression::None => Writer::Plain(file),
+            Compression::Gzip => Writer::Gzip(GzEncoder::new(file, flate2::Compression::default())),
+            Compression::Zstd => Writer::Zstd(zstd::Encoder::new(file, 0)?),
+        })
+    }
+
+    pub(crate) fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        match self {
+            Writer::Plain(w) => writeln!(w, "{line}"),
+            Writer::Gzip(w) => writeln!(w, "{line}"),
+            Writer::Zstd(w) => writeln!(w, "{line}"),
+        }
+    }
+
+    pub(crate) fn finish(self) -> std::io::Result<()> {
+        match self {
+            Writer::Plain(mut w) => w.flush(),
+            Writer::Gzip(w) => w.finish()?.flush(),
+            Writer::Zstd(w) => w.finish()?.flush(),
+        }
+    }
+}
+
+/// Columns of a csv history file
+struct Columns {
+    asset: usize,
+    timestamp: usize,
+}
+
+impl Columns {
+    fn parse(header: &str) -> eyre::Result<Self> {
+        let names: Vec<&str> = header.split(',').collect();
+        let find = |name: &str| names.iter().position(|c| *c == name).ok_or_else(|| eyre!("no {name} column"));
+        Ok(Self { asset: find("asset")?, timestamp: find("timestamp")? })
+    }
+}
+
+/**
+ * @gist exports history files into one file per UTC day
+ * @param inputs -- files written by file sinks, all csv or all json lines
+ * @param output -- directory the daily files are written to, created if missing
+ * @param filter -- rows to keep
+ * @param compression -- of the daily files
+ * @output the written files, oldest day first
+**/
+pub fn export(inputs: &[PathBuf], output: &Path, filter: &Filter, compression: Compression) -> eyre::Result<Vec<Chunk>> {
+    let Some(first) = inputs.first() else {
+        bail!("no history files to export");
+    };
+    let csv = history::is_csv(first);
+    if let Some(other) = inputs.iter().find(|p| history::is_csv(p) != csv) {
+        bail!("{} and {} are not of the same format", first.display(), other.display());
+    }
+    fs::create_dir_all(output).map_err(|e| eyre!("could not create {}: {e}", output.display()))?;
+
+    let extension = if csv { "csv" } else { "ndjson" };
+    let mut header: Option<String> = None;
+    let mut rows: BTreeMap<String, usize> = BTreeMap::new();
+    let mut current: Option<(String, Writer)> = None;
+
+    for input in inputs {
+        let mut columns = None;
+        for (n, line) in history::open(input)?.lines().enumerate() {
+            let line = line.map_err(|e| eyre!("could not read {}: {e}", input.display()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            // Every csv file, rotated ones too, starts with its header
+            if csv && line.starts_with("source,") {
+                match &header {
+                    Some(h) if *h != line => bail!("{} has other columns than {}", input.display(), first.display()),
+                    Some(_) => {}
+                    None => header = Some(line.clone()),
+                }
+                columns = Some(Columns::parse(&line)?);
+                continue;
+            }
+
+            let row = if csv {
+                let columns = columns.as_ref().ok_or_else(|| eyre!("{} has no csv header", input.display()))?;
+                read_csv(&line, columns)
+            } else {
+                read_json(&line)
+            };
+            let Some((asset, timestamp)) = row else {
+                eprintln!("{}:{}: skipping a row without asset or timestamp", input.display(), n + 1);
+                continue;
+            };
+            if !filter.keeps(&asset, timestamp) {
+                continue;
+            }
+
+            let day = chrono::DateTime::from_timestamp(timestamp as i64, 0)
+                .ok_or_else(|| eyre!("{}:{}: timestamp {timestamp} is out of range", input.display(), n + 1))?
+                .format("%Y-%m-%d")
+                .to_string();
+            let writer = match &mut current {
+                Some((d, writer)) if *d == day => writer,
+                _ => {
+                    if let Some((_, writer)) = current.take() {
+                        writer.finish()?;
+                    }
+                    let path = chunk_path(output, &day, extension, compression);
+                    let writer = open_chunk(&path, compression, rows.contains_key(&day), header.as_deref())?;
+                    &mut current.insert((day.clone(), writer)).1
+                }
+            };
+            writer.write_line(&line)?;
+            *rows.entry(day).or_default() += 1;
+        }
+    }
+    if let Some((_, writer)) = current {
+        writer.finish()?;
+    }
+
+    Ok(rows.into_iter()
+        .map(|(day, rows)| Chunk { path: chunk_path(output, &day, extension, compression), rows })
+        .collect())
+}
+
+fn chunk_path(output: &Path, day: &str, extension: &str, compression: Compression) -> PathBuf {
+    output.join(format!("{day}.{extension}{}", compression.extension()))
+}
+
+/**
+ * @gist opens the file of a day. A day seen before in this export is
+ * appended to, concatenated gzip members and zstd frames read back as one
+ * stream. Otherwise the file is replaced and starts with the csv header.
+**/
+fn open_chunk(path: &Path, compression: Compression, seen: bool, header: Option<&str>) -> eyre::Result<Writer> {
+    let file = if seen {
+        OpenOptions::new().append(true).open(path)
+    } else {
+        File::create(path)
+    }.map_err(|e| eyre!("could not open {}: {e}", path.display()))?;
+
+    let mut writer = Writer::new(file, compression)?;
+    if let (false, Some(header)) = (seen, header) {
+        writer.write_line(header)?;
+    }
+    Ok(writer)
+}
+
+fn read_csv(line: &str, columns: &Columns) -> Option<(String, u64)> {
+    let fields: Vec<&str> = line.split(',').collect();
+    let asset = fields.get(columns.asset)?;
+    let timestamp = fields.get(columns.timestamp)?.parse().ok()?;
+    Some((asset.to_string(), timestamp))
+}
+
+fn read_json(line: &str) -> Option<(String, u64)> {
+    let value: Value = serde_json::from_str(line).ok()?;
+    let asset = value.get("asset")?.as_str()?;
+    let timestamp = value.get("timestamp")?.as_u64()?;
+    Some((asset.to_string(), timestamp))
+}
@@ -0,0 +1,135 @@
+/*!
+ *
+ * Off-chain fallback for when the RPC is down. Every feed is wrapped, and a
+ * feed whose source fails is priced by CoinGecko (for assets mapped to a
+ * CoinGecko id) or by Dexscreener (using the asset address as the pair). Such
+ * quotes are tagged with the source "fallback" so consumers can tell them
+ * apart from on-chain prices.
+ *
+ * [fallback]
+ * chain = "ethereum"
+ * coingecko = { "LINK/USDT" = "chainlink" }
+ *
+**/
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::Value;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::source::{Asset, PriceSource, Quote};
+
+/// Source name of every off-chain quote
+pub const SOURCE: &str = "fallback";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct FallbackConfig {
+    /// Dexscreener chain slug of the pools
+    #[serde(default = "default_chain")]
+    pub chain: String,
+    /// CoinGecko ids by asset name, assets without one go to Dexscreener
+    #[serde(default)]
+    pub coingecko: HashMap<String, String>,
+    /// CoinGecko demo API key
+    pub coingecko_key: Option<String>,
+    #[serde(default = "default_coingecko_url")]
+    pub coingecko_url: String,
+    #[serde(default = "default_dexscreener_url")]
+    pub dexscreener_url: String,
+}
+
+fn default_chain() -> String {
+    "ethereum".to_string()
+}
+
+fn default_coingecko_url() -> String {
+    "https://api.coingecko.com/api/v3".to_string()
+}
+
+fn default_dexscreener_url() -> String {
+    "https://api.dexscreener.com/latest/dex".to_string()
+}
+
+pub struct OffChain {
+    client: Client,
+    config: FallbackConfig,
+}
+
+impl OffChain {
+    /**
+     * @gist creates the client
+     * @param client -- HTTP client used for the APIs
+     * @param config -- chain and CoinGecko ids
+    **/
+    pub fn new(client: Client, config: FallbackConfig) -> Self {
+        Self { client, config }
+    }
+
+    async fn get(&self, request: reqwest::RequestBuilder) -> eyre::Result<Value> {
+        let res = request.send().await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| eyre::eyre!("{}", e.without_url()))?;
+        Ok(res.json().await?)
+    }
+
+    /**
+     * @gist prices an asset off chain
+     * @param asset -- priced by its CoinGecko id, or as a Dexscreener pair
+    **/
+    pub async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        if let Some(id) = self.config.coingecko.get(&asset.name) {
+            let mut request = self.client.get(format!("{}/simple/price", self.config.coingecko_url))
+                .query(&[("ids", id.as_str()), ("vs_currencies", "usd")]);
+            if let Some(key) = &self.config.coingecko_key {
+                request = request.header("x-cg-demo-api-key", key);
+            }
+            let res = self.get(request).await.map_err(|e| eyre::eyre!("coingecko: {e}"))?;
+            let price = res[id]["usd"].as_f64().ok_or_else(|| eyre::eyre!("coingecko has no price for {id}"))?;
+            return Ok(Quote::new(SOURCE, &asset.name, price));
+        }
+
+        let url = format!("{}/pairs/{}/{:?}", self.config.dexscreener_url, self.config.chain, asset.address);
+        let res = self.get(self.client.get(url)).await.map_err(|e| eyre::eyre!("dexscreener: {e}"))?;
+        let pair = res["pairs"].get(0).ok_or_else(|| eyre::eyre!("dexscreener does not know {:?}", asset.address))?;
+        let price = pair["priceUsd"].as_str().and_then(|p| p.parse().ok())
+            .ok_or_else(|| eyre::eyre!("dexscreener has no price for {:?}", asset.address))?;
+
+        let mut quote = Quote::new(SOURCE, &asset.name, price);
+        quote.liquidity = pair["liquidity"]["usd"].as_f64();
+        Ok(quote)
+    }
+}
+
+/// Answers with an off-chain price when the wrapped source fails
+pub struct Fallback {
+    inner: Arc<dyn PriceSource>,
+    offchain: Arc<OffChain>,
+}
+
+impl Fallback {
+    /**
+     * @gist wraps a source
+    **/
+    pub fn new(inner: Arc<dyn PriceSource>, offchain: Arc<OffChain>) -> Self {
+        Self { inner, offchain }
+    }
+}
+
+#[async_trait]
+impl PriceSource for Fallback {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let e = match self.inner.quote(asset).await {
+            Ok(quote) => return Ok(quote),
+            Err(e) => e,
+        };
+        self.offchain.quote(asset).await.map_err(|fallback| eyre::eyre!("{e} (fallback: {fallback})"))
+    }
+}
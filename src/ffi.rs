@@ -0,0 +1,235 @@
+/*!
+ *
+ * A C ABI for embedding the poller in other runtimes, built with the
+ * `cdylib` feature:
+ *
+ * cargo build --release --features cdylib
+ *
+ * which leaves libuni_price_polling.so (.dylib, .dll) next to the binary.
+ * The prototypes are in include/uni_price_polling.h. Pools are TOKEN/WETH
+ * pairs priced in USDT through the ETH/USDT pool like `--pool`, their
+ * layout is read from the pair. Every function returns 0 or a handle on
+ * success; after a failure `upp_last_error` says why.
+ *
+**/
+
+use ethers::providers::Middleware;
+
+use std::cell::RefCell;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::checksum;
+use crate::format::{self, Format};
+use crate::metrics::Metrics;
+use crate::provider::{self, HttpOptions, Pin};
+use crate::source::{Asset, PriceSource, Quote};
+use crate::uniswap_v2::{self, get_pair_info, UniswapV2};
+
+/// Called with every quote of a subscription: the user data passed to
+/// upp_subscribe, the price, the block (0 without one) and the quote as a
+/// JSON object, valid for the duration of the call
+pub type Callback = extern "C" fn(user_data: *mut c_void, price: f64, block: u64, json: *const c_char);
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn fail(message: impl ToString) {
+    let message = CString::new(message.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = message);
+}
+
+/// A running subscription, stops when dropped
+pub struct Subscription {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        // The poller wakes up as soon as its sender is gone
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The user data of a subscription, only ever handed back to the caller
+struct UserData(*mut c_void);
+
+unsafe impl Send for UserData {}
+
+/**
+ * @gist reads a C string argument
+**/
+unsafe fn argument<'a>(s: *const c_char, name: &str) -> eyre::Result<&'a str> {
+    if s.is_null() {
+        eyre::bail!("{name} is null");
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| eyre::eyre!("{name} is not UTF-8"))
+}
+
+/**
+ * @gist a V2 source for a pool with the layout read from the pair
+ * @param rpc_url -- the JSON-RPC endpoint
+ * @param pool -- the TOKEN/WETH pair
+**/
+async fn source(rpc_url: &str, pool: &str) -> eyre::Result<(impl PriceSource, Asset)> {
+    let provider = provider::http_provider(rpc_url, &HttpOptions::default(), 16, Arc::new(Metrics::default()), Arc::new(Pin::default()))
+        .map_err(|e| eyre::eyre!("{e}"))?;
+    let provider = Arc::new(provider);
+    if provider.get_chainid().await?.as_u64() != 1 {
+        eyre::bail!("the RPC is not on mainnet");
+    }
+
+    let address = checksum::parse(pool)?;
+    let info = get_pair_info(provider.clone(), &address).await?;
+    let (inverted, token) = info.token().ok_or_else(|| eyre::eyre!("{pool} is no TOKEN/WETH pair"))?;
+    let source = UniswapV2::new(provider, uniswap_v2::ETH_USDT_POOL.parse()?).with_layout(inverted, token.decimals);
+    let name = token.symbol.clone().unwrap_or_else(|| format!("{address:?}"));
+    Ok((source, Asset { name, address }))
+}
+
+fn runtime() -> eyre::Result<tokio::runtime::Runtime> {
+    Ok(tokio::runtime::Builder::new_current_thread().enable_all().build()?)
+}
+
+/**
+ * @gist the message of the last failure on this thread, empty without one.
+ * The string stays valid until the next call on this thread.
+**/
+#[no_mangle]
+pub extern "C" fn upp_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+/**
+ * @gist quotes a pool once
+ * @param rpc_url -- the JSON-RPC endpoint, a C string
+ * @param pool -- address of the TOKEN/WETH pair, a C string
+ * @param price -- receives the USDT price of TOKEN
+ * @output 0 on success, -1 on failure
+ *
+ * # Safety
+ * The strings have to be null terminated and price has to point to a
+ * writable double.
+**/
+#[no_mangle]
+pub unsafe extern "C" fn upp_fetch_price(rpc_url: *const c_char, pool: *const c_char, price: *mut f64) -> c_int {
+    let quote = (|| {
+        let (rpc_url, pool) = (argument(rpc_url, "rpc_url")?, argument(pool, "pool")?);
+        if price.is_null() {
+            eyre::bail!("price is null");
+        }
+        runtime()?.block_on(async {
+            let (source, asset) = source(rpc_url, pool).await?;
+            source.quote(&asset).await
+        })
+    })();
+    match quote {
+        Ok(quote) => {
+            *price = quote.price;
+            0
+        }
+        Err(e) => {
+            fail(e);
+            -1
+        }
+    }
+}
+
+/**
+ * @gist quotes a pool on a thread of its own every interval until
+ * upp_unsubscribe, failed polls are skipped
+ * @param rpc_url -- the JSON-RPC endpoint, a C string
+ * @param pool -- address of the TOKEN/WETH pair, a C string
+ * @param interval_ms -- time between two quotes
+ * @param callback -- called from the polling thread with every quote, null
+ * fails
+ * @param user_data -- handed to the callback as is
+ * @output the subscription, null on failure
+ *
+ * # Safety
+ * The strings have to be null terminated. The callback and user data have
+ * to stay usable from another thread until upp_unsubscribe returns.
+**/
+#[no_mangle]
+pub unsafe extern "C" fn upp_subscribe(rpc_url: *const c_char, pool: *const c_char, interval_ms: u64, callback: Option<Callback>, user_data: *mut c_void) -> *mut Subscription {
+    let arguments = argument(rpc_url, "rpc_url").and_then(|url| {
+        let callback = callback.ok_or_else(|| eyre::eyre!("callback is null"))?;
+        Ok((url.to_string(), argument(pool, "pool")?.to_string(), callback))
+    });
+    let (rpc_url, pool, callback) = match arguments {
+        Ok(arguments) => arguments,
+        Err(e) => {
+            fail(e);
+            return std::ptr::null_mut();
+        }
+    };
+
+    // The pool is checked before the handle is returned, later failures
+    // only cost a quote
+    let (ready, checked) = mpsc::channel();
+    let (stop, stopped) = mpsc::channel::<()>();
+    let user_data = UserData(user_data);
+    let thread = std::thread::spawn(move || {
+        let checked = runtime().and_then(|runtime| Ok((runtime.block_on(source(&rpc_url, &pool))?, runtime)));
+        let ((source, asset), runtime) = match checked {
+            Ok(checked) => checked,
+            Err(e) => {
+                let _ = ready.send(Err(e));
+                return;
+            }
+        };
+        let _ = ready.send(Ok(()));
+
+        loop {
+            match runtime.block_on(source.quote(&asset)) {
+                Ok(quote) => notify(callback, &user_data, &quote),
+                Err(e) => eprintln!("could not quote {}: {e}", asset.name),
+            }
+            match stopped.recv_timeout(Duration::from_millis(interval_ms)) {
+                Err(RecvTimeoutError::Timeout) => continue,
+                _ => break,
+            }
+        }
+    });
+
+    match checked.recv() {
+        Ok(Ok(())) => Box::into_raw(Box::new(Subscription { stop: Some(stop), thread: Some(thread) })),
+        Ok(Err(e)) => {
+            fail(e);
+            std::ptr::null_mut()
+        }
+        Err(_) => {
+            fail("the polling thread panicked");
+            std::ptr::null_mut()
+        }
+    }
+}
+
+fn notify(callback: Callback, user_data: &UserData, quote: &Quote) {
+    // The raw reserves don't fit into JSON numbers
+    let json = CString::new(format::render(&quote.clone().without_raw(), Format::Json)).unwrap_or_default();
+    callback(user_data.0, quote.price, quote.block.unwrap_or_default(), json.as_ptr());
+}
+
+/**
+ * @gist stops a subscription and waits for a running callback to return
+ * @param subscription -- from upp_subscribe, null is ignored
+ *
+ * # Safety
+ * The subscription has to come from upp_subscribe and can't be used
+ * afterwards.
+**/
+#[no_mangle]
+pub unsafe extern "C" fn upp_unsubscribe(subscription: *mut Subscription) {
+    if !subscription.is_null() {
+        drop(Box::from_raw(subscription));
+    }
+}
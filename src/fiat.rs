@@ -0,0 +1,228 @@
+/*!
+ *
+ * Fiat output currencies. With `--fiat EUR` the USD rate of the currency is
+ * fetched from the ECB reference rates (through the Frankfurter API) and
+ * every quote, its components and its dollar figures are converted before
+ * they are printed, stored or alerted on:
+ *
+ * uni-price-polling --config watch.toml --fiat EUR --interval 60
+ *
+ * Only dollar quotes are converted. A quote already in another unit, a
+ * [[v3]] path ending in WETH or an [[oracle]] with a `currency`, is passed on
+ * as it is.
+ *
+ * The ECB publishes once a day, the rate is fetched again every hour. When
+ * that fails the last rate is used, quotes fail only while no rate was ever
+ * fetched.
+ *
+**/
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde_json::Value;
+use tokio::sync::Mutex;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::source::{Asset, PriceSource, Quote};
+
+/// Frankfurter, a free API over the ECB reference rates
+pub const DEFAULT_URL: &str = "https://api.frankfurter.app";
+
+/// How long a fetched rate is used before it is fetched again
+const MAX_AGE: Duration = Duration::from_secs(3600);
+
+/**
+ * @gist parses a currency code, e.g. eur or SEK
+**/
+pub fn parse_code(code: &str) -> Result<String, String> {
+    if code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(code.to_uppercase())
+    } else {
+        Err(format!("{code:?} is not a three letter currency code"))
+    }
+}
+
+/// The USD rate of a currency, cached
+pub struct Rates {
+    client: Client,
+    url: String,
+    code: String,
+    cached: Mutex<Option<(Instant, f64)>>,
+}
+
+impl Rates {
+    /**
+     * @gist creates the client
+     * @param client -- HTTP client used for the API
+     * @param url -- API base url, see DEFAULT_URL
+     * @param code -- the currency, e.g. EUR
+    **/
+    pub fn new(client: Client, url: String, code: String) -> Self {
+        Self { client, url, code, cached: Mutex::new(None) }
+    }
+
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    /**
+     * @gist units of the currency one USD buys
+    **/
+    pub async fn per_usd(&self) -> eyre::Result<f64> {
+        let mut cached = self.cached.lock().await;
+        if let Some((fetched, rate)) = *cached {
+            if fetched.elapsed() < MAX_AGE {
+                return Ok(rate);
+            }
+        }
+        match self.fetch().await {
+            Ok(rate) => {
+                *cached = Some((Instant::now(), rate));
+                Ok(rate)
+            }
+            Err(e) => match *cached {
+                Some((_, rate)) => {
+                    eprintln!("could not refresh the USD/{} rate, keeping the last one: {e}", self.code);
+                    Ok(rate)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    async fn fetch(&self) -> eyre::Result<f64> {
+        let res: Value = self.client.get(format!("{}/latest", self.url.trim_end_matches('/')))
+            .query(&[("from", "USD"), ("to", self.code.as_str())])
+            .send().await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| eyre::eyre!("rates: {}", e.without_url()))?
+            .json().await?;
+        res["rates"][&self.code].as_f64()
+            .ok_or_else(|| eyre::eyre!("no USD/{} rate", self.code))
+    }
+}
+
+/**
+ * @gist converts the dollar figures of a quote and its components, the ones
+ * in another currency are left as they are
+ * @param quote -- the quote, in USD
+ * @param rate -- units of the currency per USD
+ * @param code -- the currency
+**/
+pub fn convert(mut quote: Quote, rate: f64, code: &str) -> Quote {
+    if quote.currency.is_some() {
+        return quote;
+    }
+    quote.price *= rate;
+    let scale = |v: &mut Option<f64>| *v = v.map(|v| v * rate);
+    scale(&mut quote.liquidity);
+    scale(&mut quote.vwap);
+    scale(&mut quote.volume_24h);
+    scale(&mut quote.fees_24h);
+    scale(&mut quote.cex_price);
+    scale(&mut quote.sized_price);
+    scale(&mut quote.aggregator_price);
+    scale(&mut quote.simulated_price);
+    scale(&mut quote.bid);
+    scale(&mut quote.ask);
+    if let Some(swaps) = &mut quote.swaps {
+        swaps.buy_usd *= rate;
+        swaps.sell_usd *= rate;
+    }
+    for trade in &mut quote.trades {
+        trade.usd *= rate;
+    }
+    for event in &mut quote.liquidity_events {
+        event.usd *= rate;
+    }
+    for sandwich in &mut quote.sandwiches {
+        sandwich.victim_usd *= rate;
+        sandwich.profit_usd *= rate;
+    }
+    quote.currency = Some(code.to_string());
+    quote.components = quote.components.into_iter().map(|c| convert(c, rate, code)).collect();
+    quote
+}
+
+/// Converts the quotes of a source into a fiat currency
+pub struct InFiat {
+    inner: Arc<dyn PriceSource>,
+    rates: Arc<Rates>,
+}
+
+impl InFiat {
+    /**
+     * @gist wraps a source
+    **/
+    pub fn new(inner: Arc<dyn PriceSource>, rates: Arc<Rates>) -> Self {
+        Self { inner, rates }
+    }
+}
+
+#[async_trait]
+impl PriceSource for InFiat {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let quote = self.inner.quote(asset).await?;
+        if quote.currency.is_some() {
+            return Ok(quote);
+        }
+        Ok(convert(quote, self.rates.per_usd().await?, self.rates.code()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::swaps::Volume;
+
+    fn quote(price: f64) -> Quote {
+        let mut quote = Quote::new("UNI V2", "LINK", price);
+        quote.liquidity = Some(1_000_000.0);
+        quote.bid = Some(price * 0.99);
+        quote
+    }
+
+    #[test]
+    fn parses_codes() {
+        assert_eq!(parse_code("eur"), Ok("EUR".to_string()));
+        assert!(parse_code("EURO").is_err());
+        assert!(parse_code("E1R").is_err());
+    }
+
+    #[test]
+    fn scales_the_dollar_figures() {
+        let mut usd = quote(10.0);
+        usd.swaps = Some(Volume { buy_usd: 100.0, sell_usd: 50.0, ..Default::default() });
+        let eur = convert(usd, 0.9, "EUR");
+        assert_eq!(eur.price, 9.0);
+        assert_eq!(eur.liquidity, Some(900_000.0));
+        assert_eq!(eur.bid, Some(10.0 * 0.99 * 0.9));
+        assert_eq!(eur.vwap, None);
+        let swaps = eur.swaps.unwrap();
+        assert_eq!((swaps.buy_usd, swaps.sell_usd), (90.0, 45.0));
+        assert_eq!(eur.currency.as_deref(), Some("EUR"));
+    }
+
+    #[test]
+    fn leaves_other_currencies() {
+        let mut weth = quote(0.005);
+        weth.currency = Some("WETH".to_string());
+        let mut composite = quote(10.0);
+        composite.components = vec![quote(20.0), weth];
+
+        let eur = convert(composite, 0.5, "EUR");
+        assert_eq!(eur.price, 5.0);
+        assert_eq!(eur.components[0].price, 10.0);
+        assert_eq!(eur.components[0].currency.as_deref(), Some("EUR"));
+        assert_eq!(eur.components[1].price, 0.005);
+        assert_eq!(eur.components[1].liquidity, Some(1_000_000.0));
+        assert_eq!(eur.components[1].currency.as_deref(), Some("WETH"));
+    }
+}
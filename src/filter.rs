@@ -0,0 +1,155 @@
+/*!
+ *
+ * Token allow and block lists, so scam and test tokens never enter the
+ * quotes or alerts. Entries are addresses or symbol patterns with `*` and
+ * `?`, matched ignoring case:
+ *
+ * [tokens]
+ * block = ["0x6982508145454ce325ddbe47a25d4ec3d2311933", "TEST*", "*INU"]
+ * allow = []
+ *
+ * A token is blocked when any block entry matches it. With a non-empty allow
+ * list it must also match an allow entry. The lists apply to the [[pool]]
+ * watchlist, `pools import`, `pools discover` and `watch-new-pairs`; a pool
+ * matches by its own address, its token's address, symbol or its label.
+ *
+**/
+
+use ethers::{providers::Middleware, types::Address};
+use serde::Deserialize;
+
+use std::sync::Arc;
+
+use crate::checksum;
+use crate::pools::PoolConfig;
+use crate::uniswap_v2::{get_token, IUniswapV2Pair};
+
+/// A list entry
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "String")]
+pub enum Pattern {
+    Address(Address),
+    /// Upper case symbol pattern
+    Symbol(String),
+}
+
+impl TryFrom<String> for Pattern {
+    type Error = String;
+
+    fn try_from(entry: String) -> Result<Self, Self::Error> {
+        if entry.starts_with("0x") {
+            return checksum::parse(&entry).map(Pattern::Address).map_err(|e| e.to_string());
+        }
+        if entry.is_empty() {
+            return Err("empty token pattern".to_string());
+        }
+        Ok(Pattern::Symbol(entry.to_uppercase()))
+    }
+}
+
+impl Pattern {
+    fn matches(&self, addresses: &[Address], names: &[&str]) -> bool {
+        match self {
+            Pattern::Address(address) => addresses.contains(address),
+            Pattern::Symbol(pattern) => names.iter().any(|name| glob(pattern.as_bytes(), name.to_uppercase().as_bytes())),
+        }
+    }
+}
+
+/// The [tokens] table of the config
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TokenFilter {
+    /// Only tokens matching one of these, everything when empty
+    #[serde(default)]
+    pub allow: Vec<Pattern>,
+
+    /// Never these tokens
+    #[serde(default)]
+    pub block: Vec<Pattern>,
+}
+
+impl TokenFilter {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.block.is_empty()
+    }
+
+    /**
+     * @gist whether a token may be used
+     * @param addresses -- its address and, for a pool, the pool's
+     * @param names -- its symbol, label or alias
+    **/
+    pub fn permits(&self, addresses: &[Address], names: &[&str]) -> bool {
+        if self.block.iter().any(|p| p.matches(addresses, names)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|p| p.matches(addresses, names))
+    }
+
+    /**
+     * @gist drops the pools whose token is not permitted. The token of every
+     * pool is read once, pools that can't be read are kept and left to fail
+     * when they are polled.
+     * @param provider -- reads the tokens
+     * @param pools -- the watchlist
+    **/
+    pub async fn pools<M: Middleware + 'static>(&self, provider: Arc<M>, pools: Vec<PoolConfig>) -> Vec<PoolConfig> {
+        if self.is_empty() {
+            return pools;
+        }
+        let mut kept = Vec::with_capacity(pools.len());
+        for pool in pools {
+            let label = pool.label.as_deref().unwrap_or_default();
+            let pair = IUniswapV2Pair::new(pool.address, provider.clone());
+            let token = if pool.inverted { pair.token_1().call().await } else { pair.token_0().call().await };
+            let token = match token {
+                Ok(token) => get_token(provider.clone(), token).await.map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+            let permitted = match &token {
+                Ok(token) => self.permits(&[pool.address, token.address], &[label, token.symbol.as_deref().unwrap_or_default()]),
+                Err(e) => {
+                    eprintln!("could not read the token of {:?}: {e}", pool.address);
+                    self.permits(&[pool.address], &[label])
+                }
+            };
+            if permitted {
+                kept.push(pool);
+            } else {
+                eprintln!("leaving out {:?}, its token is not allowed", pool.address);
+            }
+        }
+        kept
+    }
+}
+
+/**
+ * @gist matches a name against a pattern with `*` for any run of characters
+ * and `?` for exactly one
+**/
+fn glob(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was and how much of the name it took
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(c) if *c == b'?' || *c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
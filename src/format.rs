@@ -0,0 +1,229 @@
+/*!
+ *
+ * Rendering of quotes for the terminal and the file sinks.
+ *
+**/
+
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt::Write;
+
+use crate::clock::{self, TimeStyle};
+use crate::schema::{Versioned, SCHEMA_VERSION};
+use crate::source::Quote;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Text,
+    Csv,
+    /// One JSON object per quote and line
+    Json,
+}
+
+/**
+ * @gist the header line of the csv format
+**/
+pub fn csv_header() -> &'static str {
+    "source,asset,price,liquidity,change_24h,timestamp,volume_24h,fees_24h,cex_price,basis_bps,block,time,buy_volume,sell_volume,buy_volume_usd,sell_volume_usd,sized_price,aggregator_price,aggregator_bps,simulated_price,reorged,thin,degraded,bid,ask,chain,symbol,labels,lagging"
+}
+
+/**
+ * @gist renders a quote, composites and baskets are followed by their
+ * components in text mode and carry them along in json mode. Raw values are
+ * listed below their quote in text mode and left out of csv. Every format
+ * carries the block and its RFC 3339 time.
+ * @param quote -- the quote to render
+ * @param format -- text, csv or json
+ * @output the rendered lines without a trailing newline
+**/
+pub fn render(quote: &Quote, format: Format) -> String {
+    render_with(quote, format, &TimeStyle::default())
+}
+
+/**
+ * @gist like render, but text mode shows times in the given style. csv and
+ * json stay in UTC.
+ * @param quote -- the quote to render
+ * @param format -- text, csv or json
+ * @param time -- zone and format of the times in text mode
+**/
+pub fn render_with(quote: &Quote, format: Format, time: &TimeStyle) -> String {
+    match format {
+        Format::Text => {
+            let cur = currency(quote);
+            let mut out = match quote.change_24h {
+                Some(change) => format!("[{}] {}: {cur}{} (24h {change:+.2}%)", quote.source, quote.asset, quote.price),
+                None => format!("[{}] {}: {cur}{}", quote.source, quote.asset, quote.price),
+            };
+            if let Some(vwap) = quote.vwap {
+                let _ = write!(out, " (vwap {cur}{vwap})");
+            }
+            if let Some(v) = quote.swaps {
+                let _ = write!(out, " (bought {cur}{:.0}, sold {cur}{:.0})", v.buy_usd, v.sell_usd);
+            }
+            if let (Some(volume), Some(fees)) = (quote.volume_24h, quote.fees_24h) {
+                let _ = write!(out, " (volume 24h {cur}{volume:.0}, fees {cur}{fees:.0})");
+            }
+            if let (Some(cex), Some(basis)) = (quote.cex_price, quote.basis_bps) {
+                let _ = write!(out, " (cex {cur}{cex}, basis {basis:+.1} bps)");
+            }
+            if let (Some(own), Some(aggregator), Some(bps)) = (quote.sized_price, quote.aggregator_price, quote.aggregator_bps) {
+                let _ = write!(out, " (sized {cur}{own}, aggregator {cur}{aggregator}, {bps:+.1} bps)");
+            }
+            if let Some(simulated) = quote.simulated_price {
+                let _ = write!(out, " (simulated {cur}{simulated})");
+            }
+            if let (Some(bid), Some(ask)) = (quote.bid, quote.ask) {
+                let _ = write!(out, " (bid {cur}{bid}, ask {cur}{ask})");
+            }
+            out.push_str(&stamp(quote, time));
+            if quote.reorged {
+                out.push_str(" (reorged)");
+            }
+            if quote.thin {
+                out.push_str(" (thin)");
+            }
+            if quote.degraded {
+                out.push_str(" (degraded)");
+            }
+            if quote.lagging {
+                out.push_str(" (lagging)");
+            }
+            write_raw(&mut out, quote, "    ");
+            for c in &quote.components {
+                let _ = match c.liquidity {
+                    Some(liquidity) => write!(out, "\n    [{}] {}: {}{} (liquidity {}{liquidity:.0})", c.source, c.asset, currency(c), c.price, currency(c)),
+                    None => write!(out, "\n    [{}] {}: {}{}", c.source, c.asset, currency(c), c.price),
+                };
+                out.push_str(&stamp(c, time));
+                write_raw(&mut out, c, "        ");
+            }
+            out
+        }
+        Format::Csv => {
+            let liquidity = quote.liquidity.map(|l| l.to_string()).unwrap_or_default();
+            let change = quote.change_24h.map(|c| c.to_string()).unwrap_or_default();
+            let timestamp = quote.timestamp.map(|t| t.to_string()).unwrap_or_default();
+            let volume = quote.volume_24h.map(|v| v.to_string()).unwrap_or_default();
+            let fees = quote.fees_24h.map(|f| f.to_string()).unwrap_or_default();
+            let cex = quote.cex_price.map(|c| c.to_string()).unwrap_or_default();
+            let basis = quote.basis_bps.map(|b| b.to_string()).unwrap_or_default();
+            let block = quote.block.map(|b| b.to_string()).unwrap_or_default();
+            let time = quote.timestamp.map(clock::rfc3339).unwrap_or_default();
+            let swaps = match quote.swaps {
+                Some(v) => format!("{},{},{},{}", v.buy_token, v.sell_token, v.buy_usd, v.sell_usd),
+                None => ",,,".to_string(),
+            };
+            let show = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+            let aggregator = format!("{},{},{}", show(quote.sized_price), show(quote.aggregator_price), show(quote.aggregator_bps));
+            let chain = quote.chain.map(|c| c.to_string()).unwrap_or_default();
+            let labels: Vec<String> = quote.labels.iter().map(|(k, v)| format!("{k}={v}")).collect();
+            format!("{},{},{},{liquidity},{change},{timestamp},{volume},{fees},{cex},{basis},{block},{time},{swaps},{aggregator},{},{},{},{},{},{},{chain},{},{},{}", quote.source, quote.asset, quote.price, show(quote.simulated_price), quote.reorged, quote.thin, quote.degraded, show(quote.bid), show(quote.ask), quote.symbol.as_deref().unwrap_or_default(), labels.join(";"), quote.lagging)
+        }
+        Format::Json => serde_json::to_value(Versioned::new(quote))
+            .map(|mut value| {
+                add_time(&mut value);
+                value.to_string()
+            })
+            .unwrap_or_else(|e| format!("{{\"error\":\"could not serialize quote: {e}\"}}")),
+    }
+}
+
+/**
+ * @gist renders an asset that could not be priced, in place of its quote
+ * @param asset -- the asset name
+ * @param error -- why it has no quote
+ * @param format -- text, csv or json
+ * @output e.g. "[error] LINK: execution reverted", in csv the source column
+ * is "error" and the others are empty
+**/
+pub fn render_error(asset: &str, error: &str, format: Format) -> String {
+    match format {
+        Format::Text => format!("[error] {asset}: {error}"),
+        Format::Csv => format!("error,{asset}{}", ",".repeat(csv_header().matches(',').count() - 1)),
+        Format::Json => serde_json::json!({ "schema_version": SCHEMA_VERSION, "asset": asset, "error": error }).to_string(),
+    }
+}
+
+/**
+ * @gist the prefix of the amounts of a quote in text mode, $ or the code of
+ * its fiat currency, e.g. "EUR "
+**/
+fn currency(quote: &Quote) -> String {
+    match &quote.currency {
+        Some(code) => format!("{code} "),
+        None => "$".to_string(),
+    }
+}
+
+/**
+ * @gist the block and time of a quote as a text suffix
+ * @output e.g. " (block 19000000, 2024-01-01T00:00:00Z)", empty when the
+ * quote has neither
+**/
+fn stamp(quote: &Quote, time: &TimeStyle) -> String {
+    match (quote.block, quote.timestamp) {
+        (Some(block), Some(t)) => format!(" (block {block}, {})", time.render(t)),
+        (Some(block), None) => format!(" (block {block})"),
+        (None, Some(t)) => format!(" ({})", time.render(t)),
+        (None, None) => String::new(),
+    }
+}
+
+/**
+ * @gist adds the RFC 3339 time next to the unix timestamp of a serialized
+ * quote and its components
+**/
+fn add_time(value: &mut Value) {
+    let Some(object) = value.as_object_mut() else {
+        return;
+    };
+    let index = object.keys().position(|k| k == "timestamp");
+    if let (Some(index), Some(t)) = (index, object.get("timestamp").and_then(Value::as_u64)) {
+        object.shift_insert(index + 1, "time".to_string(), Value::from(clock::rfc3339(t)));
+    }
+    if let Some(components) = object.get_mut("components").and_then(Value::as_array_mut) {
+        components.iter_mut().for_each(add_time);
+    }
+}
+
+/**
+ * @gist appends the hops of a quote's raw values, one line each
+ * @param out -- the rendered quote
+ * @param quote -- the quote, nothing is written without raw values
+ * @param indent -- prefix of every line
+**/
+fn write_raw(out: &mut String, quote: &Quote, indent: &str) {
+    let Some(raw) = &quote.raw else {
+        return;
+    };
+    for (i, ((reserves, decimals), ratio)) in raw.reserves.iter().zip(&raw.decimals).zip(&raw.ratios).enumerate() {
+        let _ = write!(out, "\n{indent}hop {}: reserves {} / {} (decimals {} / {}), ratio {ratio}",
+            i + 1, reserves.0, reserves.1, decimals.0, decimals.1);
+    }
+}
+
+/**
+ * @gist renders values as a unicode sparkline, one block character each
+ * @param values -- the series, oldest first
+ * @output the sparkline, empty for an empty series
+**/
+pub fn sparkline(values: &[f64]) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+
+    values.iter()
+        .map(|v| {
+            if range <= 0.0 || !range.is_finite() {
+                return BARS[3];
+            }
+            let level = ((v - min) / range * (BARS.len() - 1) as f64).round() as usize;
+            BARS[level.min(BARS.len() - 1)]
+        })
+        .collect()
+}
@@ -0,0 +1,91 @@
+/*!
+ *
+ * The stored history as a Grafana datasource, so Grafana charts the file
+ * sink directly. The `--dashboard` server answers the SimpleJSON protocol
+ * under /grafana, which the Infinity datasource speaks as well; add a
+ * JSON datasource with the URL http://127.0.0.1:8080/grafana and every
+ * asset of the history is a target:
+ *
+ * GET  /grafana/         the connection test
+ * POST /grafana/search   the assets in the history
+ * POST /grafana/query    {"range": {"from": "...", "to": "..."}, "intervalMs": 60000,
+ *                         "targets": [{"target": "LINK"}]}
+ *
+ * The history is the first json or csv file sink of the config unless the
+ * [server] table names another file with `history`. Points are averaged
+ * into buckets of the interval Grafana asks for.
+ *
+**/
+
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::history;
+
+#[derive(Deserialize, Debug)]
+pub struct Range {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Target {
+    pub target: String,
+}
+
+/// The body of POST /grafana/query, the fields that are used
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryRequest {
+    pub range: Range,
+    #[serde(default)]
+    pub interval_ms: u64,
+    pub targets: Vec<Target>,
+}
+
+/// One series of the answer
+#[derive(Serialize, Debug)]
+pub struct Series {
+    pub target: String,
+    /// [price, unix time in milliseconds], oldest first
+    pub datapoints: Vec<(f64, u64)>,
+}
+
+/**
+ * @gist the assets of a history file
+ * @output sorted by name
+**/
+pub fn search(path: &Path) -> eyre::Result<Vec<String>> {
+    let assets: BTreeSet<String> = history::rows(path)?.into_iter().map(|row| row.asset).collect();
+    Ok(assets.into_iter().collect())
+}
+
+/**
+ * @gist the series of the targets in the time range
+ * @param path -- the history file
+ * @param request -- what Grafana asks for
+**/
+pub fn query(path: &Path, request: &QueryRequest) -> eyre::Result<Vec<Series>> {
+    let time = |t: &str| DateTime::parse_from_rfc3339(t)
+        .map(|t| t.timestamp().max(0) as u64)
+        .map_err(|e| eyre::eyre!("invalid time {t:?}: {e}"));
+    let (from, to) = (time(&request.range.from)?, time(&request.range.to)?);
+
+    let rows = history::rows(path)?;
+    Ok(request.targets.iter()
+        .map(|target| {
+            let series: Vec<(u64, f64)> = rows.iter()
+                .filter(|row| row.asset == target.target)
+                .filter_map(|row| Some((row.timestamp?, row.price)))
+                .filter(|(t, _)| (from..=to).contains(t))
+                .collect();
+            let datapoints = history::resample(&series, request.interval_ms / 1000).into_iter()
+                .map(|(t, price)| (price, t * 1000))
+                .collect();
+            Series { target: target.target.clone(), datapoints }
+        })
+        .collect())
+}
@@ -0,0 +1,251 @@
+/*!
+ *
+ * Due diligence on a pool before it goes into production feeds. `health`
+ * reads a TOKEN/WETH pair once and grades it:
+ *
+ * uni-price-polling health 0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974
+ *
+ * - liquidity: USDT value of both reserves
+ * - last trade and 24h volume: from the Swap events of the last day
+ * - LP concentration: the share of the LP supply held by its largest
+ *   holder, out of the addresses that received LP tokens in the last
+ *   `--lookback-days`; burned LP tokens (held by 0x0 or 0xdead) count as
+ *   locked, not as a holder
+ * - fee-on-transfer: what a recent buyer received against what the pair
+ *   sent, plus the honeypot checks of `watch-new-pairs`
+ *
+ * Every check scores 0 to 2 and the sum is a grade from A to F. A token
+ * that can't be bought or sold is an F whatever the rest says. Holders that
+ * got their LP tokens before the lookback are not seen, so a low
+ * concentration is a lower bound.
+ *
+**/
+
+use ethers::{
+    providers::Middleware,
+    types::{Address, ValueOrArray, U256},
+};
+use serde::Serialize;
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use crate::risk::{self, Flag, Risk};
+use crate::source::{self, Head};
+use crate::swaps::{self, BLOCK_TIME};
+use crate::uniswap_v2::{self, get_pair_info, get_reserves, IERC20};
+
+/// Blocks per day
+const BLOCKS_PER_DAY: u64 = 86_400 / BLOCK_TIME;
+
+/// Block range of a single eth_getLogs request
+const CHUNK: u64 = 2000;
+
+/// Most LP holders whose balance is read
+const MAX_HOLDERS: usize = 500;
+
+/// Share lost in a buy above which the token counts as taxed, leaves room
+/// for rounding
+const FEE_TOLERANCE: f64 = 0.001;
+
+/// Addresses LP tokens are burned to
+const BURNED: [&str; 2] = ["0x0000000000000000000000000000000000000000", "0x000000000000000000000000000000000000dEaD"];
+
+/// The largest LP holder
+#[derive(Debug, Clone, Serialize)]
+pub struct Holder {
+    pub address: Address,
+    /// Of the LP supply, 0.5 for 50%
+    pub share: f64,
+}
+
+/// What `health` found about a pool
+#[derive(Debug, Clone, Serialize)]
+pub struct Health {
+    pub pool: Address,
+    pub token: Address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    pub block: u64,
+    pub liquidity_usd: f64,
+    /// Seconds since the newest swap, None when there was none in a day
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_trade_age: Option<u64>,
+    /// Seconds since the reserves last changed, by a swap or the LPs
+    pub last_update_age: u64,
+    pub swaps_24h: usize,
+    pub volume_24h_usd: f64,
+    /// None when the pair has no LP supply to speak of
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_holder: Option<Holder>,
+    /// Share of the LP supply that is burned
+    pub burned_share: f64,
+    /// LP holders that were looked at
+    pub holders_seen: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buy_tax: Option<f64>,
+    /// The token takes a fee on transfers
+    pub fee_on_transfer: bool,
+    pub flags: Vec<Flag>,
+    /// Per check, 0 to 2
+    pub scores: Scores,
+    pub grade: char,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Scores {
+    pub liquidity: u8,
+    pub activity: u8,
+    pub volume: u8,
+    pub concentration: u8,
+    pub tax: u8,
+}
+
+impl Scores {
+    /**
+     * @gist the grade of the scores, F for tokens that can't be traded
+    **/
+    fn grade(&self, flags: &[Flag]) -> char {
+        if flags.iter().any(|f| matches!(f, Flag::NoBuy | Flag::NoSell)) {
+            return 'F';
+        }
+        match self.liquidity + self.activity + self.volume + self.concentration + self.tax {
+            9.. => 'A',
+            7..=8 => 'B',
+            5..=6 => 'C',
+            3..=4 => 'D',
+            _ => 'F',
+        }
+    }
+}
+
+/**
+ * @gist reads and grades a TOKEN/WETH pool
+ * @param provider -- used for the calls and eth_getLogs
+ * @param pool -- the pair
+ * @param end_a -- the ETH/USDT pair, to value the pool and its swaps
+ * @param lookback_days -- how far back LP holders are searched
+**/
+pub async fn report<M: Middleware + 'static>(provider: Arc<M>, pool: Address, end_a: &Address, lookback_days: u64) -> eyre::Result<Health> {
+    let head = source::head(provider.as_ref()).await?;
+    let info = get_pair_info(provider.clone(), &pool).await?;
+    let (inverted, token) = info.token().ok_or_else(|| eyre::eyre!("{pool:?} is not a TOKEN/WETH pair"))?;
+    let token = token.clone();
+
+    let (eth, usdt, _) = get_reserves(provider.clone(), end_a).await?;
+    let eth_usd = uniswap_v2::reformat_usd(usdt) / uniswap_v2::reformat_wei(eth);
+    let weth_reserve = if inverted { info.reserve0 } else { info.reserve1 };
+    let liquidity_usd = 2.0 * uniswap_v2::reformat_wei(weth_reserve) * eth_usd;
+
+    let day = head.number.saturating_sub(BLOCKS_PER_DAY)..=head.number;
+    let trades = swaps::trades(provider.clone(), &pool, inverted, token.decimals, day, head, eth_usd).await?;
+    let last_trade_age = trades.iter().map(|t| t.time).max().map(|t| head.timestamp.saturating_sub(t));
+    let volume_24h_usd = trades.iter().map(|t| t.usd).sum();
+
+    let (top_holder, burned_share, holders_seen) = lp_holders(provider.clone(), pool, head, lookback_days * BLOCKS_PER_DAY).await?;
+    let risk = risk::assess(provider, pool, token.address, head.number, FEE_TOLERANCE).await
+        .unwrap_or_else(|e| {
+            eprintln!("could not check the token for taxes: {e}");
+            Risk::default()
+        });
+    let fee_on_transfer = risk.buy_tax.is_some_and(|tax| tax > FEE_TOLERANCE);
+    // Taxes are reported on their own, the other flags are the honeypot checks
+    let flags: Vec<Flag> = risk.flags.into_iter().filter(|f| *f != Flag::HighTax).collect();
+
+    let scores = Scores {
+        liquidity: match liquidity_usd {
+            l if l >= 1_000_000.0 => 2,
+            l if l >= 50_000.0 => 1,
+            _ => 0,
+        },
+        activity: match last_trade_age {
+            Some(age) if age <= 3600 => 2,
+            Some(_) => 1,
+            None => 0,
+        },
+        volume: match volume_24h_usd {
+            v if v >= 100_000.0 => 2,
+            v if v >= 1_000.0 => 1,
+            _ => 0,
+        },
+        concentration: match &top_holder {
+            Some(holder) if holder.share <= 0.5 => 2,
+            Some(holder) if holder.share <= 0.9 => 1,
+            Some(_) => 0,
+            // Everything burned is as spread out as it gets
+            None if burned_share > 0.0 => 2,
+            None => 0,
+        },
+        tax: match (risk.buy_tax, fee_on_transfer) {
+            (_, true) => 0,
+            (Some(_), false) => 2,
+            // No recent buy to measure
+            (None, _) => 1,
+        },
+    };
+    Ok(Health {
+        pool,
+        token: token.address,
+        symbol: token.symbol,
+        block: head.number,
+        liquidity_usd,
+        last_trade_age,
+        last_update_age: head.timestamp.saturating_sub(info.last_update as u64),
+        swaps_24h: trades.len(),
+        volume_24h_usd,
+        top_holder,
+        burned_share,
+        holders_seen,
+        buy_tax: risk.buy_tax,
+        fee_on_transfer,
+        grade: scores.grade(&flags),
+        flags,
+        scores,
+    })
+}
+
+/**
+ * @gist the largest holder and the burned share of the LP supply, out of
+ * the recipients of LP transfers in the lookback
+ * @output the holder, the burned share and the number of holders read
+**/
+async fn lp_holders<M: Middleware + 'static>(provider: Arc<M>, pool: Address, head: Head, lookback: u64) -> eyre::Result<(Option<Holder>, f64, usize)> {
+    let lp = IERC20::new(pool, provider);
+    let supply = lp.total_supply().call().await
+        .map_err(|e| eyre::eyre!("could not read the LP supply of {pool:?}: {e}"))?;
+    if supply.is_zero() {
+        return Ok((None, 0.0, 0));
+    }
+    let share = |balance: U256| balance.as_u128() as f64 / supply.as_u128() as f64;
+
+    let burned: Vec<Address> = BURNED.iter().map(|a| a.parse().expect("burn addresses are valid")).collect();
+    let mut recipients = BTreeSet::new();
+    let mut lo = head.number.saturating_sub(lookback);
+    while lo <= head.number {
+        let hi = (lo + CHUNK - 1).min(head.number);
+        // The generated filter matches the event of every contract, not just this one
+        let filter = lp.transfer_filter().address(ValueOrArray::Value(pool)).from_block(lo).to_block(hi);
+        for transfer in filter.query().await? {
+            recipients.insert(transfer.to);
+        }
+        lo = hi + 1;
+    }
+
+    let mut burned_share = 0.0;
+    for address in &burned {
+        burned_share += share(lp.balance_of(*address).call().await.unwrap_or_default());
+    }
+    // The pair holds LP tokens only while they are burned for their reserves
+    let holders: Vec<Address> = recipients.into_iter()
+        .filter(|a| !burned.contains(a) && *a != pool)
+        .take(MAX_HOLDERS)
+        .collect();
+    let mut top: Option<Holder> = None;
+    for address in &holders {
+        let held = share(lp.balance_of(*address).call().await?);
+        if top.as_ref().is_none_or(|t| held > t.share) {
+            top = Some(Holder { address: *address, share: held });
+        }
+    }
+    Ok((top.filter(|t| t.share > 0.0), burned_share, holders.len()))
+}
@@ -0,0 +1,231 @@
+/*!
+ *
+ * Reading back the quote history written by the file sinks, either csv or
+ * one JSON object per line. Rotated files compressed to .gz or .zst are
+ * read as well.
+ *
+**/
+
+use eyre::{bail, eyre};
+use flate2::read::MultiGzDecoder;
+use serde::Deserialize;
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::format;
+use crate::source::Quote;
+
+/// A stored quote, the fields replay and charts need
+#[derive(Deserialize, Debug, Clone)]
+pub struct Row {
+    pub source: Option<String>,
+    pub asset: String,
+    pub price: f64,
+    pub timestamp: Option<u64>,
+    pub block: Option<u64>,
+    pub liquidity: Option<f64>,
+    /// Replaces the row of the same source, asset and block
+    #[serde(default)]
+    pub reorged: bool,
+}
+
+impl Row {
+    /**
+     * @gist the stored quote as a quote again, without the fields that
+     * aren't read back
+    **/
+    pub fn quote(&self) -> Quote {
+        let mut quote = Quote::new(self.source.as_deref().unwrap_or("history"), &self.asset, self.price);
+        quote.timestamp = self.timestamp;
+        quote.block = self.block;
+        quote.liquidity = self.liquidity;
+        quote.reorged = self.reorged;
+        quote
+    }
+}
+
+/**
+ * @gist opens a history file, decompressing .gz and .zst files on the fly
+ * @param path -- the file written by a file sink, or a rotated one
+**/
+pub fn open(path: &Path) -> eyre::Result<Box<dyn BufRead>> {
+    let file = File::open(path).map_err(|e| eyre!("could not read {}: {e}", path.display()))?;
+    let reader: Box<dyn Read> = match extension(path).as_deref() {
+        // Rotated and exported files may hold several members
+        Some("gz") => Box::new(MultiGzDecoder::new(file)),
+        Some("zst") => Box::new(zstd::Decoder::new(file)?),
+        _ => Box::new(file),
+    };
+    Ok(Box::new(BufReader::new(reader)))
+}
+
+/**
+ * @gist whether a history file is csv, judged by its extension without
+ * the compression, e.g. quotes.csv.gz
+**/
+pub fn is_csv(path: &Path) -> bool {
+    let inner = match extension(path).as_deref() {
+        Some("gz" | "zst") => path.file_stem().map(Path::new),
+        _ => Some(path),
+    };
+    inner.and_then(extension).as_deref() == Some("csv")
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|e| e.to_string_lossy().to_ascii_lowercase())
+}
+
+/**
+ * @gist reads the (unix time, price) series of an asset from a history file,
+ * csv files (see is_csv) are read as csv, anything else as json lines
+ * @param path -- the file written by a file sink
+ * @param asset -- name of the asset
+ * @output the series, oldest first
+**/
+pub fn read(path: &Path, asset: &str) -> eyre::Result<Vec<(u64, f64)>> {
+    Ok(rows(path)?.into_iter()
+        .filter(|row| row.asset == asset)
+        .filter_map(|row| Some((row.timestamp?, row.price)))
+        .collect())
+}
+
+/**
+ * @gist whether a stored line is a row a reorged quote replaces, one of the
+ * same source, asset and block read before it, an earlier correction too
+ * @param correction -- the quote read again, marked reorged
+ * @param line -- a line of a history file
+ * @param csv -- the file is csv with the header of format::csv_header
+**/
+pub fn replaces(correction: &Quote, line: &str, csv: bool) -> bool {
+    let row = match csv {
+        true => read_csv(&format!("{}\n{line}", format::csv_header())).ok().and_then(|rows| rows.into_iter().next()),
+        false => serde_json::from_str::<Row>(line).ok(),
+    };
+    row.is_some_and(|row| {
+        row.source.as_deref() == Some(correction.source.as_str())
+            && row.asset == correction.asset
+            && row.block.is_some() && row.block == correction.block
+    })
+}
+
+/**
+ * @gist reads every stored quote of a history file, see read
+ * @param path -- the file written by a file sink
+ * @output the quotes of all assets, oldest first, those without a time
+ * first
+**/
+pub fn rows(path: &Path) -> eyre::Result<Vec<Row>> {
+    let mut text = String::new();
+    open(path)?.read_to_string(&mut text)
+        .map_err(|e| eyre!("could not read {}: {e}", path.display()))?;
+
+    let mut rows = if is_csv(path) { read_csv(&text)? } else { read_json(&text)? };
+
+    // Rows read from a block a reorg orphaned give way to their re-quote
+    let replaced: HashSet<(Option<String>, String, u64)> = rows.iter()
+        .filter(|row| row.reorged)
+        .filter_map(|row| Some((row.source.clone(), row.asset.clone(), row.block?)))
+        .collect();
+    rows.retain(|row| row.reorged || row.block.is_none_or(|block| !replaced.contains(&(row.source.clone(), row.asset.clone(), block))));
+
+    rows.sort_by_key(|row| row.timestamp);
+    Ok(rows)
+}
+
+fn read_json(text: &str) -> eyre::Result<Vec<Row>> {
+    text.lines().enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(n, line)| serde_json::from_str(line).map_err(|e| eyre!("line {}: {e}", n + 1)))
+        .collect()
+}
+
+fn read_csv(text: &str) -> eyre::Result<Vec<Row>> {
+    let mut lines = text.lines();
+    let header: Vec<&str> = lines.next().unwrap_or_default().split(',').collect();
+    let column = |name: &str| header.iter().position(|h| *h == name);
+
+    let (Some(asset_col), Some(price_col), Some(time_col)) = (column("asset"), column("price"), column("timestamp")) else {
+        bail!("csv history needs asset, price and timestamp columns");
+    };
+    let (source_col, block_col, liquidity_col, reorged_col) = (column("source"), column("block"), column("liquidity"), column("reorged"));
+
+    let mut rows = Vec::new();
+    for (n, line) in lines.enumerate() {
+        // A restarted sink with a new layout writes a new header into the file
+        if line.starts_with("source,") || line.trim().is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').collect();
+        let field = |col: Option<usize>| col.and_then(|c| fields.get(c)).filter(|f| !f.is_empty());
+        let parse_err = || eyre!("line {}: invalid row", n + 2);
+        rows.push(Row {
+            source: field(source_col).map(|s| s.to_string()),
+            asset: field(Some(asset_col)).ok_or_else(parse_err)?.to_string(),
+            price: field(Some(price_col)).ok_or_else(parse_err)?.parse().map_err(|_| parse_err())?,
+            timestamp: field(Some(time_col)).and_then(|t| t.parse().ok()),
+            block: field(block_col).and_then(|b| b.parse().ok()),
+            liquidity: field(liquidity_col).and_then(|l| l.parse().ok()),
+            reorged: field(reorged_col).is_some_and(|r| *r == "true"),
+        });
+    }
+    Ok(rows)
+}
+
+/**
+ * @gist averages the series into buckets of `resolution` seconds
+ * @param series -- (unix time, price) pairs, oldest first
+ * @param resolution -- bucket size in seconds, 0 keeps every point
+ * @output one point per non empty bucket, at the start of the bucket
+**/
+pub fn resample(series: &[(u64, f64)], resolution: u64) -> Vec<(u64, f64)> {
+    if resolution == 0 {
+        return series.to_vec();
+    }
+
+    let mut out: Vec<(u64, f64, usize)> = Vec::new();
+    for &(t, price) in series {
+        let bucket = t - t % resolution;
+        match out.last_mut() {
+            Some((b, sum, n)) if *b == bucket => {
+                *sum += price;
+                *n += 1;
+            }
+            _ => out.push((bucket, price, 1)),
+        }
+    }
+    out.into_iter().map(|(b, sum, n)| (b, sum / n as f64)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::format::Format;
+
+    fn quote(block: u64, reorged: bool) -> Quote {
+        let mut quote = Quote::new("UNI V2", "LINK", 10.0);
+        quote.block = Some(block);
+        quote.timestamp = Some(1_700_000_000);
+        quote.reorged = reorged;
+        quote
+    }
+
+    #[test]
+    fn finds_the_rows_a_correction_replaces() {
+        let correction = quote(100, true);
+        for (format, csv) in [(Format::Json, false), (Format::Csv, true)] {
+            let line = |q: &Quote| format::render(q, format);
+            assert!(replaces(&correction, &line(&quote(100, false)), csv));
+            assert!(!replaces(&correction, &line(&quote(101, false)), csv));
+            assert!(replaces(&correction, &line(&quote(100, true)), csv));
+            let mut other = quote(100, false);
+            other.asset = "DAI".to_string();
+            assert!(!replaces(&correction, &line(&other), csv));
+        }
+        assert!(!replaces(&correction, format::csv_header(), true));
+        assert!(!replaces(&correction, "", false));
+    }
+}
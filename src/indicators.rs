@@ -0,0 +1,258 @@
+/*!
+ *
+ * Streaming indicators over a series of prices, fed one sample per poll.
+ *
+**/
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Simple moving average over the last `period` samples
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Sma {
+    period: usize,
+    values: VecDeque<f64>,
+    sum: f64,
+}
+
+impl Sma {
+    /**
+     * @gist creates an empty average
+     * @param period -- number of samples averaged, at least 1
+    **/
+    pub fn new(period: usize) -> Self {
+        Self { period: period.max(1), values: VecDeque::new(), sum: 0.0 }
+    }
+
+    /**
+     * @gist adds a sample
+     * @output the average, None until `period` samples were seen
+    **/
+    pub fn push(&mut self, value: f64) -> Option<f64> {
+        self.values.push_back(value);
+        self.sum += value;
+        if self.values.len() > self.period {
+            self.sum -= self.values.pop_front().unwrap_or_default();
+        }
+        self.value()
+    }
+
+    /**
+     * @gist the current average, None until `period` samples were seen
+    **/
+    pub fn value(&self) -> Option<f64> {
+        (self.values.len() == self.period).then(|| self.sum / self.period as f64)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Cross {
+    /// The fast average moved above the slow one
+    Above,
+    /// The fast average moved below the slow one
+    Below,
+}
+
+/// Crossovers of a fast and a slow moving average
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Crossover {
+    fast: Sma,
+    slow: Sma,
+    /// Whether fast was above slow after the previous sample
+    above: Option<bool>,
+}
+
+impl Crossover {
+    /**
+     * @gist creates the pair of averages
+     * @param fast -- period of the fast average
+     * @param slow -- period of the slow average
+    **/
+    pub fn new(fast: usize, slow: usize) -> Self {
+        Self { fast: Sma::new(fast), slow: Sma::new(slow), above: None }
+    }
+
+    /**
+     * @gist adds a sample to both averages
+     * @output the cross this sample caused, if any. The first sample with
+     * both averages defined only sets the reference.
+    **/
+    pub fn push(&mut self, value: f64) -> Option<Cross> {
+        // Both averages need every sample, so push before bailing out
+        let fast = self.fast.push(value);
+        let slow = self.slow.push(value);
+        let above = fast? > slow?;
+        match self.above.replace(above) {
+            Some(false) if above => Some(Cross::Above),
+            Some(true) if !above => Some(Cross::Below),
+            _ => None,
+        }
+    }
+}
+
+/// Bollinger bands, the mean of a series plus and minus k standard deviations
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bands {
+    pub lower: f64,
+    pub middle: f64,
+    pub upper: f64,
+}
+
+impl Bands {
+    /**
+     * @gist computes the bands of a series
+     * @param values -- the prices, usually the last 20 samples
+     * @param k -- width of the bands in standard deviations, usually 2
+     * @output None for an empty series
+    **/
+    pub fn of(values: &[f64], k: f64) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let n = values.len() as f64;
+        let middle = values.iter().sum::<f64>() / n;
+        let deviation = (values.iter().map(|v| (v - middle).powi(2)).sum::<f64>() / n).sqrt();
+        Some(Self { lower: middle - k * deviation, middle, upper: middle + k * deviation })
+    }
+}
+
+/// Wilder's relative strength index, 0 to 100, kept up to date one sample
+/// at a time
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Rsi {
+    period: usize,
+    last: Option<f64>,
+    /// Changes seen, counted up to `period`
+    seen: usize,
+    /// Average gain and loss per change, sums while the first `period`
+    /// changes come in
+    gain: f64,
+    loss: f64,
+}
+
+impl Rsi {
+    /**
+     * @gist creates an empty index
+     * @param period -- smoothing period, usually 14
+    **/
+    pub fn new(period: usize) -> Self {
+        Self { period, ..Self::default() }
+    }
+
+    /**
+     * @gist the smoothing period it was created with
+    **/
+    pub fn period(&self) -> usize {
+        self.period
+    }
+
+    /**
+     * @gist adds a sample
+     * @output the index, None until `period` changes were seen
+    **/
+    pub fn push(&mut self, value: f64) -> Option<f64> {
+        // The first sample only sets the reference
+        let last = self.last.replace(value)?;
+        if self.period == 0 {
+            return None;
+        }
+        let change = value - last;
+        let period = self.period as f64;
+        if self.seen < self.period {
+            self.gain += change.max(0.0);
+            self.loss += (-change).max(0.0);
+            self.seen += 1;
+            // The seed is the simple average of the first changes
+            if self.seen == self.period {
+                self.gain /= period;
+                self.loss /= period;
+            }
+        } else {
+            self.gain = (self.gain * (period - 1.0) + change.max(0.0)) / period;
+            self.loss = (self.loss * (period - 1.0) + (-change).max(0.0)) / period;
+        }
+        self.value()
+    }
+
+    /**
+     * @gist the current index, None until `period` changes were seen, 100
+     * for a series that never fell
+    **/
+    pub fn value(&self) -> Option<f64> {
+        if self.period == 0 || self.seen < self.period {
+            return None;
+        }
+        if self.loss == 0.0 {
+            return Some(if self.gain == 0.0 { 50.0 } else { 100.0 });
+        }
+        Some(100.0 - 100.0 / (1.0 + self.gain / self.loss))
+    }
+}
+
+/**
+ * @gist Wilder's relative strength index of a whole series, 0 to 100
+ * @param values -- the prices, oldest first
+ * @param period -- smoothing period, usually 14
+ * @output None until the series holds `period` changes, 100 for a series
+ * that never fell
+**/
+pub fn rsi(values: &[f64], period: usize) -> Option<f64> {
+    let mut rsi = Rsi::new(period);
+    for value in values {
+        rsi.push(*value);
+    }
+    rsi.value()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bands_of_a_series() {
+        // Mean 5, standard deviation 2
+        let bands = Bands::of(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0], 2.0).unwrap();
+        assert_eq!(bands, Bands { lower: 1.0, middle: 5.0, upper: 9.0 });
+        let narrow = Bands::of(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0], 0.5).unwrap();
+        assert_eq!((narrow.lower, narrow.upper), (4.0, 6.0));
+    }
+
+    #[test]
+    fn bands_of_a_flat_series_have_no_width() {
+        assert_eq!(Bands::of(&[3.0; 20], 2.0), Some(Bands { lower: 3.0, middle: 3.0, upper: 3.0 }));
+        assert_eq!(Bands::of(&[], 2.0), None);
+    }
+
+    #[test]
+    fn rsi_seeds_then_smooths() {
+        let mut rsi = Rsi::new(2);
+        assert_eq!(rsi.push(1.0), None);
+        assert_eq!(rsi.push(2.0), None);
+        // Average gain and loss of 0.5 each
+        assert_eq!(rsi.push(1.0), Some(50.0));
+        // Gain (0.5 + 2) / 2, loss (0.5 + 0) / 2, so RS is 5
+        let value = rsi.push(3.0).unwrap();
+        assert!((value - 100.0 * 5.0 / 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rsi_of_one_way_series() {
+        assert_eq!(rsi(&[1.0, 2.0, 3.0, 4.0], 3), Some(100.0));
+        assert_eq!(rsi(&[4.0, 3.0, 2.0, 1.0], 3), Some(0.0));
+        assert_eq!(rsi(&[2.0; 5], 3), Some(50.0));
+        assert_eq!(rsi(&[1.0, 2.0, 3.0], 3), None);
+        assert_eq!(rsi(&[1.0, 2.0, 3.0], 0), None);
+    }
+
+    #[test]
+    fn rsi_streams_like_the_whole_series() {
+        let prices: Vec<f64> = (0..60).map(|i| 100.0 + (i as f64 * 0.7).sin() * 5.0 + i as f64 * 0.1).collect();
+        let mut streamed = Rsi::new(14);
+        for (i, price) in prices.iter().enumerate() {
+            assert_eq!(streamed.push(*price), rsi(&prices[..=i], 14));
+        }
+        let value = streamed.value().unwrap();
+        assert!(value > 0.0 && value < 100.0);
+    }
+}
@@ -0,0 +1,129 @@
+/*!
+ *
+ * What a quote is about beyond its asset and source, for deployments that
+ * watch several chains and DEXes from one place. Every quote carries the
+ * id of the chain it was read on, quotes of pools the symbol of TOKEN, and
+ * a [[pool]] can add labels of its own:
+ *
+ * [[pool]]
+ * address = "0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974"
+ * label = "LINK"
+ *
+ * [pool.labels]
+ * desk = "eu"
+ * tier = "core"
+ *
+ * They are fields of the json output, the chain, symbol and labels
+ * columns of csv (labels as desk=eu;tier=core) and labels of the
+ * Prometheus metrics of the asset. The DEX is the source of the quote.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{providers::Middleware, types::Address};
+use tokio::sync::OnceCell;
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::source::{Asset, PriceSource, Quote};
+use crate::uniswap_v2::{get_pair_info, get_token};
+
+/// Label names the quotes use themselves
+const RESERVED: [&str; 7] = ["asset", "source", "chain", "symbol", "side", "le", "instance"];
+
+/**
+ * @gist checks the labels of a [[pool]], they have to work as Prometheus
+ * labels and csv fields
+ * @param name -- the pool, for the error
+**/
+pub fn validate(name: &str, labels: &BTreeMap<String, String>) -> eyre::Result<()> {
+    for (key, value) in labels {
+        let valid = key.chars().enumerate().all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+        if key.is_empty() || !valid || key.starts_with("__") {
+            eyre::bail!("[[pool]] {name}: label {key:?} is not a valid label name, use letters, digits and _");
+        }
+        if RESERVED.contains(&key.as_str()) {
+            eyre::bail!("[[pool]] {name}: label {key:?} is taken by the quotes themselves");
+        }
+        if value.contains([',', ';', '=', '"', '\\', '\n']) {
+            eyre::bail!("[[pool]] {name}: the value of label {key} can't hold , ; = \" \\ or line breaks");
+        }
+    }
+    Ok(())
+}
+
+/// Where the symbol of a feed comes from
+#[derive(Debug, Clone, Copy)]
+pub enum Symbol {
+    /// TOKEN of the TOKEN/WETH pair the asset address points to
+    Pair,
+    /// This token, the first of a route
+    Token(Address),
+}
+
+/// Sets the chain, symbol and labels of the quotes of a source
+pub struct Labeled<M> {
+    inner: Arc<dyn PriceSource>,
+    provider: Arc<M>,
+    chain: u64,
+    symbol_of: Option<Symbol>,
+    /// Looked up on the first quote, None when there is none
+    symbol: OnceCell<Option<String>>,
+    labels: BTreeMap<String, String>,
+}
+
+impl<M: Middleware + 'static> Labeled<M> {
+    /**
+     * @gist wraps a source
+     * @param provider -- used to look the symbol up
+     * @param chain -- the chain id of the provider
+    **/
+    pub fn new(inner: Arc<dyn PriceSource>, provider: Arc<M>, chain: u64) -> Self {
+        Self { inner, provider, chain, symbol_of: None, symbol: OnceCell::new(), labels: BTreeMap::new() }
+    }
+
+    /**
+     * @gist adds the symbol of TOKEN to the quotes
+    **/
+    pub fn with_symbol(mut self, symbol: Symbol) -> Self {
+        self.symbol_of = Some(symbol);
+        self
+    }
+
+    /**
+     * @gist adds the labels of the [[pool]] to the quotes
+    **/
+    pub fn with_labels(mut self, labels: BTreeMap<String, String>) -> Self {
+        self.labels = labels;
+        self
+    }
+
+    async fn symbol(&self, asset: &Asset) -> Option<String> {
+        let symbol_of = self.symbol_of?;
+        self.symbol.get_or_init(|| async {
+            // A token without a symbol has its quotes go out without one
+            match symbol_of {
+                Symbol::Pair => get_pair_info(self.provider.clone(), &asset.address).await.ok()
+                    .and_then(|info| info.token().and_then(|(_, token)| token.symbol.clone())),
+                Symbol::Token(token) => get_token(self.provider.clone(), token).await.ok()
+                    .and_then(|token| token.symbol),
+            }
+        }).await.clone()
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> PriceSource for Labeled<M> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let mut quote = self.inner.quote(asset).await?;
+        quote.chain = Some(self.chain);
+        quote.symbol = self.symbol(asset).await;
+        quote.labels.extend(self.labels.clone());
+        Ok(quote)
+    }
+}
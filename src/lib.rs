@@ -0,0 +1,89 @@
+/*!
+ *
+ * Library side of uni-price-polling. The binary is a thin command line
+ * wrapper around these modules; other crates can implement `PriceSource`
+ * for their own backends and reuse the poller with them.
+ *
+**/
+
+pub mod aggregator;
+pub mod alerts;
+pub mod aliases;
+pub mod amm;
+pub mod archive;
+pub mod auth;
+pub mod backfill;
+pub mod basket;
+pub mod batch;
+pub mod bench;
+pub mod breaker;
+pub mod cache;
+pub mod cex;
+pub mod chart;
+pub mod check;
+pub mod checksum;
+pub mod clock;
+pub mod composite;
+pub mod confidence;
+pub mod config;
+pub mod curve;
+pub mod dashboard;
+pub mod depeg;
+pub mod etherscan;
+pub mod exit;
+pub mod export;
+pub mod fallback;
+#[cfg(feature = "cdylib")]
+pub mod ffi;
+pub mod fiat;
+pub mod filter;
+pub mod format;
+pub mod grafana;
+pub mod health;
+pub mod history;
+pub mod indicators;
+pub mod labels;
+pub mod metrics;
+pub mod namespace;
+pub mod notify;
+pub mod oracle;
+pub mod pairs;
+pub mod poller;
+pub mod pools;
+pub mod portfolio;
+pub mod precision;
+pub mod provider;
+pub mod queue;
+pub mod rate;
+pub mod ratio;
+pub mod rebase;
+pub mod reorg;
+pub mod repl;
+pub mod risk;
+pub mod rotate;
+pub mod route;
+pub mod router;
+pub mod sandwich;
+pub mod schedule;
+pub mod schema;
+pub mod secrets;
+pub mod simulate;
+pub mod sink;
+pub mod source;
+pub mod state;
+pub mod stream;
+pub mod subgraph;
+pub mod swaps;
+pub mod systemd;
+pub mod thin;
+pub mod tls;
+pub mod trace;
+pub mod triarb;
+pub mod uniswap_v2;
+pub mod v3;
+pub mod v4;
+pub mod vault;
+pub mod window;
+
+pub use poller::Feed;
+pub use source::{Asset, PriceSource, Quote};
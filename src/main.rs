@@ -1,102 +1,2784 @@
-/**
+/*!
  *
- * This program will return the price of an asset on uniswap using a 2-hop 
+ * This program will return the price of an asset on uniswap using a 2-hop
  * strategy. We're basically doing the following calculation:
  *
  * TOKEN / ETH * ETH / USDT = TOKEN / USDT
  *
- * TOKEN is the ERC20 token you want to know the price of. 
+ * TOKEN is the ERC20 token you want to know the price of.
  *
  * To use the program you need to input the address of the UNI V2 pool address
  * of the TOKEN / ETH pool.
- * 
+ *
 **/
 
 use ethers::{
-    prelude::{abigen, ContractError},
-    providers::{Http, Provider},
-    types::Address,
+    providers::Middleware,
+    types::{Address, H256},
 };
 
-use std::future::Future;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::Arc;
-use clap::Parser;
+use std::time::{Duration, Instant};
+use clap::{Parser, Subcommand};
+use tokio::io::{AsyncBufReadExt, BufReader};
 
-const RPC_URL: &str = "https://eth.llamarpc.com";
+use uni_price_polling::{
+    aggregator::{Aggregator, WithAggregator},
+    aliases,
+    alerts::{Alert, AlertEngine},
+    archive,
+    backfill,
+    basket::Basket,
+    batch::ReserveBatch,
+    bench,
+    cache::{self, ReserveCache},
+    breaker::Breaker,
+    cex::{Cex, WithCex},
+    chart,
+    check::{self, Level},
+    checksum,
+    clock,
+    composite::Composite,
+    config::{self, Config},
+    curve::{self, Side, WithBidAsk},
+    dashboard::{self, AdHoc, Board},
+    depeg,
+    etherscan::{self, Etherscan},
+    exit::{self, Failure, Kind},
+    export::{self, Compression},
+    fallback::{Fallback, OffChain},
+    fiat::{self, InFiat, Rates},
+    filter::TokenFilter,
+    format::{self, Format},
+    health,
+    history,
+    labels::{self, Labeled, Symbol},
+    metrics::{self, Metrics},
+    namespace::{self, Namespace},
+    notify::Notifier,
+    oracle::Oracle,
+    pairs::{AddCriteria, Candidate, NewPair, PairWatcher},
+    poller::{self, Cadence, Feed},
+    pools,
+    portfolio,
+    precision::{Rounded, Rounding},
+    provider::{self, BlockTag, Pin},
+    queue::Overflow,
+    rate::Rated,
+    ratio::{self, Leg},
+    rebase::{self, PerShare},
+    reorg::Reorgs,
+    repl::{self, Pairs},
+    risk,
+    router::WithRouter,
+    schedule::Schedule,
+    simulate::{Event, SimulateConfig, Simulator},
+    sink::{self, Dispatcher, Sink, SinkKind, Stdout},
+    state::{self, State},
+    stream,
+    subgraph::{Subgraph, WithVolume},
+    swaps::WithSwaps,
+    thin::MinLiquidity,
+    triarb::Cycle,
+    trace,
+    uniswap_v2::{self, UniswapV2},
+    v3::{self, UniswapV3},
+    v4::{self, UniswapV4},
+    vault::Vault,
+    Asset,
+    PriceSource,
+    Quote,
+};
 
-abigen!(
-    IUniswapV2Pair,
-    "[function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)]"
-);
+const RPC_URL: &str = "https://eth.llamarpc.com";
 
 #[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true)]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// The TOKEN/ETH pool, an address or a name from the address book, or -
+    /// to read one per line from stdin
+    #[arg(long, required_unless_present_any = ["config", "v4"])]
+    pub pool: Option<String>,
+
+    /// Also price the Uniswap V4 TOKEN/ETH pool given by --pool-id
+    #[arg(long, requires = "pool_id")]
+    pub v4: bool,
+
+    /// Id of the V4 pool, the hash of its pool key
+    #[arg(long, value_name = "ID", requires = "v4")]
+    pub pool_id: Option<H256>,
+
+    /// Keep polling every INTERVAL seconds instead of exiting after one quote
+    #[arg(long, value_name = "INTERVAL")]
+    pub interval: Option<u64>,
+
+    /// Keep polling whenever a cron expression matches, e.g. "0 */5 * * * *"
+    /// for every five minutes on the minute, in UTC
+    #[arg(long, value_name = "CRON", conflicts_with = "interval")]
+    pub schedule: Option<Schedule>,
+
+    /// Keep quoting the --pool route after every block that moves it, from
+    /// its Sync events over a WebSocket, e.g. wss://node.example/ws
+    #[arg(long, value_name = "URL", requires = "pool", conflicts_with_all = ["interval", "schedule"])]
+    pub ws: Option<String>,
+
+    /// Print only the price of every quote, for scripts
+    #[arg(long, short, conflicts_with_all = ["interval", "schedule"])]
+    pub quiet: bool,
+
+    /// Fail with exit code 5 when the reserves of a V2 pool were last
+    /// updated more than SECONDS before the block read at
+    #[arg(long, value_name = "SECONDS", conflicts_with_all = ["interval", "schedule"])]
+    pub max_age: Option<u64>,
+
+    /// Fail the quotes of pools holding less than USD, exit code 6, or
+    /// price them off-chain with a [fallback]
+    #[arg(long, value_name = "USD")]
+    pub min_liquidity_usd: Option<f64>,
+
+    /// Keep the quotes under --min-liquidity-usd, marked thin
+    #[arg(long, requires = "min_liquidity_usd")]
+    pub flag_thin: bool,
+
+    /// Skip the feeds that have not answered MS milliseconds into a cycle
+    /// and mark the quotes of the others degraded
+    #[arg(long, value_name = "MS")]
+    pub deadline_ms: Option<u64>,
+
+    /// In watch mode, check the blocks quoted within the last BLOCKS for
+    /// reorgs after every cycle and quote an orphaned block again
+    #[arg(long, value_name = "BLOCKS", default_value_t = 0)]
+    pub reorg_depth: u64,
+
+    /// In watch mode, warn and mark the quotes lagging when the newest
+    /// block of a cycle is more than SECONDS older than the local clock
+    #[arg(long, value_name = "SECONDS")]
+    pub max_block_lag: Option<u64>,
+
+    /// Talk to a local node over its IPC socket instead of the public HTTP RPC
+    #[arg(long, value_name = "PATH", global = true)]
+    pub ipc: Option<PathBuf>,
+
+    /// Read from this RPC for --quorum instead of the public one, once per
+    /// endpoint
+    #[arg(long, value_name = "URL", global = true, requires = "quorum", conflicts_with = "ipc")]
+    pub quorum_rpc: Vec<String>,
+
+    /// Only take the answers N of the --quorum-rpc endpoints agree on
+    #[arg(long, value_name = "N", global = true, requires = "quorum_rpc")]
+    pub quorum: Option<usize>,
+
+    /// Read every quote at this block instead of the head of the chain,
+    /// safe and finalized blocks are not reorged
+    #[arg(long, value_enum, value_name = "TAG", global = true, default_value_t = BlockTag::Latest)]
+    pub at: BlockTag,
+
+    /// Most RPC requests in flight at once, 0 for no limit
+    #[arg(long, value_name = "N", global = true, default_value_t = 16)]
+    pub max_concurrent_requests: usize,
+
+    /// Convert the quotes from USD into a fiat currency, e.g. EUR, GBP or SEK
+    #[arg(long, value_name = "CODE", value_parser = fiat::parse_code)]
+    pub fiat: Option<String>,
+
+    /// Round the prices in the output to N significant figures, a [[pool]]
+    /// with sig_figs or tick rounds its own
+    #[arg(long, value_name = "N")]
+    pub sig_figs: Option<u32>,
+
+    /// API serving the ECB reference rates for --fiat
+    #[arg(long, value_name = "URL", default_value = fiat::DEFAULT_URL)]
+    pub fiat_url: String,
+
+    /// Quote the [[v3]] paths of the config with QuoterV2 for their amount
+    /// instead of multiplying the slot0 prices of the pools
+    #[arg(long)]
+    pub v3_quoter: bool,
+
+    /// Sell USDT worth of TOKEN through the V2 router with an eth_call
+    /// every poll and add the price the sale realizes
+    #[arg(long, value_name = "USDT")]
+    pub simulate: Option<f64>,
+
+    /// Send the simulated sale as a swap from this holder, e.g. an
+    /// impersonated account on a fork
+    #[arg(long, value_name = "ADDRESS", requires = "simulate", value_parser = checksum::parse_arg)]
+    pub simulate_from: Option<Address>,
+
+    /// Add the prices selling and buying USDT worth of TOKEN through the
+    /// route would get, fees and price impact included
+    #[arg(long, value_name = "USDT")]
+    pub bid_ask: Option<f64>,
+
+    /// Read the reserves of all pools with multicalls of N pools each
+    /// instead of a call per pool, 0 turns batching off
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub multicall_chunk: usize,
+
+    /// Serve Prometheus metrics of the RPC requests and quotes at
+    /// http://ADDR/metrics in watch mode, e.g. 127.0.0.1:9100
+    #[arg(long, value_name = "ADDR")]
+    pub metrics: Option<std::net::SocketAddr>,
+
+    /// Push the metrics of watch mode to a Prometheus Pushgateway group
+    /// instead, e.g. http://pushgateway:9091/metrics/job/uni_price_polling
+    #[arg(long, value_name = "URL")]
+    pub metrics_push: Option<String>,
+
+    /// Seconds between the pushes of --metrics-push
+    #[arg(long, value_name = "SECONDS", default_value_t = 15, requires = "metrics_push")]
+    pub metrics_push_interval: u64,
+
+    /// Serve a web dashboard of the live prices and the alerts at
+    /// http://ADDR/ in watch mode, e.g. 127.0.0.1:8080
+    #[arg(long, value_name = "ADDR")]
+    pub dashboard: Option<std::net::SocketAddr>,
+
+    /// Answer getReserves() calls of the watched V2 pools at http://ADDR/
+    /// from the last cycle in watch mode and pass other JSON-RPC requests
+    /// on, e.g. 127.0.0.1:8546
+    #[arg(long, value_name = "ADDR")]
+    pub cache_rpc: Option<std::net::SocketAddr>,
+
+    /// Log the RPC request count, error rate and latency every SECONDS in
+    /// watch mode, 0 turns it off
+    #[arg(long, value_name = "SECONDS", default_value_t = 300)]
+    pub rpc_summary: u64,
+
+    /// Export a trace of every poll cycle in watch mode to an OTLP/HTTP
+    /// collector, e.g. http://localhost:4318 for Jaeger or Tempo
+    #[arg(long, value_name = "URL")]
+    pub otlp: Option<String>,
+
+    /// TOML file with additional price sources
+    #[arg(long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Keep the last block, rolling windows and alert states in FILE so
+    /// watch mode can resume after a restart
+    #[arg(long, value_name = "FILE")]
+    pub state: Option<PathBuf>,
+
+    /// Seconds between two writes of --state, it is also written when watch
+    /// mode stops on Ctrl-C or SIGTERM
+    #[arg(long, value_name = "SECONDS", default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..), requires = "state")]
+    pub state_every: u64,
+
+    /// Seconds between the samples the rate of change and acceleration of
+    /// watch mode are computed from
+    #[arg(long, value_name = "SECONDS", default_value_t = 60)]
+    pub derivative_lookback: u64,
+
+    /// Number of samples the Bollinger bands of watch mode are computed over
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    pub bollinger_period: usize,
+
+    /// Width of the Bollinger bands in standard deviations
+    #[arg(long, value_name = "K", default_value_t = 2.0)]
+    pub bollinger_k: f64,
+
+    /// Smoothing period of the RSI of watch mode, in samples
+    #[arg(long, value_name = "N", default_value_t = 14)]
+    pub rsi_period: usize,
+
+    /// Add the volume-weighted average price of the swaps of the last
+    /// SECONDS to the quotes of Uniswap V2 pools, 0 turns it off
+    #[arg(long, value_name = "SECONDS", default_value_t = 0)]
+    pub vwap: u64,
+
+    /// Add the buy and sell volume of the swaps since the previous poll to
+    /// the quotes of Uniswap V2 pools
+    #[arg(long)]
+    pub swap_volume: bool,
+
+    /// Search the swaps of Uniswap V2 pools for sandwiches, for the metrics
+    /// and sandwich_above alerts
+    #[arg(long)]
+    pub sandwiches: bool,
+
+    /// Show a sparkline of the last N prices next to every asset in watch mode
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    pub sparkline: usize,
+
+    /// Also print the unscaled reserves, decimals, per-hop ratios and block
+    /// every price was computed from (text and json only)
+    #[arg(long, visible_alias = "raw", global = true)]
+    pub verbose: bool,
+
+    /// Output format, quotes default to text and curves to csv
+    #[arg(long, value_enum, global = true)]
+    pub format: Option<Format>,
+
+    /// Time zone of the times in text output: UTC, local, an offset like
+    /// +02:00 or a name like America/New_York. csv and json stay in UTC.
+    #[arg(long, value_name = "ZONE", global = true, default_value = "UTC")]
+    pub timezone: clock::Zone,
+
+    /// strftime format of the times in text output, e.g. "%Y-%m-%d %H:%M:%S %Z",
+    /// defaults to RFC 3339
+    #[arg(long, value_name = "FORMAT", global = true)]
+    pub time_format: Option<String>,
+
+    /// Address book to resolve names from, defaults to
+    /// ~/.config/uni-price-polling/aliases.toml
+    #[arg(long, value_name = "FILE", global = true)]
+    pub aliases: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub http: provider::HttpOptions,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Execution price and price impact over a range of trade sizes
+    Curve(CurveArgs),
+    /// Render the stored price history of an asset to a PNG or SVG file
+    Chart(ChartArgs),
+    /// Work with the stored price history of the file sinks
+    #[command(subcommand)]
+    History(HistoryCommand),
+    /// Feed stored quotes through the indicators, alerts and sinks again
+    Replay(ReplayArgs),
+    /// Manage the [[pool]] watchlist of the config
+    #[command(subcommand)]
+    Pools(PoolsCommand),
+    /// Print the tokens, factory, reserves and price of a V2 pair
+    Info {
+        /// The pair, an address or a name from the address book
+        pool: String,
+    },
+    /// Grade a pool on its liquidity, trading, LP holders and transfer taxes
+    /// before adding it to the feeds
+    Health {
+        /// The pair, an address or a name from the address book
+        pool: String,
+
+        /// Days of LP token transfers searched for holders
+        #[arg(long, default_value_t = 7)]
+        lookback_days: u64,
+    },
+    /// Paper-trade a pool with the [simulate] rules of the config
+    Simulate(SimulateArgs),
+    /// Value the ether and watchlist tokens held by a wallet
+    Portfolio {
+        /// The holder, an address or a name from the address book
+        wallet: String,
+    },
+    /// Price the pairs created on the V2 factories as they are listed
+    WatchNewPairs(WatchNewPairsArgs),
+    /// Watch USDT, USDC and DAI for a loss of their $1.00 peg
+    Depeg(DepegArgs),
+    /// Price token A in token B, e.g. LINK/UNI
+    Ratio(RatioArgs),
+    /// Scan three pools forming a cycle for triangular arbitrage
+    TriArb(TriArbArgs),
+    /// Work with the [[alert]] rules of the config
+    #[command(subcommand)]
+    Alerts(AlertsCommand),
+    /// Measure quote latency and pools per second of the RPC, one call per
+    /// pool against multicalls
+    Bench(BenchArgs),
+    /// Manage the address book of short names for pools and tokens
+    #[command(subcommand)]
+    Alias(AliasCommand),
+    /// Work with the --config file
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Price, inspect and watch pools interactively, keeping the connection
+    /// and pair metadata between commands
+    Repl,
+    /// Poll the watchlist and answer getReserves() calls of its V2 pools
+    /// from memory over JSON-RPC, passing other requests on, every
+    /// --interval seconds or 12 without one
+    CacheServe {
+        /// Where to listen
+        #[arg(default_value = "127.0.0.1:8546")]
+        addr: std::net::SocketAddr,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AlertsCommand {
+    /// Evaluate a rule at a made-up price and send what it fires to the
+    /// [notify] targets once
+    Test(AlertTestArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct AlertTestArgs {
+    /// Name of the [[alert]] rule, also of the rules of the namespaces, or
+    /// NAMESPACE/RULE to pick the one of a namespace
+    #[arg(long)]
+    pub rule: String,
+
+    /// The price to evaluate the rule at, the value of its field for rules
+    /// with a field
+    #[arg(long)]
+    pub price: f64,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Check every address of the config on-chain and show what would be
+    /// polled, without polling
+    Check,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AliasCommand {
+    /// Name an address on the current chain
+    Add {
+        name: String,
+        #[arg(value_parser = checksum::parse_arg)]
+        address: Address,
+    },
+    /// Forget a name
+    Rm { name: String },
+    /// Print the names of the current chain
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HistoryCommand {
+    /// Copy history files into one file per UTC day, optionally compressed
+    Export(ExportArgs),
+    /// Recover the prices of a range of blocks from the Sync events of a
+    /// route
+    Backfill(BackfillArgs),
+    /// Write the reserves of a route over a range of blocks as snapshots
+    /// and the changes between them
+    Archive(ArchiveArgs),
+    /// Rebuild the reserves and price at blocks of an archive
+    At(AtArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct BackfillArgs {
+    /// The TOKEN/ETH pool, an address or a name from the address book
     #[arg(long)]
-    pub pool: String 
+    pub pool: String,
+
+    /// Start of the range: unix time, RFC 3339, a date or a duration ago
+    /// like 24h, looked up as the first block at or after it
+    #[arg(long, required_unless_present = "from_block", conflicts_with = "from_block")]
+    pub from: Option<String>,
+
+    /// End of the range, same formats as --from, the head without it
+    #[arg(long, conflicts_with = "to_block")]
+    pub to: Option<String>,
+
+    /// First block of the range
+    #[arg(long, value_name = "BLOCK")]
+    pub from_block: Option<u64>,
+
+    /// Last block of the range
+    #[arg(long, value_name = "BLOCK")]
+    pub to_block: Option<u64>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ArchiveArgs {
+    #[command(flatten)]
+    pub range: BackfillArgs,
+
+    /// Where the archive is written, compressed when the name ends in .gz
+    /// or .zst
+    #[arg(long, short, value_name = "FILE")]
+    pub output: PathBuf,
+
+    /// Blocks between two full snapshots of the reserves
+    #[arg(long, value_name = "BLOCKS", default_value_t = archive::DEFAULT_SNAPSHOT_EVERY, value_parser = clap::value_parser!(u64).range(1..))]
+    pub snapshot_every: u64,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct AtArgs {
+    /// An archive written by `history archive`
+    pub archive: PathBuf,
+
+    /// Block to rebuild, repeat for several
+    #[arg(long = "block", required = true, value_name = "BLOCK")]
+    pub blocks: Vec<u64>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ExportArgs {
+    /// Files written by a file sink, rotated .gz files included
+    #[arg(required = true, value_name = "FILE")]
+    pub inputs: Vec<PathBuf>,
+
+    /// Directory the daily files are written to
+    #[arg(long, short, value_name = "DIR")]
+    pub output: PathBuf,
+
+    #[arg(long, value_enum, default_value_t = Compression::None)]
+    pub compress: Compression,
+
+    /// Only export this asset, as named in the output (e.g. TOKEN/USDT)
+    #[arg(long)]
+    pub asset: Option<String>,
+
+    /// Start of the range: unix time, RFC 3339, a date or a duration ago like 24h
+    #[arg(long)]
+    pub from: Option<String>,
+
+    /// End of the range, same formats as --from
+    #[arg(long)]
+    pub to: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PoolsCommand {
+    /// Check the pools of a CSV or JSON file and append them to --config
+    Import(ImportArgs),
+    /// Find the deepest WETH pool of every token of a token list and print
+    /// them as a watchlist
+    Discover(DiscoverArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ImportArgs {
+    /// `address[,label]` lines, or a .json array of addresses or {address, label}
+    pub file: PathBuf,
+
+    /// Only check the addresses, do not query the pools
+    #[arg(long)]
+    pub no_check: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DiscoverArgs {
+    /// Token list url or file, see tokenlists.org
+    #[arg(long, value_name = "URL")]
+    pub list: String,
+
+    /// V2 factories to search, defaults to Uniswap V2 and SushiSwap
+    #[arg(long = "factory", value_name = "ADDRESS", value_parser = checksum::parse_arg)]
+    pub factories: Vec<Address>,
+
+    /// Skip pools holding less than this much, in USDT
+    #[arg(long, default_value_t = 0.0)]
+    pub min_liquidity: f64,
+
+    /// Write the watchlist to FILE instead of stdout
+    #[arg(long, short, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TriArbArgs {
+    /// The three pools of the cycle A/B, B/C and C/A, addresses or names
+    /// from the address book
+    #[arg(long = "pool", required = true, value_name = "POOL")]
+    pub pools: Vec<String>,
+
+    /// Seconds between two checks
+    #[arg(long, default_value_t = 12)]
+    pub interval: u64,
+
+    /// Basis points above 1.0 the round trip has to return to be reported
+    #[arg(long, default_value_t = 10.0)]
+    pub min_bps: f64,
+
+    /// Fee of every pool in basis points
+    #[arg(long, default_value_t = 30.0)]
+    pub fee_bps: f64,
+
+    /// Amount of the first token of the first pool to trade around, the
+    /// marginal prices are used without it
+    #[arg(long)]
+    pub size: Option<f64>,
+
+    /// Send opportunities to the [notify] targets of the config
+    #[arg(long)]
+    pub notify: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct BenchArgs {
+    /// Pools to quote, addresses or names from the address book, defaults
+    /// to the [[pool]] watchlist of the config
+    #[arg(long = "pool", value_name = "POOL")]
+    pub pools: Vec<String>,
+
+    /// Rounds of every mode, after one warm-up round
+    #[arg(long, default_value_t = 5)]
+    pub rounds: usize,
+
+    /// Modes to run, defaults to all of them, event with --ws only
+    #[arg(long = "mode", value_enum, value_name = "MODE")]
+    pub modes: Vec<bench::Mode>,
+
+    /// WebSocket endpoint of the event mode, e.g. wss://node.example/ws
+    #[arg(long, value_name = "URL")]
+    pub ws: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RatioArgs {
+    /// Token A, an address or a name from the address book
+    pub base: String,
+
+    /// Token B, an address or a name from the address book
+    pub quote: String,
+
+    /// V2 factories to search, defaults to Uniswap V2 and SushiSwap
+    #[arg(long = "factory", value_name = "ADDRESS", value_parser = checksum::parse_arg)]
+    pub factories: Vec<Address>,
+
+    /// Keep printing the ratio every this many seconds
+    #[arg(long)]
+    pub interval: Option<u64>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DepegArgs {
+    /// Seconds between two checks
+    #[arg(long, default_value_t = 12)]
+    pub interval: u64,
+
+    /// Basis points away from 1.00 a price may be
+    #[arg(long, default_value_t = 50.0)]
+    pub max_bps: f64,
+
+    /// Send depegs to the [notify] targets of the config
+    #[arg(long)]
+    pub notify: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct WatchNewPairsArgs {
+    /// V2 factories to watch, defaults to Uniswap V2 and SushiSwap
+    #[arg(long = "factory", value_name = "ADDRESS", value_parser = checksum::parse_arg)]
+    pub factories: Vec<Address>,
+
+    /// Seconds between two reads of the factory events
+    #[arg(long, default_value_t = 12)]
+    pub interval: u64,
+
+    /// First block to read, defaults to the current one
+    #[arg(long, value_name = "BLOCK")]
+    pub from_block: Option<u64>,
+
+    /// Send every priced pair to the [notify] targets of the config
+    #[arg(long)]
+    pub notify: bool,
+
+    /// Only notify about pairs holding at least this much, in USDT
+    #[arg(long, default_value_t = 0.0)]
+    pub min_liquidity: f64,
+
+    /// Price the config's [[pool]]s every interval and add new WETH pairs
+    /// meeting the --add-* criteria to them and to the config
+    #[arg(long)]
+    pub auto_add: bool,
+
+    /// Liquidity a new pair needs to be added, in USDT
+    #[arg(long, value_name = "USDT", default_value_t = 50_000.0)]
+    pub add_min_liquidity: f64,
+
+    /// Swap volume since the listing a new pair needs to be added, in USDT
+    #[arg(long, value_name = "USDT", default_value_t = 0.0)]
+    pub add_min_volume: f64,
+
+    /// Seconds after its listing a pair may still be added
+    #[arg(long, value_name = "SECONDS", default_value_t = 86_400)]
+    pub add_within: u64,
+
+    /// Buy tax above which a token is flagged, 0.1 for 10%
+    #[arg(long, default_value_t = 0.1)]
+    pub max_tax: f64,
+
+    /// Notify about and add pairs whose token failed the honeypot checks
+    #[arg(long)]
+    pub allow_risky: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct CurveArgs {
+    /// The TOKEN/ETH pool, an address or a name from the address book
+    #[arg(long)]
+    pub pool: String,
+
+    #[arg(long, value_enum, default_value_t = Side::Sell)]
+    pub side: Side,
+
+    /// Largest trade size, in TOKEN when selling and in USDT when buying
+    #[arg(long)]
+    pub max: f64,
+
+    /// Number of sizes between max/steps and max
+    #[arg(long, default_value_t = 10)]
+    pub steps: usize,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SimulateArgs {
+    /// The TOKEN/ETH pool, an address or a name from the address book
+    #[arg(long)]
+    pub pool: String,
+
+    /// Seconds between two polls
+    #[arg(long, default_value_t = 15)]
+    pub interval: u64,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ReplayArgs {
+    /// Files written by a file sink, rotated .gz and .zst files included
+    #[arg(required = true, value_name = "FILE")]
+    pub inputs: Vec<PathBuf>,
+
+    /// Only replay this asset, as named in the output (e.g. TOKEN/USDT)
+    #[arg(long)]
+    pub asset: Option<String>,
+
+    /// Start of the range: unix time, RFC 3339, a date or a duration ago like 24h
+    #[arg(long)]
+    pub from: Option<String>,
+
+    /// End of the range, same formats as --from
+    #[arg(long)]
+    pub to: Option<String>,
+
+    /// Playback speed as a multiple of the recorded time, 60 plays an hour
+    /// in a minute, 0 replays as fast as possible
+    #[arg(long, default_value_t = 0.0)]
+    pub speed: f64,
+
+    /// Deliver the alerts that fire to the [notify] targets, they are only
+    /// printed otherwise
+    #[arg(long)]
+    pub notify: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ChartArgs {
+    /// History written by a file sink, .csv or json lines
+    #[arg(long, value_name = "FILE")]
+    pub input: PathBuf,
+
+    /// Asset to chart, as named in the output (e.g. TOKEN/USDT)
+    #[arg(long, default_value = "TOKEN/USDT")]
+    pub asset: String,
+
+    /// Start of the range: unix time, RFC 3339, a date or a duration ago like 24h
+    #[arg(long)]
+    pub from: Option<String>,
+
+    /// End of the range, same formats as --from
+    #[arg(long)]
+    pub to: Option<String>,
+
+    /// Average prices into buckets of this size, e.g. 5m
+    #[arg(long)]
+    pub resolution: Option<String>,
+
+    #[arg(long, default_value_t = 1200)]
+    pub width: u32,
+
+    #[arg(long, default_value_t = 600)]
+    pub height: u32,
+
+    /// Image to write, .svg for SVG and .png for PNG
+    #[arg(long, short)]
+    pub output: PathBuf,
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>>{
-    
+async fn main() -> ExitCode {
+    match start().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            exit::code(e.as_ref())
+        }
+    }
+}
+
+/**
+ * @gist parses the command line and runs it, see exit for the codes the
+ * errors map to
+**/
+async fn start() -> Result<(), Box<dyn std::error::Error>> {
+
     // Initialize provider and define addresses
-    let provider = Arc::new(Provider::try_from(RPC_URL)?);
-    let args = Args::parse();
+    let mut args = Args::parse();
+
+    // cache-serve is watch mode with --cache-rpc
+    if let Some(Command::CacheServe { addr }) = args.command {
+        args.command = None;
+        args.cache_rpc = Some(addr);
+        if args.schedule.is_none() {
+            args.interval.get_or_insert(12);
+        }
+    }
+
+    // Charts and exports only read local files, no provider needed
+    match &args.command {
+        Some(Command::Chart(chart_args)) => return write_chart(chart_args),
+        Some(Command::History(HistoryCommand::Export(export_args))) => return export_history(export_args),
+        Some(Command::History(HistoryCommand::At(at_args))) => return print_archived(at_args, &args),
+        _ => {}
+    }
+
+    let config = match &args.config {
+        // Importing may create the config
+        Some(path) if matches!(args.command, Some(Command::Pools(_))) && !path.exists() => Config::default(),
+        Some(path) => config::load(path)?,
+        None => Config::default(),
+    };
+
+    // Replays only read local files too, but use the alerts and sinks
+    if let Some(Command::Replay(replay_args)) = &args.command {
+        return replay(replay_args, &args, config).await;
+    }
+    // Alert tests only talk to the notifiers
+    if let Some(Command::Alerts(AlertsCommand::Test(test_args))) = &args.command {
+        return test_alert(test_args, &args, config).await;
+    }
+
+    if args.command.is_none() && args.pool.is_none() && !args.v4 && config.pool.is_empty()
+        && config.oracle.is_empty() && config.composite.is_empty() && config.basket.is_empty() && config.vault.is_empty() && config.rate.is_empty() && config.v3.is_empty() {
+        return Err("nothing to poll, pass --pool or --v4 or add a [[pool]], [[oracle]], [[composite]], [[basket]], [[vault]], [[rate]] or [[v3]] to the config".into());
+    }
+
+    let metrics = Arc::new(Metrics::default());
+    let pin = Arc::new(Pin::default());
+    pin.set(args.at.pin());
+    match (&args.ipc, args.quorum) {
+        (Some(path), _) => {
+            let provider = Arc::new(provider::ipc_provider(path, args.max_concurrent_requests, metrics.clone(), pin.clone()).await?);
+            run(provider, &args, config, metrics).await
+        }
+        (None, Some(needed)) => {
+            let provider = Arc::new(provider::quorum_provider(&args.quorum_rpc, needed, &args.http, args.max_concurrent_requests, metrics.clone(), pin.clone())?);
+            run(provider, &args, config, metrics).await
+        }
+        (None, None) => {
+            let provider = Arc::new(provider::http_provider(RPC_URL, &args.http, args.max_concurrent_requests, metrics.clone(), pin.clone())?);
+            run(provider, &args, config, metrics).await
+        }
+    }
+
+}
+
+/**
+ * @gist builds the feeds and prints a single round of quotes, or keeps
+ * polling when --interval is given
+ * @param provider -- any ethers middleware, http or ipc
+ * @param args -- the parsed command line
+ * @param config -- the parsed config file
+ * @param metrics -- shared with the provider, served in watch mode
+**/
+async fn run<M: Middleware + 'static>(provider: Arc<M>, args: &Args, mut config: Config, metrics: Arc<Metrics>) -> Result<(), Box<dyn std::error::Error>> {
+
+    let end_a: Address = uniswap_v2::ETH_USDT_POOL.parse()?;
+    let time = clock::TimeStyle::new(args.timezone, args.time_format.clone())?;
+
+    // Names are kept per chain
+    let book_path = args.aliases.clone().unwrap_or_else(aliases::default_path);
+    let mut book = aliases::load(&book_path)?;
+    let chain = match provider.get_chainid().await {
+        Ok(chain) => chain.as_u64(),
+        // The routes are mainnet pools anyway, keep going on off-chain prices
+        Err(e) if config.fallback.is_some() && args.command.is_none() => {
+            eprintln!("could not read the chain id, assuming mainnet: {e}");
+            1
+        }
+        Err(e) => return Err(Failure::new(Kind::Rpc, format!("could not read the chain id: {e}")).into()),
+    };
+
+    if let Some(Command::Alias(alias_command)) = &args.command {
+        return edit_aliases(&mut book, &book_path, chain, alias_command);
+    }
+    if let Some(Command::Config(ConfigCommand::Check)) = &args.command {
+        return check_config(provider, args, config).await;
+    }
+    config.pool = config.tokens.pools(provider.clone(), config.pool).await;
+
+    // User supplied pools are checked on etherscan when a key is configured
+    let etherscan = match config.etherscan.clone() {
+        Some(c) => Some(Etherscan::new(provider::http_client(&args.http)?, c, chain)),
+        None => None,
+    };
+    if let Some(Command::Info { pool }) = &args.command {
+        let pair = book.resolve(chain, pool).map_err(|e| Failure::new(Kind::BadAddress, e))?;
+        warn_unverified(etherscan.as_ref(), provider.clone(), &pair).await;
+        let info = uniswap_v2::get_pair_info(provider.clone(), &pair).await?;
+        warn_rebasing(provider, &info).await;
+        return print_info(&info, &book, chain, args.format.unwrap_or(Format::Text), &time);
+    }
+    if let Some(Command::Health { pool, lookback_days }) = &args.command {
+        let pair = book.resolve(chain, pool).map_err(|e| Failure::new(Kind::BadAddress, e))?;
+        let health = health::report(provider, pair, &end_a, *lookback_days).await?;
+        return print_health(&health, args.format.unwrap_or(Format::Text));
+    }
+    if let Some(Command::Repl) = &args.command {
+        let alerts = AlertEngine::new(config.alert.clone());
+        return run_repl(provider, &book, chain, alerts, args.format.unwrap_or(Format::Text), &time).await;
+    }
+    if let Some(Command::Simulate(sim_args)) = &args.command {
+        let start_a = book.resolve(chain, &sim_args.pool)?;
+        warn_unverified(etherscan.as_ref(), provider.clone(), &start_a).await;
+        let sim_config = config.simulate.ok_or("simulate needs a [simulate] table in the config")?;
+        let interval = Duration::from_secs(sim_args.interval);
+        return paper_trade(provider, &start_a, &end_a, interval, sim_config, args.format.unwrap_or(Format::Text)).await;
+    }
+    if let Some(Command::Portfolio { wallet }) = &args.command {
+        let wallet = book.resolve(chain, wallet)?;
+        let watchlist: Vec<pools::PoolConfig> = config.pool.iter()
+            .map(|p| match (&p.label, book.name_of(chain, &p.address)) {
+                (None, Some(name)) => pools::PoolConfig { label: Some(name.to_string()), ..p.clone() },
+                _ => p.clone(),
+            })
+            .collect();
+        let portfolio = portfolio::value(provider, wallet, &watchlist, end_a).await?;
+        return print_portfolio(&portfolio, args.format.unwrap_or(Format::Text));
+    }
+    if let Some(Command::Curve(curve_args)) = &args.command {
+        let start_a = book.resolve(chain, &curve_args.pool)?;
+        warn_unverified(etherscan.as_ref(), provider.clone(), &start_a).await;
+        return print_curve(provider, curve_args, args.format.unwrap_or(Format::Csv), &start_a, &end_a).await;
+    }
+    if let Some(Command::Bench(bench_args)) = &args.command {
+        let pools = match bench_args.pools.is_empty() {
+            true => config.pool.iter().map(|p| p.address).collect(),
+            false => bench_args.pools.iter().map(|p| book.resolve(chain, p)).collect::<Result<Vec<_>, _>>()?,
+        };
+        if pools.is_empty() {
+            return Err("bench needs pools, give them with --pool or as [[pool]] tables of the config".into());
+        }
+        let modes = match bench_args.modes.is_empty() {
+            true => vec![bench::Mode::Sequential, bench::Mode::Concurrent, bench::Mode::Multicall, bench::Mode::Event],
+            false => bench_args.modes.clone(),
+        };
+        // By default the event mode only runs with a WebSocket to follow
+        if bench_args.ws.is_none() && !bench_args.modes.is_empty() && modes.contains(&bench::Mode::Event) {
+            return Err("the event mode of bench needs --ws URL".into());
+        }
+        let chunk = match args.multicall_chunk {
+            0 => bench::DEFAULT_CHUNK,
+            chunk => chunk,
+        };
+        let mut reports = Vec::with_capacity(modes.len());
+        for mode in modes {
+            let report = match (mode, &bench_args.ws) {
+                (bench::Mode::Event, None) => continue,
+                (bench::Mode::Event, Some(url)) => {
+                    eprintln!("event: {} pools, {} blocks", pools.len(), bench_args.rounds);
+                    bench::run_events(url, &pools, end_a, bench_args.rounds).await?
+                }
+                _ => {
+                    eprintln!("{}: {} pools, {} rounds", mode.name(), pools.len(), bench_args.rounds);
+                    bench::run(provider.clone(), &pools, end_a, mode, bench_args.rounds, chunk).await
+                }
+            };
+            reports.push(report);
+        }
+        return print_bench(&reports, args.format.unwrap_or(Format::Text));
+    }
+    if let Some(Command::History(HistoryCommand::Backfill(backfill_args))) = &args.command {
+        let start_a = book.resolve(chain, &backfill_args.pool)?;
+        return backfill_range(provider, backfill_args, &start_a, &end_a, args.format.unwrap_or(Format::Text), &time).await;
+    }
+    if let Some(Command::History(HistoryCommand::Archive(archive_args))) = &args.command {
+        let start_a = book.resolve(chain, &archive_args.range.pool)?;
+        let (from, to) = block_range(provider.as_ref(), &archive_args.range).await?;
+        eprintln!("archiving blocks {from}..={to} to {}", archive_args.output.display());
+        let summary = archive::build(provider, &start_a, &end_a, from, to, archive_args.snapshot_every, &archive_args.output).await?;
+        eprintln!("wrote {} snapshots and {} deltas for {} blocks", summary.snapshots, summary.deltas, summary.blocks);
+        return Ok(());
+    }
+    if let Some(Command::Pools(PoolsCommand::Import(import_args))) = &args.command {
+        return import_pools(provider, import_args, args.config.as_deref(), &config, etherscan.as_ref()).await;
+    }
+    if let Some(Command::WatchNewPairs(pair_args)) = &args.command {
+        let notifier = Notifier::new(provider::http_client(&args.http)?, config.notify);
+        let watchlist = Watchlist { path: args.config.as_deref(), pools: config.pool.clone() };
+        return watch_new_pairs(provider, pair_args, &end_a, &notifier, watchlist, &config.tokens, args.format.unwrap_or(Format::Text)).await;
+    }
+    if let Some(Command::TriArb(arb_args)) = &args.command {
+        let pools = arb_args.pools.iter().map(|p| book.resolve(chain, p)).collect::<Result<Vec<_>, _>>()?;
+        let pools: [Address; 3] = pools.try_into().map_err(|_| "tri-arb takes exactly three --pool")?;
+        let cycle = Cycle::new(provider.clone(), pools, arb_args.fee_bps / 10_000.0).await?;
+        let notifier = Notifier::new(provider::http_client(&args.http)?, config.notify);
+        return scan_cycle(provider, arb_args, &cycle, &notifier, args.format.unwrap_or(Format::Text), &time).await;
+    }
+    if let Some(Command::Ratio(ratio_args)) = &args.command {
+        let base = book.resolve(chain, &ratio_args.base)?;
+        let quote = book.resolve(chain, &ratio_args.quote)?;
+        return print_ratio(provider, ratio_args, base, quote, &end_a, args.format.unwrap_or(Format::Text)).await;
+    }
+    if let Some(Command::Depeg(depeg_args)) = &args.command {
+        let notifier = Notifier::new(provider::http_client(&args.http)?, config.notify);
+        return watch_depeg(provider, depeg_args, &notifier, args.format.unwrap_or(Format::Text), &time).await;
+    }
+    if let Some(Command::Pools(PoolsCommand::Discover(discover_args))) = &args.command {
+        return discover_pools(provider, discover_args, &args.http, &config, &end_a).await;
+    }
+
     //let start_a: Address = "0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974".parse()?;
-    let start_a: Address = args.pool.parse()?;
-    let end_a: Address = "0x0d4a11d5EEaaC28EC3F61d100daF4d40471f1852".parse()?;
+    let start_a = args.pool.as_deref().filter(|p| *p != "-").map(|p| book.resolve(chain, p)).transpose()
+        .map_err(|e| Failure::new(Kind::BadAddress, e))?;
+    // With `--pool -` every line of stdin is a pool, named as given
+    let piped: Vec<(String, Option<Address>)> = match args.pool.as_deref() {
+        Some("-") => read_pools(&book, chain)?,
+        _ => Vec::new(),
+    };
+    if let Some(start_a) = &start_a {
+        warn_unverified(etherscan.as_ref(), provider.clone(), start_a).await;
+    }
 
+    // The pools of the namespaces are watched like the others, their label
+    // routes the quotes
+    let namespaced = config.tokens.pools(provider.clone(), namespace::pools(&config.namespace)).await;
+    config.pool.extend(namespaced);
 
-    let p1 = provider.clone();
+    // One batch serves the reserves of every V2 route of the watchlist
+    let batch = (args.multicall_chunk > 0).then(|| {
+        let pools = start_a.iter().copied()
+            .chain(piped.iter().filter_map(|(_, pool)| *pool))
+            .chain(config.pool.iter().map(|p| p.address))
+            .chain(config.composite.iter().flat_map(|c| c.source.iter().map(|m| m.pool)))
+            .chain(config.basket.iter().flat_map(|b| b.component.iter().map(|c| c.pool)))
+            .chain(config.vault.iter().map(|v| v.pool))
+            .chain(config.rate.iter().map(|r| r.pool))
+            .chain([end_a])
+            .collect();
+        Arc::new(ReserveBatch::new(provider.clone(), args.multicall_chunk, pools))
+    });
+    let reserve_cache = args.cache_rpc.map(|_| Arc::new(ReserveCache::default()));
+    let route = |inverted: bool, decimals: u8| {
+        let mut route = UniswapV2::new(provider.clone(), end_a).with_layout(inverted, decimals);
+        if let Some(batch) = &batch {
+            route = route.with_batch(batch.clone());
+        }
+        if let Some(cache) = &reserve_cache {
+            route = route.with_cache(cache.clone());
+        }
+        route
+    };
 
-    // Gets UNI reserves of the assets in TOKEN/ETH pool and ETH/USDT pool
-    let (token_1, eth_1, _) = get_reserves(provider, &start_a).await?;
-    let (eth_2, usdt_1, _) = get_reserves(p1, &end_a).await?;
+    let v2 = Arc::new(route(false, 18));
+    let mut feeds = Vec::new();
+    // Symbols and labels of the pools, by asset name and address
+    let mut tags: HashMap<(String, Address), (Symbol, BTreeMap<String, String>)> = HashMap::new();
+    let mut roundings: HashMap<(String, Address), Rounding> = HashMap::new();
+    let mut intervals: HashMap<(String, Address), Duration> = HashMap::new();
 
+    // Plain pools get their volume from the subgraph, if there is one
+    let subgraph = match config.subgraph.clone() {
+        Some(c) => Some(Arc::new(Subgraph::new(provider::http_client(&args.http)?, c))),
+        None => None,
+    };
+    let with_volume = |source: Arc<dyn PriceSource>| -> Arc<dyn PriceSource> {
+        match &subgraph {
+            Some(subgraph) => Arc::new(WithVolume::new(source, subgraph.clone())),
+            None => source,
+        }
+    };
 
-    // Reformat into decimals
-    let (token_f64, eth_f64) = (reformat_wei(token_1), reformat_wei(eth_1));
-    let (eth2_f64, usdt_f64) = (reformat_wei(eth_2), reformat_usd(usdt_1));
+    // Per share prices of rebasing tokens don't compare to their swaps
+    let whales = config.alert.iter().any(|a| a.swap_above.is_some());
+    let lp_changes = config.alert.iter().any(|a| a.liquidity_change_above.is_some());
+    let sandwiches = args.sandwiches || config.alert.iter().any(|a| a.sandwich_above.is_some());
+    let with_swaps = |source: Arc<dyn PriceSource>, inverted: bool, decimals: u8| -> Arc<dyn PriceSource> {
+        if args.vwap == 0 && !args.swap_volume && !whales && !lp_changes && !sandwiches {
+            return source;
+        }
+        let mut swaps = WithSwaps::new(source, provider.clone(), inverted, decimals).with_vwap(args.vwap);
+        if args.swap_volume {
+            swaps = swaps.with_volume();
+        }
+        if lp_changes {
+            swaps = swaps.with_liquidity_events();
+        }
+        if sandwiches {
+            swaps = swaps.with_sandwiches();
+        }
+        Arc::new(swaps)
+    };
 
-    // Get UNI V2 price of link
-    let token_per_usdt = 1.0 / 
-        ((token_f64 / eth_f64) * 
-        (eth2_f64 / usdt_f64));
+    // Only plain routes, the asset address of the rest is no TOKEN/ETH pool
+    let with_router = |source: Arc<dyn PriceSource>| -> Arc<dyn PriceSource> {
+        let Some(size) = args.simulate else {
+            return source;
+        };
+        let router = WithRouter::new(source, provider.clone(), size);
+        match args.simulate_from {
+            Some(holder) => Arc::new(router.with_holder(holder)),
+            None => Arc::new(router),
+        }
+    };
 
-    
-    println!("[UNI V2] TOKEN/USDT: ${token_per_usdt}");
+    if let Some(start_a) = start_a {
+        tags.insert(("TOKEN/USDT".to_string(), start_a), (Symbol::Pair, BTreeMap::new()));
+        feeds.push(Feed::new(with_router(with_volume(with_swaps(v2.clone(), false, 18))), Asset { name: "TOKEN/USDT".to_string(), address: start_a }));
+    }
+    if let Some(id) = args.pool_id {
+        let asset = Asset { name: "TOKEN/USDT".to_string(), address: v4::POOL_MANAGER.parse()? };
+        feeds.push(Feed::new(Arc::new(UniswapV4::new(provider.clone(), id, end_a)), asset));
+    }
+    for (input, pool) in &piped {
+        if let Some(pool) = pool {
+            tags.insert((input.clone(), *pool), (Symbol::Pair, BTreeMap::new()));
+            feeds.push(Feed::new(with_router(with_volume(with_swaps(v2.clone(), false, 18))), Asset { name: input.clone(), address: *pool }));
+        }
+    }
+    for p in &config.pool {
+        let mut asset = p.asset();
+        if let (None, Some(name)) = (&p.label, book.name_of(chain, &p.address)) {
+            asset.name = name.to_string();
+        }
+        labels::validate(&asset.name, &p.labels)?;
+        if let Some(rounding) = Rounding::of_pool(&asset.name, p.sig_figs, p.tick)? {
+            roundings.insert((asset.name.clone(), p.address), rounding);
+        }
+        if p.interval == Some(0) {
+            return Err(format!("[[pool]] {}: interval has to be at least 1 second", asset.name).into());
+        }
+        if let Some(every) = p.interval {
+            intervals.insert((asset.name.clone(), p.address), Duration::from_secs(every));
+        }
+        let symbol = p.route.as_ref().map_or(Symbol::Pair, |r| Symbol::Token(r.path[0]));
+        tags.insert((asset.name.clone(), p.address), (symbol, p.labels.clone()));
+        let mut source: Arc<dyn PriceSource> = Arc::new(route(p.inverted, p.decimals));
+        match (&p.rebase, &p.route) {
+            (Some(_), Some(_)) => return Err(format!("[[pool]] {}: a rebasing pool can't have a route", asset.name).into()),
+            (Some(rebase), None) => source = Arc::new(PerShare::new(provider.clone(), source, p.inverted, p.decimals, rebase)),
+            // Swaps and the router need the reserves of the ETH/USDT route
+            (None, Some(r)) => {
+                r.validate(&asset.name)?;
+                let quoter = args.v3_quoter.then(|| v3::QUOTER_V2.parse()).transpose()?;
+                source = r.source(provider.clone(), &asset.name, p.address, quoter)?;
+            }
+            (None, None) => source = with_router(with_swaps(source, p.inverted, p.decimals)),
+        }
+        let source = with_volume(source);
+        feeds.push(Feed::new(source, asset));
+    }
+    for o in config.oracle {
+        let asset = o.asset();
+        feeds.push(Feed::new(Arc::new(Oracle::new(provider.clone(), o)), asset));
+    }
+    for c in config.composite {
+        let asset = c.asset();
+        let members = c.source.iter()
+            .map(|m| Feed::new(Arc::new(route(m.inverted, m.decimals)), Asset { name: m.label.clone(), address: m.pool }))
+            .collect();
+        c.validate()?;
+        feeds.push(Feed::new(Arc::new(Composite::new(members, c.method, c.trim).with_half_life(c.half_life)), asset));
+    }
+    for b in config.basket {
+        let asset = b.asset();
+        let components = b.component.iter()
+            .map(|c| (Feed::new(Arc::new(route(c.inverted, c.decimals)), Asset { name: c.label.clone(), address: c.pool }), c.weight))
+            .collect();
+        feeds.push(Feed::new(Arc::new(Basket::new(components)), asset));
+    }
+    for v in config.vault {
+        let underlying = Feed::new(Arc::new(route(v.inverted, v.decimals)), v.underlying());
+        feeds.push(Feed::new(Arc::new(Vault::new(provider.clone(), underlying, v.decimals)), v.asset()));
+    }
+    for r in config.rate {
+        let base = Feed::new(Arc::new(route(r.inverted, r.decimals)), r.base());
+        feeds.push(Feed::new(Arc::new(Rated::new(provider.clone(), base, r.oracle(), r.divide)), r.asset()));
+    }
+    for p in config.v3 {
+        p.validate()?;
+        let asset = p.asset();
+        let mut source = UniswapV3::new(provider.clone(), p);
+        if args.v3_quoter {
+            source = source.with_quoter(v3::QUOTER_V2.parse()?);
+        }
+        feeds.push(Feed::new(Arc::new(source), asset));
+    }
+
+    // Inside the fallback, so a skipped feed is still priced off-chain
+    feeds = feeds.into_iter()
+        .map(|f| Feed::new(Arc::new(Breaker::new(f.source, config.breaker.clone())), f.asset))
+        .collect();
+    if let Some(min_usd) = args.min_liquidity_usd {
+        feeds = feeds.into_iter()
+            .map(|f| {
+                let guard = MinLiquidity::new(f.source, min_usd);
+                let guard = if args.flag_thin { guard.with_flag() } else { guard };
+                Feed::new(Arc::new(guard), f.asset)
+            })
+            .collect();
+    }
+    if let Some(c) = config.fallback.clone() {
+        let offchain = Arc::new(OffChain::new(provider::http_client(&args.http)?, c));
+        feeds = feeds.into_iter()
+            .map(|f| Feed::new(Arc::new(Fallback::new(f.source, offchain.clone())), f.asset))
+            .collect();
+    }
+    if let Some(c) = config.cex.clone() {
+        let cex = Arc::new(Cex::new(provider::http_client(&args.http)?, c));
+        feeds = feeds.into_iter()
+            .map(|f| if cex.lists(&f.asset.name) {
+                Feed::new(Arc::new(WithCex::new(f.source, cex.clone())), f.asset)
+            } else {
+                f
+            })
+            .collect();
+    }
+    if let Some(c) = config.aggregator.clone() {
+        let aggregator = Arc::new(Aggregator::new(provider::http_client(&args.http)?, c)?);
+        feeds = feeds.into_iter()
+            .map(|f| if aggregator.lists(&f.asset.name) {
+                Feed::new(Arc::new(WithAggregator::new(f.source, aggregator.clone())), f.asset)
+            } else {
+                f
+            })
+            .collect();
+    }
+    if let Some(size) = args.bid_ask {
+        feeds = feeds.into_iter()
+            .map(|f| Feed::new(Arc::new(WithBidAsk::new(f.source, size)), f.asset))
+            .collect();
+    }
+    // Last, so every dollar figure of the quote is converted
+    if let Some(code) = args.fiat.clone() {
+        let rates = Arc::new(Rates::new(provider::http_client(&args.http)?, args.fiat_url.clone(), code));
+        feeds = feeds.into_iter()
+            .map(|f| Feed::new(Arc::new(InFiat::new(f.source, rates.clone())), f.asset))
+            .collect();
+    }
+    // After the conversion, the rounding is of the price that goes out
+    let sig_figs = args.sig_figs.map(Rounding::sig_figs).transpose()?;
+    feeds = feeds.into_iter()
+        .map(|f| match roundings.get(&(f.asset.name.clone(), f.asset.address)).copied().or(sig_figs) {
+            Some(rounding) => Feed::new(Arc::new(Rounded::new(f.source, rounding)), f.asset),
+            None => f,
+        })
+        .collect();
+    // Outside the fallback, so off-chain quotes are labeled too
+    feeds = feeds.into_iter()
+        .map(|f| {
+            let mut labeled = Labeled::new(f.source, provider.clone(), chain);
+            if let Some((symbol, labels)) = tags.get(&(f.asset.name.clone(), f.asset.address)) {
+                labeled = labeled.with_symbol(*symbol).with_labels(labels.clone());
+            }
+            let every = intervals.get(&(f.asset.name.clone(), f.asset.address)).copied();
+            let feed = Feed::new(Arc::new(labeled), f.asset);
+            match every {
+                Some(every) => feed.with_every(every),
+                None => feed,
+            }
+        })
+        .collect();
+
+    let format = args.format.unwrap_or(Format::Text);
+    let deadline = args.deadline_ms.map(Duration::from_millis);
+
+    let cadence = match (args.interval, &args.schedule) {
+        (Some(interval), _) => Some(Cadence::Every(Duration::from_secs(interval))),
+        (None, Some(schedule)) => Some(Cadence::Schedule(schedule.clone())),
+        (None, None) => None,
+    };
+    if cadence.is_none() && args.ws.is_none() {
+        if format == Format::Csv {
+            println!("{}", format::csv_header());
+        }
+        let mut results = poller::poll_within(&feeds, deadline).await;
+        poller::degrade(&mut results);
+        let mut quotes = Vec::with_capacity(feeds.len());
+        for (feed, quote) in feeds.iter().zip(results) {
+            quotes.push(match (quote, args.max_age) {
+                (Ok(quote), Some(max_age)) => check_age(provider.clone(), &feed.asset, quote, max_age).await,
+                (quote, _) => quote,
+            });
+        }
+        let mut quotes = quotes.into_iter();
+        let print = |quote: Quote| match args.quiet {
+            true => println!("{}", quote.price),
+            false => println!("{}", format::render_with(&keep_raw(quote, args.verbose), format, &time)),
+        };
+
+        // One line per piped pool in input order, failures included, so the
+        // output lines up with the input. The first failure is the exit code.
+        let mut failed = None;
+        for (input, pool) in &piped {
+            let quote = match pool {
+                Some(_) => quotes.next().unwrap_or_else(|| Err(eyre::eyre!("not polled"))),
+                None => Err(Failure::new(Kind::BadAddress, "invalid pool").into()),
+            };
+            match quote {
+                Ok(quote) => print(quote),
+                Err(e) => {
+                    match args.quiet {
+                        true => println!(),
+                        false => println!("{}", format::render_error(input, &e.to_string(), format)),
+                    }
+                    failed.get_or_insert(exit::classified::<M>(e));
+                }
+            }
+        }
+        for quote in quotes {
+            match quote {
+                // A degraded run still prints what made the deadline
+                Err(e) if e.is::<poller::Missed>() => eprintln!("{e}"),
+                quote => print(quote.map_err(exit::classified::<M>)?),
+            }
+        }
+        if let Some(e) = failed {
+            return Err(e);
+        }
+        return Ok(());
+    }
+
+    let mut state = match &args.state {
+        Some(path) => state::load(path)?,
+        None => State::default(),
+    };
+
+    if let Some(start_a) = start_a {
+        backfill_gap(provider.clone(), &mut state, &start_a, &end_a).await;
+    }
+
+    let client = provider::http_client(&args.http)?;
+    let mut alerts = AlertEngine::with_states(config.alert, &state.alerts);
+    let notifier = Notifier::new(client.clone(), config.notify.clone());
+
+    let mut sinks = Dispatcher::new();
+    let stdout: Arc<dyn Sink> = Arc::new(Stdout::new(format).with_sparkline(args.sparkline).with_time_style(time));
+    sinks.add(stdout.clone(), 1024, Overflow::Block);
+    for s in &config.sink {
+        sinks.add(sink::from_config(&s.kind, &client)?, s.queue, s.overflow);
+    }
+    let mut namespaces = BTreeMap::new();
+    for ns in std::mem::take(&mut config.namespace) {
+        let ns = Namespace::new(ns, &client, stdout.clone(), &config.notify, &state.alerts)?;
+        namespaces.insert(ns.name.clone(), ns);
+    }
+
+    if let Some(addr) = args.metrics {
+        metrics::serve(addr, metrics.clone()).await?;
+    }
+    if let Some(url) = &args.metrics_push {
+        metrics::push(client.clone(), url.clone(), metrics.clone(), Duration::from_secs(args.metrics_push_interval.max(1)));
+    }
+    let board = Arc::new(Board::default());
+    if let Some(addr) = args.dashboard {
+        let mut server = config.server.clone();
+        server.history = server.history.or_else(|| config.sink.iter().find_map(|s| match &s.kind {
+            SinkKind::File { path, format, .. } if *format != Format::Text => Some(path.clone()),
+            _ => None,
+        }));
+        let tenants = namespaces.values().map(|ns| (ns.name.clone(), ns.tenant())).collect();
+        dashboard::serve(addr, board.clone(), tenants, Arc::new(AdHoc::new(provider.clone(), end_a)), &server).await?;
+    }
+    if let (Some(addr), Some(cache)) = (args.cache_rpc, &reserve_cache) {
+        cache::serve(addr, cache.clone(), provider.clone()).await?;
+    }
+    if let Some(endpoint) = &args.otlp {
+        trace::init(client.clone(), endpoint);
+    }
+    if args.rpc_summary > 0 {
+        let metrics = metrics.clone();
+        let every = Duration::from_secs(args.rpc_summary);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+            loop {
+                ticker.tick().await;
+                for line in metrics.summary() {
+                    eprintln!("{line}");
+                }
+            }
+        });
+    }
+
+    let mut reorgs = Reorgs::new(args.reorg_depth);
+    let mut down = false;
+    let mut behind = false;
+    let mut saved = Instant::now();
+    let on_cycle = async |mut quotes: Vec<Quote>| {
+        let now = clock::unix_now();
+
+        // A cycle without any quote means the node is gone, once it answers
+        // again the blocks in between are recovered like after a restart
+        let answered = !quotes.is_empty();
+        if let (true, true, Some(start_a)) = (down, answered, start_a) {
+            backfill_gap(provider.clone(), &mut state, &start_a, &end_a).await;
+        }
+        down = !answered;
+
+        // Blocks come every few seconds, a newest block far behind the local
+        // clock is a node that stopped following the chain
+        if let (Some(max_lag), Some(age)) = (args.max_block_lag, poller::block_age(&quotes, now)) {
+            match (age > max_lag, behind) {
+                (true, false) => eprintln!("warning: the newest block is {age}s old, the RPC is probably serving a node behind the chain"),
+                (false, true) => eprintln!("the newest block is {age}s old again, the node caught up"),
+                _ => {}
+            }
+            behind = age > max_lag;
+            for quote in quotes.iter_mut().filter(|quote| quote.block.is_some()) {
+                quote.lagging = behind;
+            }
+        }
+
+        for quote in quotes {
+            if let (true, Some(block)) = (args.reorg_depth > 0, quote.block) {
+                reorgs.quoted(block, &quote.asset);
+            }
+            let quote = with_indicators(&mut state, args, keep_raw(quote, args.verbose), now);
+
+            metrics.observe_quote(&quote);
+            // The quotes and alerts of a namespace only show on its own board
+            match namespace::of(&quote).and_then(|name| namespaces.get_mut(name)) {
+                Some(ns) => {
+                    ns.board.observe_quote(&quote, now);
+                    ns.sinks.send(&quote).await;
+                    for alert in ns.alerts.observe(&quote) {
+                        ns.fire(alert, now);
+                    }
+                }
+                None => {
+                    board.observe_quote(&quote, now);
+                    sinks.send(&quote).await;
+                    for alert in alerts.observe(&quote) {
+                        board.observe_alert(&alert, now);
+                        notifier.spawn(alert);
+                    }
+                }
+            }
+            state.block = state.block.max(quote.block);
+        }
+        for alert in alerts.silent(now) {
+            board.observe_alert(&alert, now);
+            notifier.spawn(alert);
+        }
+        for ns in namespaces.values_mut() {
+            for alert in ns.alerts.silent(now) {
+                ns.fire(alert, now);
+            }
+        }
+        // The corrections only go to the sinks, the alerts saw the orphaned
+        // quotes already
+        if args.reorg_depth > 0 {
+            for quote in requote_orphaned(provider.as_ref(), &mut reorgs, &feeds).await {
+                let quote = keep_raw(quote, args.verbose);
+                match namespace::of(&quote).and_then(|name| namespaces.get(name)) {
+                    Some(ns) => ns.sinks.send(&quote).await,
+                    None => sinks.send(&quote).await,
+                }
+            }
+        }
+
+        if let Some(batch) = &batch {
+            batch.next_cycle();
+        }
+
+        if answered {
+            state.timestamp = Some(now);
+        }
+        // The windows hold a day of prices, writing them every cycle costs
+        // more than the few cycles a crash can lose
+        if let (Some(path), true) = (&args.state, saved.elapsed().as_secs() >= args.state_every) {
+            state.alerts = alerts.states();
+            state.alerts.extend(namespaces.values().flat_map(Namespace::states));
+            if let Err(e) = state::save(path, &state) {
+                eprintln!("could not save state: {e}");
+            }
+            saved = Instant::now();
+        }
+    };
+    let watching = async {
+        match (&args.ws, start_a, cadence) {
+            (Some(url), Some(start_a), _) => stream::watch(url, start_a, end_a, on_cycle).await,
+            (None, _, Some(cadence)) => poller::watch(&feeds, cadence, deadline, on_cycle).await,
+            _ => Err(eyre::eyre!("--ws follows one --pool address, not pools piped on stdin")),
+        }
+    };
+    tokio::select! {
+        watched = watching => watched?,
+        _ = shutdown() => {}
+    }
+    if let Some(path) = &args.state {
+        state.alerts = alerts.states();
+        state.alerts.extend(namespaces.values().flat_map(Namespace::states));
+        state::save(path, &state).map_err(|e| format!("could not save state: {e}"))?;
+    }
+    Ok(())
+}
+
+/**
+ * @gist resolves once the process is asked to stop, on Ctrl-C or the
+ * SIGTERM of systemd and docker
+**/
+async fn shutdown() {
+    match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+        Ok(mut term) => tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = term.recv() => {}
+        },
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+/**
+ * @gist prints the etherscan warnings about a pool and its tokens, nothing
+ * happens without an etherscan config
+ * @param etherscan -- the API client
+ * @param provider -- any ethers middleware, http or ipc
+ * @param pool -- the user supplied pair
+**/
+async fn warn_unverified<M: Middleware + 'static>(etherscan: Option<&Etherscan>, provider: Arc<M>, pool: &Address) {
+    let Some(etherscan) = etherscan else {
+        return;
+    };
+    for warning in etherscan::check_pool(etherscan, provider, pool).await {
+        eprintln!("warning: {warning}");
+    }
+}
+
+/**
+ * @gist prints a warning for every token of a pair whose supply moved
+ * without transfers over the last day of blocks
+ * @param provider -- any ethers middleware, http or ipc
+ * @param info -- the pair and its tokens
+**/
+async fn warn_rebasing<M: Middleware + 'static>(provider: Arc<M>, info: &uniswap_v2::PairInfo) {
+    let to = match provider.get_block_number().await {
+        Ok(block) => block.as_u64(),
+        Err(e) => return eprintln!("rebase check skipped: {e}"),
+    };
+    let from = to.saturating_sub(backfill::MAX_BLOCKS);
+
+    for token in [&info.token0, &info.token1] {
+        let name = token.symbol.clone().unwrap_or_else(|| format!("{:?}", token.address));
+        match rebase::check_supply(provider.clone(), token.address, token.decimals, from, to).await {
+            Ok(check) if check.rebases() => eprintln!("warning: {name} rebases, its supply moved by {} without transfers over blocks {from}..={to}", check.unexplained),
+            Ok(_) => {}
+            Err(e) => eprintln!("could not check the supply of {name}: {e}"),
+        }
+    }
+}
+
+/**
+ * @gist prints the findings of check::check and a summary of the feeds,
+ * alerts and sinks the config would run
+ * @param provider -- any ethers middleware, http or ipc
+ * @param args -- the parsed command line
+ * @param config -- the parsed config file
+**/
+async fn check_config<M: Middleware + 'static>(provider: Arc<M>, args: &Args, config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let path = args.config.as_deref().ok_or("config check needs --config")?;
+    println!("{} parses", path.display());
+
+    let findings = check::check(provider.clone(), &config).await?;
+    for finding in &findings {
+        let level = match finding.level {
+            Level::Ok => "ok",
+            Level::Warning => "warning",
+            Level::Error => "error",
+        };
+        println!("{level:<7} {} {}: {}", finding.table, finding.name, finding.message);
+    }
+    let listed = config.pool.len();
+    let pools = config.tokens.pools(provider, config.pool.clone()).await;
+    if pools.len() < listed {
+        println!("warning [tokens] keeps {} of the {listed} pools out", listed - pools.len());
+    }
+
+    let feeds = [
+        (pools.len(), "[[pool]]"),
+        (config.oracle.len(), "[[oracle]]"),
+        (config.composite.len(), "[[composite]]"),
+        (config.basket.len(), "[[basket]]"),
+        (config.vault.len(), "[[vault]]"),
+        (config.rate.len(), "[[rate]]"),
+        (config.v3.len(), "[[v3]]"),
+    ];
+    let total: usize = feeds.iter().map(|(n, _)| n).sum();
+    let parts: Vec<String> = feeds.iter().filter(|(n, _)| *n > 0).map(|(n, table)| format!("{n} {table}")).collect();
+    println!();
+    println!("would poll {total} feeds{}", if parts.is_empty() { String::new() } else { format!(": {}", parts.join(", ")) });
+
+    let rules: Vec<&str> = config.alert.iter().map(|r| r.name.as_str()).collect();
+    println!("alerts: {}", if rules.is_empty() { "none".to_string() } else { rules.join(", ") });
+    let targets: Vec<&str> = [config.notify.webhook.as_ref().map(|_| "webhook"), config.notify.telegram.as_ref().map(|_| "telegram")]
+        .into_iter().flatten().collect();
+    println!("notify: {}", if targets.is_empty() { "stderr only".to_string() } else { targets.join(", ") });
+    let sinks: Vec<String> = config.sink.iter()
+        .map(|s| match &s.kind {
+            SinkKind::File { path, .. } => format!("file {}", path.display()),
+            SinkKind::Webhook { url } => format!("webhook {url}"),
+        })
+        .collect();
+    println!("sinks: stdout{}", sinks.iter().map(|s| format!(", {s}")).collect::<String>());
+    for ns in &config.namespace {
+        println!("namespace {}: {} [[pool]], {} alerts, {} sinks", ns.name, ns.pool.len(), ns.alert.len(), ns.sink.len());
+    }
+    let extras: Vec<&str> = [
+        config.subgraph.as_ref().map(|_| "subgraph"),
+        config.etherscan.as_ref().map(|_| "etherscan"),
+        config.fallback.as_ref().map(|_| "fallback"),
+        config.cex.as_ref().map(|_| "cex"),
+        config.aggregator.as_ref().map(|_| "aggregator"),
+        config.simulate.as_ref().map(|_| "simulate"),
+    ].into_iter().flatten().collect();
+    if !extras.is_empty() {
+        println!("also configured: {}", extras.join(", "));
+    }
+
+    let errors = findings.iter().filter(|f| f.level == Level::Error).count();
+    if errors > 0 {
+        return Err(format!("{errors} problems in {}", path.display()).into());
+    }
+    Ok(())
+}
+
+/**
+ * @gist adds the quote to the rolling window of its asset and fills in the
+ * change and the indicators from it
+ * @param state -- holds the windows
+ * @param args -- the indicator periods
+ * @param quote -- the new quote
+ * @param now -- unix time of the quote
+**/
+fn with_indicators(state: &mut State, args: &Args, mut quote: Quote, now: u64) -> Quote {
+    let window = state.window(namespace::of(&quote), &quote.asset, quote.address);
+    window.push(now, quote.price);
+    quote.change_24h = window.change();
+    if let Some((rate, accel)) = window.derivatives(args.derivative_lookback) {
+        quote.change_per_min = Some(rate);
+        quote.accel_per_min2 = Some(accel);
+    }
+    quote.bollinger = window.bollinger(args.bollinger_period, args.bollinger_k);
+    quote.rsi = window.rsi(args.rsi_period);
+    quote
+}
+
+/**
+ * @gist feeds the quotes of history files through the indicators, alert
+ * rules and sinks of the config in the order they were taken, the alerts
+ * see the recorded times
+ * @param replay_args -- inputs, range and speed
+ * @param args -- the parsed command line
+ * @param config -- alerts, notify targets and sinks
+**/
+async fn replay(replay_args: &ReplayArgs, args: &Args, config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let now = clock::unix_now();
+    let from = replay_args.from.as_deref().map(|t| clock::parse_time(t, now)).transpose()?.unwrap_or(0);
+    let to = replay_args.to.as_deref().map(|t| clock::parse_time(t, now)).transpose()?.unwrap_or(u64::MAX);
+    let time = clock::TimeStyle::new(args.timezone, args.time_format.clone())?;
+
+    let mut rows = Vec::new();
+    for input in &replay_args.inputs {
+        rows.extend(history::rows(input)?);
+    }
+    rows.retain(|row| row.timestamp.is_some_and(|t| (from..=to).contains(&t))
+        && replay_args.asset.as_ref().is_none_or(|asset| *asset == row.asset));
+    // Stable, so quotes of the same time keep the order of the inputs
+    rows.sort_by_key(|row| row.timestamp);
+
+    // Backtests block rather than drop quotes when a sink falls behind
+    let client = provider::http_client(&args.http)?;
+    let mut sinks = Dispatcher::new();
+    sinks.add(Arc::new(Stdout::new(args.format.unwrap_or(Format::Text)).with_sparkline(args.sparkline).with_time_style(time.clone())), 1024, Overflow::Block);
+    for s in &config.sink {
+        sinks.add(sink::from_config(&s.kind, &client)?, s.queue, Overflow::Block);
+    }
+    let mut alerts = AlertEngine::new(config.alert);
+    let notifier = Notifier::new(client, config.notify);
+
+    let mut state = State::default();
+    let (mut previous, mut fired) = (None, 0);
+    for row in &rows {
+        let t = row.timestamp.expect("rows without a time were dropped");
+        if let Some(previous) = previous.filter(|_| replay_args.speed > 0.0) {
+            tokio::time::sleep(Duration::from_secs_f64((t - previous) as f64 / replay_args.speed)).await;
+        }
+        previous = Some(t);
+
+        let quote = with_indicators(&mut state, args, row.quote(), t);
+        sinks.send(&quote).await;
+        for alert in alerts.observe_at(&quote, t) {
+            fired += 1;
+            match replay_args.notify {
+                true => notifier.send(&alert).await,
+                false => eprintln!("{} ALERT {}", time.render(t), alert.message),
+            }
+        }
+    }
+    sinks.flush().await;
+    eprintln!("replayed {} quotes, {fired} alerts fired", rows.len());
+    Ok(())
+}
+
+/**
+ * @gist quotes the feeds of every block a reorg orphaned again, at the same
+ * height of the new chain
+ * @param provider -- any ethers middleware, http or ipc
+ * @param reorgs -- the blocks quoted so far
+ * @param feeds -- the feeds of watch mode
+ * @output the new quotes, marked reorged
+**/
+async fn requote_orphaned<M: Middleware + 'static>(provider: &M, reorgs: &mut Reorgs, feeds: &[Feed]) -> Vec<Quote> {
+    let orphaned = match reorgs.check(provider).await {
+        Ok(orphaned) => orphaned,
+        Err(e) => {
+            eprintln!("could not check for reorgs: {e}");
+            return Vec::new();
+        }
+    };
+
+    let mut quotes = Vec::new();
+    for o in orphaned {
+        let affected: Vec<Feed> = feeds.iter().filter(|f| o.assets.contains(&f.asset.name)).cloned().collect();
+        eprintln!("block {} was reorged, quoting {} feeds again", o.block, affected.len());
+
+        let results = provider::pinned_at(Some(o.block.into()), poller::poll(&affected)).await;
+
+        for (feed, result) in affected.iter().zip(results) {
+            match result {
+                Ok(mut quote) => {
+                    quote.reorged = true;
+                    quotes.push(quote);
+                }
+                Err(e) => eprintln!("could not quote {} again at block {}: {e}", feed.asset.name, o.block),
+            }
+        }
+    }
+    quotes
+}
+
+/**
+ * @gist keeps the raw values of a quote only when they were asked for
+**/
+fn keep_raw(quote: Quote, verbose: bool) -> Quote {
+    if verbose { quote } else { quote.without_raw() }
+}
+
+/**
+ * @gist fails the quote of a V2 route when its pool was last updated more
+ * than max_age seconds before the block it was read at
+ * @param provider -- any ethers middleware, http or ipc
+ * @param asset -- the asset of the feed, a TOKEN/ETH pair for routes
+ * @param quote -- the quote of the feed
+ * @param max_age -- the --max-age in seconds
+**/
+async fn check_age<M: Middleware + 'static>(provider: Arc<M>, asset: &Asset, quote: Quote, max_age: u64) -> eyre::Result<Quote> {
+    // Only routes keep the reserves they were priced from
+    let (Some(_), Some(block), Some(timestamp)) = (&quote.raw, quote.block, quote.timestamp) else {
+        return Ok(quote);
+    };
+    let (_, _, updated) = uniswap_v2::get_reserves_at(provider, &asset.address, Some(block.into())).await?;
+    let age = timestamp.saturating_sub(updated as u64);
+    if age > max_age {
+        return Err(Failure::new(Kind::Stale, format!("{} was last updated {age} seconds before block {block}", asset.name)).into());
+    }
+    Ok(quote)
+}
+
+/**
+ * @gist reads the pools of `--pool -`, one address or name per line, blank
+ * lines and # comments skipped
+ * @param book -- resolves the names
+ * @param chain -- the chain id of the provider
+ * @output every pool as given and its address, None when it doesn't resolve
+**/
+fn read_pools(book: &aliases::AddressBook, chain: u64) -> std::io::Result<Vec<(String, Option<Address>)>> {
+    let mut pools = Vec::new();
+    for line in std::io::stdin().lines() {
+        let line = line?;
+        let input = line.trim();
+        if input.is_empty() || input.starts_with('#') {
+            continue;
+        }
+        let pool = book.resolve(chain, input)
+            .inspect_err(|e| eprintln!("skipping {input}: {e}"))
+            .ok();
+        // Addresses are named in lower case, however they were written
+        let name = match pool {
+            Some(pool) if checksum::is_address(input) => format!("{pool:?}"),
+            _ => input.to_string(),
+        };
+        pools.push((name, pool));
+    }
+    Ok(pools)
+}
+
+/**
+ * @gist fills the rolling window of the --pool feed with the prices of the
+ * blocks that were missed since the state was saved. Only the --pool route
+ * is recovered, [[pool]] tables and the other sources keep the gap. Block
+ * times are interpolated between the first backfilled block and the current
+ * time, failures only cost the backfill.
+ * @param provider -- any ethers middleware, http or ipc
+ * @param state -- the restored state
+ * @param start_a -- the TOKEN/ETH pair address
+ * @param end_a -- the ETH/USDT pair address
+**/
+async fn backfill_gap<M: Middleware + 'static>(provider: Arc<M>, state: &mut State, start_a: &Address, end_a: &Address) {
+    let (Some(from), Some(from_time)) = (state.block, state.timestamp) else {
+        return;
+    };
+    let to = match provider.get_block_number().await {
+        Ok(block) => block.as_u64(),
+        Err(e) => return eprintln!("backfill skipped: {e}"),
+    };
+    if to <= from {
+        return;
+    }
+
+    // A gap longer than MAX_BLOCKS starts later than the saved block
+    let clamped = from.max(to.saturating_sub(backfill::MAX_BLOCKS));
+    let from_time = match clamped == from {
+        true => from_time,
+        false => match backfill::block_time(provider.as_ref(), clamped).await {
+            Ok(time) => time,
+            Err(e) => return eprintln!("backfill skipped: {e}"),
+        },
+    };
+    let from = clamped;
+    let to_time = clock::unix_now();
+    let prices = match backfill::sync_prices(provider, start_a, end_a, from, to).await {
+        Ok(prices) => prices,
+        Err(e) => return eprintln!("backfill failed: {e}"),
+    };
+
+    eprintln!("backfilled {} prices from blocks {}..={to}", prices.len(), from + 1);
+    let window = state.window(None, "TOKEN/USDT", Some(*start_a));
+    for (block, price) in prices {
+        window.push(backfill::interpolate(block, (from, from_time), (to, to_time)), price);
+    }
+}
+
+/**
+ * @gist prints the route price after every Sync event of a range. The times
+ * of the blocks in between the two ends are interpolated.
+ * @param provider -- any ethers middleware, http or ipc
+ * @param backfill_args -- the range, as blocks or times
+ * @param start_a -- the TOKEN/ETH pair address
+ * @param end_a -- the ETH/USDT pair address
+ * @param format -- text, csv or json
+ * @param time -- zone and format of the times in text mode
+**/
+async fn backfill_range<M: Middleware + 'static>(provider: Arc<M>, backfill_args: &BackfillArgs, start_a: &Address, end_a: &Address, format: Format, time: &clock::TimeStyle) -> Result<(), Box<dyn std::error::Error>> {
+    let (from, to) = block_range(provider.as_ref(), backfill_args).await?;
+
+    let from_time = backfill::block_time(provider.as_ref(), from).await?;
+    let to_time = backfill::block_time(provider.as_ref(), to).await?;
+    eprintln!("backfilling blocks {from}..={to} ({} to {})", clock::rfc3339(from_time), clock::rfc3339(to_time));
+
+    // The reserves at the start are read at the block before it
+    let prices = backfill::sync_prices(provider, start_a, end_a, from.saturating_sub(1), to).await?;
+    if format == Format::Csv {
+        println!("{}", format::csv_header());
+    }
+    for (block, price) in prices {
+        let mut quote = Quote::new("UNI V2", "TOKEN/USDT", price);
+        quote.block = Some(block);
+        quote.timestamp = Some(backfill::interpolate(block, (from, from_time), (to, to_time)));
+        println!("{}", format::render_with(&quote, format, time));
+    }
+    Ok(())
+}
+
+/**
+ * @gist the blocks of a range given as blocks or as times
+ * @output the first and the last block, both in the range
+**/
+async fn block_range<M: Middleware + 'static>(provider: &M, range: &BackfillArgs) -> Result<(u64, u64), Box<dyn std::error::Error>> {
+    let now = clock::unix_now();
+    let from = match (range.from_block, &range.from) {
+        (Some(block), _) => block,
+        (None, Some(from)) => backfill::block_at(provider, clock::parse_time(from, now)?).await?,
+        (None, None) => unreachable!("clap requires --from or --from-block"),
+    };
+    let to = match (range.to_block, &range.to) {
+        (Some(block), _) => block,
+        // The last block before the end, the end itself belongs to the next range
+        (None, Some(to)) => backfill::block_at(provider, clock::parse_time(to, now)?).await?.saturating_sub(1),
+        (None, None) => provider.get_block_number().await?.as_u64(),
+    };
+    if to < from {
+        return Err(format!("the range ends at block {to}, before its start at block {from}").into());
+    }
+    Ok((from, to))
+}
+
+/**
+ * @gist prints the route at blocks of an archive, like backfilled quotes
+ * @param at_args -- the archive and the blocks
+ * @param args -- for the output format and the time style
+**/
+fn print_archived(at_args: &AtArgs, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let format = args.format.unwrap_or(Format::Text);
+    let time = clock::TimeStyle::new(args.timezone, args.time_format.clone())?;
+    let points = archive::at(&at_args.archive, &at_args.blocks)?;
+    if format == Format::Csv {
+        println!("{}", format::csv_header());
+    }
+    for point in points {
+        let mut quote = Quote::new("UNI V2", "TOKEN/USDT", point.price);
+        quote.block = Some(point.block);
+        quote.timestamp = Some(point.timestamp);
+        println!("{}", format::render_with(&quote, format, &time));
+        if format == Format::Text {
+            println!("  TOKEN/ETH reserves {} / {}, ETH/USDT reserves {} / {}", point.start.0, point.start.1, point.end.0, point.end.1);
+        }
+    }
+    Ok(())
+}
+
+/**
+ * @gist polls the route and paper-trades it until ctrl-c, then prints the
+ * result
+ * @param provider -- any ethers middleware, http or ipc
+ * @param start_a -- the TOKEN/ETH pair address
+ * @param end_a -- the ETH/USDT pair address
+ * @param interval -- time between two polls
+ * @param sim_config -- the entry and exit rules
+ * @param format -- json prints the trades as JSON lines, anything else as
+ * text with a status line per poll
+**/
+async fn paper_trade<M: Middleware + 'static>(provider: Arc<M>, start_a: &Address, end_a: &Address, interval: Duration, sim_config: SimulateConfig, format: Format) -> Result<(), Box<dyn std::error::Error>> {
+    let mut sim = Simulator::new(sim_config);
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+        let route = match uniswap_v2::get_route(provider.clone(), start_a, end_a).await {
+            Ok(route) => route,
+            Err(e) => {
+                eprintln!("poll failed: {e}");
+                continue;
+            }
+        };
+
+        let event = sim.step(&route);
+        if format == Format::Json {
+            if let Some(event) = event {
+                println!("{}", serde_json::to_string(&event)?);
+            }
+            continue;
+        }
+
+        match event {
+            Some(Event::Buy { price, tokens, cost, .. }) => println!("BUY  {tokens} TOKEN at ${price} for ${cost}"),
+            Some(Event::Sell { price, tokens, proceeds, pnl, reason, .. }) => println!("SELL {tokens} TOKEN at ${price} for ${proceeds} ({reason}, pnl ${pnl:+.2})"),
+            None => {}
+        }
+        let position = match (sim.position(), sim.unrealized(&route)) {
+            (Some(p), Some(u)) => format!("long {} TOKEN (unrealized ${u:+.2})", p.tokens),
+            _ => "flat".to_string(),
+        };
+        println!("[SIM] mid ${} | {position} | realized ${:+.2} over {} trades", route.mid_price(), sim.realized(), sim.trades());
+    }
+
+    eprintln!("realized ${:+.2} over {} trades", sim.realized(), sim.trades());
+    Ok(())
+}
+
+/**
+ * @gist prints the depth curve of the TOKEN -> ETH -> USDT route
+ * @param provider -- any ethers middleware, http or ipc
+ * @param curve_args -- pool, side and sizes to sweep
+ * @param format -- csv or json, text is printed as csv
+ * @param start_a -- the TOKEN/ETH pair address
+ * @param end_a -- the ETH/USDT pair address
+**/
+async fn print_curve<M: Middleware + 'static>(provider: Arc<M>, curve_args: &CurveArgs, format: Format, start_a: &Address, end_a: &Address) -> Result<(), Box<dyn std::error::Error>> {
+    let route = uniswap_v2::get_route(provider, start_a, end_a).await?;
+    let points = curve::depth_curve(&route, curve_args.side, curve_args.max, curve_args.steps);
+
+    match format {
+        Format::Text | Format::Csv => {
+            println!("size,price,impact_pct");
+            for p in &points {
+                println!("{},{},{}", p.size, p.price, p.impact);
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(&points)?),
+    }
 
     Ok(())
+}
+
+/**
+ * @gist fires a rule at a made-up price and reports how every notifier
+ * took it, the rules of the namespaces go to their own targets
+ * @param test_args -- the rule and the price
+ * @param args -- for the HTTP options of the notifiers
+ * @param config -- the rules and the [notify] targets
+**/
+async fn test_alert(test_args: &AlertTestArgs, args: &Args, config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    // The top level rules, then those of every namespace, also known as
+    // namespace/rule like in the state file
+    let top = config.alert.iter().map(|rule| (None, rule, &config.notify));
+    let namespaced = config.namespace.iter().flat_map(|ns| {
+        let notify = ns.notify.as_ref().unwrap_or(&config.notify);
+        ns.alert.iter().map(move |rule| (Some(ns.name.as_str()), rule, notify))
+    });
+    let found: Vec<_> = top.chain(namespaced)
+        .filter(|(ns, rule, _)| rule.name == test_args.rule || ns.is_some_and(|ns| test_args.rule == format!("{ns}/{}", rule.name)))
+        .collect();
+    let (ns, rule, notify) = match found.as_slice() {
+        [] => return Err(format!("no [[alert]] or [[namespace.alert]] is named {:?}", test_args.rule).into()),
+        [one] => *one,
+        many => {
+            let names: Vec<String> = many.iter().map(|(ns, rule, _)| match ns {
+                Some(ns) => format!("{ns}/{}", rule.name),
+                None => rule.name.clone(),
+            }).collect();
+            return Err(format!("{} rules are named {:?}, pick one of {}", many.len(), test_args.rule, names.join(", ")).into());
+        }
+    };
+    match ns {
+        Some(ns) => println!("rule {} of [[namespace]] {ns}", rule.name),
+        None => println!("rule {} of the top level", rule.name),
+    }
+    let Some(alert) = rule.test(test_args.price) else {
+        return Err(format!("{} does not fire at {}, nothing was sent", rule.name, test_args.price).into());
+    };
+    println!("{}", alert.message);
 
+    let notifier = Notifier::new(provider::http_client(&args.http)?, notify.clone());
+    let outcomes = notifier.deliver(&alert).await;
+    if outcomes.is_empty() {
+        return Err("the config has no [notify] targets, the alert only went to stdout".into());
+    }
+    let mut failed = 0;
+    for (target, res) in outcomes {
+        match res {
+            Ok(()) => println!("{target}: delivered"),
+            Err(e) => {
+                println!("{target}: failed, {e}");
+                failed += 1;
+            }
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        n => Err(format!("{n} notifier(s) failed").into()),
+    }
+}
+
+/**
+ * @gist prints what `health` found about a pool
+ * @param health -- the report
+ * @param format -- json, anything else is printed as text
+**/
+fn print_health(health: &health::Health, format: Format) -> Result<(), Box<dyn std::error::Error>> {
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(health)?);
+        return Ok(());
+    }
+
+    let scores = &health.scores;
+    let symbol = health.symbol.as_deref().unwrap_or("TOKEN");
+    println!("pool           {:?} ({symbol}/WETH, block {})", health.pool, health.block);
+    println!("liquidity      ${:.0} [{}/2]", health.liquidity_usd, scores.liquidity);
+    match health.last_trade_age {
+        Some(age) => println!("last trade     {age}s ago [{}/2]", scores.activity),
+        None => println!("last trade     none in 24h, reserves changed {}s ago [{}/2]", health.last_update_age, scores.activity),
+    }
+    println!("volume 24h     ${:.0} in {} swaps [{}/2]", health.volume_24h_usd, health.swaps_24h, scores.volume);
+    match &health.top_holder {
+        Some(holder) => println!("LP holders     top {:?} holds {:.1}%, {:.1}% burned, {} seen [{}/2]",
+            holder.address, holder.share * 100.0, health.burned_share * 100.0, health.holders_seen, scores.concentration),
+        None => println!("LP holders     none seen, {:.1}% burned [{}/2]", health.burned_share * 100.0, scores.concentration),
+    }
+    match (health.buy_tax, health.fee_on_transfer) {
+        (Some(tax), true) => println!("transfer tax   fee-on-transfer, {:.2}% of a buy [{}/2]", tax * 100.0, scores.tax),
+        (Some(_), false) => println!("transfer tax   none [{}/2]", scores.tax),
+        (None, _) => println!("transfer tax   unknown, no recent buy [{}/2]", scores.tax),
+    }
+    if !health.flags.is_empty() {
+        let flags: Vec<String> = health.flags.iter().map(|f| f.to_string()).collect();
+        println!("flags          {}", flags.join(", "));
+    }
+    println!("grade          {}", health.grade);
+    Ok(())
 }
 
 /**
- * @gist converts wei into eth values, 1 ETH = 10**16 wei
- * @param wei_int -- this is the wei value to be converted into eth
- * @output -- ETH value
+ * @gist prints the reports of `bench`, one line per mode
+ * @param reports -- in the order the modes ran
+ * @param format -- text, csv or json
 **/
-fn reformat_wei(wei_int: u128) -> f64 {
-    wei_int as f64 / 10_f64.powf(16.0)
+fn print_bench(reports: &[bench::Report], format: Format) -> Result<(), Box<dyn std::error::Error>> {
+    match format {
+        Format::Text => {
+            println!("{:<12}{:>7}{:>9}{:>8}{:>10}{:>10}{:>10}{:>11}{:>10}", "mode", "pools", "quotes", "errors", "p50 ms", "p95 ms", "max ms", "pools/s", "delay ms");
+            for r in reports {
+                let delay = r.delay_p50_ms.map(|d| format!("{d:.0}")).unwrap_or_default();
+                println!("{:<12}{:>7}{:>9}{:>8}{:>10.1}{:>10.1}{:>10.1}{:>11.1}{delay:>10}", r.mode.name(), r.pools, r.quotes, r.errors, r.p50_ms, r.p95_ms, r.max_ms, r.pools_per_sec);
+            }
+        }
+        Format::Csv => {
+            println!("mode,pools,rounds,quotes,errors,p50_ms,p95_ms,max_ms,pools_per_sec,delay_p50_ms");
+            for r in reports {
+                let delay = r.delay_p50_ms.map(|d| d.to_string()).unwrap_or_default();
+                println!("{},{},{},{},{},{},{},{},{},{delay}", r.mode.name(), r.pools, r.rounds, r.quotes, r.errors, r.p50_ms, r.p95_ms, r.max_ms, r.pools_per_sec);
+            }
+        }
+        Format::Json => println!("{}", serde_json::to_string_pretty(reports)?),
+    }
+    Ok(())
 }
 
 /**
- * @gist converts the USDT values in UNI pools into decimals
- * @param usd_int -- this is the usdt value returned by a UNI pool
- * @output USDT value
+ * @gist prints what `info` found about a pair, addresses from the address
+ * book are shown with their name
+ * @param info -- the pair metadata
+ * @param book -- the address book
+ * @param chain -- the chain id of the provider
+ * @param format -- json, anything else is printed as text
+ * @param time -- zone and format of the update time
 **/
-fn reformat_usd(usd_int:u128) -> f64 {
-    usd_int as f64 / 10_f64.powf(4.0)
+fn print_info(info: &uniswap_v2::PairInfo, book: &aliases::AddressBook, chain: u64, format: Format, time: &clock::TimeStyle) -> Result<(), Box<dyn std::error::Error>> {
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(info)?);
+        return Ok(());
+    }
+
+    let named = |address: &Address| match book.name_of(chain, address) {
+        Some(name) => format!("{name} ({address:?})"),
+        None => format!("{address:?}"),
+    };
+    let symbol0 = info.token0.symbol.as_deref().unwrap_or("token0");
+    let symbol1 = info.token1.symbol.as_deref().unwrap_or("token1");
+
+    println!("pair      {}", named(&info.address));
+    println!("factory   {}", named(&info.factory));
+    println!("token0    {symbol0} {} ({} decimals)", named(&info.token0.address), info.token0.decimals);
+    println!("token1    {symbol1} {} ({} decimals)", named(&info.token1.address), info.token1.decimals);
+    println!("reserves  {} / {}", info.reserve0, info.reserve1);
+    println!("updated   {}", time.render(info.last_update.into()));
+    println!("price     1 {symbol0} = {} {symbol1}", info.price0);
+    println!("          1 {symbol1} = {} {symbol0}", info.price1);
+    Ok(())
+}
+
+/**
+ * @gist reads commands from stdin until quit or the end of the input, a
+ * failing command only prints its error
+ * @param provider -- any ethers middleware, http or ipc
+ * @param book -- resolves pool names
+ * @param chain -- the chain of the provider, for the names
+ * @param alerts -- the rules of the config, evaluated on every quote
+ * @param format -- text, csv or json
+ * @param time -- zone and format of the times in text mode
+**/
+async fn run_repl<M: Middleware + 'static>(provider: Arc<M>, book: &aliases::AddressBook, chain: u64, mut alerts: AlertEngine, format: Format, time: &clock::TimeStyle) -> Result<(), Box<dyn std::error::Error>> {
+    let mut pairs = Pairs::new(provider, uniswap_v2::ETH_USDT_POOL.parse()?);
+    let mut watched: Vec<(String, Address)> = Vec::new();
+    // Piped input gets no prompts
+    let prompt = std::io::stdin().is_terminal();
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    loop {
+        if prompt {
+            print!("> ");
+            std::io::stdout().flush()?;
+        }
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match line.parse::<repl::Command>() {
+            Ok(command) => command,
+            Err(e) => {
+                eprintln!("{e}");
+                continue;
+            }
+        };
+
+        let resolve = |pool: &str| book.resolve(chain, pool);
+        let mut print_quote = |name: &str, quote: eyre::Result<Quote>| match quote {
+            Ok(quote) => {
+                println!("{}", format::render_with(&quote.clone().without_raw(), format, time));
+                for alert in alerts.observe(&quote) {
+                    println!("ALERT {}", alert.message);
+                }
+            }
+            Err(e) => eprintln!("could not quote {name}: {e}"),
+        };
+        match command {
+            repl::Command::Price(pool) => match resolve(&pool) {
+                Ok(address) => print_quote(&pool, pairs.quote(&pool, &address).await),
+                Err(e) => eprintln!("{e}"),
+            },
+            repl::Command::Info(pool) => match resolve(&pool) {
+                Ok(address) => match pairs.info(&address).await {
+                    Ok(info) => print_info(&info, book, chain, format, time)?,
+                    Err(e) => eprintln!("{e}"),
+                },
+                Err(e) => eprintln!("{e}"),
+            },
+            repl::Command::WatchAdd(pool) => match resolve(&pool) {
+                Ok(address) if watched.iter().any(|(_, a)| *a == address) => eprintln!("{pool} is watched already"),
+                Ok(address) => watched.push((pool, address)),
+                Err(e) => eprintln!("{e}"),
+            },
+            repl::Command::WatchRm(pool) => {
+                let before = watched.len();
+                watched.retain(|(name, address)| *name != pool && resolve(&pool).ok() != Some(*address));
+                if watched.len() == before {
+                    eprintln!("{pool} is not watched");
+                }
+            }
+            repl::Command::WatchList => {
+                for (name, address) in &watched {
+                    println!("{name} {address:?}");
+                }
+            }
+            repl::Command::Poll => {
+                for (name, address) in &watched {
+                    print_quote(name, pairs.quote(name, address).await);
+                }
+            }
+            repl::Command::AlertsList => {
+                for (rule, state) in alerts.rules() {
+                    let status = match (state.firing, state.last_fired) {
+                        (true, _) => "firing".to_string(),
+                        (false, Some(last)) => format!("last fired {}", time.render(last)),
+                        (false, None) => "armed".to_string(),
+                    };
+                    println!("{} on {}: {status}", rule.name, rule.asset);
+                }
+            }
+            repl::Command::Help => println!("{}", repl::HELP),
+            repl::Command::Quit => break,
+        }
+    }
+    Ok(())
+}
+
+/// The pools watch-new-pairs prices and adds to
+struct Watchlist<'a> {
+    /// The config the added pools are written to, None keeps them in memory
+    path: Option<&'a Path>,
+    pools: Vec<pools::PoolConfig>,
+}
+
+/**
+ * @gist prints the pairs created on the factories every interval until
+ * interrupted, failed reads are retried on the next tick. With --auto-add
+ * the watchlist is priced too and grows by the new pairs that qualify.
+ * @param provider -- any ethers middleware, http or ipc
+ * @param pair_args -- factories, interval, notifications and criteria
+ * @param end_a -- the ETH/USDT pair
+ * @param notifier -- where the new pairs are sent with --notify
+ * @param watchlist -- the pools of the config
+ * @param tokens -- new pairs of other tokens are left out
+ * @param format -- text, csv or json
+**/
+async fn watch_new_pairs<M: Middleware + 'static>(provider: Arc<M>, pair_args: &WatchNewPairsArgs, end_a: &Address, notifier: &Notifier, mut watchlist: Watchlist<'_>, tokens: &TokenFilter, format: Format) -> Result<(), Box<dyn std::error::Error>> {
+    let factories = if pair_args.factories.is_empty() {
+        vec![uniswap_v2::UNISWAP_V2_FACTORY.parse()?, uniswap_v2::SUSHISWAP_FACTORY.parse()?]
+    } else {
+        pair_args.factories.clone()
+    };
+    let watcher = PairWatcher::new(provider.clone(), factories, *end_a)?;
+    let mut next = match pair_args.from_block {
+        Some(block) => block,
+        None => provider.get_block_number().await?.as_u64(),
+    };
+
+    let criteria = AddCriteria {
+        min_liquidity: pair_args.add_min_liquidity,
+        min_volume: pair_args.add_min_volume,
+        within: pair_args.add_within,
+    };
+    let feed = |pool: &pools::PoolConfig| {
+        let source = UniswapV2::new(provider.clone(), *end_a).with_layout(pool.inverted, pool.decimals);
+        Feed::new(Arc::new(source), pool.asset())
+    };
+    let mut feeds: Vec<Feed> = if pair_args.auto_add { watchlist.pools.iter().map(feed).collect() } else { Vec::new() };
+    let mut candidates: Vec<Candidate> = Vec::new();
+
+    if format == Format::Csv {
+        println!("block,tx,factory,pair,token,symbol,counter,price,liquidity,risk");
+    }
+    let mut ticker = tokio::time::interval(Duration::from_secs(pair_args.interval));
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+        let head = match provider.get_block_number().await {
+            Ok(head) => head.as_u64(),
+            Err(e) => {
+                eprintln!("poll failed: {e}");
+                continue;
+            }
+        };
+        if head < next {
+            continue;
+        }
+        let pairs = match watcher.new_pairs(next..=head).await {
+            Ok(pairs) => pairs.into_iter()
+                .filter(|p| tokens.permits(&[p.pair, p.token.address], &[p.token.symbol.as_deref().unwrap_or_default()]))
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                eprintln!("could not read new pairs: {e}");
+                continue;
+            }
+        };
+        next = head + 1;
+
+        for mut pair in pairs {
+            if pair.price.is_some() {
+                pair.risk = check_risk(provider.clone(), &pair, head, pair_args.max_tax).await;
+            }
+            println!("{}", render_pair(&pair, format));
+            let safe = pair_args.allow_risky || pair.risk.as_ref().is_some_and(|r| !r.is_risky());
+            if let (true, true, Some(price)) = (pair_args.notify, safe, pair.price) {
+                if pair.liquidity.unwrap_or_default() >= pair_args.min_liquidity {
+                    notifier.spawn(Alert {
+                        rule: "new-pair".to_string(),
+                        asset: pair.name(),
+                        price,
+                        message: format!("new pair {} at {:?}: ${price} (liquidity ${:.0}), tx {:?}", pair.name(), pair.pair, pair.liquidity.unwrap_or_default(), pair.tx),
+                    });
+                }
+            }
+            let known = watchlist.pools.iter().any(|p| p.address == pair.pair) || candidates.iter().any(|c| c.pair.pair == pair.pair);
+            if pair_args.auto_add && pair.pool().is_some() && !known {
+                candidates.push(Candidate::new(pair));
+            }
+        }
+
+        if !pair_args.auto_add {
+            continue;
+        }
+        // Candidates are checked every interval until they qualify or expire
+        let mut added = Vec::new();
+        let mut waiting = Vec::with_capacity(candidates.len());
+        for mut candidate in candidates.drain(..) {
+            if let Err(e) = watcher.update(&mut candidate, head).await {
+                eprintln!("could not check {}: {e}", candidate.pair.name());
+                waiting.push(candidate);
+                continue;
+            }
+            match candidate.pair.pool() {
+                Some(pool) if candidate.qualifies(&criteria) => {
+                    // The token may have changed since the listing, or not been tradeable yet
+                    let risk = check_risk(provider.clone(), &candidate.pair, head, pair_args.max_tax).await;
+                    if !pair_args.allow_risky && risk.as_ref().is_none_or(|r| r.is_risky()) {
+                        if !candidate.expired(&criteria, head) {
+                            waiting.push(candidate);
+                        }
+                        continue;
+                    }
+                    eprintln!("adding {} {:?} to the watchlist (liquidity ${:.0}, volume ${:.0})",
+                        candidate.pair.name(), candidate.pair.pair, candidate.pair.liquidity.unwrap_or_default(), candidate.volume);
+                    added.push(pool);
+                }
+                _ if candidate.expired(&criteria, head) => {}
+                _ => waiting.push(candidate),
+            }
+        }
+        candidates = waiting;
+
+        if let (Some(path), false) = (watchlist.path, added.is_empty()) {
+            if let Err(e) = pools::append(path, &watchlist.pools, &added) {
+                eprintln!("could not save the watchlist: {e}");
+            }
+        }
+        for pool in added {
+            feeds.push(feed(&pool));
+            watchlist.pools.push(pool);
+        }
+        // csv rows of new pairs and quotes don't share a header
+        if format == Format::Csv {
+            continue;
+        }
+        for quote in poller::poll(&feeds).await {
+            match quote {
+                Ok(quote) => println!("{}", format::render(&quote.without_raw(), format)),
+                Err(e) => eprintln!("{e}"),
+            }
+        }
+    }
+}
+
+/**
+ * @gist prices the round trips of a cycle every interval until interrupted,
+ * a failed check is retried on the next tick
+ * @param provider -- any ethers middleware, http or ipc
+ * @param arb_args -- interval, margin, size and notifications
+ * @param cycle -- the three pools
+ * @param notifier -- where opportunities are sent with --notify
+ * @param format -- text, csv or json
+ * @param time -- how times are shown in text output
+**/
+async fn scan_cycle<M: Middleware + 'static>(provider: Arc<M>, arb_args: &TriArbArgs, cycle: &Cycle, notifier: &Notifier, format: Format, time: &clock::TimeStyle) -> Result<(), Box<dyn std::error::Error>> {
+    // Directions above the margin, alerted once until they fall below it
+    let mut open: HashSet<String> = HashSet::new();
+    if format == Format::Csv {
+        println!("time,path,product,bps,block");
+    }
+    let mut ticker = tokio::time::interval(Duration::from_secs(arb_args.interval));
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+        let trips = match cycle.check(provider.clone(), arb_args.size).await {
+            Ok(trips) => trips,
+            Err(e) => {
+                eprintln!("poll failed: {e}");
+                continue;
+            }
+        };
+        let now = clock::unix_now();
+        for trip in trips {
+            let profitable = trip.bps > arb_args.min_bps;
+            match format {
+                Format::Text => println!("{}{}: {:.6} ({:+.1} bps) (block {}, {})", if profitable { "[ARB] " } else { "" }, trip.path, trip.product, trip.bps, trip.block, time.render(now)),
+                Format::Csv => println!("{now},{},{},{},{}", trip.path, trip.product, trip.bps, trip.block),
+                Format::Json => println!("{}", serde_json::json!({ "time": now, "path": trip.path, "product": trip.product, "bps": trip.bps, "block": trip.block })),
+            }
+            if !profitable {
+                open.remove(&trip.path);
+            } else if open.insert(trip.path.clone()) && arb_args.notify {
+                notifier.spawn(Alert {
+                    rule: "tri-arb".to_string(),
+                    asset: trip.path.clone(),
+                    price: trip.product,
+                    message: format!("{} returns {:.6} ({:+.1} bps) at block {}", trip.path, trip.product, trip.bps, trip.block),
+                });
+            }
+        }
+    }
 }
 
 /**
- * @gist get_reserves returns the reserves of tokens in a given uniswap pool.
- * @param provider -- this is used to send request to the UniswapV2Pair SC 
- * @param pair_address -- the pair address you want the reserves from
- * @output a future object containing the values of the reserves and a timestamp.
+ * @gist prints the price of one token in another, once or every interval
+ * until interrupted
+ * @param provider -- any ethers middleware, http or ipc
+ * @param ratio_args -- factories and interval
+ * @param base -- token A
+ * @param quote -- token B
+ * @param end_a -- the ETH/USDT pair
+ * @param format -- text, csv or json
 **/
-fn get_reserves<'a>(provider: Arc<Provider<Http>>, pair_address: &'a Address) -> impl Future<Output = Result<(u128, u128, u32), ContractError<Provider<Http>>>> + 'a {
+async fn print_ratio<M: Middleware + 'static>(provider: Arc<M>, ratio_args: &RatioArgs, base: Address, quote: Address, end_a: &Address, format: Format) -> Result<(), Box<dyn std::error::Error>> {
+    let factories = if ratio_args.factories.is_empty() {
+        vec![uniswap_v2::UNISWAP_V2_FACTORY.parse()?, uniswap_v2::SUSHISWAP_FACTORY.parse()?]
+    } else {
+        ratio_args.factories.clone()
+    };
+    let base = Leg::find(provider.clone(), &factories, base).await?;
+    let quote = Leg::find(provider.clone(), &factories, quote).await?;
 
-    async move {
-        let uniswap_v2_pair = IUniswapV2Pair::new(*pair_address, provider);
-        uniswap_v2_pair.get_reserves().call().await
+    if format == Format::Csv {
+        println!("pair,ratio,base_usd,quote_usd,block");
     }
+    let Some(interval) = ratio_args.interval else {
+        let ratio = ratio::ratio(provider, *end_a, &base, &quote).await?;
+        println!("{}", render_ratio(&ratio, format));
+        return Ok(());
+    };
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+        match ratio::ratio(provider.clone(), *end_a, &base, &quote).await {
+            Ok(ratio) => println!("{}", render_ratio(&ratio, format)),
+            Err(e) => eprintln!("poll failed: {e}"),
+        }
+    }
+}
+
+fn render_ratio(ratio: &ratio::Ratio, format: Format) -> String {
+    let block = ratio.block.map(|b| b.to_string()).unwrap_or_default();
+    match format {
+        Format::Text => format!("{}: {} (${} / ${}) (block {block})", ratio.pair, ratio.ratio, ratio.base_usd, ratio.quote_usd),
+        Format::Csv => format!("{},{},{},{},{block}", ratio.pair, ratio.ratio, ratio.base_usd, ratio.quote_usd),
+        Format::Json => serde_json::to_string(ratio).unwrap_or_else(|e| format!("{{\"error\":\"could not serialize ratio: {e}\"}}")),
+    }
+}
+
+/**
+ * @gist checks the stablecoin pegs every interval until interrupted, a
+ * failed check is retried on the next tick
+ * @param provider -- any ethers middleware, http or ipc
+ * @param depeg_args -- interval, threshold and notifications
+ * @param notifier -- where depegs are sent with --notify
+ * @param format -- text, csv or json
+ * @param time -- how times are shown in text output
+**/
+async fn watch_depeg<M: Middleware + 'static>(provider: Arc<M>, depeg_args: &DepegArgs, notifier: &Notifier, format: Format, time: &clock::TimeStyle) -> Result<(), Box<dyn std::error::Error>> {
+    // Prices already away from the peg, alerted once until they return
+    let mut off_peg: HashSet<String> = HashSet::new();
+    if format == Format::Csv {
+        println!("time,asset,price,bps");
+    }
+    let mut ticker = tokio::time::interval(Duration::from_secs(depeg_args.interval));
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+        let pegs = match depeg::check(provider.clone()).await {
+            Ok(pegs) => pegs,
+            Err(e) => {
+                eprintln!("poll failed: {e}");
+                continue;
+            }
+        };
+        let now = clock::unix_now();
+        for peg in pegs {
+            let off = peg.bps.abs() > depeg_args.max_bps;
+            match format {
+                Format::Text => println!("{}{}: ${:.4} ({:+.1} bps, {})", if off { "[DEPEG] " } else { "" }, peg.asset, peg.price, peg.bps, time.render(now)),
+                Format::Csv => println!("{now},{},{},{}", peg.asset, peg.price, peg.bps),
+                Format::Json => println!("{}", serde_json::json!({ "time": now, "asset": peg.asset, "price": peg.price, "bps": peg.bps })),
+            }
+            if !off {
+                off_peg.remove(&peg.asset);
+            } else if off_peg.insert(peg.asset.clone()) && depeg_args.notify {
+                notifier.spawn(Alert {
+                    rule: "depeg".to_string(),
+                    asset: peg.asset.clone(),
+                    price: peg.price,
+                    message: format!("{} is off its peg at ${:.4} ({:+.1} bps)", peg.asset, peg.price, peg.bps),
+                });
+            }
+        }
+    }
+}
+
+/**
+ * @gist runs the honeypot checks on the token of a pair, None when they
+ * could not run, which counts as risky
+**/
+async fn check_risk<M: Middleware + 'static>(provider: Arc<M>, pair: &NewPair, head: u64, max_tax: f64) -> Option<risk::Risk> {
+    risk::assess(provider, pair.pair, pair.token.address, head, max_tax).await
+        .inspect_err(|e| eprintln!("could not check {}: {e}", pair.name()))
+        .ok()
+}
+
+fn render_pair(pair: &NewPair, format: Format) -> String {
+    let show = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+    match format {
+        Format::Json => serde_json::to_string(pair).unwrap_or_else(|e| format!("{{\"error\":\"could not serialize pair: {e}\"}}")),
+        Format::Csv => {
+            let flags = pair.risk.as_ref().map(|r| r.flags.iter().map(|f| f.to_string()).collect::<Vec<_>>().join(" ")).unwrap_or_default();
+            format!("{},{:?},{:?},{:?},{:?},{},{},{},{},{flags}", pair.block, pair.tx, pair.factory, pair.pair, pair.token.address,
+                pair.token.symbol.as_deref().unwrap_or_default(), pair.counter, show(pair.price), show(pair.liquidity))
+        }
+        Format::Text => {
+            let mut out = match (pair.price, pair.liquidity) {
+                (Some(price), Some(liquidity)) => format!("[NEW] {} {:?}: ${price} (liquidity ${liquidity:.0}) (block {})", pair.name(), pair.pair, pair.block),
+                _ => format!("[NEW] {} {:?}: no liquidity yet (block {})", pair.name(), pair.pair, pair.block),
+            };
+            if let Some(risk) = pair.risk.as_ref().filter(|r| r.is_risky()) {
+                let flags: Vec<String> = risk.flags.iter().map(|f| f.to_string()).collect();
+                out.push_str(&format!(" RISK {}", flags.join(", ")));
+            }
+            out
+        }
+    }
+}
+
+/**
+ * @gist prints the holdings of a wallet and their total
+ * @param portfolio -- the valued holdings
+ * @param format -- text, csv or json
+**/
+fn print_portfolio(portfolio: &portfolio::Portfolio, format: Format) -> Result<(), Box<dyn std::error::Error>> {
+    let show = |v: Option<f64>| v.map(|v| v.to_string()).unwrap_or_default();
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(portfolio)?),
+        Format::Csv => {
+            println!("asset,balance,price,value");
+            for h in &portfolio.holdings {
+                println!("{},{},{},{}", h.asset, h.balance, show(h.price), show(h.value));
+            }
+        }
+        Format::Text => {
+            println!("wallet {:?}", portfolio.wallet);
+            for h in &portfolio.holdings {
+                match (h.price, h.value) {
+                    (Some(price), Some(value)) => println!("{}: {} at ${price} = ${value:.2}", h.asset, h.balance),
+                    _ => println!("{}: {} (no price)", h.asset, h.balance),
+                }
+            }
+            println!("total ${:.2}", portfolio.total);
+        }
+    }
+    Ok(())
+}
+
+/**
+ * @gist runs an alias subcommand against the address book
+ * @param book -- the loaded book
+ * @param path -- where the book is saved
+ * @param chain -- the chain id of the provider
+ * @param alias_command -- what to do
+**/
+fn edit_aliases(book: &mut aliases::AddressBook, path: &Path, chain: u64, alias_command: &AliasCommand) -> Result<(), Box<dyn std::error::Error>> {
+    match alias_command {
+        AliasCommand::Add { name, address } => {
+            if let Some(old) = book.add(chain, name, *address)? {
+                eprintln!("{name} was {old:?}");
+            }
+        }
+        AliasCommand::Rm { name } => {
+            book.remove(chain, name).ok_or_else(|| format!("unknown alias {name:?} on chain {chain}"))?;
+        }
+        AliasCommand::List => {
+            for (name, address) in book.entries(chain) {
+                println!("{name} {address:?}");
+            }
+            return Ok(());
+        }
+    }
+    aliases::save(path, book)?;
+    Ok(())
+}
+
+/**
+ * @gist validates the pools of an import file and appends the good ones to
+ * the config, bad pools are reported and skipped
+ * @param provider -- any ethers middleware, http or ipc
+ * @param import_args -- the file to import
+ * @param path -- the config to append to
+ * @param config -- the pools already in it and the token lists
+ * @param etherscan -- also checks the pools on etherscan, if configured
+**/
+async fn import_pools<M: Middleware + 'static>(provider: Arc<M>, import_args: &ImportArgs, path: Option<&Path>, config: &Config, etherscan: Option<&Etherscan>) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path.ok_or("pools import needs --config to write to")?;
+    let candidates = config.tokens.pools(provider.clone(), pools::read(&import_args.file)?).await;
+
+    let mut valid = Vec::with_capacity(candidates.len());
+    for pool in candidates {
+        if !import_args.no_check {
+            if let Err(e) = pools::validate(provider.clone(), &pool).await {
+                eprintln!("skipping {:?}: {e}", pool.address);
+                continue;
+            }
+            warn_unverified(etherscan, provider.clone(), &pool.address).await;
+        }
+        valid.push(pool);
+    }
+
+    let added = pools::append(path, &config.pool, &valid)?;
+    eprintln!("added {added} of {} valid pools to {}", valid.len(), path.display());
+    Ok(())
+}
+
+/**
+ * @gist resolves the tokens of a token list to their deepest WETH pool and
+ * prints the pools above the liquidity floor as [[pool]] tables
+ * @param provider -- any ethers middleware, http or ipc
+ * @param discover_args -- the list, factories and liquidity floor
+ * @param http -- options for the client fetching the list
+ * @param config -- its subgraph is asked first when configured, the
+ * factories are the fallback, and its token lists apply
+ * @param end_a -- the ETH/USDT pair address, prices the WETH reserves
+**/
+async fn discover_pools<M: Middleware + 'static>(provider: Arc<M>, discover_args: &DiscoverArgs, http: &provider::HttpOptions, config: &Config, end_a: &Address) -> Result<(), Box<dyn std::error::Error>> {
+    let client = provider::http_client(http)?;
+    let list = pools::token_list(&client, &discover_args.list).await?;
+    let subgraph = config.subgraph.clone().map(|c| Subgraph::new(client.clone(), c));
+    let chain_id = provider.get_chainid().await?.as_u64();
+
+    let weth: Address = uniswap_v2::WETH.parse()?;
+    let factories = if discover_args.factories.is_empty() {
+        vec![uniswap_v2::UNISWAP_V2_FACTORY.parse()?, uniswap_v2::SUSHISWAP_FACTORY.parse()?]
+    } else {
+        discover_args.factories.clone()
+    };
+    let (eth, usdt, _) = uniswap_v2::get_reserves(provider.clone(), end_a).await?;
+    let eth_price = uniswap_v2::reformat_usd(usdt) / uniswap_v2::reformat_wei(eth);
+
+    let mut found = Vec::new();
+    let tokens = list.tokens.iter()
+        .filter(|t| t.chain_id == chain_id && t.address != weth)
+        .filter(|t| config.tokens.permits(&[t.address], &[&t.symbol]));
+    for token in tokens {
+        let from_subgraph = match &subgraph {
+            Some(subgraph) => pools::subgraph_pool(subgraph, &weth, token).await
+                .inspect_err(|e| eprintln!("{}: {e}, asking the factories", token.symbol))
+                .ok(),
+            None => None,
+        };
+        let pool = match from_subgraph {
+            Some(pool) => Ok(pool),
+            None => pools::deepest_pool(provider.clone(), &factories, &weth, token).await,
+        };
+        match pool {
+            Ok(Some((pool, eth))) if 2.0 * eth * eth_price >= discover_args.min_liquidity => found.push(pool),
+            Ok(_) => {}
+            Err(e) => eprintln!("skipping {}: {e}", token.symbol),
+        }
+    }
+
+    let text = pools::to_toml(&found)?;
+    match &discover_args.output {
+        Some(path) => std::fs::write(path, text)?,
+        None => print!("{text}"),
+    }
+    eprintln!("found {} pools for {} tokens", found.len(), list.tokens.len());
+    Ok(())
+}
+
+/**
+ * @gist charts the history of an asset read from a file sink's output
+ * @param chart_args -- input, range, resolution and output image
+**/
+fn write_chart(chart_args: &ChartArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let now = clock::unix_now();
+    let from = chart_args.from.as_deref().map(|t| clock::parse_time(t, now)).transpose()?.unwrap_or(0);
+    let to = chart_args.to.as_deref().map(|t| clock::parse_time(t, now)).transpose()?.unwrap_or(u64::MAX);
+    let resolution = chart_args.resolution.as_deref().map(clock::parse_duration).transpose()?.unwrap_or(0);
+
+    let series: Vec<(u64, f64)> = history::read(&chart_args.input, &chart_args.asset)?
+        .into_iter()
+        .filter(|(t, _)| (from..=to).contains(t))
+        .collect();
+    let series = history::resample(&series, resolution);
+
+    chart::render(&series, &chart_args.asset, &chart_args.output, (chart_args.width, chart_args.height))?;
+    eprintln!("wrote {} points to {}", series.len(), chart_args.output.display());
+    Ok(())
+}
+
+/**
+ * @gist exports the history of file sinks into daily files
+ * @param export_args -- inputs, output directory, range and compression
+**/
+fn export_history(export_args: &ExportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let now = clock::unix_now();
+    let filter = export::Filter {
+        asset: export_args.asset.clone(),
+        from: export_args.from.as_deref().map(|t| clock::parse_time(t, now)).transpose()?,
+        to: export_args.to.as_deref().map(|t| clock::parse_time(t, now)).transpose()?,
+    };
+
+    let chunks = export::export(&export_args.inputs, &export_args.output, &filter, export_args.compress)?;
+    for chunk in &chunks {
+        eprintln!("wrote {} rows to {}", chunk.rows, chunk.path.display());
+    }
+    if chunks.is_empty() {
+        eprintln!("no rows to export");
+    }
+    Ok(())
 }
@@ -0,0 +1,431 @@
+/*!
+ *
+ * Prometheus metrics. Every RPC request is timed and counted per endpoint
+ * and method by the `Measured` transport, watch mode adds the last price,
+ * liquidity, rate of change and RSI of every asset and the swap volume and
+ * sandwiches of the pools. `--metrics 127.0.0.1:9100`
+ * serves them at /metrics:
+ *
+ * uni_price_polling_rpc_request_duration_seconds_bucket{endpoint="eth.llamarpc.com",method="eth_call",le="0.1"} 42
+ * uni_price_polling_rpc_requests_total{endpoint="eth.llamarpc.com",method="eth_call",outcome="error"} 1
+ * uni_price_polling_price{asset="LINK",source="UNI V2",chain="1",symbol="LINK"} 14.2
+ *
+ * The series of an asset carry the chain, symbol and labels of its quotes,
+ * see [labels].
+ *
+ * Where the poller can't be scraped, e.g. behind NAT, `--metrics-push`
+ * PUTs the same text to a Pushgateway every `--metrics-push-interval`
+ * seconds instead. The URL names the group, so several pollers keep apart:
+ *
+ * uni-price-polling --config watch.toml --interval 12 --metrics-push http://pushgateway:9091/metrics/job/uni_price_polling/instance/eu-1
+ *
+ * `summary` gives one line per endpoint over the time since the previous
+ * summary, for the periodic log line of watch mode.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::providers::JsonRpcClient;
+use reqwest::Client;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Write};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::source::Quote;
+use crate::swaps::Volume;
+use crate::trace::Span;
+
+/// Upper bounds of the latency buckets, in seconds
+const BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Latency and outcome of the requests of one endpoint and method
+#[derive(Debug, Clone, Default)]
+struct RpcStats {
+    /// Requests per bucket, not cumulative; the last one is +Inf
+    buckets: [u64; BUCKETS.len() + 1],
+    seconds: f64,
+    ok: u64,
+    errors: u64,
+}
+
+impl RpcStats {
+    fn count(&self) -> u64 {
+        self.ok + self.errors
+    }
+
+    fn observe(&mut self, seconds: f64, ok: bool) {
+        let bucket = BUCKETS.iter().position(|b| seconds <= *b).unwrap_or(BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.seconds += seconds;
+        if ok { self.ok += 1 } else { self.errors += 1 }
+    }
+
+    fn add(&mut self, other: &RpcStats) {
+        for (b, o) in self.buckets.iter_mut().zip(other.buckets) {
+            *b += o;
+        }
+        self.seconds += other.seconds;
+        self.ok += other.ok;
+        self.errors += other.errors;
+    }
+
+    fn since(&self, earlier: &RpcStats) -> RpcStats {
+        let mut diff = self.clone();
+        for (b, e) in diff.buckets.iter_mut().zip(earlier.buckets) {
+            *b -= e;
+        }
+        diff.seconds -= earlier.seconds;
+        diff.ok -= earlier.ok;
+        diff.errors -= earlier.errors;
+        diff
+    }
+
+    /**
+     * @gist upper bound of the bucket holding the q-th quantile, None when
+     * it falls into +Inf
+    **/
+    fn quantile(&self, q: f64) -> Option<f64> {
+        let rank = (q * self.count() as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return BUCKETS.get(bucket).copied();
+            }
+        }
+        None
+    }
+}
+
+/// Last quote of an asset
+#[derive(Debug, Clone)]
+struct Last {
+    price: f64,
+    liquidity: Option<f64>,
+    change_per_min: Option<f64>,
+    accel_per_min2: Option<f64>,
+    rsi: Option<f64>,
+    confidence: Option<f64>,
+}
+
+/// Metrics shared by the transport, the poller and the endpoint
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// By endpoint and method
+    rpc: Mutex<BTreeMap<(String, String), RpcStats>>,
+    /// Per endpoint totals at the previous summary
+    summarized: Mutex<BTreeMap<String, RpcStats>>,
+    /// By asset and source
+    quotes: Mutex<BTreeMap<(String, String), Last>>,
+    /// Swap volume since the start, by asset and source
+    swaps: Mutex<BTreeMap<(String, String), Volume>>,
+    /// Sandwiches since the start, by asset and source
+    sandwiches: Mutex<BTreeMap<(String, String), Sandwiches>>,
+    /// The chain, symbol and labels of the last quote, as Prometheus
+    /// labels after the asset and source, by asset and source
+    labels: Mutex<BTreeMap<(String, String), String>>,
+}
+
+/// Totals of the sandwiches of a pool
+#[derive(Debug, Clone, Copy, Default)]
+struct Sandwiches {
+    count: u64,
+    victim_usd: f64,
+    profit_usd: f64,
+}
+
+impl Metrics {
+    /**
+     * @gist records one RPC request
+     * @param endpoint -- label of the RPC
+     * @param method -- the JSON-RPC method
+     * @param seconds -- time until the response
+     * @param ok -- whether it succeeded
+    **/
+    pub fn observe_rpc(&self, endpoint: &str, method: &str, seconds: f64, ok: bool) {
+        self.rpc.lock().unwrap()
+            .entry((endpoint.to_string(), method.to_string()))
+            .or_default()
+            .observe(seconds, ok);
+    }
+
+    /**
+     * @gist records the latest price and liquidity of an asset and adds its
+     * swap volume
+    **/
+    pub fn observe_quote(&self, quote: &Quote) {
+        let mut labels = String::new();
+        if let Some(chain) = quote.chain {
+            let _ = write!(labels, ",chain=\"{chain}\"");
+        }
+        if let Some(symbol) = &quote.symbol {
+            let _ = write!(labels, ",symbol=\"{}\"", escape(symbol));
+        }
+        for (key, value) in &quote.labels {
+            let _ = write!(labels, ",{key}=\"{}\"", escape(value));
+        }
+        self.labels.lock().unwrap().insert((quote.asset.clone(), quote.source.clone()), labels);
+        if let Some(volume) = &quote.swaps {
+            self.swaps.lock().unwrap()
+                .entry((quote.asset.clone(), quote.source.clone()))
+                .or_default()
+                .add(volume);
+        }
+        if !quote.sandwiches.is_empty() {
+            let mut sandwiches = self.sandwiches.lock().unwrap();
+            let totals = sandwiches.entry((quote.asset.clone(), quote.source.clone())).or_default();
+            for s in &quote.sandwiches {
+                totals.count += 1;
+                totals.victim_usd += s.victim_usd;
+                totals.profit_usd += s.profit_usd;
+            }
+        }
+        self.quotes.lock().unwrap().insert(
+            (quote.asset.clone(), quote.source.clone()),
+            Last {
+                price: quote.price,
+                liquidity: quote.liquidity,
+                change_per_min: quote.change_per_min,
+                accel_per_min2: quote.accel_per_min2,
+                rsi: quote.rsi,
+                confidence: quote.confidence.map(f64::from),
+            },
+        );
+    }
+
+    /**
+     * @gist the metrics in the Prometheus text format
+    **/
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let rpc = self.rpc.lock().unwrap().clone();
+
+        let name = "uni_price_polling_rpc_request_duration_seconds";
+        let _ = writeln!(out, "# HELP {name} Time until the RPC answered");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for ((endpoint, method), stats) in &rpc {
+            let labels = format!("endpoint=\"{}\",method=\"{}\"", escape(endpoint), escape(method));
+            let mut cumulative = 0;
+            for (bound, n) in BUCKETS.iter().zip(stats.buckets) {
+                cumulative += n;
+                let _ = writeln!(out, "{name}_bucket{{{labels},le=\"{bound}\"}} {cumulative}");
+            }
+            let _ = writeln!(out, "{name}_bucket{{{labels},le=\"+Inf\"}} {}", stats.count());
+            let _ = writeln!(out, "{name}_sum{{{labels}}} {}", stats.seconds);
+            let _ = writeln!(out, "{name}_count{{{labels}}} {}", stats.count());
+        }
+
+        let name = "uni_price_polling_rpc_requests_total";
+        let _ = writeln!(out, "# HELP {name} RPC requests by outcome");
+        let _ = writeln!(out, "# TYPE {name} counter");
+        for ((endpoint, method), stats) in &rpc {
+            let labels = format!("endpoint=\"{}\",method=\"{}\"", escape(endpoint), escape(method));
+            let _ = writeln!(out, "{name}{{{labels},outcome=\"ok\"}} {}", stats.ok);
+            let _ = writeln!(out, "{name}{{{labels},outcome=\"error\"}} {}", stats.errors);
+        }
+
+        let extra = self.labels.lock().unwrap().clone();
+        let asset_labels = |(asset, source): &(String, String)| {
+            let extra = extra.get(&(asset.clone(), source.clone())).map(String::as_str).unwrap_or_default();
+            format!("asset=\"{}\",source=\"{}\"{extra}", escape(asset), escape(source))
+        };
+
+        let quotes = self.quotes.lock().unwrap().clone();
+        if !quotes.is_empty() {
+            let _ = writeln!(out, "# HELP uni_price_polling_price Last USDT price of the asset");
+            let _ = writeln!(out, "# TYPE uni_price_polling_price gauge");
+            for (key, last) in &quotes {
+                let _ = writeln!(out, "uni_price_polling_price{{{}}} {}", asset_labels(key), last.price);
+            }
+            let optional = [
+                ("uni_price_polling_liquidity", "Last USDT value of the pool", (|l: &Last| l.liquidity) as fn(&Last) -> Option<f64>),
+                ("uni_price_polling_change_per_minute", "Rate of change of the price per minute", |l| l.change_per_min),
+                ("uni_price_polling_acceleration_per_minute2", "Change of the rate of change per minute", |l| l.accel_per_min2),
+                ("uni_price_polling_rsi", "Relative strength index of the price", |l| l.rsi),
+                ("uni_price_polling_confidence", "Confidence score of the last quote, 0 to 100", |l| l.confidence),
+            ];
+            for (name, help, value) in optional {
+                let _ = writeln!(out, "# HELP {name} {help}");
+                let _ = writeln!(out, "# TYPE {name} gauge");
+                for (key, last) in &quotes {
+                    if let Some(v) = value(last) {
+                        let _ = writeln!(out, "{name}{{{}}} {v}", asset_labels(key));
+                    }
+                }
+            }
+        }
+
+        let swaps = self.swaps.lock().unwrap().clone();
+        if !swaps.is_empty() {
+            let counters = [
+                ("uni_price_polling_swaps_total", "Swaps of the pool by side", (|v: &Volume| (v.buys as f64, v.sells as f64)) as fn(&Volume) -> (f64, f64)),
+                ("uni_price_polling_swap_volume_tokens_total", "Traded TOKEN amount by side", |v| (v.buy_token, v.sell_token)),
+                ("uni_price_polling_swap_volume_usd_total", "USDT value of the trades by side", |v| (v.buy_usd, v.sell_usd)),
+            ];
+            for (name, help, value) in counters {
+                let _ = writeln!(out, "# HELP {name} {help}");
+                let _ = writeln!(out, "# TYPE {name} counter");
+                for (key, volume) in &swaps {
+                    let labels = asset_labels(key);
+                    let (buy, sell) = value(volume);
+                    let _ = writeln!(out, "{name}{{{labels},side=\"buy\"}} {buy}");
+                    let _ = writeln!(out, "{name}{{{labels},side=\"sell\"}} {sell}");
+                }
+            }
+        }
+
+        let sandwiches = self.sandwiches.lock().unwrap().clone();
+        if !sandwiches.is_empty() {
+            let counters = [
+                ("uni_price_polling_sandwiches_total", "Sandwiches found in the swaps of the pool", (|s: &Sandwiches| s.count as f64) as fn(&Sandwiches) -> f64),
+                ("uni_price_polling_sandwiched_volume_usd_total", "USDT value of the swaps that were sandwiched", |s| s.victim_usd),
+                ("uni_price_polling_sandwich_profit_usd_total", "USDT the attackers made before gas", |s| s.profit_usd),
+            ];
+            for (name, help, value) in counters {
+                let _ = writeln!(out, "# HELP {name} {help}");
+                let _ = writeln!(out, "# TYPE {name} counter");
+                for (key, totals) in &sandwiches {
+                    let _ = writeln!(out, "{name}{{{}}} {}", asset_labels(key), value(totals));
+                }
+            }
+        }
+        out
+    }
+
+    /**
+     * @gist one line per endpoint with the requests, error rate and latency
+     * since the previous summary
+    **/
+    pub fn summary(&self) -> Vec<String> {
+        let mut totals: BTreeMap<String, RpcStats> = BTreeMap::new();
+        for ((endpoint, _), stats) in self.rpc.lock().unwrap().iter() {
+            totals.entry(endpoint.clone()).or_default().add(stats);
+        }
+
+        let mut summarized = self.summarized.lock().unwrap();
+        let lines = totals.iter()
+            .map(|(endpoint, total)| {
+                let stats = total.since(summarized.get(endpoint).unwrap_or(&RpcStats::default()));
+                let ms = |q: f64| stats.quantile(q).map_or(">10s".to_string(), |s| format!("<={}ms", s * 1000.0));
+                let mean = if stats.count() == 0 { 0.0 } else { stats.seconds / stats.count() as f64 * 1000.0 };
+                let rate = if stats.count() == 0 { 0.0 } else { stats.errors as f64 / stats.count() as f64 * 100.0 };
+                format!("rpc {endpoint}: {} requests, {} errors ({rate:.1}%), mean {mean:.0}ms, p50 {}, p95 {}",
+                    stats.count(), stats.errors, ms(0.5), ms(0.95))
+            })
+            .collect();
+        *summarized = totals;
+        lines
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// A transport that records the latency and outcome of every request
+#[derive(Debug, Clone)]
+pub struct Measured<T> {
+    inner: T,
+    endpoint: String,
+    metrics: Arc<Metrics>,
+}
+
+impl<T> Measured<T> {
+    /**
+     * @gist wraps a transport
+     * @param inner -- the transport
+     * @param endpoint -- label of the RPC in the metrics, keep secrets like
+     * API keys in the url out of it
+     * @param metrics -- where the requests are recorded
+    **/
+    pub fn new(inner: T, endpoint: impl Into<String>, metrics: Arc<Metrics>) -> Self {
+        Self { inner, endpoint: endpoint.into(), metrics }
+    }
+}
+
+#[async_trait]
+impl<T: JsonRpcClient> JsonRpcClient for Measured<T> {
+    type Error = T::Error;
+
+    async fn request<P, R>(&self, method: &str, params: P) -> Result<R, Self::Error>
+    where
+        P: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        // Requests outside a poll cycle are not traced
+        let mut span = Span::child(&format!("rpc {method}")).client();
+        span.set("rpc.endpoint", &self.endpoint);
+
+        let start = Instant::now();
+        let result = self.inner.request(method, params).await;
+        self.metrics.observe_rpc(&self.endpoint, method, start.elapsed().as_secs_f64(), result.is_ok());
+        if let Err(e) = &result {
+            span.fail(e);
+        }
+        result
+    }
+}
+
+/**
+ * @gist serves the metrics at GET /metrics until the process exits
+ * @param addr -- address to listen on
+ * @param metrics -- what to serve
+**/
+pub async fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> eyre::Result<()> {
+    let listener = TcpListener::bind(addr).await
+        .map_err(|e| eyre::eyre!("could not listen on {addr}: {e}"))?;
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                continue;
+            };
+            let metrics = metrics.clone();
+            tokio::spawn(async move {
+                let mut request = vec![0; 1024];
+                let Ok(n) = stream.read(&mut request).await else {
+                    return;
+                };
+                let request = String::from_utf8_lossy(&request[..n]);
+                let response = match request.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+                    ["GET", "/metrics"] => {
+                        let body = metrics.render();
+                        format!("HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len())
+                    }
+                    _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    Ok(())
+}
+
+/**
+ * @gist pushes the metrics to a Pushgateway in the background, replacing
+ * the previous push of the group every time
+ * @param client -- HTTP client of the pushes
+ * @param url -- the group, e.g. http://pushgateway:9091/metrics/job/uni_price_polling
+ * @param every -- time between pushes
+**/
+pub fn push(client: Client, url: String, metrics: Arc<Metrics>, every: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(every);
+        loop {
+            ticker.tick().await;
+            let sent = client.put(&url)
+                .header("Content-Type", "text/plain; version=0.0.4")
+                .body(metrics.render())
+                .send().await
+                .and_then(|r| r.error_for_status());
+            if let Err(e) = sent {
+                eprintln!("metrics push to {url} failed: {}", e.without_url());
+            }
+        }
+    });
+}
@@ -0,0 +1,281 @@
+/*!
+ *
+ * Several consumers on one poller. A `[[namespace]]` is a watchlist of its
+ * own, e.g. of a team or a strategy, with its own pools, sinks and alert
+ * rules:
+ *
+ * [[namespace]]
+ * name = "treasury"
+ * api_keys = ["5d2a..."]
+ *
+ * [[namespace.pool]]
+ * address = "0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974"
+ * label = "LINK"
+ *
+ * [[namespace.sink]]
+ * type = "file"
+ * path = "treasury.ndjson"
+ * format = "json"
+ *
+ * [[namespace.alert]]
+ * name = "link-low"
+ * asset = "LINK"
+ * below = 10.0
+ *
+ * The pools of every namespace are polled in the same cycles as the top
+ * level ones. Their quotes carry the label `namespace` and go to stdout and
+ * the sinks of their namespace only, and only its rules see them. Their
+ * rolling windows are their own, a label in two namespaces doesn't mix the
+ * prices of both. Its alerts go to its `[namespace.notify]`, the top level
+ * `[notify]` without one. With `--dashboard` every namespace has its own
+ * endpoints:
+ *
+ * GET /ns                   the names of the namespaces
+ * GET /ns/{name}/prices     the last quote of every asset of the namespace
+ * GET /ns/{name}/quotes     like /api/quotes, for the namespace
+ * GET /ns/{name}/alerts     like /api/alerts, for the namespace
+ *
+ * The keys of a namespace open its own endpoints, the keys of `[server]` all
+ * of them, which are needed too once a namespace has keys. The quotes and
+ * alerts of a namespace don't show on `/api/quotes` and `/api/alerts`.
+ *
+**/
+
+use ethers::types::Address;
+use reqwest::Client;
+use serde::Deserialize;
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::alerts::{Alert, AlertEngine, AlertRule, RuleState};
+use crate::dashboard::{Board, Tenant};
+use crate::notify::{NotifyConfig, Notifier};
+use crate::pools::PoolConfig;
+use crate::queue::Overflow;
+use crate::sink::{self, Dispatcher, Sink, SinkConfig};
+use crate::source::Quote;
+
+/// Label the quotes of a namespace carry its name in
+pub const LABEL: &str = "namespace";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct NamespaceConfig {
+    /// Letters, digits, - and _, as it appears in the paths of the API
+    pub name: String,
+    /// Keys that open the endpoints of this namespace
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    #[serde(default)]
+    pub pool: Vec<PoolConfig>,
+    #[serde(default)]
+    pub sink: Vec<SinkConfig>,
+    #[serde(default)]
+    pub alert: Vec<AlertRule>,
+    /// Where the alerts go, the top level [notify] if unset
+    pub notify: Option<NotifyConfig>,
+}
+
+/**
+ * @gist checks the names, keys and pools of the namespaces
+ * @param namespaces -- the [[namespace]] tables
+ * @param pools -- the top level [[pool]] tables
+**/
+pub fn validate(namespaces: &[NamespaceConfig], pools: &[PoolConfig]) -> eyre::Result<()> {
+    let mut names = HashSet::new();
+    // By asset name and address, a feed can only carry one namespace
+    let mut watched: HashMap<(String, Address), &str> = HashMap::new();
+    for p in pools {
+        if p.labels.contains_key(LABEL) {
+            eyre::bail!("[[pool]] {}: label {LABEL} is set by [[namespace]]", p.asset().name);
+        }
+        watched.insert((p.asset().name, p.address), "the top level");
+    }
+    for ns in namespaces {
+        let valid = ns.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if ns.name.is_empty() || !valid {
+            eyre::bail!("[[namespace]] {:?}: use letters, digits, - and _ in the name", ns.name);
+        }
+        if !names.insert(ns.name.as_str()) {
+            eyre::bail!("[[namespace]] {} is there twice", ns.name);
+        }
+        if ns.api_keys.iter().any(|k| k.trim().is_empty()) {
+            eyre::bail!("[[namespace]] {}: api_keys holds an empty key", ns.name);
+        }
+        for p in &ns.pool {
+            let asset = p.asset().name;
+            if p.labels.contains_key(LABEL) {
+                eyre::bail!("[[namespace]] {}: {asset}: label {LABEL} is set by the namespace", ns.name);
+            }
+            if let Some(other) = watched.insert((asset.clone(), p.address), &ns.name) {
+                eyre::bail!("[[namespace]] {}: {asset} is watched by {other} already, give it another label", ns.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+/**
+ * @gist the pools of every namespace, labeled with it
+**/
+pub fn pools(namespaces: &[NamespaceConfig]) -> Vec<PoolConfig> {
+    namespaces.iter()
+        .flat_map(|ns| ns.pool.iter().map(|p| {
+            let mut p = p.clone();
+            p.labels.insert(LABEL.to_string(), ns.name.clone());
+            p
+        }))
+        .collect()
+}
+
+/**
+ * @gist the namespace of a quote, None for the top level
+**/
+pub fn of(quote: &Quote) -> Option<&str> {
+    quote.labels.get(LABEL).map(String::as_str)
+}
+
+/// A namespace as watch mode runs it
+pub struct Namespace {
+    pub name: String,
+    pub alerts: AlertEngine,
+    pub notifier: Notifier,
+    pub sinks: Dispatcher,
+    pub board: Arc<Board>,
+    pub api_keys: Vec<String>,
+}
+
+impl Namespace {
+    /**
+     * @gist opens the sinks and sets up the rules of a namespace
+     * @param config -- the [[namespace]] table
+     * @param client -- for the webhook sinks and the notifier
+     * @param stdout -- shared with the top level
+     * @param notify -- the top level [notify]
+     * @param states -- of the rules, as the state file has them
+    **/
+    pub fn new(config: NamespaceConfig, client: &Client, stdout: Arc<dyn Sink>, notify: &NotifyConfig, states: &HashMap<String, RuleState>) -> eyre::Result<Self> {
+        let mut sinks = Dispatcher::new();
+        sinks.add(stdout, 1024, Overflow::Block);
+        for s in &config.sink {
+            sinks.add(sink::from_config(&s.kind, client)?, s.queue, s.overflow);
+        }
+        let prefix = format!("{}/", config.name);
+        let states = states.iter()
+            .filter_map(|(rule, state)| Some((rule.strip_prefix(&prefix)?.to_string(), state.clone())))
+            .collect();
+        Ok(Self {
+            name: config.name,
+            alerts: AlertEngine::with_states(config.alert, &states),
+            notifier: Notifier::new(client.clone(), config.notify.unwrap_or_else(|| notify.clone())),
+            sinks,
+            board: Arc::new(Board::default()),
+            api_keys: config.api_keys,
+        })
+    }
+
+    /**
+     * @gist the state of the rules, as the state file keeps them next to the
+     * top level ones
+    **/
+    pub fn states(&self) -> impl Iterator<Item = (String, RuleState)> + '_ {
+        self.alerts.states().into_iter().map(|(rule, state)| (format!("{}/{rule}", self.name), state))
+    }
+
+    /**
+     * @gist shows and delivers an alert of the namespace
+    **/
+    pub fn fire(&self, alert: Alert, now: u64) {
+        self.board.observe_alert(&alert, now);
+        self.notifier.spawn(alert);
+    }
+
+    /**
+     * @gist the namespace as the --dashboard server sees it
+    **/
+    pub fn tenant(&self) -> Tenant {
+        Tenant { board: self.board.clone(), api_keys: self.api_keys.clone() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Config {
+        #[serde(default)]
+        pool: Vec<PoolConfig>,
+        #[serde(default)]
+        namespace: Vec<NamespaceConfig>,
+    }
+
+    fn check(toml: &str) -> eyre::Result<()> {
+        let config: Config = toml::from_str(toml).unwrap();
+        validate(&config.namespace, &config.pool)
+    }
+
+    const TWO: &str = r#"
+        [[pool]]
+        address = "0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974"
+        label = "LINK"
+
+        [[namespace]]
+        name = "treasury"
+
+        [[namespace.pool]]
+        address = "0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974"
+        label = "LINK-T"
+
+        [[namespace]]
+        name = "trading"
+
+        [[namespace.pool]]
+        address = "0xa478c2975ab1ea89e8196811f51a7b7ade33eb11"
+        label = "LINK-T"
+    "#;
+
+    #[test]
+    fn accepts_labels_reused_on_other_pools() {
+        check(TWO).unwrap();
+    }
+
+    #[test]
+    fn refuses_a_pool_watched_twice() {
+        let toml = TWO.replace("0xa478c2975ab1ea89e8196811f51a7b7ade33eb11", "0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974");
+        let err = check(&toml).unwrap_err();
+        assert_eq!(err.to_string(), "[[namespace]] trading: LINK-T is watched by treasury already, give it another label");
+
+        let toml = TWO.replace("LINK-T", "LINK");
+        let err = check(&toml).unwrap_err();
+        assert_eq!(err.to_string(), "[[namespace]] treasury: LINK is watched by the top level already, give it another label");
+    }
+
+    #[test]
+    fn checks_names_and_keys() {
+        let err = check(&TWO.replace("\"trading\"", "\"treasury\"")).unwrap_err();
+        assert_eq!(err.to_string(), "[[namespace]] treasury is there twice");
+        let err = check(&TWO.replace("\"trading\"", "\"a b\"")).unwrap_err();
+        assert_eq!(err.to_string(), "[[namespace]] \"a b\": use letters, digits, - and _ in the name");
+        assert!(check("[[namespace]]\nname = \"\"").is_err());
+        let err = check("[[namespace]]\nname = \"x\"\napi_keys = [\" \"]").unwrap_err();
+        assert_eq!(err.to_string(), "[[namespace]] x: api_keys holds an empty key");
+    }
+
+    #[test]
+    fn keeps_the_label_to_itself() {
+        let toml = TWO.replacen("label = \"LINK-T\"", "label = \"LINK-T\"\nlabels = { namespace = \"x\" }", 1);
+        let err = check(&toml).unwrap_err();
+        assert_eq!(err.to_string(), "[[namespace]] treasury: LINK-T: label namespace is set by the namespace");
+    }
+
+    #[test]
+    fn labels_the_pools() {
+        let config: Config = toml::from_str(TWO).unwrap();
+        let pools = pools(&config.namespace);
+        assert_eq!(pools.len(), 2);
+        assert_eq!(pools[0].labels[LABEL], "treasury");
+        assert_eq!(pools[1].labels[LABEL], "trading");
+    }
+}
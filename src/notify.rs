@@ -0,0 +1,97 @@
+/*!
+ *
+ * Where fired alerts go. Alerts are always written to stderr, a webhook and
+ * a Telegram bot can be added in the config:
+ *
+ * [notify]
+ * webhook = "https://example.com/hook"
+ *
+ * [notify.telegram]
+ * bot_token = "123456:ABC..."
+ * chat_id = "-100123456"
+ *
+**/
+
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::alerts::Alert;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NotifyConfig {
+    /// URL that receives every alert as a JSON POST
+    pub webhook: Option<String>,
+    pub telegram: Option<TelegramConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+#[derive(Clone)]
+pub struct Notifier {
+    client: Client,
+    config: NotifyConfig,
+}
+
+impl Notifier {
+    /**
+     * @gist creates the notifier
+     * @param client -- HTTP client used for the webhook and Telegram
+     * @param config -- the configured targets
+    **/
+    pub fn new(client: Client, config: NotifyConfig) -> Self {
+        Self { client, config }
+    }
+
+    /**
+     * @gist delivers an alert to every configured target, failures are
+     * reported on stderr
+    **/
+    pub async fn send(&self, alert: &Alert) {
+        eprintln!("ALERT {}", alert.message);
+        for (target, res) in self.deliver(alert).await {
+            if let Err(e) = res {
+                eprintln!("{target} notification failed: {e}");
+            }
+        }
+    }
+
+    /**
+     * @gist delivers an alert to every configured target
+     * @output the outcome by target, "webhook" or "telegram", empty when
+     * none is configured
+    **/
+    pub async fn deliver(&self, alert: &Alert) -> Vec<(&'static str, Result<(), reqwest::Error>)> {
+        let mut outcomes = Vec::new();
+
+        if let Some(url) = &self.config.webhook {
+            let res = self.client.post(url).json(alert).send().await.and_then(|r| r.error_for_status());
+            outcomes.push(("webhook", res.map(drop)));
+        }
+
+        if let Some(telegram) = &self.config.telegram {
+            let url = format!("https://api.telegram.org/bot{}/sendMessage", telegram.bot_token);
+            let body = json!({ "chat_id": telegram.chat_id, "text": alert.message });
+            let res = self.client.post(url).json(&body).send().await.and_then(|r| r.error_for_status());
+            // The URL holds the bot token
+            outcomes.push(("telegram", res.map(drop).map_err(|e| e.without_url())));
+        }
+
+        outcomes
+    }
+
+    /**
+     * @gist sends the alert in the background so a slow target can't hold up
+     * the poller
+    **/
+    pub fn spawn(&self, alert: Alert) {
+        let notifier = self.clone();
+        tokio::spawn(async move { notifier.send(&alert).await });
+    }
+}
@@ -0,0 +1,144 @@
+/*!
+ *
+ * Custom on-chain price sources. A source is any view function described by a
+ * one line human readable ABI, for example
+ *
+ * [[oracle]]
+ * name = "ETH/USD chainlink"
+ * address = "0x5f4eC3Df9cbd43714FE2740f5E3616155c5b8419"
+ * abi = "function latestRoundData() view returns (uint80, int256, uint256, uint256, uint80)"
+ * output = 1
+ * decimals = 8
+ *
+ * The selected return value has to be an integer, it is scaled down by
+ * `decimals` to get the price. The price is in dollars unless the oracle
+ * names another `currency`, e.g. "ETH" for a token/ETH feed.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{
+    abi::{
+        token::{LenientTokenizer, Tokenizer},
+        HumanReadableParser, Token,
+    },
+    providers::Middleware,
+    types::{transaction::eip2718::TypedTransaction, Address, BlockId, TransactionRequest, I256},
+    utils::format_units,
+};
+use eyre::{bail, eyre};
+use serde::Deserialize;
+
+use std::sync::Arc;
+
+use crate::source::{self, Asset, PriceSource, Quote};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct OracleConfig {
+    /// Label used in the output
+    pub name: String,
+    /// Contract to call
+    pub address: Address,
+    /// Human readable signature of the view function
+    pub abi: String,
+    /// Arguments passed to the function, parsed according to the ABI
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Index of the return value holding the price
+    #[serde(default)]
+    pub output: usize,
+    /// Number of decimals the returned integer carries
+    #[serde(default)]
+    pub decimals: u32,
+    /// What the price is in, None for USD
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+impl OracleConfig {
+    /**
+     * @gist the asset this oracle prices
+    **/
+    pub fn asset(&self) -> Asset {
+        Asset { name: self.name.clone(), address: self.address }
+    }
+}
+
+/// A configured view function as a PriceSource, the asset address is the contract called
+pub struct Oracle<M> {
+    provider: Arc<M>,
+    config: OracleConfig,
+}
+
+impl<M: Middleware + 'static> Oracle<M> {
+    /**
+     * @gist creates the source
+     * @param provider -- used to send the eth_call
+     * @param config -- the function to call and how to scale its result
+    **/
+    pub fn new(provider: Arc<M>, config: OracleConfig) -> Self {
+        Self { provider, config }
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> PriceSource for Oracle<M> {
+    fn name(&self) -> &str {
+        "ORACLE"
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let head = source::head(self.provider.as_ref()).await?;
+        let price = read(self.provider.as_ref(), &asset.address, &self.config, Some(head.number.into())).await?;
+
+        let mut quote = Quote::new(self.name(), &asset.name, price);
+        quote.block = Some(head.number);
+        quote.timestamp = Some(head.timestamp);
+        quote.currency = self.config.currency.clone();
+        Ok(quote)
+    }
+}
+
+/**
+ * @gist calls the configured view function and scales the result
+ * @param provider -- used to send the eth_call
+ * @param address -- the contract to call
+ * @param oracle -- the function to call and how to scale its result
+ * @param block -- the block to read at, None for latest
+ * @output the price as a decimal number
+**/
+pub async fn read<M: Middleware + 'static>(provider: &M, address: &Address, oracle: &OracleConfig, block: Option<BlockId>) -> eyre::Result<f64> {
+    let function = HumanReadableParser::parse_function(&oracle.abi)
+        .map_err(|e| eyre!("{}: invalid abi: {e}", oracle.name))?;
+
+    if function.inputs.len() != oracle.args.len() {
+        bail!("{}: {} expects {} arguments, got {}",
+            oracle.name, function.name, function.inputs.len(), oracle.args.len());
+    }
+
+    let args = function.inputs.iter()
+        .zip(&oracle.args)
+        .map(|(param, value)| LenientTokenizer::tokenize(&param.kind, value))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let tx: TypedTransaction = TransactionRequest::new()
+        .to(*address)
+        .data(function.encode_input(&args)?)
+        .into();
+
+    let raw = provider.call(&tx, block).await?;
+    let mut outputs = function.decode_output(&raw)?;
+
+    if oracle.output >= outputs.len() {
+        bail!("{}: {} only returns {} values", oracle.name, function.name, outputs.len());
+    }
+
+    let value = match outputs.swap_remove(oracle.output) {
+        Token::Uint(v) => format_units(v, oracle.decimals)?,
+        Token::Int(v) => format_units(I256::from_raw(v), oracle.decimals)?,
+        other => bail!("{}: return value {other} is not an integer", oracle.name),
+    };
+
+    Ok(value.parse()?)
+}
@@ -0,0 +1,311 @@
+/*!
+ *
+ * New pair listings. `watch-new-pairs` reads the PairCreated events of the
+ * V2 factories every few seconds and prices every new pair whose other
+ * token is WETH or a dollar stablecoin right away:
+ *
+ * uni-price-polling watch-new-pairs --interval 12 --notify
+ *
+ * Pairs are often created in one transaction and receive their first
+ * liquidity in a later one, those are listed without a price.
+ *
+ * With `--auto-add` every new WETH pair is a candidate for the watchlist
+ * for a day. Once it holds enough liquidity and has traded enough volume
+ * since its listing it is priced with the watchlist every interval and
+ * appended to the config as a [[pool]].
+ *
+ * Priced pairs are checked for honeypot signs first, see `risk`, and risky
+ * ones are neither notified about nor added.
+ *
+**/
+
+use ethers::{
+    providers::Middleware,
+    types::{Address, ValueOrArray, H256},
+};
+use serde::Serialize;
+
+use std::collections::BTreeMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+use crate::pools::PoolConfig;
+use crate::risk::Risk;
+use crate::swaps::BLOCK_TIME;
+use crate::uniswap_v2::{self, get_reserves, get_token, IUniswapV2Factory, IUniswapV2Pair, Token};
+
+/// Dollar stablecoins on mainnet a new pair can be priced against
+pub const STABLES: [(&str, &str, u8); 3] = [
+    ("USDT", "0xdAC17F958D2ee523a2206206994597C13D831ec7", 6),
+    ("USDC", "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", 6),
+    ("DAI", "0x6B175474E89094C44Da98b954EedeAC495271d0F", 18),
+];
+
+/**
+ * @gist whether a token is one of the dollar stablecoins
+ * @param token -- the token
+**/
+pub fn is_stable(token: &Address) -> bool {
+    STABLES.iter().any(|(_, address, _)| address.parse::<Address>().ok().as_ref() == Some(token))
+}
+
+/// Block range of a single eth_getLogs request
+const CHUNK: u64 = 2000;
+
+/// A pair created on one of the factories
+#[derive(Debug, Clone, Serialize)]
+pub struct NewPair {
+    pub pair: Address,
+    pub factory: Address,
+    /// The listed token
+    pub token: Token,
+    /// Symbol of the other token, WETH or a stablecoin
+    pub counter: String,
+    #[serde(skip)]
+    counter_decimals: u8,
+    /// WETH or the stablecoin is token0 of the pair
+    pub inverted: bool,
+    pub block: u64,
+    pub tx: H256,
+    /// USDT price of the token, None while the pair holds no liquidity
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub price: Option<f64>,
+    /// USDT value of the pair
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub liquidity: Option<f64>,
+    /// Outcome of the honeypot checks, once they ran
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub risk: Option<Risk>,
+}
+
+impl NewPair {
+    /**
+     * @gist the pair as symbol/counter, e.g. PEPE/WETH
+    **/
+    pub fn name(&self) -> String {
+        let symbol = self.token.symbol.clone().unwrap_or_else(|| format!("{:?}", self.token.address));
+        format!("{symbol}/{}", self.counter)
+    }
+
+    /**
+     * @gist the pair as a watchlist entry, only WETH pairs can be priced
+     * by the Uniswap V2 route
+    **/
+    pub fn pool(&self) -> Option<PoolConfig> {
+        (self.counter == "WETH").then(|| PoolConfig {
+            address: self.pair,
+            label: self.token.symbol.clone(),
+            inverted: self.inverted,
+            decimals: self.token.decimals,
+            rebase: None,
+            route: None,
+            labels: BTreeMap::new(),
+            sig_figs: None,
+            tick: None,
+            interval: None,
+        })
+    }
+}
+
+/// What a new pair needs to be added to the watchlist
+#[derive(Debug, Clone)]
+pub struct AddCriteria {
+    /// USDT value of the pair
+    pub min_liquidity: f64,
+    /// USDT volume of its swaps since the listing
+    pub min_volume: f64,
+    /// Seconds after the listing a pair is checked for, it is dropped after
+    pub within: u64,
+}
+
+/// A new pair that is not on the watchlist yet
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub pair: NewPair,
+    /// USDT volume of its swaps since the listing
+    pub volume: f64,
+    /// First block whose swaps were not counted yet
+    next: u64,
+}
+
+impl Candidate {
+    pub fn new(pair: NewPair) -> Self {
+        let next = pair.block;
+        Self { pair, volume: 0.0, next }
+    }
+
+    /**
+     * @gist whether the candidate meets the criteria
+    **/
+    pub fn qualifies(&self, criteria: &AddCriteria) -> bool {
+        self.pair.liquidity.is_some_and(|l| l >= criteria.min_liquidity) && self.volume >= criteria.min_volume
+    }
+
+    /**
+     * @gist whether the candidate was listed too long ago to be checked again
+    **/
+    pub fn expired(&self, criteria: &AddCriteria, head: u64) -> bool {
+        head.saturating_sub(self.pair.block) * BLOCK_TIME > criteria.within
+    }
+}
+
+/// The other side of a new pair
+struct Counter {
+    symbol: String,
+    decimals: u8,
+    weth: bool,
+}
+
+/// Finds the pairs created on a set of V2 factories
+pub struct PairWatcher<M> {
+    provider: Arc<M>,
+    factories: Vec<Address>,
+    end_a: Address,
+    weth: Address,
+    stables: Vec<(String, Address, u8)>,
+}
+
+impl<M: Middleware + 'static> PairWatcher<M> {
+    /**
+     * @gist creates the watcher
+     * @param provider -- used for eth_getLogs and to read the pairs
+     * @param factories -- the V2 factories to watch
+     * @param end_a -- the ETH/USDT pair WETH pairs are priced through
+    **/
+    pub fn new(provider: Arc<M>, factories: Vec<Address>, end_a: Address) -> eyre::Result<Self> {
+        let stables = STABLES.iter()
+            .map(|(symbol, address, decimals)| Ok((symbol.to_string(), address.parse()?, *decimals)))
+            .collect::<eyre::Result<_>>()?;
+        Ok(Self { provider, factories, end_a, weth: uniswap_v2::WETH.parse()?, stables })
+    }
+
+    /**
+     * @gist the pairs created in a range of blocks, pairs of two other
+     * tokens are left out
+     * @param blocks -- the blocks to read, both ends included
+     * @output the pairs, oldest first
+    **/
+    pub async fn new_pairs(&self, blocks: RangeInclusive<u64>) -> eyre::Result<Vec<NewPair>> {
+        let (from, to) = blocks.into_inner();
+        let mut found = Vec::new();
+        for factory in &self.factories {
+            let contract = IUniswapV2Factory::new(*factory, self.provider.clone());
+            let mut lo = from;
+            while lo <= to {
+                let hi = (lo + CHUNK - 1).min(to);
+                // The generated filter matches the event of every contract, not just this one
+                let filter = contract.pair_created_filter().address(ValueOrArray::Value(*factory)).from_block(lo).to_block(hi);
+                for (created, meta) in filter.query_with_meta().await? {
+                    let Some((token, inverted, counter)) = self.classify(created.token_0, created.token_1) else {
+                        continue;
+                    };
+                    match self.price(created.pair, token, inverted, &counter).await {
+                        Ok((token, price, liquidity)) => found.push(NewPair {
+                            pair: created.pair,
+                            factory: *factory,
+                            token,
+                            counter: counter.symbol,
+                            counter_decimals: counter.decimals,
+                            inverted,
+                            block: meta.block_number.as_u64(),
+                            tx: meta.transaction_hash,
+                            price,
+                            liquidity,
+                            risk: None,
+                        }),
+                        Err(e) => eprintln!("skipping new pair {:?}: {e}", created.pair),
+                    }
+                }
+                lo = hi + 1;
+            }
+        }
+        found.sort_by_key(|p| p.block);
+        Ok(found)
+    }
+
+    /**
+     * @gist the listed token of a pair and whether WETH or the stablecoin
+     * is token0, WETH wins over a stablecoin
+    **/
+    fn classify(&self, token0: Address, token1: Address) -> Option<(Address, bool, Counter)> {
+        let counter = |address: Address| -> Option<Counter> {
+            if address == self.weth {
+                return Some(Counter { symbol: "WETH".to_string(), decimals: 18, weth: true });
+            }
+            self.stables.iter()
+                .find(|(_, a, _)| *a == address)
+                .map(|(symbol, _, decimals)| Counter { symbol: symbol.clone(), decimals: *decimals, weth: false })
+        };
+        match (counter(token0), counter(token1)) {
+            (Some(c0), Some(c1)) if c1.weth && !c0.weth => Some((token0, false, c1)),
+            (Some(c0), _) => Some((token1, true, c0)),
+            (None, Some(c1)) => Some((token0, false, c1)),
+            (None, None) => None,
+        }
+    }
+
+    /**
+     * @gist reads the reserves of a candidate again and counts the volume
+     * of its swaps up to a block
+     * @param candidate -- the pair to update
+     * @param head -- the last block to count
+    **/
+    pub async fn update(&self, candidate: &mut Candidate, head: u64) -> eyre::Result<()> {
+        let pair = &candidate.pair;
+        let counter = Counter { symbol: pair.counter.clone(), decimals: pair.counter_decimals, weth: pair.counter == "WETH" };
+        let (_, price, liquidity) = self.price(pair.pair, pair.token.address, pair.inverted, &counter).await?;
+        let usd = self.counter_usd(&counter).await?;
+
+        let contract = IUniswapV2Pair::new(pair.pair, self.provider.clone());
+        let mut lo = candidate.next;
+        while lo <= head {
+            let hi = (lo + CHUNK - 1).min(head);
+            let filter = contract.swap_filter().address(ValueOrArray::Value(pair.pair)).from_block(lo).to_block(hi);
+            for swap in filter.query().await? {
+                let (amount_in, amount_out) = if pair.inverted {
+                    (swap.amount_0_in, swap.amount_0_out)
+                } else {
+                    (swap.amount_1_in, swap.amount_1_out)
+                };
+                let traded = amount_in.abs_diff(amount_out).as_u128() as f64 / 10_f64.powi(counter.decimals as i32);
+                candidate.volume += traded * usd;
+            }
+            lo = hi + 1;
+        }
+
+        candidate.next = candidate.next.max(head + 1);
+        candidate.pair.price = price;
+        candidate.pair.liquidity = liquidity;
+        Ok(())
+    }
+
+    /**
+     * @gist USDT price of WETH or a stablecoin, stablecoins are taken at $1
+    **/
+    async fn counter_usd(&self, counter: &Counter) -> eyre::Result<f64> {
+        if !counter.weth {
+            return Ok(1.0);
+        }
+        let (eth, usdt, _) = get_reserves(self.provider.clone(), &self.end_a).await?;
+        Ok(uniswap_v2::reformat_usd(usdt) / uniswap_v2::reformat_wei(eth))
+    }
+
+    /**
+     * @gist reads the token and reserves of a pair
+     * @output the token, its USDT price and the USDT value of the pair, both
+     * None without reserves
+    **/
+    async fn price(&self, pair: Address, token: Address, inverted: bool, counter: &Counter) -> eyre::Result<(Token, Option<f64>, Option<f64>)> {
+        let token = get_token(self.provider.clone(), token).await?;
+        let (reserve0, reserve1, _) = get_reserves(self.provider.clone(), &pair).await?;
+        let (token_reserve, counter_reserve) = if inverted { (reserve1, reserve0) } else { (reserve0, reserve1) };
+        if token_reserve == 0 || counter_reserve == 0 {
+            return Ok((token, None, None));
+        }
+
+        let amount = token_reserve as f64 / 10_f64.powi(token.decimals as i32);
+        let counter_amount = counter_reserve as f64 / 10_f64.powi(counter.decimals as i32);
+        let usd = self.counter_usd(counter).await?;
+        Ok((token, Some(counter_amount / amount * usd), Some(2.0 * counter_amount * usd)))
+    }
+}
@@ -0,0 +1,312 @@
+/*!
+ *
+ * The polling loop. A feed pairs an asset with the source that prices it;
+ * the poller quotes every feed once per cycle and hands the results to the
+ * caller, so it works the same for built-in and third-party sources.
+ *
+**/
+
+use chrono::{DateTime, Utc};
+use tokio::time::Instant;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::breaker;
+use crate::clock::unix_now;
+use crate::confidence;
+use crate::provider;
+use crate::schedule::Schedule;
+use crate::source::{Asset, PriceSource, Quote};
+use crate::systemd;
+use crate::trace::{self, Span};
+
+/// An asset together with the source that prices it
+#[derive(Clone)]
+pub struct Feed {
+    pub source: Arc<dyn PriceSource>,
+    pub asset: Asset,
+    /// Time between two quotes in watch mode when it is longer than a
+    /// cycle, None for every cycle
+    pub every: Option<Duration>,
+}
+
+impl Feed {
+    /**
+     * @gist pairs an asset with the source that prices it
+    **/
+    pub fn new(source: Arc<dyn PriceSource>, asset: Asset) -> Self {
+        Self { source, asset, every: None }
+    }
+
+    /**
+     * @gist quotes the feed only every so often in watch mode, in the first
+     * cycle after that time passed
+    **/
+    pub fn with_every(mut self, every: Duration) -> Self {
+        self.every = Some(every);
+        self
+    }
+}
+
+/**
+ * @gist when each feed is first due, the feeds sharing an interval are
+ * spread over it so their quotes don't all land in the same cycle
+ * @param feeds -- the watchlist
+ * @param start -- the first cycle
+**/
+fn first_due(feeds: &[Feed], start: Instant) -> Vec<Instant> {
+    feeds.iter().enumerate()
+        .map(|(i, feed)| {
+            let Some(every) = feed.every else {
+                return start;
+            };
+            let shared = feeds.iter().filter(|f| f.every == Some(every)).count() as u32;
+            let rank = feeds[..i].iter().filter(|f| f.every == Some(every)).count() as u32;
+            start + every * rank / shared
+        })
+        .collect()
+}
+
+/// Returned for a feed that had not answered when the deadline passed
+#[derive(Debug)]
+pub struct Missed {
+    pub asset: String,
+    pub deadline: Duration,
+}
+
+impl std::fmt::Display for Missed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: no quote within the {} ms deadline, skipped this cycle", self.asset, self.deadline.as_millis())
+    }
+}
+
+impl std::error::Error for Missed {}
+
+/**
+ * @gist quotes every feed once, all feeds at the same time. The provider
+ * limits how many requests are actually in flight. Quotes without a
+ * timestamp are stamped with the local time.
+ * @param feeds -- what to quote
+ * @output one result per feed, in the same order
+**/
+pub async fn poll(feeds: &[Feed]) -> Vec<eyre::Result<Quote>> {
+    poll_within(feeds, None).await
+}
+
+/**
+ * @gist like poll, but the feeds still quoting when the deadline passes
+ * are cancelled and fail with Missed
+ * @param feeds -- what to quote
+ * @param deadline -- time the feeds have from the start, None to wait for
+ * all of them
+ * @output one result per feed, in the same order
+**/
+pub async fn poll_within(feeds: &[Feed], deadline: Option<Duration>) -> Vec<eyre::Result<Quote>> {
+    let until = deadline.map(|d| tokio::time::Instant::now() + d);
+    let parent = trace::current();
+    let pinned = provider::pinned();
+    let tasks: Vec<_> = feeds.iter().cloned()
+        .map(|feed| tokio::spawn(async move {
+            let mut span = Span::child_of("quote", parent);
+            span.set("asset", &feed.asset.name);
+            span.set("source", feed.source.name());
+
+            let quote = span.run(provider::pinned_at(pinned, feed.source.quote(&feed.asset))).await;
+            if let Err(e) = &quote {
+                span.fail(e);
+            }
+            quote.map(|mut q| {
+                q.timestamp.get_or_insert_with(unix_now);
+                q.address.get_or_insert(feed.asset.address);
+                if q.confidence.is_none() {
+                    q.confidence = Some(confidence::score(&q, unix_now()));
+                }
+                q
+            })
+        }))
+        .collect();
+
+    let mut quotes = Vec::with_capacity(feeds.len());
+    for (feed, mut task) in feeds.iter().zip(tasks) {
+        let joined = match until {
+            Some(until) => match tokio::time::timeout_at(until, &mut task).await {
+                Ok(joined) => joined,
+                Err(_) => {
+                    task.abort();
+                    let deadline = deadline.unwrap_or_default();
+                    quotes.push(Err(Missed { asset: feed.asset.name.clone(), deadline }.into()));
+                    continue;
+                }
+            },
+            None => task.await,
+        };
+        quotes.push(joined.unwrap_or_else(|e| Err(eyre::eyre!("the quote task failed: {e}"))));
+    }
+    quotes
+}
+
+/**
+ * @gist marks the quotes of a cycle degraded when any feed missed the
+ * deadline, they are only part of the watchlist
+ * @param results -- the results of poll_within
+**/
+pub fn degrade(results: &mut [eyre::Result<Quote>]) {
+    if results.iter().any(|r| r.as_ref().is_err_and(|e| e.is::<Missed>())) {
+        for quote in results.iter_mut().flatten() {
+            quote.degraded = true;
+        }
+    }
+}
+
+/**
+ * @gist age of the newest block the quotes of a cycle were read at
+ * @param quotes -- the quotes of a cycle
+ * @param now -- unix time
+ * @output seconds, None without on-chain quotes
+**/
+pub fn block_age(quotes: &[Quote], now: u64) -> Option<u64> {
+    quotes.iter()
+        .filter(|quote| quote.block.is_some())
+        .filter_map(|quote| quote.timestamp)
+        .max()
+        .map(|newest| now.saturating_sub(newest))
+}
+
+/// When the poller runs a cycle
+#[derive(Debug, Clone)]
+pub enum Cadence {
+    /// Every interval, the first cycle right away
+    Every(Duration),
+    /// Whenever the cron expression matches
+    Schedule(Schedule),
+}
+
+impl From<Duration> for Cadence {
+    fn from(interval: Duration) -> Self {
+        Cadence::Every(interval)
+    }
+}
+
+enum Ticker {
+    Interval(tokio::time::Interval),
+    Schedule { schedule: Schedule, last: Option<DateTime<Utc>> },
+}
+
+impl Ticker {
+    async fn tick(&mut self) -> eyre::Result<()> {
+        match self {
+            Ticker::Interval(interval) => {
+                interval.tick().await;
+            }
+            Ticker::Schedule { schedule, last } => {
+                // The timer may wake up just before the second it aimed for,
+                // so a match is never picked twice
+                let now = Utc::now();
+                let from = last.map_or(now, |last| last.max(now));
+                let next = schedule.next_after(from).ok_or_else(|| eyre::eyre!("the schedule never matches again"))?;
+                tokio::time::sleep(next.signed_duration_since(now).to_std().unwrap_or_default()).await;
+                *last = Some(next);
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+ * @gist polls the feeds forever, errors are reported but do not stop the
+ * poller. Under systemd READY=1 is sent after the first cycle in which any
+ * feed answered and the watchdog is fed after every such cycle.
+ * @param feeds -- what to quote, the ones with an interval of their own only
+ * in the cycles they are due
+ * @param cadence -- time between two cycles, or a schedule
+ * @param deadline -- time a cycle has until the slow feeds are skipped
+ * @param on_cycle -- called after every cycle with the successful quotes
+**/
+pub async fn watch<F: AsyncFnMut(Vec<Quote>)>(feeds: &[Feed], cadence: impl Into<Cadence>, deadline: Option<Duration>, mut on_cycle: F) -> eyre::Result<()> {
+    let cadence = cadence.into();
+
+    // Under systemd the watchdog is only fed after successful polls, so
+    // the interval has to stay well below WatchdogSec=
+    if let Some(timeout) = systemd::watchdog_timeout() {
+        let gap = match &cadence {
+            Cadence::Every(interval) => Some(*interval),
+            Cadence::Schedule(schedule) => schedule.next_after(Utc::now())
+                .and_then(|first| Some((first, schedule.next_after(first)?)))
+                .and_then(|(first, second)| second.signed_duration_since(first).to_std().ok()),
+        };
+        if let Some(gap) = gap.filter(|gap| *gap * 2 > timeout) {
+            eprintln!("warning: polls {}s apart are more than half of the systemd watchdog timeout ({}s)",
+                gap.as_secs(), timeout.as_secs());
+        }
+    }
+
+    // Failing to reach systemd must not stop the polling
+    let ping = || if let Err(e) = systemd::watchdog() {
+        eprintln!("could not ping the systemd watchdog: {e}");
+    };
+    let mut ready = false;
+    // Whether the last cycle that polled got a quote
+    let mut healthy = false;
+    let mut due = first_due(feeds, Instant::now());
+    let mut ticker = match cadence {
+        Cadence::Every(interval) => Ticker::Interval(tokio::time::interval(interval)),
+        Cadence::Schedule(schedule) => Ticker::Schedule { schedule, last: None },
+    };
+
+    loop {
+        ticker.tick().await?;
+
+        // Feeds with an interval of their own sit out the cycles in between
+        let now = Instant::now();
+        let mut polled = Vec::with_capacity(feeds.len());
+        for (feed, due) in feeds.iter().zip(&mut due) {
+            if *due > now {
+                continue;
+            }
+            if let Some(every) = feed.every {
+                *due = if *due + every > now { *due + every } else { now + every };
+            }
+            polled.push(feed.clone());
+        }
+        // A cycle with nothing due is no failure, the last poll still counts
+        if polled.is_empty() {
+            if healthy {
+                ping();
+            }
+            continue;
+        }
+
+        let mut cycle = Span::start("poll cycle");
+        cycle.set("feeds", polled.len());
+        let ok = cycle.run(async {
+            let mut quotes = Vec::with_capacity(polled.len());
+            let mut results = poll_within(&polled, deadline).await;
+            degrade(&mut results);
+            for quote in results {
+                match quote {
+                    Ok(quote) => quotes.push(quote),
+                    // The breaker reported the feed when it tripped
+                    Err(e) if e.is::<breaker::Open>() => {}
+                    Err(e) => eprintln!("poll failed: {e}"),
+                }
+            }
+
+            let ok = !quotes.is_empty();
+            on_cycle(quotes).await;
+            ok
+        }).await;
+        drop(cycle);
+
+        healthy = ok;
+        if ok {
+            if !ready {
+                if let Err(e) = systemd::ready() {
+                    eprintln!("could not tell systemd the poller is ready: {e}");
+                }
+                ready = true;
+            }
+            ping();
+        }
+    }
+}
@@ -0,0 +1,291 @@
+/*!
+ *
+ * The watchlist: plain TOKEN/ETH pools listed as [[pool]] tables in the
+ * config, each priced like --pool. Large universes are brought in with
+ * `pools import`, which reads a CSV or JSON file, checks every pool on chain
+ * and appends the new ones to the config, or with `pools discover`, which
+ * finds the deepest WETH pool of every token of a token list.
+ *
+**/
+
+use ethers::{providers::Middleware, types::Address};
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::checksum;
+use crate::rebase::RebaseConfig;
+use crate::route::RouteConfig;
+use crate::source::Asset;
+use crate::subgraph::Subgraph;
+use crate::uniswap_v2::{self, IUniswapV2Pair};
+
+/// A TOKEN/ETH pool on the watchlist
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PoolConfig {
+    pub address: Address,
+
+    /// Asset name in the output, defaults to the pool address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+
+    /// WETH is token0 of the pool and TOKEN is token1
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub inverted: bool,
+
+    /// Decimals of TOKEN
+    #[serde(default = "default_decimals", skip_serializing_if = "is_default_decimals")]
+    pub decimals: u8,
+
+    /// TOKEN rebases, price it per share
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rebase: Option<RebaseConfig>,
+
+    /// Price TOKEN along a path of its own instead of through the ETH/USDT
+    /// pool
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route: Option<RouteConfig>,
+
+    /// Added to the quotes of the pool in every output, see [labels]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+
+    /// Significant figures of the prices in the output, see [precision]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sig_figs: Option<u32>,
+
+    /// Round the prices in the output to a multiple of this
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tick: Option<f64>,
+
+    /// Seconds between two quotes of the pool in watch mode, when it is
+    /// polled less often than every cycle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval: Option<u64>,
+}
+
+fn is_false(b: &bool) -> bool {
+    !b
+}
+
+fn default_decimals() -> u8 {
+    18
+}
+
+fn is_default_decimals(d: &u8) -> bool {
+    *d == default_decimals()
+}
+
+impl PoolConfig {
+    /**
+     * @gist the asset priced by this pool
+    **/
+    pub fn asset(&self) -> Asset {
+        let name = self.label.clone().unwrap_or_else(|| format!("{:?}", self.address));
+        Asset { name, address: self.address }
+    }
+}
+
+/// An entry of a JSON import file, either a bare address or an object
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Entry {
+    Address(String),
+    Pool { address: String, label: Option<String> },
+}
+
+/**
+ * @gist reads pools from a CSV (`address[,label]` per line, optional header,
+ * `#` comments) or JSON file (array of addresses or {address, label})
+ * @param path -- the file to import, .json is parsed as JSON
+ * @output the pools in file order, or the first line that is not valid
+**/
+pub fn read(path: &Path) -> eyre::Result<Vec<PoolConfig>> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| eyre::eyre!("could not read {}: {e}", path.display()))?;
+
+    if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json")) {
+        let entries: Vec<Entry> = serde_json::from_str(&text)
+            .map_err(|e| eyre::eyre!("invalid pool list {}: {e}", path.display()))?;
+        return entries.into_iter()
+            .enumerate()
+            .map(|(i, entry)| {
+                let (address, label) = match entry {
+                    Entry::Address(address) => (address, None),
+                    Entry::Pool { address, label } => (address, label),
+                };
+                parse(&address, label).map_err(|e| eyre::eyre!("{} entry {}: {e}", path.display(), i + 1))
+            })
+            .collect();
+    }
+
+    let mut pools = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (address, label) = match line.split_once(',') {
+            Some((address, label)) => (address.trim(), Some(label.trim())),
+            None => (line, None),
+        };
+        if i == 0 && address.eq_ignore_ascii_case("address") {
+            continue;
+        }
+        let label = label.filter(|l| !l.is_empty()).map(str::to_string);
+        pools.push(parse(address, label).map_err(|e| eyre::eyre!("{} line {}: {e}", path.display(), i + 1))?);
+    }
+    Ok(pools)
+}
+
+fn parse(address: &str, label: Option<String>) -> eyre::Result<PoolConfig> {
+    let address = checksum::parse(address)?;
+    Ok(PoolConfig { address, label, inverted: false, decimals: default_decimals(), rebase: None, route: None, labels: BTreeMap::new(), sig_figs: None, tick: None, interval: None })
+}
+
+/**
+ * @gist checks that a pool answers getReserves and holds liquidity on both
+ * sides
+ * @param provider -- any ethers middleware, http or ipc
+ * @param pool -- the pool to check
+ * @output Ok(()) if the pool can be priced
+**/
+pub async fn validate<M: Middleware + 'static>(provider: Arc<M>, pool: &PoolConfig) -> eyre::Result<()> {
+    let (token, eth, _) = uniswap_v2::get_reserves(provider, &pool.address).await
+        .map_err(|e| eyre::eyre!("not a V2 pair: {e}"))?;
+    if token == 0 || eth == 0 {
+        eyre::bail!("pool is empty");
+    }
+    Ok(())
+}
+
+/**
+ * @gist appends pools to the config as [[pool]] tables, keeping the rest of
+ * the file untouched. Pools already in the config are skipped.
+ * @param path -- the config file, created if missing
+ * @param existing -- the pools already on the watchlist
+ * @param pools -- the pools to add
+ * @output how many pools were written
+**/
+pub fn append(path: &Path, existing: &[PoolConfig], pools: &[PoolConfig]) -> eyre::Result<usize> {
+    let mut seen: HashSet<Address> = existing.iter().map(|p| p.address).collect();
+    let pool: Vec<PoolConfig> = pools.iter().filter(|p| seen.insert(p.address)).cloned().collect();
+    if pool.is_empty() {
+        return Ok(0);
+    }
+    let added = pool.len();
+
+    let mut text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => eyre::bail!("could not read {}: {e}", path.display()),
+    };
+    if !text.is_empty() && !text.ends_with("\n\n") {
+        text.push_str(if text.ends_with('\n') { "\n" } else { "\n\n" });
+    }
+    text.push_str(&to_toml(&pool)?);
+
+    std::fs::write(path, text)
+        .map_err(|e| eyre::eyre!("could not write {}: {e}", path.display()))?;
+    Ok(added)
+}
+
+/// A Uniswap token list, see https://tokenlists.org
+#[derive(Deserialize, Debug)]
+pub struct TokenList {
+    pub tokens: Vec<TokenInfo>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenInfo {
+    pub chain_id: u64,
+    pub address: Address,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+/**
+ * @gist fetches a token list
+ * @param client -- http client used for urls
+ * @param location -- an http(s) url or a local file
+ * @output the parsed list
+**/
+pub async fn token_list(client: &reqwest::Client, location: &str) -> eyre::Result<TokenList> {
+    let text = if location.starts_with("http://") || location.starts_with("https://") {
+        client.get(location).send().await?.error_for_status()?.text().await?
+    } else {
+        std::fs::read_to_string(location).map_err(|e| eyre::eyre!("could not read {location}: {e}"))?
+    };
+    serde_json::from_str(&text).map_err(|e| eyre::eyre!("invalid token list {location}: {e}"))
+}
+
+/**
+ * @gist finds the WETH pool of a token with the largest WETH reserve among
+ * the factories
+ * @param provider -- any ethers middleware, http or ipc
+ * @param factories -- V2 factories to look the pair up on
+ * @param weth -- the WETH address
+ * @param token -- the token to find a pool for
+ * @output the pool and its WETH reserve in ETH, None if no factory has one
+**/
+pub async fn deepest_pool<M: Middleware + 'static>(provider: Arc<M>, factories: &[Address], weth: &Address, token: &TokenInfo) -> eyre::Result<Option<(PoolConfig, f64)>> {
+    let inverted = token.address > *weth;
+    let mut best: Option<(Address, u128)> = None;
+
+    for factory in factories {
+        let Some(pair) = uniswap_v2::get_pair(provider.clone(), factory, &token.address, weth).await? else {
+            continue;
+        };
+        let (reserve0, reserve1, _) = IUniswapV2Pair::new(pair, provider.clone()).get_reserves().call().await?;
+        let eth = if inverted { reserve0 } else { reserve1 };
+        if best.is_none_or(|(_, b)| eth > b) {
+            best = Some((pair, eth));
+        }
+    }
+
+    Ok(best.map(|(address, eth)| (discovered(address, weth, token), uniswap_v2::reformat_wei(eth))))
+}
+
+/**
+ * @gist like deepest_pool but asks the subgraph instead of the factories
+ * @param subgraph -- a v2 subgraph
+ * @param weth -- the WETH address
+ * @param token -- the token to find a pool for
+ * @output the pool and its WETH reserve in ETH, None if there is no pair
+**/
+pub async fn subgraph_pool(subgraph: &Subgraph, weth: &Address, token: &TokenInfo) -> eyre::Result<Option<(PoolConfig, f64)>> {
+    Ok(subgraph.pair(&token.address, weth).await?.map(|(address, eth)| (discovered(address, weth, token), eth)))
+}
+
+/// The watchlist entry of a TOKEN/WETH pair found for a token list entry
+fn discovered(address: Address, weth: &Address, token: &TokenInfo) -> PoolConfig {
+    PoolConfig {
+        address,
+        label: Some(format!("{}/USDT", token.symbol)),
+        inverted: token.address > *weth,
+        decimals: token.decimals,
+        rebase: None,
+        route: None,
+        labels: BTreeMap::new(),
+        sig_figs: None,
+        tick: None,
+        interval: None,
+    }
+}
+
+/**
+ * @gist renders pools as [[pool]] tables
+ * @param pools -- the watchlist
+ * @output TOML text that can be used as or appended to a config
+**/
+pub fn to_toml(pools: &[PoolConfig]) -> eyre::Result<String> {
+    #[derive(Serialize)]
+    struct Tables<'a> {
+        pool: &'a [PoolConfig],
+    }
+    Ok(toml::to_string(&Tables { pool: pools })?)
+}
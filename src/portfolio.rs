@@ -0,0 +1,126 @@
+/*!
+ *
+ * Wallet valuation over the watchlist. The TOKEN of every [[pool]] and the
+ * wallet's balance of it are read with two multicalls, the wallet's ether is
+ * read in the same batch. Every token held is then priced through its pool
+ * like a regular quote.
+ *
+**/
+
+use ethers::{
+    prelude::Multicall,
+    providers::Middleware,
+    types::{Address, U256},
+};
+use serde::Serialize;
+
+use std::sync::Arc;
+
+use crate::pools::PoolConfig;
+use crate::poller::{self, Feed};
+use crate::uniswap_v2::{self, UniswapV2, IERC20, IUniswapV2Pair};
+
+/// Asset name of the ether held by the wallet
+pub const ETH: &str = "ETH";
+
+/// One asset held by the wallet
+#[derive(Debug, Clone, Serialize)]
+pub struct Holding {
+    pub asset: String,
+    /// The token contract, None for ether
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<Address>,
+    /// Balance in TOKEN, scaled by its decimals
+    pub balance: f64,
+    /// USDT per TOKEN, None when the pool could not be priced
+    pub price: Option<f64>,
+    /// USDT value of the balance
+    pub value: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Portfolio {
+    pub wallet: Address,
+    pub holdings: Vec<Holding>,
+    /// USDT value of the priced holdings
+    pub total: f64,
+}
+
+/**
+ * @gist reads the ether balance of a wallet and its balance of the TOKEN of
+ * every pool
+ * @param provider -- this is used to send request to the multicall SC
+ * @param wallet -- the holder
+ * @param pools -- the watchlist, the TOKEN side follows `inverted`
+ * @output the wei balance and the (token, unscaled balance) of every pool
+**/
+pub async fn balances<M: Middleware + 'static>(provider: Arc<M>, wallet: Address, pools: &[PoolConfig]) -> eyre::Result<(U256, Vec<(Address, U256)>)> {
+    let mut multicall = Multicall::new(provider.clone(), None).await?;
+
+    let tokens: Vec<Address> = if pools.is_empty() {
+        Vec::new()
+    } else {
+        for p in pools {
+            let pair = IUniswapV2Pair::new(p.address, provider.clone());
+            multicall.add_call(if p.inverted { pair.token_1() } else { pair.token_0() }, false);
+        }
+        multicall.call_array().await.map_err(|e| eyre::eyre!("could not read the pool tokens: {e}"))?
+    };
+
+    multicall.clear_calls();
+    multicall.add_get_eth_balance(wallet, false);
+    for token in &tokens {
+        multicall.add_call(IERC20::new(*token, provider.clone()).balance_of(wallet), false);
+    }
+    let mut amounts: Vec<U256> = multicall.call_array().await.map_err(|e| eyre::eyre!("could not read the balances: {e}"))?;
+    let eth = amounts.remove(0);
+    Ok((eth, tokens.into_iter().zip(amounts).collect()))
+}
+
+/**
+ * @gist values a wallet in USDT. Tokens with a zero balance are left out,
+ * a pool that cannot be priced leaves its holding without a value.
+ * @param provider -- any ethers middleware, http or ipc
+ * @param wallet -- the holder
+ * @param pools -- the watchlist, labels name the holdings
+ * @param end_a -- the ETH/USDT pair address, also prices the ether
+ * @output the holdings and their total
+**/
+pub async fn value<M: Middleware + 'static>(provider: Arc<M>, wallet: Address, pools: &[PoolConfig], end_a: Address) -> eyre::Result<Portfolio> {
+    let (eth, tokens) = balances(provider.clone(), wallet, pools).await?;
+
+    let held: Vec<(&PoolConfig, Address, f64)> = pools.iter().zip(tokens)
+        .filter(|(_, (_, amount))| !amount.is_zero())
+        .map(|(p, (token, amount))| (p, token, scale(amount, p.decimals)))
+        .collect();
+    let feeds: Vec<Feed> = held.iter()
+        .map(|(p, _, _)| Feed::new(Arc::new(UniswapV2::new(provider.clone(), end_a).with_layout(p.inverted, p.decimals)), p.asset()))
+        .collect();
+
+    let mut holdings = Vec::with_capacity(held.len() + 1);
+    if !eth.is_zero() {
+        let (eth_reserve, usdt_reserve, _) = uniswap_v2::get_reserves(provider, &end_a).await?;
+        let price = uniswap_v2::reformat_usd(usdt_reserve) / uniswap_v2::reformat_wei(eth_reserve);
+        holdings.push(holding(ETH.to_string(), None, scale(eth, 18), Some(price)));
+    }
+    for ((p, token, balance), quote) in held.into_iter().zip(poller::poll(&feeds).await) {
+        let price = quote.inspect_err(|e| eprintln!("could not price {}: {e}", p.asset().name)).ok().map(|q| q.price);
+        holdings.push(holding(p.asset().name, Some(token), balance, price));
+    }
+
+    let total = holdings.iter().filter_map(|h| h.value).sum();
+    Ok(Portfolio { wallet, holdings, total })
+}
+
+fn holding(asset: String, token: Option<Address>, balance: f64, price: Option<f64>) -> Holding {
+    Holding { asset, token, balance, price, value: price.map(|p| p * balance) }
+}
+
+/**
+ * @gist converts an unscaled token amount into decimals
+**/
+fn scale(amount: U256, decimals: u8) -> f64 {
+    ethers::utils::format_units(amount, decimals as u32).ok()
+        .and_then(|units| units.parse().ok())
+        .unwrap_or(f64::NAN)
+}
@@ -0,0 +1,171 @@
+/*!
+ *
+ * How precise the prices in the output are, for downstream systems whose
+ * schema rejects more digits than they store. `--sig-figs N` rounds the
+ * prices of every feed to N significant figures and a [[pool]] can pick
+ * its own precision, either significant figures or a tick size:
+ *
+ * [[pool]]
+ * address = "0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974"
+ * label = "LINK"
+ * tick = 0.001
+ *
+ * The quotes are rounded where they come out of their source, so the text
+ * output, every sink, the metrics and the alerts see the same numbers. The
+ * price, bid, ask, VWAP and the compared prices are rounded, amounts like
+ * the liquidity are not.
+ *
+**/
+
+use async_trait::async_trait;
+
+use std::sync::Arc;
+
+use crate::source::{Asset, PriceSource, Quote};
+
+/// Most significant figures an f64 holds
+const MAX_SIG_FIGS: u32 = 17;
+
+/// How a price is rounded
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rounding {
+    /// To this many significant figures
+    SigFigs(u32),
+    /// To the nearest multiple of the tick
+    Tick(f64),
+}
+
+impl Rounding {
+    /**
+     * @gist the rounding of a [[pool]]
+     * @param name -- the pool, for the error
+     * @param sig_figs -- its sig_figs
+     * @param tick -- its tick
+     * @output None when it sets neither
+    **/
+    pub fn of_pool(name: &str, sig_figs: Option<u32>, tick: Option<f64>) -> eyre::Result<Option<Self>> {
+        match (sig_figs, tick) {
+            (Some(_), Some(_)) => eyre::bail!("[[pool]] {name}: set either sig_figs or tick, not both"),
+            (Some(n), None) => Self::sig_figs(n).map(Some).map_err(|e| eyre::eyre!("[[pool]] {name}: {e}")),
+            (None, Some(tick)) if !tick.is_finite() || tick <= 0.0 => eyre::bail!("[[pool]] {name}: tick has to be above 0"),
+            (None, Some(tick)) => Ok(Some(Rounding::Tick(tick))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /**
+     * @gist rounding to n significant figures
+    **/
+    pub fn sig_figs(n: u32) -> eyre::Result<Self> {
+        if !(1..=MAX_SIG_FIGS).contains(&n) {
+            eyre::bail!("the significant figures have to be between 1 and {MAX_SIG_FIGS}");
+        }
+        Ok(Rounding::SigFigs(n))
+    }
+
+    /**
+     * @gist rounds a value
+    **/
+    pub fn apply(&self, value: f64) -> f64 {
+        if !value.is_finite() {
+            return value;
+        }
+        // Through the decimal representation, so 0.1 + 0.2 comes out as 0.3
+        // and not 0.30000000000000004
+        let rounded = match *self {
+            Rounding::SigFigs(n) => format!("{:.*e}", n as usize - 1, value),
+            Rounding::Tick(tick) => {
+                let decimals = tick.to_string().split_once('.').map(|(_, fraction)| fraction.len()).unwrap_or(0);
+                format!("{:.*}", decimals, (value / tick).round() * tick)
+            }
+        };
+        rounded.parse().unwrap_or(value)
+    }
+
+    /**
+     * @gist rounds the prices of a quote and its components
+    **/
+    pub fn round(&self, quote: &mut Quote) {
+        quote.price = self.apply(quote.price);
+        for price in [
+            &mut quote.bid, &mut quote.ask, &mut quote.vwap, &mut quote.cex_price,
+            &mut quote.sized_price, &mut quote.aggregator_price, &mut quote.simulated_price,
+        ] {
+            *price = price.map(|p| self.apply(p));
+        }
+        for component in &mut quote.components {
+            self.round(component);
+        }
+    }
+}
+
+/// Rounds the prices of the quotes of a source
+pub struct Rounded {
+    inner: Arc<dyn PriceSource>,
+    rounding: Rounding,
+}
+
+impl Rounded {
+    /**
+     * @gist wraps a source
+     * @param inner -- the source
+     * @param rounding -- how its prices are rounded
+    **/
+    pub fn new(inner: Arc<dyn PriceSource>, rounding: Rounding) -> Self {
+        Self { inner, rounding }
+    }
+}
+
+#[async_trait]
+impl PriceSource for Rounded {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let mut quote = self.inner.quote(asset).await?;
+        self.rounding.round(&mut quote);
+        Ok(quote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_to_significant_figures() {
+        let three = Rounding::sig_figs(3).unwrap();
+        assert_eq!(three.apply(12345.678), 12300.0);
+        assert_eq!(three.apply(0.000123456), 0.000123);
+        assert_eq!(three.apply(-9.996), -10.0);
+        assert_eq!(Rounding::sig_figs(15).unwrap().apply(0.1 + 0.2), 0.3);
+    }
+
+    #[test]
+    fn rounds_to_the_nearest_tick() {
+        assert_eq!(Rounding::Tick(0.001).apply(1.23456), 1.235);
+        assert_eq!(Rounding::Tick(0.25).apply(1.3), 1.25);
+        assert_eq!(Rounding::Tick(0.25).apply(1.4), 1.5);
+        assert_eq!(Rounding::Tick(5.0).apply(12.4), 10.0);
+    }
+
+    #[test]
+    fn leaves_what_is_not_a_number() {
+        let three = Rounding::sig_figs(3).unwrap();
+        assert!(three.apply(f64::NAN).is_nan());
+        assert_eq!(three.apply(f64::INFINITY), f64::INFINITY);
+        assert_eq!(Rounding::Tick(0.01).apply(f64::NEG_INFINITY), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn checks_the_settings() {
+        assert!(Rounding::sig_figs(0).is_err());
+        assert!(Rounding::sig_figs(MAX_SIG_FIGS + 1).is_err());
+        assert!(Rounding::of_pool("LINK", Some(4), Some(0.01)).is_err());
+        assert!(Rounding::of_pool("LINK", None, Some(0.0)).is_err());
+        assert!(Rounding::of_pool("LINK", None, Some(f64::NAN)).is_err());
+        assert_eq!(Rounding::of_pool("LINK", None, None).unwrap(), None);
+        assert_eq!(Rounding::of_pool("LINK", None, Some(0.01)).unwrap(), Some(Rounding::Tick(0.01)));
+    }
+}
@@ -0,0 +1,353 @@
+/*!
+ *
+ * Construction of the JSON-RPC provider. `Provider::try_from` gives us a
+ * reqwest client without any timeouts, which means a slow public RPC can
+ * hang a poll cycle forever. Here we build the HTTP client ourselves so the
+ * timeouts, connection reuse and outbound proxy can be tuned from the
+ * command line.
+ *
+ * Both transports are wrapped in `Limited`, which caps the number of
+ * requests in flight so polling a large watchlist does not hit the RPC with
+ * hundreds of simultaneous eth_calls, `Measured`, which records the
+ * latency and outcome of every request in the metrics, and `Pinned`, which
+ * can point every read of the latest block at another one. `--at safe` or
+ * `--at finalized` pins it to that tag for good, every cycle then reads a
+ * block that won't be reorged at the cost of lagging the chain by a few
+ * minutes. What `pinned_at` runs reads a block of its own, e.g. to quote a
+ * block again after a reorg, and what `unpinned` runs reads the blocks it
+ * asks for.
+ *
+ * With `--quorum N` the reads go to every `--quorum-rpc` endpoint at once
+ * and only an answer N of them return alike is taken, so a single broken
+ * or lying RPC can't move a price. Calls at the latest block are sent at
+ * the lowest head the endpoints report, nodes a block apart still agree.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{Http, Ipc, JsonRpcClient, Provider, Quorum, QuorumProvider, WeightedProvider},
+    types::BlockNumber,
+};
+use reqwest::{Client, Proxy};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Semaphore;
+
+use std::fmt::Debug;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::metrics::{Measured, Metrics};
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct HttpOptions {
+    /// Total time in seconds a single RPC request may take
+    #[arg(long, value_name = "SECONDS", global = true, default_value_t = 30)]
+    pub timeout: u64,
+
+    /// Time in seconds allowed for establishing the TCP/TLS connection
+    #[arg(long, value_name = "SECONDS", global = true, default_value_t = 10)]
+    pub connect_timeout: u64,
+
+    /// How long in seconds an idle connection is kept for reuse, 0 disables reuse
+    #[arg(long, value_name = "SECONDS", global = true, default_value_t = 90)]
+    pub keep_alive: u64,
+
+    /// Route RPC requests through a proxy, e.g. http://proxy:3128 or socks5h://127.0.0.1:9050.
+    /// Without it the HTTPS_PROXY/HTTP_PROXY/NO_PROXY environment variables are honoured
+    #[arg(long, value_name = "URL", global = true)]
+    pub proxy: Option<String>,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self { timeout: 30, connect_timeout: 10, keep_alive: 90, proxy: None }
+    }
+}
+
+/**
+ * @gist builds the reqwest client used to talk to the RPC
+ * @param opts -- timeout, keep-alive and proxy settings
+ * @output a configured client
+**/
+pub fn http_client(opts: &HttpOptions) -> reqwest::Result<Client> {
+    let mut builder = Client::builder()
+        .timeout(Duration::from_secs(opts.timeout))
+        .connect_timeout(Duration::from_secs(opts.connect_timeout));
+
+    builder = if opts.keep_alive == 0 {
+        builder.pool_max_idle_per_host(0)
+    } else {
+        let keep_alive = Duration::from_secs(opts.keep_alive);
+        builder.pool_idle_timeout(keep_alive).tcp_keepalive(keep_alive)
+    };
+
+    // reqwest already picks up the standard proxy environment variables,
+    // an explicit --proxy replaces them
+    if let Some(proxy) = &opts.proxy {
+        builder = builder.proxy(Proxy::all(proxy.as_str())?);
+    }
+
+    builder.build()
+}
+
+/// A transport that lets at most a fixed number of requests be in flight,
+/// the others wait for a free slot
+#[derive(Debug, Clone)]
+pub struct Limited<T> {
+    inner: T,
+    permits: Option<Arc<Semaphore>>,
+}
+
+impl<T> Limited<T> {
+    /**
+     * @gist wraps a transport
+     * @param inner -- the transport
+     * @param max_concurrent -- requests in flight at once, 0 for no limit
+    **/
+    pub fn new(inner: T, max_concurrent: usize) -> Self {
+        let permits = (max_concurrent > 0).then(|| Arc::new(Semaphore::new(max_concurrent)));
+        Self { inner, permits }
+    }
+}
+
+#[async_trait]
+impl<T: JsonRpcClient> JsonRpcClient for Limited<T> {
+    type Error = T::Error;
+
+    async fn request<P, R>(&self, method: &str, params: P) -> Result<R, Self::Error>
+    where
+        P: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let _permit = match &self.permits {
+            Some(permits) => Some(permits.acquire().await.expect("the semaphore is never closed")),
+            None => None,
+        };
+        self.inner.request(method, params).await
+    }
+}
+
+/// The block every cycle reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum BlockTag {
+    /// The head of the chain
+    #[default]
+    Latest,
+    /// The head the beacon chain expects to stay, about a minute behind
+    #[value(alias = "latest-safe")]
+    Safe,
+    /// The last block of a finalized epoch, about 13 minutes behind
+    Finalized,
+}
+
+impl BlockTag {
+    /**
+     * @gist what the pin of the provider is set to, None for the latest block
+    **/
+    pub fn pin(self) -> Option<BlockNumber> {
+        match self {
+            BlockTag::Latest => None,
+            BlockTag::Safe => Some(BlockNumber::Safe),
+            BlockTag::Finalized => Some(BlockNumber::Finalized),
+        }
+    }
+}
+
+/// The block `Pinned` reads instead of the latest one, shared with whoever
+/// moves it
+#[derive(Debug, Default)]
+pub struct Pin(Mutex<Option<BlockNumber>>);
+
+impl Pin {
+    /**
+     * @gist points the reads at a block, None for the latest one again
+    **/
+    pub fn set(&self, block: Option<BlockNumber>) {
+        *self.0.lock().unwrap() = block;
+    }
+
+    /**
+     * @gist the block the reads are pointed at, None for the latest
+    **/
+    pub fn get(&self) -> Option<BlockNumber> {
+        *self.0.lock().unwrap()
+    }
+}
+
+tokio::task_local! {
+    /// Set for the requests that pass the pin by, see unpinned
+    static UNPINNED: ();
+
+    /// The block of the requests of a pinned_at future, over the pin
+    static PINNED_AT: Option<BlockNumber>;
+}
+
+/**
+ * @gist runs a future whose requests read a block of its own instead of the
+ * pin, e.g. to quote a reorged block again while the rest keeps reading the
+ * latest one
+ * @param block -- where the reads go, None for wherever the pin points
+ * @param fut -- the work
+**/
+pub async fn pinned_at<F: Future>(block: Option<BlockNumber>, fut: F) -> F::Output {
+    PINNED_AT.scope(block, fut).await
+}
+
+/**
+ * @gist the block of the pinned_at future this runs in, to carry it into the
+ * tasks it spawns
+**/
+pub fn pinned() -> Option<BlockNumber> {
+    PINNED_AT.try_with(|block| *block).ok().flatten()
+}
+
+/**
+ * @gist runs a future whose requests read the blocks they ask for, whatever
+ * the pin, e.g. those passed on for other tools by --cache-rpc
+ * @param fut -- the work
+**/
+pub async fn unpinned<F: Future>(fut: F) -> F::Output {
+    UNPINNED.scope((), fut).await
+}
+
+/// A transport that replaces the `latest` block tag of every request with
+/// the block of its pin
+#[derive(Debug, Clone)]
+pub struct Pinned<T> {
+    inner: T,
+    pin: Arc<Pin>,
+}
+
+impl<T> Pinned<T> {
+    /**
+     * @gist wraps a transport
+     * @param inner -- the transport
+     * @param pin -- where the reads go, the latest block while it is unset
+    **/
+    pub fn new(inner: T, pin: Arc<Pin>) -> Self {
+        Self { inner, pin }
+    }
+}
+
+/**
+ * @gist replaces `latest` in the params of a request, also as the from and
+ * to block of a log filter
+**/
+fn repin(params: &mut Value, block: &Value) {
+    let latest = |v: &Value| v.as_str() == Some("latest");
+    let Value::Array(params) = params else {
+        return;
+    };
+    for param in params {
+        if latest(param) {
+            *param = block.clone();
+        }
+        if let Value::Object(filter) = param {
+            for key in ["fromBlock", "toBlock"] {
+                if filter.get(key).is_some_and(latest) {
+                    filter.insert(key.to_string(), block.clone());
+                }
+            }
+        }
+    }
+}
+
+/// The number of a block header, a header the node doesn't have fails to
+/// deserialize like any other bad answer
+#[derive(Deserialize)]
+#[serde(bound = "R: DeserializeOwned")]
+struct Numbered<R> {
+    number: R,
+}
+
+#[async_trait]
+impl<T: JsonRpcClient> JsonRpcClient for Pinned<T> {
+    type Error = T::Error;
+
+    async fn request<P, R>(&self, method: &str, params: P) -> Result<R, Self::Error>
+    where
+        P: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let Some(block) = pinned().or_else(|| self.pin.get()).filter(|_| UNPINNED.try_with(|_| ()).is_err()) else {
+            return self.inner.request(method, params).await;
+        };
+        let block = serde_json::to_value(block).expect("a block number serializes");
+
+        // eth_blockNumber takes no tag, the pinned block's header has the number
+        if method == "eth_blockNumber" {
+            let header: Numbered<R> = self.inner.request("eth_getBlockByNumber", (block, false)).await?;
+            return Ok(header.number);
+        }
+        let mut params = serde_json::to_value(&params).expect("the params of a request serialize");
+        repin(&mut params, &block);
+        self.inner.request(method, params).await
+    }
+}
+
+/// The layers around the HTTP or IPC transport of a provider
+pub type Transport<T> = Pinned<Limited<Measured<T>>>;
+
+/// The layers around the HTTP transports of a quorum provider
+pub type QuorumTransport = Pinned<QuorumProvider<Limited<Measured<Http>>>>;
+
+/**
+ * @gist creates an HTTP provider for the given RPC url
+ * @param url -- the JSON-RPC endpoint, its host labels the metrics
+ * @param opts -- timeout, keep-alive and proxy settings
+ * @param max_concurrent -- requests in flight at once, 0 for no limit
+ * @param metrics -- records every request
+ * @param pin -- the block reads of the latest one go to
+ * @output the provider
+**/
+pub fn http_provider(url: &str, opts: &HttpOptions, max_concurrent: usize, metrics: Arc<Metrics>, pin: Arc<Pin>) -> Result<Provider<Transport<Http>>, Box<dyn std::error::Error>> {
+    let client = http_client(opts)?;
+    let url = url.parse::<reqwest::Url>()?;
+    // Only the host, the path often carries an API key
+    let endpoint = url.host_str().unwrap_or("http").to_string();
+    let http = Measured::new(Http::new_with_client(url, client), endpoint, metrics);
+    Ok(Provider::new(Pinned::new(Limited::new(http, max_concurrent), pin)))
+}
+
+/**
+ * @gist connects to a local node over its IPC socket, e.g. ~/.ethereum/geth.ipc
+ * @param path -- path to the IPC socket, labels the metrics
+ * @param max_concurrent -- requests in flight at once, 0 for no limit
+ * @param metrics -- records every request
+ * @param pin -- the block reads of the latest one go to
+ * @output the provider
+**/
+pub async fn ipc_provider(path: &Path, max_concurrent: usize, metrics: Arc<Metrics>, pin: Arc<Pin>) -> Result<Provider<Transport<Ipc>>, Box<dyn std::error::Error>> {
+    let ipc = Measured::new(Ipc::connect(path).await?, path.display().to_string(), metrics);
+    Ok(Provider::new(Pinned::new(Limited::new(ipc, max_concurrent), pin)))
+}
+
+/**
+ * @gist creates a provider that reads from several HTTP RPCs and takes the
+ * answers enough of them agree on
+ * @param urls -- the JSON-RPC endpoints, their hosts label the metrics
+ * @param needed -- how many endpoints have to return the same answer
+ * @param opts -- timeout, keep-alive and proxy settings
+ * @param max_concurrent -- requests in flight at once per endpoint, 0 for no limit
+ * @param metrics -- records every request
+ * @param pin -- the block reads of the latest one go to
+ * @output the provider
+**/
+pub fn quorum_provider(urls: &[String], needed: usize, opts: &HttpOptions, max_concurrent: usize, metrics: Arc<Metrics>, pin: Arc<Pin>) -> Result<Provider<QuorumTransport>, Box<dyn std::error::Error>> {
+    if needed == 0 || needed > urls.len() {
+        return Err(format!("--quorum {needed} needs between 1 and the {} --quorum-rpc endpoints to agree", urls.len()).into());
+    }
+    let client = http_client(opts)?;
+    let mut endpoints = Vec::with_capacity(urls.len());
+    for url in urls {
+        let url = url.parse::<reqwest::Url>().map_err(|e| format!("invalid --quorum-rpc {url}: {e}"))?;
+        let endpoint = url.host_str().unwrap_or("http").to_string();
+        let http = Measured::new(Http::new_with_client(url, client.clone()), endpoint, metrics.clone());
+        endpoints.push(WeightedProvider::new(Limited::new(http, max_concurrent)));
+    }
+    Ok(Provider::new(Pinned::new(QuorumProvider::new(Quorum::ProviderCount(needed), endpoints), pin)))
+}
@@ -0,0 +1,148 @@
+/*!
+ *
+ * Bounded queue between the poller and a sink. Every sink gets its own
+ * queue and worker task, so a slow database or webhook only ever delays
+ * that sink. What happens when the queue is full is up to the policy:
+ *
+ * drop-oldest  the oldest queued quote is discarded
+ * block        the poller waits until there is room (backpressure)
+ * coalesce     a queued quote of the same asset is replaced by the new one,
+ *              if there is none the oldest quote is discarded
+ *
+**/
+
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+use crate::source::Quote;
+use crate::trace::{self, SpanContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Overflow {
+    #[default]
+    DropOldest,
+    Block,
+    Coalesce,
+}
+
+pub struct Queue {
+    /// Quotes with the span they were queued in
+    buffer: Mutex<VecDeque<(Quote, Option<SpanContext>)>>,
+    capacity: usize,
+    overflow: Overflow,
+    items: Notify,
+    space: Notify,
+    dropped: AtomicU64,
+    /// Quotes popped but not yet marked done
+    in_flight: AtomicUsize,
+}
+
+impl Queue {
+    /**
+     * @gist creates an empty queue
+     * @param capacity -- number of quotes it holds, at least one
+     * @param overflow -- what to do when it is full
+    **/
+    pub fn new(capacity: usize, overflow: Overflow) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            buffer: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            overflow,
+            items: Notify::new(),
+            space: Notify::new(),
+            dropped: AtomicU64::new(0),
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /**
+     * @gist queues a quote, only waits when the queue is full and the policy
+     * is block
+    **/
+    pub async fn push(&self, quote: Quote) {
+        let mut quote = Some((quote, trace::current()));
+        loop {
+            {
+                let mut buffer = self.buffer.lock().unwrap();
+                let q = quote.take().expect("quote is only taken once");
+
+                if buffer.len() < self.capacity {
+                    buffer.push_back(q);
+                } else {
+                    match self.overflow {
+                        Overflow::Block => quote = Some(q),
+                        Overflow::DropOldest => {
+                            buffer.pop_front();
+                            buffer.push_back(q);
+                            self.dropped.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Overflow::Coalesce => match buffer.iter_mut().find(|p| p.0.asset == q.0.asset) {
+                            Some(pending) => *pending = q,
+                            None => {
+                                buffer.pop_front();
+                                buffer.push_back(q);
+                                self.dropped.fetch_add(1, Ordering::Relaxed);
+                            }
+                        },
+                    }
+                }
+            }
+
+            if quote.is_none() {
+                self.items.notify_one();
+                return;
+            }
+            self.space.notified().await;
+        }
+    }
+
+    /**
+     * @gist takes the oldest quote and the span it was queued in, waits
+     * until there is one
+    **/
+    pub async fn pop(&self) -> (Quote, Option<SpanContext>) {
+        loop {
+            let next = {
+                let mut buffer = self.buffer.lock().unwrap();
+                let next = buffer.pop_front();
+                if next.is_some() {
+                    self.in_flight.fetch_add(1, Ordering::SeqCst);
+                }
+                next
+            };
+            if let Some(quote) = next {
+                self.space.notify_one();
+                return quote;
+            }
+            self.items.notified().await;
+        }
+    }
+
+    /**
+     * @gist marks a popped quote as handled
+    **/
+    pub fn done(&self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /**
+     * @gist whether every queued quote was popped and handled
+    **/
+    pub fn is_idle(&self) -> bool {
+        // pop counts the quote in flight under the same lock
+        let buffer = self.buffer.lock().unwrap();
+        buffer.is_empty() && self.in_flight.load(Ordering::SeqCst) == 0
+    }
+
+    /**
+     * @gist number of quotes discarded because the queue was full
+    **/
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,141 @@
+/*!
+ *
+ * Rate adapters for wrapped tokens. A pool price is multiplied by an
+ * on-chain exchange rate, so a liquid staking derivative can be priced from
+ * the deepest pool of its wrapper (or the other way around with `divide`):
+ *
+ * [[rate]]
+ * name = "wstETH"
+ * pool = "0x4028DAAC072e492d34a3Afdbef0ba7e35D8b55C4"     # stETH/ETH
+ * address = "0x7f39C581F595B53c5cb19bD0b3f8dA6c935E2Ca0"  # wstETH
+ * abi = "function stEthPerToken() view returns (uint256)"
+ * rate_decimals = 18
+ *
+ * The rate is any view function, called like an [[oracle]] at the block the
+ * pool was priced at.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{providers::Middleware, types::Address};
+use serde::Deserialize;
+
+use std::sync::Arc;
+
+use crate::oracle::{self, OracleConfig};
+use crate::poller::Feed;
+use crate::source::{Asset, PriceSource, Quote};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RateConfig {
+    /// Label used in the output
+    pub name: String,
+    /// TOKEN/ETH pool of the token the rate converts from
+    pub pool: Address,
+    /// WETH is token0 of the pool
+    #[serde(default)]
+    pub inverted: bool,
+    /// Decimals of the pool's TOKEN
+    #[serde(default = "default_decimals")]
+    pub decimals: u8,
+    /// Contract exposing the rate
+    pub address: Address,
+    /// Human readable signature of the rate function
+    pub abi: String,
+    /// Arguments passed to the function, parsed according to the ABI
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Index of the return value holding the rate
+    #[serde(default)]
+    pub output: usize,
+    /// Number of decimals the rate carries
+    #[serde(default = "default_rate_decimals")]
+    pub rate_decimals: u32,
+    /// Divide the pool price by the rate instead of multiplying
+    #[serde(default)]
+    pub divide: bool,
+}
+
+fn default_decimals() -> u8 {
+    18
+}
+
+fn default_rate_decimals() -> u32 {
+    18
+}
+
+impl RateConfig {
+    /**
+     * @gist the asset the adapter prices
+    **/
+    pub fn asset(&self) -> Asset {
+        Asset { name: self.name.clone(), address: self.address }
+    }
+
+    /**
+     * @gist the asset the pool is priced as
+    **/
+    pub fn base(&self) -> Asset {
+        Asset { name: format!("{} base", self.name), address: self.pool }
+    }
+
+    /**
+     * @gist the rate function as an oracle
+    **/
+    pub fn oracle(&self) -> OracleConfig {
+        OracleConfig {
+            name: format!("{} rate", self.name),
+            address: self.address,
+            abi: self.abi.clone(),
+            args: self.args.clone(),
+            output: self.output,
+            decimals: self.rate_decimals,
+            currency: None,
+        }
+    }
+}
+
+/// A pool price converted by an on-chain rate
+pub struct Rated<M> {
+    provider: Arc<M>,
+    base: Feed,
+    rate: OracleConfig,
+    divide: bool,
+}
+
+impl<M: Middleware + 'static> Rated<M> {
+    /**
+     * @gist creates the source
+     * @param provider -- used to call the rate function
+     * @param base -- prices the token the rate converts from
+     * @param rate -- the rate function
+     * @param divide -- divide by the rate instead of multiplying
+    **/
+    pub fn new(provider: Arc<M>, base: Feed, rate: OracleConfig, divide: bool) -> Self {
+        Self { provider, base, rate, divide }
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> PriceSource for Rated<M> {
+    fn name(&self) -> &str {
+        "RATE"
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let base = self.base.source.quote(&self.base.asset).await
+            .map_err(|e| eyre::eyre!("{}: {e}", self.base.asset.name))?;
+        let rate = oracle::read(self.provider.as_ref(), &self.rate.address, &self.rate, base.block.map(Into::into)).await?;
+        if rate == 0.0 {
+            eyre::bail!("{}: the rate is zero", self.rate.name);
+        }
+
+        let price = if self.divide { base.price / rate } else { base.price * rate };
+        let mut quote = Quote::new(self.name(), &asset.name, price);
+        quote.block = base.block;
+        quote.timestamp = base.timestamp;
+        quote.components = vec![base];
+        Ok(quote)
+    }
+}
@@ -0,0 +1,111 @@
+/*!
+ *
+ * The price of one token in another, for relative value. Both tokens are
+ * priced in USDT through their deepest WETH pool, like a [[pool]], and
+ * divided:
+ *
+ * uni-price-polling ratio 0x514910771af9ca656af840dff83e8264ecf986ca 0x1f9840a85d5af5bf1d1762f925bdaddc4201f984
+ *
+ * The pools are looked up once. WETH itself is priced on the ETH/USDT pool.
+ *
+**/
+
+use ethers::{providers::Middleware, types::Address};
+use serde::Serialize;
+
+use std::sync::Arc;
+
+use crate::pools::{self, PoolConfig, TokenInfo};
+use crate::source::{PriceSource, Quote};
+use crate::uniswap_v2::{self, get_reserves, get_token, Token, UniswapV2};
+
+/// A token and the pool it is priced on
+pub struct Leg {
+    pub token: Token,
+    /// None for WETH
+    pool: Option<PoolConfig>,
+}
+
+impl Leg {
+    /**
+     * @gist finds the deepest WETH pool of a token
+     * @param provider -- any ethers middleware
+     * @param factories -- V2 factories to look the pair up on
+     * @param token -- the token to price
+    **/
+    pub async fn find<M: Middleware + 'static>(provider: Arc<M>, factories: &[Address], token: Address) -> eyre::Result<Self> {
+        let weth: Address = uniswap_v2::WETH.parse()?;
+        let token = get_token(provider.clone(), token).await?;
+        if token.address == weth {
+            return Ok(Self { token, pool: None });
+        }
+        let info = TokenInfo {
+            chain_id: provider.get_chainid().await?.as_u64(),
+            address: token.address,
+            symbol: token.symbol.clone().unwrap_or_else(|| format!("{:?}", token.address)),
+            decimals: token.decimals,
+        };
+        let (pool, _) = pools::deepest_pool(provider, factories, &weth, &info).await?
+            .ok_or_else(|| eyre::eyre!("{} has no WETH pool on the factories", info.symbol))?;
+        Ok(Self { token, pool: Some(pool) })
+    }
+
+    /**
+     * @gist the symbol of the token, or its address
+    **/
+    pub fn name(&self) -> String {
+        self.token.symbol.clone().unwrap_or_else(|| format!("{:?}", self.token.address))
+    }
+
+    /**
+     * @gist the USDT price of the token
+     * @param provider -- any ethers middleware
+     * @param end_a -- the ETH/USDT pair
+    **/
+    pub async fn quote<M: Middleware + 'static>(&self, provider: Arc<M>, end_a: Address) -> eyre::Result<Quote> {
+        let Some(pool) = &self.pool else {
+            let (eth, usdt, _) = get_reserves(provider, &end_a).await?;
+            return Ok(Quote::new("UNI V2", "WETH", uniswap_v2::reformat_usd(usdt) / uniswap_v2::reformat_wei(eth)));
+        };
+        let source = UniswapV2::new(provider, end_a).with_layout(pool.inverted, pool.decimals);
+        let mut asset = pool.asset();
+        asset.name = self.name();
+        source.quote(&asset).await
+    }
+}
+
+/// Token A priced in token B
+#[derive(Debug, Clone, Serialize)]
+pub struct Ratio {
+    /// A/B, e.g. LINK/UNI
+    pub pair: String,
+    /// How much B one A buys
+    pub ratio: f64,
+    /// USDT prices of A and B
+    pub base_usd: f64,
+    pub quote_usd: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<u64>,
+}
+
+/**
+ * @gist prices both legs and divides them
+ * @param provider -- any ethers middleware
+ * @param end_a -- the ETH/USDT pair
+ * @param base -- token A
+ * @param quote -- token B
+**/
+pub async fn ratio<M: Middleware + 'static>(provider: Arc<M>, end_a: Address, base: &Leg, quote: &Leg) -> eyre::Result<Ratio> {
+    let a = base.quote(provider.clone(), end_a).await?;
+    let b = quote.quote(provider, end_a).await?;
+    if b.price == 0.0 {
+        eyre::bail!("{} has no price", quote.name());
+    }
+    Ok(Ratio {
+        pair: format!("{}/{}", base.name(), quote.name()),
+        ratio: a.price / b.price,
+        base_usd: a.price,
+        quote_usd: b.price,
+        block: a.block.or(b.block),
+    })
+}
@@ -0,0 +1,182 @@
+/*!
+ *
+ * Rebasing tokens. Their balances, and so the reserves of their pools,
+ * change without any transfer, which makes the per token price a misleading
+ * time series. `info` detects them by comparing the change of the total
+ * supply with the mints and burns over the last day of blocks.
+ *
+ * A [[pool]] with a `rebase` table is priced per share instead, the per
+ * token price is kept as a component named "<asset> per token":
+ *
+ * [[pool]]
+ * address = "0xc5be99A02C6857f9Eac67BbCE58DF5572498F40C"   # AMPL/ETH
+ * decimals = 9
+ * rebase = { shares = "function scaledTotalSupply() view returns (uint256)" }
+ *
+ * Price per share = price per token * totalSupply / shares.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{
+    providers::Middleware,
+    types::{Address, H256, I256, U256},
+    utils::format_units,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use std::sync::Arc;
+
+use crate::oracle::{self, OracleConfig};
+use crate::source::{Asset, PriceSource, Quote};
+use crate::uniswap_v2::{IUniswapV2Pair, IERC20};
+
+/// Block range of a single eth_getLogs request
+const CHUNK: u64 = 2000;
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RebaseConfig {
+    /// Human readable signature of the view function returning the total
+    /// number of shares, called on the token
+    pub shares: String,
+    /// Number of decimals the shares carry
+    #[serde(default = "default_shares_decimals")]
+    pub shares_decimals: u32,
+}
+
+fn default_shares_decimals() -> u32 {
+    18
+}
+
+/// How the supply of a token moved over a block range
+#[derive(Debug, Clone, Copy)]
+pub struct SupplyCheck {
+    pub from: u64,
+    pub to: u64,
+    /// Change of totalSupply
+    pub change: f64,
+    /// Part of the change no mint or burn accounts for
+    pub unexplained: f64,
+}
+
+impl SupplyCheck {
+    /**
+     * @gist whether the supply moved without transfers
+    **/
+    pub fn rebases(&self) -> bool {
+        self.unexplained != 0.0
+    }
+}
+
+/**
+ * @gist compares the change of a token's total supply with its mints and
+ * burns, the Transfer events from and to the zero address
+ * @param provider -- used for totalSupply and eth_getLogs
+ * @param token -- the ERC20 token
+ * @param decimals -- decimals of the token, scales the result
+ * @param from -- first block of the range
+ * @param to -- last block of the range
+**/
+pub async fn check_supply<M: Middleware + 'static>(provider: Arc<M>, token: Address, decimals: u8, from: u64, to: u64) -> eyre::Result<SupplyCheck> {
+    let erc20 = IERC20::new(token, provider);
+    let before = erc20.total_supply().block(from).call().await
+        .map_err(|e| eyre::eyre!("{token:?} has no totalSupply() at block {from}: {e}"))?;
+    let after = erc20.total_supply().block(to).call().await
+        .map_err(|e| eyre::eyre!("{token:?} has no totalSupply() at block {to}: {e}"))?;
+
+    let (mut minted, mut burned) = (U256::zero(), U256::zero());
+    let mut lo = from + 1;
+    while lo <= to {
+        let hi = (lo + CHUNK - 1).min(to);
+        for transfer in erc20.transfer_filter().topic1(H256::zero()).from_block(lo).to_block(hi).query().await? {
+            minted = minted.saturating_add(transfer.value);
+        }
+        for transfer in erc20.transfer_filter().topic2(H256::zero()).from_block(lo).to_block(hi).query().await? {
+            burned = burned.saturating_add(transfer.value);
+        }
+        lo = hi + 1;
+    }
+
+    // after - before = minted - burned for any token that only moves with transfers
+    let change = I256::from_raw(after) - I256::from_raw(before);
+    let unexplained = change - (I256::from_raw(minted) - I256::from_raw(burned));
+    let scale = |v: I256| -> eyre::Result<f64> { Ok(format_units(v, decimals as u32)?.parse()?) };
+
+    Ok(SupplyCheck {
+        from,
+        to,
+        change: scale(change)?,
+        unexplained: scale(unexplained)?,
+    })
+}
+
+/// Prices a rebasing token per share through a per token source
+pub struct PerShare<M> {
+    provider: Arc<M>,
+    inner: Arc<dyn PriceSource>,
+    inverted: bool,
+    decimals: u8,
+    shares: OracleConfig,
+    /// The token of the pool, read on the first quote
+    token: OnceCell<Address>,
+}
+
+impl<M: Middleware + 'static> PerShare<M> {
+    /**
+     * @gist wraps the per token source of a pool
+     * @param provider -- used to read the supply and the shares
+     * @param inner -- prices the token, the asset address is its pool
+     * @param inverted -- the token is token1 of the pool
+     * @param decimals -- decimals of the token
+     * @param rebase -- the shares function
+    **/
+    pub fn new(provider: Arc<M>, inner: Arc<dyn PriceSource>, inverted: bool, decimals: u8, rebase: &RebaseConfig) -> Self {
+        let shares = OracleConfig {
+            name: "shares".to_string(),
+            address: Address::zero(),
+            abi: rebase.shares.clone(),
+            args: Vec::new(),
+            output: 0,
+            decimals: rebase.shares_decimals,
+            currency: None,
+        };
+        Self { provider, inner, inverted, decimals, shares, token: OnceCell::new() }
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> PriceSource for PerShare<M> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let per_token = self.inner.quote(asset).await?;
+        let token = *self.token.get_or_try_init(|| async {
+            let pair = IUniswapV2Pair::new(asset.address, self.provider.clone());
+            let call = if self.inverted { pair.token_1() } else { pair.token_0() };
+            call.call().await.map_err(|e| eyre::eyre!("could not read the token of {:?}: {e}", asset.address))
+        }).await?;
+
+        let block = per_token.block.map(Into::into);
+        let call = IERC20::new(token, self.provider.clone()).total_supply();
+        let supply = match block {
+            Some(block) => call.block(block),
+            None => call,
+        }.call().await.map_err(|e| eyre::eyre!("{token:?} has no totalSupply(): {e}"))?;
+        let supply: f64 = format_units(supply, self.decimals as u32)?.parse()?;
+        let shares = oracle::read(self.provider.as_ref(), &token, &self.shares, block).await?;
+        if shares == 0.0 {
+            eyre::bail!("{token:?} has no shares");
+        }
+
+        let mut quote = Quote::new(self.name(), &asset.name, per_token.price * supply / shares);
+        quote.block = per_token.block;
+        quote.timestamp = per_token.timestamp;
+        quote.liquidity = per_token.liquidity;
+        quote.components = vec![Quote { asset: format!("{} per token", asset.name), ..per_token }];
+        Ok(quote)
+    }
+}
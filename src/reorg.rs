@@ -0,0 +1,87 @@
+/*!
+ *
+ * Reorgs in watch mode. Every quote is read at a block; `--reorg-depth 12`
+ * remembers the hash of the blocks quoted in the last 12 and compares it
+ * with the canonical chain after every cycle. A block whose hash changed
+ * was orphaned, with it the state its quotes were read from. Its feeds are
+ * quoted once more at the same height of the new chain and sent to the
+ * sinks marked `reorged`. File sinks drop the orphaned rows from their
+ * active file, history readers keep the corrections over those rotated
+ * out:
+ *
+ * uni-price-polling --config watch.toml --interval 12 --reorg-depth 12
+ *
+**/
+
+use ethers::{providers::Middleware, types::H256};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A quoted block
+struct Seen {
+    hash: H256,
+    /// Assets quoted at the block
+    assets: BTreeSet<String>,
+}
+
+/// A block that was quoted and then orphaned
+#[derive(Debug, Clone)]
+pub struct Orphaned {
+    pub block: u64,
+    /// Assets whose quotes were read at the block
+    pub assets: BTreeSet<String>,
+}
+
+/// The hashes of the recently quoted blocks
+pub struct Reorgs {
+    depth: u64,
+    seen: BTreeMap<u64, Seen>,
+}
+
+impl Reorgs {
+    /**
+     * @gist tracks the blocks of the last `depth`
+    **/
+    pub fn new(depth: u64) -> Self {
+        Self { depth, seen: BTreeMap::new() }
+    }
+
+    /**
+     * @gist remembers that an asset was quoted at a block, the hash is
+     * looked up on the next check
+    **/
+    pub fn quoted(&mut self, block: u64, asset: &str) {
+        self.seen.entry(block)
+            .or_insert_with(|| Seen { hash: H256::zero(), assets: BTreeSet::new() })
+            .assets.insert(asset.to_string());
+    }
+
+    /**
+     * @gist compares the remembered hashes with the chain and forgets the
+     * blocks that fell out of the depth
+     * @param provider -- any ethers middleware
+     * @output the blocks whose hash changed, oldest first
+    **/
+    pub async fn check<M: Middleware + 'static>(&mut self, provider: &M) -> eyre::Result<Vec<Orphaned>> {
+        let Some(&newest) = self.seen.keys().next_back() else {
+            return Ok(Vec::new());
+        };
+        self.seen.retain(|block, _| *block + self.depth > newest);
+
+        let mut orphaned = Vec::new();
+        for (&number, seen) in &mut self.seen {
+            let hash = provider.get_block(number).await?
+                .and_then(|b| b.hash)
+                .ok_or_else(|| eyre::eyre!("the node has no block {number}"))?;
+            // A block quoted for the first time only gets its hash, a reorg
+            // before this lookup goes unnoticed
+            if seen.hash.is_zero() {
+                seen.hash = hash;
+            } else if seen.hash != hash {
+                seen.hash = hash;
+                orphaned.push(Orphaned { block: number, assets: seen.assets.clone() });
+            }
+        }
+        Ok(orphaned)
+    }
+}
@@ -0,0 +1,114 @@
+/*!
+ *
+ * The interactive session of `repl`. The provider connection and the
+ * metadata of every pair looked at stay around between commands, so a
+ * second price of the same pool is a single reserve read:
+ *
+ * > price LINK
+ * > watch add 0xa478c2975ab1ea89e8196811f51a7b7ade33eb11
+ * > poll
+ * > info LINK
+ * > alerts list
+ *
+ * Pools are addresses or names from the address book.
+ *
+**/
+
+use ethers::{providers::Middleware, types::Address};
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::source::{Asset, PriceSource, Quote};
+use crate::uniswap_v2::{get_pair_info, PairInfo, UniswapV2};
+
+pub const HELP: &str = "\
+price POOL        quote a TOKEN/WETH pool in USDT
+info POOL         tokens, factory and reserves of a pair
+watch add POOL    add a pool to the watchlist of the session
+watch rm POOL     remove it again
+watch list        the watched pools
+poll              quote every watched pool
+alerts list       the alert rules of the config and their state
+help              this list
+quit              end the session, as does ctrl-d";
+
+/// One line of input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Price(String),
+    Info(String),
+    WatchAdd(String),
+    WatchRm(String),
+    WatchList,
+    Poll,
+    AlertsList,
+    Help,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+        let command = match words[..] {
+            ["price", pool] => Command::Price(pool.to_string()),
+            ["info", pool] => Command::Info(pool.to_string()),
+            ["watch", "add", pool] => Command::WatchAdd(pool.to_string()),
+            ["watch", "rm", pool] => Command::WatchRm(pool.to_string()),
+            ["watch", "list"] => Command::WatchList,
+            ["poll"] => Command::Poll,
+            ["alerts", "list"] => Command::AlertsList,
+            ["help"] => Command::Help,
+            ["quit"] | ["exit"] => Command::Quit,
+            ["price" | "info"] => return Err(format!("{} needs a pool", words[0])),
+            _ => return Err(format!("unknown command {:?}, try help", s.trim())),
+        };
+        Ok(command)
+    }
+}
+
+/// Pair metadata and quoting for the session
+pub struct Pairs<M> {
+    provider: Arc<M>,
+    end_a: Address,
+    cache: HashMap<Address, PairInfo>,
+}
+
+impl<M: Middleware + 'static> Pairs<M> {
+    /**
+     * @gist starts with an empty cache
+     * @param provider -- kept for the whole session
+     * @param end_a -- the ETH/USDT pair
+    **/
+    pub fn new(provider: Arc<M>, end_a: Address) -> Self {
+        Self { provider, end_a, cache: HashMap::new() }
+    }
+
+    /**
+     * @gist reads a pair and keeps its metadata
+     * @output the pair with its current reserves
+    **/
+    pub async fn info(&mut self, pool: &Address) -> eyre::Result<PairInfo> {
+        let info = get_pair_info(self.provider.clone(), pool).await?;
+        self.cache.insert(*pool, info.clone());
+        Ok(info)
+    }
+
+    /**
+     * @gist quotes a TOKEN/WETH pool, its layout is read on the first quote
+     * @param name -- what the quote is named, as typed
+     * @param pool -- the pair
+    **/
+    pub async fn quote(&mut self, name: &str, pool: &Address) -> eyre::Result<Quote> {
+        if !self.cache.contains_key(pool) {
+            self.info(pool).await?;
+        }
+        let info = &self.cache[pool];
+        let (inverted, token) = info.token().ok_or_else(|| eyre::eyre!("{name} is no TOKEN/WETH pair"))?;
+        let source = UniswapV2::new(self.provider.clone(), self.end_a).with_layout(inverted, token.decimals);
+        source.quote(&Asset { name: name.to_string(), address: *pool }).await
+    }
+}
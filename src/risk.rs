@@ -0,0 +1,160 @@
+/*!
+ *
+ * Honeypot and scam token heuristics for new pairs. Before a new pair is
+ * sent to the notifiers or added to the watchlist its token is checked:
+ *
+ * - owned: owner() is still set, the ownership was not renounced
+ * - no-buy: a transfer out of the pair, the token side of a buy, reverts
+ * - no-sell: a transfer into the pair by a recent buyer, the token side of
+ *   a sell, reverts
+ * - high-tax: a recent buyer received less than `max_tax` below what the
+ *   pair sent out
+ *
+ * The trades are simulated with eth_call on the token itself, the router
+ * would need an approval first. Tokens without trades yet can't be checked
+ * for sells and taxes, a clean result is no guarantee.
+ *
+**/
+
+use ethers::{
+    prelude::abigen,
+    providers::Middleware,
+    types::{Address, ValueOrArray, H256, U256},
+};
+use serde::Serialize;
+
+use std::sync::Arc;
+
+use crate::uniswap_v2::{IUniswapV2Pair, IERC20};
+
+abigen!(
+    IOwnable,
+    r#"[
+        function owner() external view returns (address)
+    ]"#
+);
+
+/// The burn address ownership is often "renounced" to
+const DEAD: &str = "0x000000000000000000000000000000000000dEaD";
+
+/// Blocks searched for a buy to test the sell side with
+const LOOKBACK: u64 = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Flag {
+    Owned,
+    NoBuy,
+    NoSell,
+    HighTax,
+}
+
+impl std::fmt::Display for Flag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Flag::Owned => "owned",
+            Flag::NoBuy => "no-buy",
+            Flag::NoSell => "no-sell",
+            Flag::HighTax => "high-tax",
+        })
+    }
+}
+
+/// Outcome of the checks of a token
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Risk {
+    pub flags: Vec<Flag>,
+    /// Share of a recent buy that did not reach the buyer, 0.05 for 5%
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buy_tax: Option<f64>,
+}
+
+impl Risk {
+    pub fn is_risky(&self) -> bool {
+        !self.flags.is_empty()
+    }
+}
+
+/**
+ * @gist checks the token of a new pair
+ * @param provider -- used for the eth_calls and eth_getLogs
+ * @param pair -- the pair the token trades in
+ * @param token -- the listed token
+ * @param head -- the current block, buys are searched before it
+ * @param max_tax -- the buy tax above which the token is flagged, 0.1 for 10%
+**/
+pub async fn assess<M: Middleware + 'static>(provider: Arc<M>, pair: Address, token: Address, head: u64, max_tax: f64) -> eyre::Result<Risk> {
+    let mut risk = Risk::default();
+    let erc20 = IERC20::new(token, provider.clone());
+
+    // Tokens without owner() have nothing to renounce
+    if let Ok(owner) = IOwnable::new(token, provider.clone()).owner().call().await {
+        if !owner.is_zero() && owner != DEAD.parse()? {
+            risk.flags.push(Flag::Owned);
+        }
+    }
+
+    let held = erc20.balance_of(pair).call().await
+        .map_err(|e| eyre::eyre!("{token:?} has no balanceOf(): {e}"))?;
+    let probe = Address::from_low_u64_be(0xdead_beef);
+    if !held.is_zero() && !transfers(&erc20, pair, probe, held / 1000).await {
+        risk.flags.push(Flag::NoBuy);
+    }
+
+    let Some((buyer, tx, block, sent)) = last_buy(provider.clone(), pair, token, head).await? else {
+        return Ok(risk);
+    };
+    let balance = erc20.balance_of(buyer).call().await.unwrap_or_default();
+    if !balance.is_zero() && !transfers(&erc20, buyer, pair, balance / 2).await {
+        risk.flags.push(Flag::NoSell);
+    }
+
+    // What reached the buyer in the buy's transaction, a taxed token sends
+    // the rest elsewhere or burns it
+    let filter = erc20.transfer_filter()
+        .address(ValueOrArray::Value(token))
+        .topic1(pair)
+        .topic2(buyer)
+        .from_block(block)
+        .to_block(block);
+    let received = filter.query_with_meta().await?
+        .into_iter()
+        .filter(|(_, meta)| meta.transaction_hash == tx)
+        .fold(U256::zero(), |sum, (transfer, _)| sum + transfer.value);
+    if !sent.is_zero() {
+        let tax = 1.0 - received.as_u128() as f64 / sent.as_u128() as f64;
+        risk.buy_tax = Some(tax.max(0.0));
+        if tax > max_tax {
+            risk.flags.push(Flag::HighTax);
+        }
+    }
+    Ok(risk)
+}
+
+/**
+ * @gist whether a token transfer would go through, simulated with eth_call
+**/
+async fn transfers<M: Middleware + 'static>(erc20: &IERC20<M>, from: Address, to: Address, amount: U256) -> bool {
+    matches!(erc20.transfer(to, amount).from(from).call().await, Ok(true))
+}
+
+/**
+ * @gist the newest buy of the token from the pair before `head`
+ * @output the recipient, transaction, block and TOKEN amount the pair sent
+**/
+async fn last_buy<M: Middleware + 'static>(provider: Arc<M>, pair: Address, token: Address, head: u64) -> eyre::Result<Option<(Address, H256, u64, U256)>> {
+    let contract = IUniswapV2Pair::new(pair, provider.clone());
+    let inverted = contract.token_1().call().await.map_err(|e| eyre::eyre!("{pair:?} is not a V2 pair: {e}"))? == token;
+    let filter = contract.swap_filter()
+        .address(ValueOrArray::Value(pair))
+        .from_block(head.saturating_sub(LOOKBACK))
+        .to_block(head);
+    let buy = filter.query_with_meta().await?
+        .into_iter()
+        .rev()
+        .find_map(|(swap, meta)| {
+            let out = if inverted { swap.amount_1_out } else { swap.amount_0_out };
+            (!out.is_zero()).then_some((swap.to, meta.transaction_hash, meta.block_number.as_u64(), out))
+        });
+    Ok(buy)
+}
@@ -0,0 +1,263 @@
+/*!
+ *
+ * Rotation of the file sinks. A file is rolled over when a new hour or day
+ * starts (UTC) or when it would grow past `max_bytes`, whichever comes
+ * first. The rotated file is named after the time it was started, e.g.
+ * quotes.2024-01-01T000000.csv, optionally gzipped, and only the newest
+ * `keep` rotated files are kept:
+ *
+ * [[sink]]
+ * type = "file"
+ * path = "quotes.csv"
+ * format = "csv"
+ * rotate = { every = "daily", max_bytes = 100_000_000, keep = 30, gzip = true }
+ *
+**/
+
+use chrono::DateTime;
+use flate2::{write::GzEncoder, Compression};
+use serde::Deserialize;
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RotateConfig {
+    /// Start a new file every hour or day
+    pub every: Option<Period>,
+    /// Start a new file before this size would be exceeded
+    pub max_bytes: Option<u64>,
+    /// Number of rotated files kept, all when unset
+    pub keep: Option<usize>,
+    /// Compress rotated files to .gz
+    #[serde(default)]
+    pub gzip: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Hourly,
+    Daily,
+}
+
+impl Period {
+    /**
+     * @gist start of the period a unix time falls into, in UTC
+    **/
+    pub fn start(self, unix: u64) -> u64 {
+        let len = match self {
+            Period::Hourly => 60 * 60,
+            Period::Daily => 24 * 60 * 60,
+        };
+        unix - unix % len
+    }
+}
+
+/// An append-only file that rolls over according to a RotateConfig
+pub struct RotatingFile {
+    path: PathBuf,
+    config: RotateConfig,
+    /// First line of every new file, e.g. the csv header
+    header: Option<String>,
+    file: File,
+    size: u64,
+    /// Unix time the current file was started
+    started: u64,
+}
+
+impl RotatingFile {
+    /**
+     * @gist opens the file for appending, an existing file counts as started
+     * at its last modification
+     * @param path -- the active file
+     * @param config -- when to rotate
+     * @param header -- written at the top of every new file
+     * @param now -- the current unix time
+    **/
+    pub fn open(path: PathBuf, config: RotateConfig, header: Option<&str>, now: u64) -> eyre::Result<Self> {
+        let file = open_append(&path)?;
+        let metadata = file.metadata()?;
+        let started = metadata.modified().ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .filter(|_| metadata.len() > 0)
+            .map_or(now, |d| d.as_secs());
+
+        let mut rotating = Self { path, config, header: header.map(str::to_string), file, size: metadata.len(), started };
+        if rotating.size == 0 {
+            rotating.write_header()?;
+        }
+        Ok(rotating)
+    }
+
+    /**
+     * @gist appends a line, rotating first when it is due
+     * @param line -- the line without its newline
+     * @param now -- the current unix time
+     * @output the rotated file, if this write rotated
+    **/
+    pub fn write_line(&mut self, line: &str, now: u64) -> eyre::Result<Option<PathBuf>> {
+        let len = line.len() as u64 + 1;
+        let rotated = if self.due(len, now) { Some(self.rotate(now)?) } else { None };
+
+        writeln!(self.file, "{line}")?;
+        self.size += len;
+        Ok(rotated)
+    }
+
+    fn due(&self, len: u64, now: u64) -> bool {
+        // A file holding only its header is never rotated
+        let empty = self.size <= self.header.as_ref().map_or(0, |h| h.len() as u64 + 1);
+        if empty {
+            return false;
+        }
+        let new_period = self.config.every.is_some_and(|p| p.start(now) != p.start(self.started));
+        let too_big = self.config.max_bytes.is_some_and(|max| self.size + len > max);
+        new_period || too_big
+    }
+
+    fn rotate(&mut self, now: u64) -> eyre::Result<PathBuf> {
+        self.file.flush()?;
+        let rotated = archive_name(&self.path, self.started);
+        fs::rename(&self.path, &rotated)
+            .map_err(|e| eyre::eyre!("could not rotate {}: {e}", self.path.display()))?;
+
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        self.started = now;
+        self.write_header()?;
+        Ok(rotated)
+    }
+
+    fn write_header(&mut self) -> eyre::Result<()> {
+        if let Some(header) = &self.header {
+            writeln!(self.file, "{header}")?;
+            self.size += header.len() as u64 + 1;
+        }
+        Ok(())
+    }
+
+    /**
+     * @gist rewrites the active file without the lines `keep` refuses,
+     * through a temporary file like the state file
+     * @param keep -- whether a line stays, the header included
+     * @output the number of lines dropped
+    **/
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) -> eyre::Result<usize> {
+        self.file.flush()?;
+        let text = fs::read_to_string(&self.path)
+            .map_err(|e| eyre::eyre!("could not read {}: {e}", self.path.display()))?;
+        let (kept, dropped): (Vec<&str>, Vec<&str>) = text.lines().partition(|line| keep(line));
+        if dropped.is_empty() {
+            return Ok(0);
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        let rewritten: String = kept.iter().map(|line| format!("{line}\n")).collect();
+        fs::write(&tmp, &rewritten)?;
+        fs::rename(&tmp, &self.path)
+            .map_err(|e| eyre::eyre!("could not rewrite {}: {e}", self.path.display()))?;
+        self.file = open_append(&self.path)?;
+        self.size = rewritten.len() as u64;
+        Ok(dropped.len())
+    }
+
+    /**
+     * @gist the rotation settings of the file
+    **/
+    pub fn config(&self) -> &RotateConfig {
+        &self.config
+    }
+
+    /**
+     * @gist the active file
+    **/
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn open_append(path: &Path) -> eyre::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+        .map_err(|e| eyre::eyre!("could not open {}: {e}", path.display()))
+}
+
+/// The stem and extension rotated files share with the active file
+fn split_name(path: &Path) -> (String, String) {
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let ext = path.extension().map(|e| format!(".{}", e.to_string_lossy())).unwrap_or_default();
+    (stem, ext)
+}
+
+/**
+ * @gist name of a rotated file, next to the active one. A number is added if
+ * a file of the same second already exists.
+**/
+fn archive_name(path: &Path, started: u64) -> PathBuf {
+    let (stem, ext) = split_name(path);
+    let time = DateTime::from_timestamp(started as i64, 0)
+        .map(|t| t.format("%Y-%m-%dT%H%M%S").to_string())
+        .unwrap_or_else(|| started.to_string());
+
+    let mut n = 0;
+    loop {
+        let suffix = if n == 0 { String::new() } else { format!("-{n}") };
+        let candidate = path.with_file_name(format!("{stem}.{time}{suffix}{ext}"));
+        if !candidate.exists() && !gz_path(&candidate).exists() {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+fn gz_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+/**
+ * @gist compresses a rotated file if configured and deletes the oldest
+ * rotated files beyond `keep`. This blocks, run it off the async threads.
+ * @param active -- the active file, names the rotated ones
+ * @param rotated -- the file that was just rotated
+ * @param config -- gzip and retention
+**/
+pub fn archive(active: &Path, rotated: &Path, config: &RotateConfig) -> eyre::Result<()> {
+    if config.gzip {
+        let mut input = File::open(rotated)?;
+        let mut encoder = GzEncoder::new(File::create(gz_path(rotated))?, Compression::default());
+        io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?;
+        fs::remove_file(rotated)?;
+    }
+
+    let Some(keep) = config.keep else {
+        return Ok(());
+    };
+    let (stem, ext) = split_name(active);
+    let dir = match active.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let active_name = active.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+
+    // The time in the name makes lexical order the rotation order
+    let mut rotated_files: Vec<String> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| *name != active_name)
+        .filter(|name| name.strip_prefix(&format!("{stem}.")).is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit())))
+        .filter(|name| name.ends_with(&ext) || name.ends_with(&format!("{ext}.gz")))
+        .collect();
+    rotated_files.sort();
+
+    let excess = rotated_files.len().saturating_sub(keep);
+    for name in &rotated_files[..excess] {
+        fs::remove_file(dir.join(name))?;
+    }
+    Ok(())
+}
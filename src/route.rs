@@ -0,0 +1,244 @@
+/*!
+ *
+ * Per pool routes. A `[[pool]]` is priced through the global ETH/USDT pool
+ * unless it has a route of its own, a path of tokens from TOKEN to the
+ * quote token on one DEX:
+ *
+ * [[pool]]
+ * address = "0xc40d16476380e4037e6b1a2594caf6a6cc8da967"
+ * label = "LINK"
+ *
+ * [pool.route]
+ * dex = "sushiswap"   # "uniswap" (default) and "sushiswap" are V2, or "v3"
+ * path = ["0x514910771af9ca656af840dff83e8264ecf986ca", "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"]
+ * fees = [500, 500]   # v3 only, fee tier of every hop
+ *
+ * A path ends in USDT, USDC or DAI, a V2 path may also end in WETH and is
+ * then taken on to USDT through the ETH/USDT pool. V2 routes multiply the
+ * reserve ratios of the pairs, the first of which is the [[pool]] itself
+ * and the rest come from the factory. V3 routes are priced like a [[v3]]
+ * path.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{providers::Middleware, types::Address};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use std::sync::Arc;
+
+use crate::pairs;
+use crate::source::{self, Asset, PriceSource, Quote};
+use crate::uniswap_v2::{self, get_pair, get_reserves_at, get_token, IUniswapV2Pair};
+use crate::v3::{UniswapV3, V3Config};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Dex {
+    #[default]
+    Uniswap,
+    Sushiswap,
+    V3,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RouteConfig {
+    #[serde(default)]
+    pub dex: Dex,
+    /// The tokens of the path, TOKEN first and the quote token last
+    pub path: Vec<Address>,
+    /// Fee tier of every hop for v3, in hundredths of a bip
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fees: Vec<u32>,
+}
+
+impl RouteConfig {
+    /**
+     * @gist checks the path against the DEX
+     * @param name -- the pool, for the error
+    **/
+    pub fn validate(&self, name: &str) -> eyre::Result<()> {
+        if self.path.len() < 2 {
+            eyre::bail!("[[pool]] {name}: a route needs at least two tokens");
+        }
+        let last = self.path[self.path.len() - 1];
+        match self.dex {
+            Dex::V3 if self.fees.len() != self.path.len() - 1 => eyre::bail!("[[pool]] {name}: a v3 route of n tokens needs n - 1 fees"),
+            Dex::Uniswap | Dex::Sushiswap if !self.fees.is_empty() => eyre::bail!("[[pool]] {name}: fees are for v3 routes only"),
+            _ if pairs::is_stable(&last) => Ok(()),
+            Dex::Uniswap | Dex::Sushiswap if last == uniswap_v2::WETH.parse::<Address>()? => Ok(()),
+            Dex::V3 => eyre::bail!("[[pool]] {name}: a v3 route has to end in USDT, USDC or DAI"),
+            _ => eyre::bail!("[[pool]] {name}: a route has to end in USDT, USDC, DAI or WETH"),
+        }
+    }
+
+    /**
+     * @gist the source pricing the route
+     * @param provider -- used to read the pools
+     * @param name -- name of the asset
+     * @param pool -- the [[pool]], the first pair of a V2 route
+     * @param quoter -- QuoterV2 for v3 routes, like --v3-quoter
+    **/
+    pub fn source<M: Middleware + 'static>(&self, provider: Arc<M>, name: &str, pool: Address, quoter: Option<Address>) -> eyre::Result<Arc<dyn PriceSource>> {
+        let factory = match self.dex {
+            Dex::Uniswap => uniswap_v2::UNISWAP_V2_FACTORY,
+            Dex::Sushiswap => uniswap_v2::SUSHISWAP_FACTORY,
+            Dex::V3 => {
+                let config = V3Config { name: name.to_string(), path: self.path.clone(), fees: self.fees.clone(), amount: 1.0 };
+                let source = UniswapV3::new(provider, config);
+                return Ok(match quoter {
+                    Some(quoter) => Arc::new(source.with_quoter(quoter)),
+                    None => Arc::new(source),
+                });
+            }
+        };
+        let name = if self.dex == Dex::Sushiswap { "SUSHI" } else { "UNI V2" };
+        Ok(Arc::new(V2Path { provider, name, factory: factory.parse()?, pool, path: self.path.clone(), hops: OnceCell::new() }))
+    }
+}
+
+/// A pair of the path and its orientation
+struct Hop {
+    pair: Address,
+    /// The path goes from token0 to token1
+    zero_for_one: bool,
+    /// Decimals of the token going in and the token coming out
+    decimals: (u8, u8),
+}
+
+/// Prices a token along a path of pairs of one V2 factory
+pub struct V2Path<M> {
+    provider: Arc<M>,
+    name: &'static str,
+    factory: Address,
+    /// The pair of the first two tokens
+    pool: Address,
+    path: Vec<Address>,
+    /// Pairs and decimals of the path, looked up on the first quote
+    hops: OnceCell<Vec<Hop>>,
+}
+
+impl<M: Middleware + 'static> V2Path<M> {
+    async fn hops(&self) -> eyre::Result<&Vec<Hop>> {
+        self.hops.get_or_try_init(|| async {
+            let mut hops = Vec::with_capacity(self.path.len());
+            for (i, pair) in self.path.windows(2).enumerate() {
+                let (from, to) = (pair[0], pair[1]);
+                let pair = match i {
+                    0 => self.first_pair(from, to).await?,
+                    _ => get_pair(self.provider.clone(), &self.factory, &from, &to).await?
+                        .ok_or_else(|| eyre::eyre!("{} has no pair for {from:?}/{to:?}", self.name))?,
+                };
+                let decimals = (get_token(self.provider.clone(), from).await?.decimals, get_token(self.provider.clone(), to).await?.decimals);
+                hops.push(Hop { pair, zero_for_one: from < to, decimals });
+            }
+            // WETH is token0 of the ETH/USDT pool
+            if self.path[self.path.len() - 1] == uniswap_v2::WETH.parse::<Address>()? {
+                hops.push(Hop { pair: uniswap_v2::ETH_USDT_POOL.parse()?, zero_for_one: true, decimals: (18, 6) });
+            }
+            Ok(hops)
+        }).await
+    }
+
+    /**
+     * @gist checks that the [[pool]] trades the first two tokens
+    **/
+    async fn first_pair(&self, from: Address, to: Address) -> eyre::Result<Address> {
+        let pair = IUniswapV2Pair::new(self.pool, self.provider.clone());
+        let token0 = pair.token_0().call().await.map_err(|e| eyre::eyre!("{:?} is not a V2 pair: {e}", self.pool))?;
+        let token1 = pair.token_1().call().await.map_err(|e| eyre::eyre!("{:?} is not a V2 pair: {e}", self.pool))?;
+        if (token0, token1) != (from.min(to), from.max(to)) {
+            eyre::bail!("{:?} is not the pair of the first two tokens of its route", self.pool);
+        }
+        Ok(self.pool)
+    }
+}
+
+/**
+ * @gist the price along a path and the liquidity of its first pair
+ * @param reserves -- reserve of the token going in and of the token coming
+ * out of every pair, in whole tokens
+ * @output the price in the last token, and both sides of the first pair
+ * in it
+**/
+fn price_and_liquidity(reserves: &[(f64, f64)]) -> (f64, f64) {
+    let ratios: Vec<f64> = reserves.iter().map(|(reserve_in, reserve_out)| reserve_out / reserve_in).collect();
+    let price = ratios.iter().product();
+    let liquidity = 2.0 * reserves[0].1 * ratios[1..].iter().product::<f64>();
+    (price, liquidity)
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> PriceSource for V2Path<M> {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let hops = self.hops().await?;
+        let head = source::head(self.provider.as_ref()).await?;
+        let mut reserves = Vec::with_capacity(hops.len());
+        let mut updated = 0;
+        for (i, hop) in hops.iter().enumerate() {
+            let (reserve0, reserve1, last) = get_reserves_at(self.provider.clone(), &hop.pair, Some(head.number.into())).await?;
+            if i == 0 {
+                updated = last;
+            }
+            let (reserve_in, reserve_out) = if hop.zero_for_one { (reserve0, reserve1) } else { (reserve1, reserve0) };
+            reserves.push((reserve_in as f64 / 10_f64.powi(hop.decimals.0 as i32), reserve_out as f64 / 10_f64.powi(hop.decimals.1 as i32)));
+        }
+        let (price, liquidity) = price_and_liquidity(&reserves);
+        let mut quote = Quote::new(self.name(), &asset.name, price);
+        quote.block = Some(head.number);
+        quote.timestamp = Some(head.timestamp);
+        quote.liquidity = Some(liquidity);
+        quote.updated = Some(updated as u64);
+        Ok(quote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINK: &str = "0x514910771af9ca656af840dff83e8264ecf986ca";
+
+    fn route(dex: Dex, path: &[&str], fees: &[u32]) -> RouteConfig {
+        RouteConfig { dex, path: path.iter().map(|a| a.parse().unwrap()).collect(), fees: fees.to_vec() }
+    }
+
+    #[test]
+    fn routes_end_in_a_dollar() {
+        let (usdc, weth) = (pairs::STABLES[1].1, uniswap_v2::WETH);
+        assert!(route(Dex::Uniswap, &[LINK, weth, usdc], &[]).validate("LINK").is_ok());
+        assert!(route(Dex::Sushiswap, &[LINK, weth], &[]).validate("LINK").is_ok());
+        assert!(route(Dex::V3, &[LINK, weth, usdc], &[3000, 500]).validate("LINK").is_ok());
+
+        let err = route(Dex::V3, &[LINK, weth], &[3000]).validate("LINK").unwrap_err();
+        assert_eq!(err.to_string(), "[[pool]] LINK: a v3 route has to end in USDT, USDC or DAI");
+        let err = route(Dex::Uniswap, &[weth, LINK], &[]).validate("LINK").unwrap_err();
+        assert_eq!(err.to_string(), "[[pool]] LINK: a route has to end in USDT, USDC, DAI or WETH");
+    }
+
+    #[test]
+    fn paths_need_their_fees() {
+        let usdc = pairs::STABLES[1].1;
+        assert!(route(Dex::Uniswap, &[usdc], &[]).validate("LINK").is_err());
+        assert!(route(Dex::V3, &[LINK, usdc], &[]).validate("LINK").is_err());
+        assert!(route(Dex::Uniswap, &[LINK, usdc], &[3000]).validate("LINK").is_err());
+    }
+
+    #[test]
+    fn multiplies_the_ratios() {
+        // 1 LINK = 0.005 ETH, 1 ETH = 2000 USDT
+        let (price, liquidity) = price_and_liquidity(&[(200_000.0, 1_000.0), (10_000.0, 20_000_000.0)]);
+        assert!((price - 10.0).abs() < 1e-9);
+        // 1000 ETH on each side of the LINK pair
+        assert!((liquidity - 4_000_000.0).abs() < 1e-6);
+
+        let (price, liquidity) = price_and_liquidity(&[(100.0, 250.0)]);
+        assert_eq!((price, liquidity), (2.5, 500.0));
+    }
+}
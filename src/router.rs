@@ -0,0 +1,168 @@
+/*!
+ *
+ * Ground truth prices from the V2 router. With `--simulate 1000` every
+ * route sells 1000 USDT worth of TOKEN through the router of its pool's
+ * factory, TOKEN -> WETH -> USDT, in an eth_call at the block of the quote
+ * and adds what the sale returns per TOKEN:
+ *
+ * uni-price-polling --config watch.toml --simulate 1000 --simulate-from 0x28c6c06298d514db089934071355e5743bf21d60
+ *
+ * With `--simulate-from` the call is an actual swapExactTokensForTokens
+ * sent from that holder, so transfer taxes and whatever else the token does
+ * on a transfer are included. The holder needs the TOKEN and an allowance
+ * for the router, on a fork impersonate it and approve first. Without a
+ * holder, or when the swap reverts, the router's getAmountsOut is asked
+ * instead, which checks the path but leaves out the token's own behavior.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{
+    prelude::abigen,
+    providers::Middleware,
+    types::{Address, U256},
+};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::source::{Asset, PriceSource, Quote};
+use crate::uniswap_v2::{self, IUniswapV2Pair};
+
+abigen!(
+    IUniswapV2Router02,
+    r#"[
+        function getAmountsOut(uint256 amountIn, address[] path) external view returns (uint256[] amounts)
+        function swapExactTokensForTokens(uint256 amountIn, uint256 amountOutMin, address[] path, address to, uint256 deadline) external returns (uint256[] amounts)
+    ]"#
+);
+
+/// The Uniswap V2 router on mainnet
+pub const UNISWAP_V2_ROUTER: &str = "0x7a250d5630B4cF539739dF2C5dAcb4c659F2488D";
+
+/// The SushiSwap router on mainnet
+pub const SUSHISWAP_ROUTER: &str = "0xd9e1cE17f2641f24aE83637ab66a2cca9C378B9F";
+
+/// Where the sale of a pool's TOKEN goes
+#[derive(Clone)]
+struct Route {
+    router: Address,
+    /// TOKEN, WETH, USDT
+    path: Vec<Address>,
+}
+
+/// Adds the price a sale through the router realizes to V2 route quotes
+pub struct WithRouter<M> {
+    inner: Arc<dyn PriceSource>,
+    provider: Arc<M>,
+    /// USDT value of the sale
+    size: f64,
+    /// Account the swap is sent from, getAmountsOut only without
+    holder: Option<Address>,
+    /// Routes by pool, looked up on the first quote
+    routes: Mutex<HashMap<Address, Route>>,
+}
+
+impl<M: Middleware + 'static> WithRouter<M> {
+    /**
+     * @gist wraps a source whose asset addresses are TOKEN/ETH pools
+     * @param inner -- the source, its quotes need the raw values
+     * @param provider -- used for the eth_calls
+     * @param size -- USDT value of the simulated sale
+    **/
+    pub fn new(inner: Arc<dyn PriceSource>, provider: Arc<M>, size: f64) -> Self {
+        Self { inner, provider, size, holder: None, routes: Mutex::new(HashMap::new()) }
+    }
+
+    /**
+     * @gist sends an actual swap from a holder instead of asking
+     * getAmountsOut
+    **/
+    pub fn with_holder(mut self, holder: Address) -> Self {
+        self.holder = Some(holder);
+        self
+    }
+
+    async fn route(&self, pool: &Address) -> eyre::Result<Route> {
+        if let Some(route) = self.routes.lock().unwrap().get(pool) {
+            return Ok(route.clone());
+        }
+        let pair = IUniswapV2Pair::new(*pool, self.provider.clone());
+        let not_a_pair = |e| eyre::eyre!("{pool:?} is not a V2 pair: {e}");
+        let factory = pair.factory().call().await.map_err(not_a_pair)?;
+        let (token0, token1) = (pair.token_0().call().await.map_err(not_a_pair)?, pair.token_1().call().await.map_err(not_a_pair)?);
+
+        let router = match factory {
+            f if f == uniswap_v2::UNISWAP_V2_FACTORY.parse()? => UNISWAP_V2_ROUTER.parse()?,
+            f if f == uniswap_v2::SUSHISWAP_FACTORY.parse()? => SUSHISWAP_ROUTER.parse()?,
+            f => eyre::bail!("no known router for the factory {f:?}"),
+        };
+        let weth: Address = uniswap_v2::WETH.parse()?;
+        let token = if token0 == weth { token1 } else { token0 };
+        let route = Route { router, path: vec![token, weth, uniswap_v2::USDT.parse()?] };
+        self.routes.lock().unwrap().insert(*pool, route.clone());
+        Ok(route)
+    }
+
+    /**
+     * @gist sells `size` USDT worth of TOKEN through the router
+     * @param pool -- the TOKEN/ETH pool
+     * @param price -- the quoted price, sizes the sale
+     * @param decimals -- decimals of TOKEN
+     * @param block -- the block of the quote
+     * @output USDT per TOKEN
+    **/
+    async fn simulate(&self, pool: &Address, price: f64, decimals: u8, block: Option<u64>) -> eyre::Result<f64> {
+        let route = self.route(pool).await?;
+        let router = IUniswapV2Router02::new(route.router, self.provider.clone());
+        let amount = self.size / price;
+        let amount_in = U256::from((amount * 10_f64.powi(decimals as i32)) as u128);
+
+        let swapped = match self.holder {
+            Some(holder) => {
+                let mut call = router.swap_exact_tokens_for_tokens(amount_in, U256::zero(), route.path.clone(), holder, U256::MAX).from(holder);
+                if let Some(block) = block {
+                    call = call.block(block);
+                }
+                call.call().await
+                    .inspect_err(|e| eprintln!("the swap from {holder:?} reverted, asking getAmountsOut: {e}"))
+                    .ok()
+            }
+            None => None,
+        };
+        let amounts = match swapped {
+            Some(amounts) => amounts,
+            None => {
+                let mut call = router.get_amounts_out(amount_in, route.path);
+                if let Some(block) = block {
+                    call = call.block(block);
+                }
+                call.call().await?
+            }
+        };
+        let out = amounts.last().ok_or_else(|| eyre::eyre!("the router returned no amounts"))?;
+        Ok(uniswap_v2::reformat_usd(out.as_u128()) / amount)
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> PriceSource for WithRouter<M> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let mut quote = self.inner.quote(asset).await?;
+        let Some(&(decimals, _)) = quote.raw.as_ref().and_then(|raw| raw.decimals.first()) else {
+            return Ok(quote);
+        };
+        if quote.price <= 0.0 {
+            return Ok(quote);
+        }
+        match self.simulate(&asset.address, quote.price, decimals, quote.block).await {
+            Ok(price) => quote.simulated_price = Some(price),
+            Err(e) => eprintln!("could not simulate a sale of {}: {e}", asset.name),
+        }
+        Ok(quote)
+    }
+}
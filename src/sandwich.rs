@@ -0,0 +1,100 @@
+/*!
+ *
+ * Sandwiches in the swaps of a watched pool. A sandwich is the classic
+ * pattern of a bot trading around a victim within one block:
+ *
+ * front-run   the bot buys, pushing the price up
+ * victim      someone buys at the worse price
+ * back-run    the bot sells what it bought at the higher price
+ *
+ * or the same with sells. The bot is told apart by the recipient of its
+ * swaps, it trades from its own contract and the front- and back-run go
+ * to the same address in different transactions. How often the victims of a
+ * pool get sandwiched and for how much says how toxic trading in it is:
+ *
+ * uni-price-polling --config watch.toml --sandwiches --metrics 127.0.0.1:9100
+ *
+**/
+
+use ethers::types::{Address, H256};
+use serde::Serialize;
+
+use crate::swaps::Trade;
+
+/// The back-run has to sell at least this share of what the front-run
+/// bought, a bot that only takes part of it is still a bot but a trader that
+/// happens to trade twice is not
+const MATCHED: f64 = 0.5;
+
+/// One sandwich found in a block
+#[derive(Debug, Clone, Serialize)]
+pub struct Sandwich {
+    pub block: u64,
+    /// Recipient of the front- and back-run swaps
+    pub attacker: Address,
+    pub front: H256,
+    pub back: H256,
+    /// Transactions of the swaps in between
+    pub victims: Vec<H256>,
+    /// The front-run bought TOKEN, false for a sell
+    pub buy: bool,
+    /// USDT value of the victims' swaps
+    pub victim_usd: f64,
+    /// USDT the attacker made, the ETH it got back less what it paid,
+    /// before gas and bribes
+    pub profit_usd: f64,
+}
+
+/**
+ * @gist finds the sandwiches in some trades
+ * @param trades -- swaps of one pool, oldest first
+ * @output the sandwiches, oldest first
+**/
+pub fn detect(trades: &[Trade]) -> Vec<Sandwich> {
+    let mut found = Vec::new();
+    // Each swap can be part of one sandwich only
+    let mut used = vec![false; trades.len()];
+
+    for (i, front) in trades.iter().enumerate() {
+        if used[i] {
+            continue;
+        }
+        let block = trades[i + 1..].iter().take_while(|t| t.block == front.block);
+
+        let mut victims: Vec<&Trade> = Vec::new();
+        for (offset, t) in block.enumerate() {
+            let j = i + 1 + offset;
+            if used[j] || t.tx == front.tx {
+                continue;
+            }
+            if t.to == front.to {
+                if t.buy == front.buy || t.token < front.token * MATCHED {
+                    continue;
+                }
+                if victims.is_empty() {
+                    break;
+                }
+                used[i] = true;
+                used[j] = true;
+                // The bot pays ETH for a buy and gets it back on the sell
+                let profit_eth = if front.buy { t.eth - front.eth } else { front.eth - t.eth };
+                let eth_usd = if front.eth > 0.0 { front.usd / front.eth } else { 0.0 };
+                found.push(Sandwich {
+                    block: front.block,
+                    attacker: front.to,
+                    front: front.tx,
+                    back: t.tx,
+                    victims: victims.iter().map(|v| v.tx).collect(),
+                    buy: front.buy,
+                    victim_usd: victims.iter().map(|v| v.usd).sum(),
+                    profit_usd: profit_eth * eth_usd,
+                });
+                break;
+            }
+            if t.buy == front.buy {
+                victims.push(t);
+            }
+        }
+    }
+    found
+}
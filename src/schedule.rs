@@ -0,0 +1,205 @@
+/*!
+ *
+ * Cron expressions for when quotes are taken, so samples land on clock
+ * boundaries instead of drifting with the start time:
+ *
+ * uni-price-polling --config watch.toml --schedule "0 0-59/5 * * * *"
+ *
+ * Six fields, second minute hour day-of-month month day-of-week, or five
+ * without the seconds. A field is `*`, a number, a range `a-b`, any of
+ * those with a step `/n`, or a comma separated list. Days of the week run
+ * from 0 (Sunday) to 6, 7 is Sunday too. Like cron, when both days are
+ * restricted either may match. Times are UTC.
+ *
+**/
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, TimeZone, Timelike, Utc};
+
+use std::str::FromStr;
+
+/// The values of one field that match, bit n for value n
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field(u64);
+
+impl Field {
+    /**
+     * @gist parses one field
+     * @param s -- the field, e.g. 1-5 or 0,30
+     * @param min -- smallest value of the field
+     * @param max -- largest value of the field
+    **/
+    fn parse(s: &str, min: u32, max: u32) -> Result<Self, String> {
+        let mut bits = 0;
+        for part in s.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)
+                    .ok_or_else(|| format!("bad step in {part:?}"))?),
+                None => (part, 1),
+            };
+            let number = |n: &str| n.parse::<u32>().ok().filter(|n| (min..=max).contains(n))
+                .ok_or_else(|| format!("{n:?} is not in {min}-{max}"));
+            let (from, to) = match range {
+                "*" => (min, max),
+                _ => match range.split_once('-') {
+                    Some((from, to)) => (number(from)?, number(to)?),
+                    // a/n runs from a to the end, like cron
+                    None if step > 1 => (number(range)?, max),
+                    None => (number(range)?, number(range)?),
+                },
+            };
+            if from > to {
+                return Err(format!("{part:?} is an empty range"));
+            }
+            for n in (from..=to).step_by(step as usize) {
+                bits |= 1 << n;
+            }
+        }
+        Ok(Self(bits))
+    }
+
+    fn has(self, n: u32) -> bool {
+        self.0 & (1 << n) != 0
+    }
+
+    fn is_every(self, min: u32, max: u32) -> bool {
+        (min..=max).all(|n| self.has(n))
+    }
+}
+
+/// A parsed cron expression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    seconds: Field,
+    minutes: Field,
+    hours: Field,
+    days: Field,
+    months: Field,
+    weekdays: Field,
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let fields = match fields.len() {
+            6 => fields,
+            5 => [&["0"], &fields[..]].concat(),
+            n => return Err(format!("a schedule has 5 or 6 fields, got {n}")),
+        };
+        let mut weekdays = Field::parse(fields[5], 0, 7)?;
+        if weekdays.has(7) {
+            weekdays.0 |= 1;
+        }
+        let schedule = Self {
+            seconds: Field::parse(fields[0], 0, 59)?,
+            minutes: Field::parse(fields[1], 0, 59)?,
+            hours: Field::parse(fields[2], 0, 23)?,
+            days: Field::parse(fields[3], 1, 31)?,
+            months: Field::parse(fields[4], 1, 12)?,
+            weekdays,
+        };
+        if schedule.next_after(DateTime::UNIX_EPOCH).is_none() {
+            return Err(format!("{s:?} never matches"));
+        }
+        Ok(schedule)
+    }
+}
+
+impl Schedule {
+    fn day_matches(&self, t: DateTime<Utc>) -> bool {
+        let day = self.days.has(t.day());
+        let weekday = self.weekdays.has(t.weekday().num_days_from_sunday());
+        match (self.days.is_every(1, 31), self.weekdays.is_every(0, 6)) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+
+    /**
+     * @gist the first time after a given one the schedule matches
+     * @param after -- excluded
+     * @output None when nothing matches within 28 years, the cycle of
+     * weekdays and leap years
+    **/
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = |date: NaiveDate| Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).expect("midnight exists"));
+        let mut t = after.with_nanosecond(0)? + Duration::seconds(1);
+        let limit = t + Duration::days(366 * 28);
+
+        while t < limit {
+            if !self.months.has(t.month()) {
+                let (year, month) = if t.month() == 12 { (t.year() + 1, 1) } else { (t.year(), t.month() + 1) };
+                t = start(NaiveDate::from_ymd_opt(year, month, 1)?);
+            } else if !self.day_matches(t) {
+                t = start(t.date_naive().succ_opt()?);
+            } else if !self.hours.has(t.hour()) {
+                t = t.with_minute(0)?.with_second(0)? + Duration::hours(1);
+            } else if !self.minutes.has(t.minute()) {
+                t = t.with_second(0)? + Duration::minutes(1);
+            } else if !self.seconds.has(t.second()) {
+                t += Duration::seconds(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        s.parse().expect("a valid time")
+    }
+
+    fn next(schedule: &str, after: &str) -> DateTime<Utc> {
+        schedule.parse::<Schedule>().unwrap().next_after(at(after)).unwrap()
+    }
+
+    #[test]
+    fn steps_land_on_the_boundaries() {
+        assert_eq!(next("0 */5 * * * *", "2024-01-01T10:02:17Z"), at("2024-01-01T10:05:00Z"));
+        assert_eq!(next("*/15 * * * * *", "2024-01-01T10:02:17.5Z"), at("2024-01-01T10:02:30Z"));
+        // a/n runs from a to the end of the field
+        assert_eq!(next("0 10/20 * * * *", "2024-01-01T10:31:00Z"), at("2024-01-01T10:50:00Z"));
+    }
+
+    #[test]
+    fn after_is_excluded() {
+        assert_eq!(next("0 */5 * * * *", "2024-01-01T10:05:00Z"), at("2024-01-01T10:10:00Z"));
+    }
+
+    #[test]
+    fn five_fields_start_on_the_minute() {
+        assert_eq!(next("30 9 * * *", "2024-01-01T09:30:00Z"), at("2024-01-02T09:30:00Z"));
+        assert_eq!(next("0 0 1 1 *", "2024-06-15T12:00:00Z"), at("2025-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn either_restricted_day_matches() {
+        // The 15th or a Sunday, 2024-01-07 is a Sunday
+        assert_eq!(next("0 0 15 * 0", "2024-01-01T00:00:00Z"), at("2024-01-07T00:00:00Z"));
+        assert_eq!(next("0 0 15 * 0", "2024-01-14T00:00:00Z"), at("2024-01-15T00:00:00Z"));
+        // 7 is Sunday too
+        assert_eq!(next("0 0 * * 7", "2024-01-01T00:00:00Z"), at("2024-01-07T00:00:00Z"));
+        // Only the weekday restricted, Monday to Friday
+        assert_eq!(next("0 12 * * 1-5", "2024-01-05T13:00:00Z"), at("2024-01-08T12:00:00Z"));
+    }
+
+    #[test]
+    fn leap_days_wait_for_a_leap_year() {
+        assert_eq!(next("0 0 29 2 *", "2024-03-01T00:00:00Z"), at("2028-02-29T00:00:00Z"));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!("* * * *".parse::<Schedule>().is_err());
+        assert!("60 * * * * *".parse::<Schedule>().is_err());
+        assert!("0 */0 * * * *".parse::<Schedule>().is_err());
+        assert!("0 30-10 * * * *".parse::<Schedule>().is_err());
+        assert!("0 0 0 31 2 *".parse::<Schedule>().is_err());
+    }
+}
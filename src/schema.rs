@@ -0,0 +1,67 @@
+/*!
+ *
+ * The json of a quote as an interface other programs can build on. Every
+ * quote in json, in the lines of json file sinks, in the bodies of webhook
+ * sinks and in the answers of /api/quotes and /prices starts with the
+ * version of its schema:
+ *
+ * {"schema_version":1,"source":"UNI V2","asset":"LINK","price":14.52,...}
+ *
+ * Within a version fields are only added, never renamed, removed or given
+ * another meaning, so consumers have to ignore fields they don't know.
+ * Anything else raises the version. Rust consumers can read the quotes
+ * back with the types of this crate:
+ *
+ * let quote = uni_price_polling::schema::parse(&line)?;
+ *
+ * Versions:
+ *
+ * 1  the fields of Quote as of the first versioned release
+ *
+**/
+
+use serde::{Deserialize, Serialize, Serializer};
+
+use crate::source::Quote;
+
+/// Version of the json schema of the quotes
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A value as it goes out, with the version of its schema in front
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Versioned<T> {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub value: T,
+}
+
+impl<T> Versioned<T> {
+    /**
+     * @gist tags a value with the current schema version
+    **/
+    pub fn new(value: T) -> Self {
+        Self { schema_version: SCHEMA_VERSION, value }
+    }
+}
+
+/**
+ * @gist serializes a quote field of a struct with its schema version, for
+ * serialize_with
+**/
+pub fn versioned<S: Serializer>(quote: &Quote, serializer: S) -> Result<S::Ok, S::Error> {
+    Versioned::new(quote).serialize(serializer)
+}
+
+/**
+ * @gist reads a quote the poller wrote as json
+ * @param json -- one quote, e.g. a line of a json file sink
+ * @output an error for quotes of a newer schema or without a version
+**/
+pub fn parse(json: &str) -> eyre::Result<Quote> {
+    let versioned: Versioned<Quote> = serde_json::from_str(json)
+        .map_err(|e| eyre::eyre!("not a quote: {e}"))?;
+    if versioned.schema_version > SCHEMA_VERSION {
+        eyre::bail!("the quote has schema version {}, this build reads up to {SCHEMA_VERSION}", versioned.schema_version);
+    }
+    Ok(versioned.value)
+}
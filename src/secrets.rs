@@ -0,0 +1,167 @@
+/*!
+ *
+ * Secrets in the config without putting them in the file. Any string of
+ * the config can name environment variables, and any key can be read from
+ * a file instead by adding _file to its name, e.g. for Docker or systemd
+ * credentials:
+ *
+ * [notify.telegram]
+ * bot_token_file = "/run/secrets/telegram_token"
+ * chat_id = "${TELEGRAM_CHAT_ID}"
+ *
+ * [[sink]]
+ * type = "webhook"
+ * url = "https://hooks.example.com/${HOOK_ID:-prices}"
+ *
+ * `${NAME:-default}` falls back to the default when NAME is unset, an
+ * unset NAME without one is an error, and `$${` is a literal `${`. The
+ * contents of a file lose the line break at their end. Variables are
+ * expanded first, so the path of a file can come from the environment too.
+ *
+**/
+
+use toml::Value;
+
+use std::env;
+
+/// Suffix of the keys read from a file
+const FILE_SUFFIX: &str = "_file";
+
+/**
+ * @gist expands the variables and reads the files of a parsed config
+ * @param value -- the whole config, changed in place
+**/
+pub fn resolve(value: &mut Value) -> eyre::Result<()> {
+    resolve_at(value, "")
+}
+
+fn resolve_at(value: &mut Value, at: &str) -> eyre::Result<()> {
+    match value {
+        Value::String(s) => *s = expand(s).map_err(|e| eyre::eyre!("{at}: {e}"))?,
+        Value::Array(items) => {
+            for item in items {
+                resolve_at(item, at)?;
+            }
+        }
+        Value::Table(table) => {
+            let keys: Vec<String> = table.keys().cloned().collect();
+            for key in keys {
+                let path = if at.is_empty() { key.clone() } else { format!("{at}.{key}") };
+                resolve_at(table.get_mut(&key).expect("key of the table"), &path)?;
+
+                let Some(name) = key.strip_suffix(FILE_SUFFIX).filter(|n| !n.is_empty()) else {
+                    continue;
+                };
+                let Some(Value::String(file)) = table.get(&key) else {
+                    continue;
+                };
+                if table.contains_key(name) {
+                    eyre::bail!("{path}: set either {name} or {key}, not both");
+                }
+                let text = std::fs::read_to_string(file)
+                    .map_err(|e| eyre::eyre!("{path}: could not read {file}: {e}"))?;
+                let secret = text.strip_suffix('\n').map(|t| t.strip_suffix('\r').unwrap_or(t)).unwrap_or(&text).to_string();
+                table.remove(&key);
+                table.insert(name.to_string(), Value::String(secret));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/**
+ * @gist replaces ${NAME} and ${NAME:-default} by the environment
+**/
+fn expand(s: &str) -> eyre::Result<String> {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find("${") {
+        if rest[..i].ends_with('$') {
+            out.push_str(&rest[..i - 1]);
+            out.push_str("${");
+            rest = &rest[i + 2..];
+            continue;
+        }
+        out.push_str(&rest[..i]);
+        let end = rest[i..].find('}').ok_or_else(|| eyre::eyre!("unclosed ${{ in {s:?}"))?;
+        let inner = &rest[i + 2..i + end];
+        let (name, default) = match inner.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (inner, None),
+        };
+        match (env::var(name), default) {
+            (Ok(value), _) => out.push_str(&value),
+            (Err(_), Some(default)) => out.push_str(default),
+            (Err(_), None) => eyre::bail!("the environment variable {name} is not set"),
+        }
+        rest = &rest[i + end + 1..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(toml: &str) -> eyre::Result<Value> {
+        let mut value: Value = toml::from_str(toml).unwrap();
+        resolve(&mut value)?;
+        Ok(value)
+    }
+
+    #[test]
+    fn expands_variables() {
+        env::set_var("UPP_TEST_HOOK", "abc");
+        assert_eq!(expand("https://hooks.example.com/${UPP_TEST_HOOK}/x").unwrap(), "https://hooks.example.com/abc/x");
+        assert_eq!(expand("${UPP_TEST_HOOK}${UPP_TEST_HOOK}").unwrap(), "abcabc");
+        assert_eq!(expand("no variables").unwrap(), "no variables");
+    }
+
+    #[test]
+    fn falls_back_to_the_default() {
+        env::set_var("UPP_TEST_SET", "set");
+        assert_eq!(expand("${UPP_TEST_UNSET:-prices}").unwrap(), "prices");
+        assert_eq!(expand("${UPP_TEST_UNSET:-}").unwrap(), "");
+        assert_eq!(expand("${UPP_TEST_SET:-prices}").unwrap(), "set");
+    }
+
+    #[test]
+    fn refuses_unset_and_unclosed_variables() {
+        let unset = expand("${UPP_TEST_UNSET}").unwrap_err().to_string();
+        assert!(unset.contains("UPP_TEST_UNSET is not set"), "{unset}");
+        assert!(expand("${UPP_TEST_UNSET").is_err());
+    }
+
+    #[test]
+    fn keeps_escaped_dollars() {
+        assert_eq!(expand("$${UPP_TEST_UNSET} costs $5").unwrap(), "${UPP_TEST_UNSET} costs $5");
+    }
+
+    #[test]
+    fn reads_file_keys() {
+        let path = env::temp_dir().join(format!("uni-price-polling-{}-token", std::process::id()));
+        std::fs::write(&path, "123:abc\r\n").unwrap();
+        env::set_var("UPP_TEST_SECRETS", env::temp_dir());
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let value = resolved(&format!("[notify.telegram]\nbot_token_file = \"${{UPP_TEST_SECRETS}}/{name}\"\nchat_id = \"1\"\n")).unwrap();
+        let telegram = &value["notify"]["telegram"];
+        assert_eq!(telegram["bot_token"].as_str(), Some("123:abc"));
+        assert!(telegram.get("bot_token_file").is_none());
+
+        let both = resolved(&format!("bot_token = \"x\"\nbot_token_file = \"{}\"\n", path.display())).unwrap_err().to_string();
+        assert!(both.contains("set either bot_token or bot_token_file"), "{both}");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn errors_say_where() {
+        let missing = resolved("[[sink]]\npath_file = \"/nonexistent/upp\"\n").unwrap_err().to_string();
+        assert!(missing.starts_with("sink.path_file: could not read /nonexistent/upp"), "{missing}");
+        let unset = resolved("[[sink]]\nurl = \"${UPP_TEST_UNSET}\"\n").unwrap_err().to_string();
+        assert!(unset.starts_with("sink.url: "), "{unset}");
+        // A key named just _file is left alone
+        assert_eq!(resolved("_file = \"x\"\n").unwrap()["_file"].as_str(), Some("x"));
+    }
+}
@@ -0,0 +1,187 @@
+/*!
+ *
+ * Paper trading on the live route. Entry and exit rules are evaluated on the
+ * mid price of every poll; trades are filled at the execution price of the
+ * route, so fees and price impact are included in the PnL:
+ *
+ * [simulate]
+ * size = 1000.0                                     # USDT per entry
+ * entry = { type = "ma-cross-above", fast = 5, slow = 20 }
+ * exit = { type = "above", price = 12.0 }
+ * take_profit = 10.0                                # percent, optional
+ * stop_loss = 5.0                                   # percent, optional
+ *
+**/
+
+use serde::{Deserialize, Serialize};
+
+use crate::curve::{Route, Side};
+use crate::indicators::{Cross, Crossover};
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Rule {
+    /// The mid price is above a threshold
+    Above { price: f64 },
+    /// The mid price is below a threshold
+    Below { price: f64 },
+    /// The fast moving average crossed above the slow one
+    MaCrossAbove { fast: usize, slow: usize },
+    /// The fast moving average crossed below the slow one
+    MaCrossBelow { fast: usize, slow: usize },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SimulateConfig {
+    /// USDT spent on every entry
+    pub size: f64,
+    pub entry: Rule,
+    pub exit: Rule,
+    /// Close once the position is up this many percent
+    pub take_profit: Option<f64>,
+    /// Close once the position is down this many percent
+    pub stop_loss: Option<f64>,
+}
+
+/// A rule together with the indicator state it needs
+struct Signal {
+    rule: Rule,
+    crossover: Option<Crossover>,
+}
+
+impl Signal {
+    fn new(rule: Rule) -> Self {
+        let crossover = match rule {
+            Rule::MaCrossAbove { fast, slow } | Rule::MaCrossBelow { fast, slow } => Some(Crossover::new(fast, slow)),
+            Rule::Above { .. } | Rule::Below { .. } => None,
+        };
+        Self { rule, crossover }
+    }
+
+    /// Feeds the mid price, must be called on every poll
+    fn update(&mut self, mid: f64) -> bool {
+        let cross = self.crossover.as_mut().and_then(|c| c.push(mid));
+        match self.rule {
+            Rule::Above { price } => mid > price,
+            Rule::Below { price } => mid < price,
+            Rule::MaCrossAbove { .. } => cross == Some(Cross::Above),
+            Rule::MaCrossBelow { .. } => cross == Some(Cross::Below),
+        }
+    }
+}
+
+/// The open virtual position
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Position {
+    /// TOKEN held
+    pub tokens: f64,
+    /// USDT paid
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum Event {
+    /// Bought `tokens` for `cost` USDT at `price` USDT per TOKEN
+    Buy { mid: f64, price: f64, tokens: f64, cost: f64 },
+    /// Sold the position for `proceeds` USDT at `price`
+    Sell { mid: f64, price: f64, tokens: f64, proceeds: f64, pnl: f64, reason: &'static str },
+}
+
+pub struct Simulator {
+    config: SimulateConfig,
+    entry: Signal,
+    exit: Signal,
+    position: Option<Position>,
+    realized: f64,
+    trades: u32,
+}
+
+impl Simulator {
+    /**
+     * @gist starts flat with no realized PnL
+    **/
+    pub fn new(config: SimulateConfig) -> Self {
+        Self {
+            entry: Signal::new(config.entry),
+            exit: Signal::new(config.exit),
+            config,
+            position: None,
+            realized: 0.0,
+            trades: 0,
+        }
+    }
+
+    /**
+     * @gist evaluates the rules on a new poll and trades if they say so
+     * @param route -- the current reserves of the route
+     * @output the trade that was made, if any
+    **/
+    pub fn step(&mut self, route: &Route) -> Option<Event> {
+        let mid = route.mid_price();
+        let enter = self.entry.update(mid);
+        let exit = self.exit.update(mid);
+
+        let Some(position) = self.position else {
+            if !enter {
+                return None;
+            }
+            let price = route.execution_price(Side::Buy, self.config.size);
+            let tokens = self.config.size / price;
+            self.position = Some(Position { tokens, cost: self.config.size });
+            return Some(Event::Buy { mid, price, tokens, cost: self.config.size });
+        };
+
+        let price = route.execution_price(Side::Sell, position.tokens);
+        let proceeds = position.tokens * price;
+        let pnl = proceeds - position.cost;
+        let pnl_pct = pnl / position.cost * 100.0;
+
+        let reason = if exit {
+            "exit rule"
+        } else if self.config.take_profit.is_some_and(|tp| pnl_pct >= tp) {
+            "take profit"
+        } else if self.config.stop_loss.is_some_and(|sl| pnl_pct <= -sl) {
+            "stop loss"
+        } else {
+            return None;
+        };
+
+        self.position = None;
+        self.realized += pnl;
+        self.trades += 1;
+        Some(Event::Sell { mid, price, tokens: position.tokens, proceeds, pnl, reason })
+    }
+
+    /**
+     * @gist the open position, None when flat
+    **/
+    pub fn position(&self) -> Option<Position> {
+        self.position
+    }
+
+    /**
+     * @gist PnL of the closed trades in USDT
+    **/
+    pub fn realized(&self) -> f64 {
+        self.realized
+    }
+
+    /**
+     * @gist number of closed trades
+    **/
+    pub fn trades(&self) -> u32 {
+        self.trades
+    }
+
+    /**
+     * @gist PnL of the open position if it were sold now, fees and impact
+     * included
+     * @output None when flat
+    **/
+    pub fn unrealized(&self, route: &Route) -> Option<f64> {
+        let position = self.position?;
+        Some(position.tokens * route.execution_price(Side::Sell, position.tokens) - position.cost)
+    }
+}
@@ -0,0 +1,318 @@
+/*!
+ *
+ * Sinks receive the quotes produced in watch mode. Stdout is always there,
+ * more sinks can be added in the config:
+ *
+ * [[sink]]
+ * type = "file"               # "file" or "webhook"
+ * path = "quotes.ndjson"
+ * format = "json"
+ * queue = 1000                # quotes buffered for this sink
+ * overflow = "drop-oldest"    # "drop-oldest", "block" or "coalesce"
+ *
+ * [[sink]]
+ * type = "webhook"
+ * url = "https://example.com/quotes"
+ *
+ * Every sink is fed through its own bounded queue, see queue.rs.
+ *
+**/
+
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::clock::{self, TimeStyle};
+use crate::format::{self, Format};
+use crate::history;
+use crate::queue::{Overflow, Queue};
+use crate::rotate::{self, RotateConfig, RotatingFile};
+use crate::source::Quote;
+use crate::trace::Span;
+
+#[async_trait]
+pub trait Sink: Send + Sync {
+    /**
+     * @gist short name used in error messages
+    **/
+    fn name(&self) -> &str;
+
+    /**
+     * @gist delivers a single quote
+    **/
+    async fn write(&self, quote: &Quote) -> eyre::Result<()>;
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SinkConfig {
+    #[serde(flatten)]
+    pub kind: SinkKind,
+    /// Number of quotes buffered for this sink
+    #[serde(default = "default_queue")]
+    pub queue: usize,
+    #[serde(default)]
+    pub overflow: Overflow,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SinkKind {
+    File {
+        path: PathBuf,
+        #[serde(default)]
+        format: Format,
+        /// When to roll over to a new file, never by default
+        #[serde(default)]
+        rotate: RotateConfig,
+    },
+    Webhook {
+        url: String,
+    },
+}
+
+fn default_queue() -> usize {
+    1024
+}
+
+/// Prints quotes to stdout
+pub struct Stdout {
+    format: Format,
+    /// Length of the sparkline shown in text mode, 0 for none
+    sparkline: usize,
+    /// Zone and format of the times in text mode
+    time: TimeStyle,
+    recent: Mutex<HashMap<String, VecDeque<f64>>>,
+}
+
+impl Stdout {
+    /**
+     * @gist creates the sink, prints the header right away for csv
+    **/
+    pub fn new(format: Format) -> Self {
+        if format == Format::Csv {
+            println!("{}", format::csv_header());
+        }
+        Self { format, sparkline: 0, time: TimeStyle::default(), recent: Mutex::new(HashMap::new()) }
+    }
+
+    /**
+     * @gist shows a sparkline of the last `len` prices next to every asset in
+     * text mode
+    **/
+    pub fn with_sparkline(mut self, len: usize) -> Self {
+        self.sparkline = len;
+        self
+    }
+
+    /**
+     * @gist shows times in text mode in a zone and format other than UTC
+     * RFC 3339
+    **/
+    pub fn with_time_style(mut self, time: TimeStyle) -> Self {
+        self.time = time;
+        self
+    }
+
+    fn spark(&self, quote: &Quote) -> String {
+        let mut recent = self.recent.lock().unwrap();
+        let prices = recent.entry(quote.asset.clone()).or_default();
+        prices.push_back(quote.price);
+        while prices.len() > self.sparkline {
+            prices.pop_front();
+        }
+        format::sparkline(prices.make_contiguous())
+    }
+}
+
+#[async_trait]
+impl Sink for Stdout {
+    fn name(&self) -> &str {
+        "stdout"
+    }
+
+    async fn write(&self, quote: &Quote) -> eyre::Result<()> {
+        let rendered = format::render_with(quote, self.format, &self.time);
+        if self.format != Format::Text || self.sparkline == 0 {
+            println!("{rendered}");
+            return Ok(());
+        }
+
+        // The sparkline goes at the end of the first line, after the price
+        let spark = self.spark(quote);
+        match rendered.split_once('\n') {
+            Some((first, rest)) => println!("{first} {spark}\n{rest}"),
+            None => println!("{rendered} {spark}"),
+        }
+        Ok(())
+    }
+}
+
+/// Appends quotes to a file, one per line
+pub struct FileSink {
+    name: String,
+    file: Mutex<RotatingFile>,
+    format: Format,
+}
+
+impl FileSink {
+    /**
+     * @gist opens the file for appending, new csv files get a header
+     * @param path -- the active file
+     * @param format -- text, csv or json
+     * @param rotate -- when to roll over to a new file
+    **/
+    pub fn open(path: PathBuf, format: Format, rotate: RotateConfig) -> eyre::Result<Self> {
+        let header = (format == Format::Csv).then(format::csv_header);
+        let name = path.display().to_string();
+        let file = RotatingFile::open(path, rotate, header, clock::unix_now())?;
+        Ok(Self { name, file: Mutex::new(file), format })
+    }
+}
+
+#[async_trait]
+impl Sink for FileSink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn write(&self, quote: &Quote) -> eyre::Result<()> {
+        let line = format::render(quote, self.format);
+        let (rotated, active, config) = {
+            let mut file = self.file.lock().unwrap();
+            // A correction takes the place of the rows of the orphaned block,
+            // those already rotated out are left to the history readers
+            if quote.reorged && self.format != Format::Text {
+                let csv = self.format == Format::Csv;
+                file.retain(|line| !history::replaces(quote, line, csv))?;
+            }
+            let rotated = file.write_line(&line, clock::unix_now())?;
+            (rotated, file.path().to_path_buf(), file.config().clone())
+        };
+
+        // Compressing a large file takes a while, keep it off the runtime
+        if let Some(rotated) = rotated {
+            tokio::task::spawn_blocking(move || rotate::archive(&active, &rotated, &config)).await??;
+        }
+        Ok(())
+    }
+}
+
+/// POSTs every quote as JSON
+pub struct Webhook {
+    client: Client,
+    url: String,
+}
+
+impl Webhook {
+    /**
+     * @gist creates the sink
+     * @param client -- HTTP client used for the requests
+     * @param url -- where the quotes are POSTed
+    **/
+    pub fn new(client: Client, url: String) -> Self {
+        Self { client, url }
+    }
+}
+
+#[async_trait]
+impl Sink for Webhook {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn write(&self, quote: &Quote) -> eyre::Result<()> {
+        self.client.post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(format::render(quote, Format::Json))
+            .send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/**
+ * @gist creates the sink described by a config entry
+ * @param kind -- the sink section of the config
+ * @param client -- HTTP client for webhooks
+**/
+pub fn from_config(kind: &SinkKind, client: &Client) -> eyre::Result<Arc<dyn Sink>> {
+    Ok(match kind {
+        SinkKind::File { path, format, rotate } => Arc::new(FileSink::open(path.clone(), *format, rotate.clone())?),
+        SinkKind::Webhook { url } => Arc::new(Webhook::new(client.clone(), url.clone())),
+    })
+}
+
+/// Fans quotes out to the sinks, each behind its own queue and worker task
+pub struct Dispatcher {
+    queues: Vec<Arc<Queue>>,
+}
+
+impl Dispatcher {
+    /**
+     * @gist creates a dispatcher without sinks
+    **/
+    pub fn new() -> Self {
+        Self { queues: Vec::new() }
+    }
+
+    /**
+     * @gist starts the worker task of a sink
+     * @param sink -- where the quotes go
+     * @param capacity -- size of its queue
+     * @param overflow -- what to do when the queue is full
+    **/
+    pub fn add(&mut self, sink: Arc<dyn Sink>, capacity: usize, overflow: Overflow) {
+        let queue = Arc::new(Queue::new(capacity, overflow));
+        let worker = queue.clone();
+
+        tokio::spawn(async move {
+            let mut reported = 0;
+            loop {
+                let (quote, parent) = worker.pop().await;
+                let mut span = Span::child_of("sink write", parent);
+                span.set("sink", sink.name());
+                span.set("asset", &quote.asset);
+                if let Err(e) = sink.write(&quote).await {
+                    span.fail(&e);
+                    eprintln!("sink {} failed: {e}", sink.name());
+                }
+                drop(span);
+                worker.done();
+
+                let dropped = worker.dropped();
+                if dropped > reported {
+                    eprintln!("sink {} is falling behind, {} quotes dropped so far", sink.name(), dropped);
+                    reported = dropped;
+                }
+            }
+        });
+
+        self.queues.push(queue);
+    }
+
+    /**
+     * @gist hands a quote to every sink
+    **/
+    pub async fn send(&self, quote: &Quote) {
+        for queue in &self.queues {
+            queue.push(quote.clone()).await;
+        }
+    }
+
+    /**
+     * @gist waits until every sink wrote the quotes handed to it
+    **/
+    pub async fn flush(&self) {
+        while !self.queues.iter().all(|q| q.is_idle()) {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
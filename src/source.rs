@@ -0,0 +1,258 @@
+/*!
+ *
+ * The PriceSource abstraction. Every backend (the Uniswap V2 2-hop route,
+ * custom oracles, or anything a downstream crate brings along) implements
+ * this trait, so the poller does not care where a quote comes from.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{
+    providers::Middleware,
+    types::{Address, BlockNumber},
+};
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+
+use crate::indicators::Bands;
+use crate::sandwich::Sandwich;
+use crate::swaps::{LiquidityEvent, Trade, Volume};
+
+/// Something that can be priced
+#[derive(Debug, Clone)]
+pub struct Asset {
+    /// Label used in the output, e.g. "LINK/USDT"
+    pub name: String,
+    /// Where the source should look, e.g. the TOKEN/ETH pool or oracle contract
+    pub address: Address,
+}
+
+/// A price produced by a source, as json see the schema module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Quote {
+    /// Name of the source that produced the quote, e.g. "UNI V2"
+    pub source: String,
+    /// Name of the asset
+    pub asset: String,
+    /// The price in the quote currency
+    pub price: f64,
+    /// Value of the pool reserves in the quote currency, if the source knows it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub liquidity: Option<f64>,
+    /// Block the quote was read at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<u64>,
+    /// Unix time of the block the quote was read at, the local time for
+    /// off-chain quotes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Unix time the reserves of the pool last changed, its blockTimestampLast
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<u64>,
+    /// Change over the last 24 hours in percent, filled in by watch mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_24h: Option<f64>,
+    /// Rate of change of the price per minute, filled in by watch mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change_per_min: Option<f64>,
+    /// Change of change_per_min per minute, filled in by watch mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accel_per_min2: Option<f64>,
+    /// Bollinger bands of the recent prices, filled in by watch mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bollinger: Option<Bands>,
+    /// Relative strength index, filled in by watch mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rsi: Option<f64>,
+    /// Volume-weighted average price of the recent swaps of the pool
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vwap: Option<f64>,
+    /// Buy and sell volume of the swaps since the previous quote
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swaps: Option<Volume>,
+    /// The swaps since the previous quote, for the alerts
+    #[serde(skip)]
+    pub trades: Vec<Trade>,
+    /// Liquidity added and removed since the previous quote, for the alerts
+    #[serde(skip)]
+    pub liquidity_events: Vec<LiquidityEvent>,
+    /// Sandwiches in the swaps since the previous quote
+    #[serde(skip)]
+    pub sandwiches: Vec<Sandwich>,
+    /// Traded volume over the last 24 hours in USD, from the subgraph
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume_24h: Option<f64>,
+    /// Fees earned by the pool over the last 24 hours in USD
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fees_24h: Option<f64>,
+    /// Last price of the asset on the configured exchange
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cex_price: Option<f64>,
+    /// Premium of price over cex_price in basis points
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub basis_bps: Option<f64>,
+    /// USDT per TOKEN selling the aggregator size through the route
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sized_price: Option<f64>,
+    /// USDT per TOKEN the configured aggregator pays for the same trade
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aggregator_price: Option<f64>,
+    /// Premium of aggregator_price over sized_price in basis points
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aggregator_bps: Option<f64>,
+    /// USDT per TOKEN a --simulate sale through the router returns
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simulated_price: Option<f64>,
+    /// USDT per TOKEN selling the --bid-ask size through the route, fees
+    /// and price impact included
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bid: Option<f64>,
+    /// USDT per TOKEN paid buying with the --bid-ask size
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ask: Option<f64>,
+    /// Quoted again after a reorg orphaned the block of an earlier quote,
+    /// this one replaces it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reorged: bool,
+    /// The pool holds less than --min-liquidity-usd
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub thin: bool,
+    /// Some feeds of the cycle missed the --deadline-ms, the quote is
+    /// part of an incomplete update
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub degraded: bool,
+    /// Even the newest block of the cycle was older than --max-block-lag,
+    /// the node is probably behind the chain
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub lagging: bool,
+    /// 0 to 100, how far the price can be trusted
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<u8>,
+    /// Currency or token the price and the other amounts are in, None for USD
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// Id of the chain the quote was read on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain: Option<u64>,
+    /// Symbol of TOKEN, for quotes of a pool
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    /// The labels of the [[pool]] in the config
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// The individual quotes a composite price was made of
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<Quote>,
+    /// Unscaled inputs of the price, for auditing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw: Option<Raw>,
+    /// Address of the asset the quote was polled for, tells feeds sharing a
+    /// label apart, not part of the json
+    #[serde(skip)]
+    pub address: Option<Address>,
+}
+
+/// The values a route price was computed from, one entry per hop
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Raw {
+    /// Unscaled reserves of every pool as (base, quote), e.g. (TOKEN, ETH)
+    pub reserves: Vec<(u128, u128)>,
+    /// Decimals the reserves were scaled with, same order
+    pub decimals: Vec<(u8, u8)>,
+    /// Price of the base in the quote token for every hop, e.g. TOKEN/ETH
+    pub ratios: Vec<f64>,
+}
+
+impl Quote {
+    /**
+     * @gist creates a plain quote without components
+    **/
+    pub fn new(source: &str, asset: &str, price: f64) -> Self {
+        Self {
+            source: source.to_string(),
+            asset: asset.to_string(),
+            price,
+            liquidity: None,
+            block: None,
+            timestamp: None,
+            updated: None,
+            change_24h: None,
+            change_per_min: None,
+            accel_per_min2: None,
+            bollinger: None,
+            rsi: None,
+            vwap: None,
+            swaps: None,
+            trades: Vec::new(),
+            liquidity_events: Vec::new(),
+            sandwiches: Vec::new(),
+            volume_24h: None,
+            fees_24h: None,
+            cex_price: None,
+            basis_bps: None,
+            sized_price: None,
+            aggregator_price: None,
+            aggregator_bps: None,
+            simulated_price: None,
+            bid: None,
+            ask: None,
+            reorged: false,
+            thin: false,
+            degraded: false,
+            lagging: false,
+            confidence: None,
+            currency: None,
+            chain: None,
+            symbol: None,
+            labels: BTreeMap::new(),
+            components: Vec::new(),
+            raw: None,
+            address: None,
+        }
+    }
+
+    /**
+     * @gist drops the raw values of the quote and its components
+    **/
+    pub fn without_raw(mut self) -> Self {
+        self.raw = None;
+        self.components = self.components.into_iter().map(Quote::without_raw).collect();
+        self
+    }
+}
+
+/// Number and time of the block a quote is read at
+#[derive(Debug, Clone, Copy)]
+pub struct Head {
+    pub number: u64,
+    /// Unix time from the block header
+    pub timestamp: u64,
+}
+
+/**
+ * @gist reads the latest block header, on-chain sources read at this block
+ * and stamp their quotes with its number and time
+ * @param provider -- any ethers middleware
+**/
+pub async fn head<M: Middleware + 'static>(provider: &M) -> eyre::Result<Head> {
+    let block = provider.get_block(BlockNumber::Latest).await?
+        .ok_or_else(|| eyre::eyre!("the node returned no latest block"))?;
+    let number = block.number.ok_or_else(|| eyre::eyre!("the latest block has no number"))?;
+    Ok(Head { number: number.as_u64(), timestamp: block.timestamp.as_u64() })
+}
+
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    /**
+     * @gist short name of the backend, used to tag the quotes
+    **/
+    fn name(&self) -> &str;
+
+    /**
+     * @gist fetches the current price of an asset
+     * @param asset -- the asset to price
+     * @output the quote or why the source could not produce one
+    **/
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote>;
+}
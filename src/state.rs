@@ -0,0 +1,122 @@
+/*!
+ *
+ * State that survives restarts of watch mode (--state FILE): the last
+ * processed block, the rolling windows and the alert states. The file is
+ * rewritten every --state-every seconds and when watch mode is stopped.
+ *
+**/
+
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::alerts::RuleState;
+use crate::window::Window;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct State {
+    /// The newest block any quote was read at
+    pub block: Option<u64>,
+    /// Unix time the block was processed
+    pub timestamp: Option<u64>,
+    /// Rolling windows by namespace, asset name and address, see State::window
+    #[serde(default)]
+    pub windows: HashMap<String, Window>,
+    /// Alert states by rule name
+    #[serde(default)]
+    pub alerts: HashMap<String, RuleState>,
+}
+
+impl State {
+    /**
+     * @gist the rolling window of a feed, the top level feed takes over the
+     * window older state files kept under the bare asset name
+     * @param namespace -- of the feed, None for the top level
+     * @param asset -- name of the asset
+     * @param address -- of the asset, None where it isn't known like in a replay
+    **/
+    pub fn window(&mut self, namespace: Option<&str>, asset: &str, address: Option<Address>) -> &mut Window {
+        let key = window_key(namespace, asset, address);
+        if !self.windows.contains_key(&key) {
+            let old = namespace.is_none().then(|| self.windows.remove(asset)).flatten();
+            self.windows.insert(key.clone(), old.unwrap_or_default());
+        }
+        self.windows.get_mut(&key).expect("inserted above")
+    }
+}
+
+/**
+ * @gist the key of a window in the state file, a json array of the
+ * namespace, asset and address so no label can collide with another
+**/
+fn window_key(namespace: Option<&str>, asset: &str, address: Option<Address>) -> String {
+    serde_json::json!([namespace, asset, address]).to_string()
+}
+
+/**
+ * @gist reads the state file, a missing file is an empty state
+ * @param path -- location of the state file
+ * @output the stored state
+**/
+pub fn load(path: &Path) -> eyre::Result<State> {
+    if !path.exists() {
+        return Ok(State::default());
+    }
+    let text = std::fs::read_to_string(path)?;
+    serde_json::from_str(&text)
+        .map_err(|e| eyre::eyre!("invalid state file {}: {e}", path.display()))
+}
+
+/**
+ * @gist writes the state file, through a temporary file so a crash while
+ * writing can't leave a truncated state behind
+ * @param path -- location of the state file
+ * @param state -- the state to store
+**/
+pub fn save(path: &Path, state: &State) -> eyre::Result<()> {
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec(state)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINK: &str = "0xa2107fa5b38d9bbd2c461d6edf11b11a50f6b974";
+
+    #[test]
+    fn namespaces_sharing_a_label_keep_their_own_window() {
+        let mut state = State::default();
+        let pool = Some(LINK.parse().unwrap());
+        state.window(Some("treasury"), "LINK", pool).push(1, 10.0);
+        state.window(Some("trading"), "LINK", pool).push(1, 20.0);
+        state.window(None, "LINK", pool).push(1, 30.0);
+
+        assert_eq!(state.windows.len(), 3);
+        assert_eq!(state.window(Some("treasury"), "LINK", pool).samples().map(|s| s.1).collect::<Vec<_>>(), [10.0]);
+        assert_eq!(state.window(Some("trading"), "LINK", pool).samples().map(|s| s.1).collect::<Vec<_>>(), [20.0]);
+        assert_eq!(state.window(None, "LINK", pool).samples().map(|s| s.1).collect::<Vec<_>>(), [30.0]);
+    }
+
+    #[test]
+    fn labels_do_not_collide() {
+        // A top level "a/b" and asset "b" of namespace "a"
+        assert_ne!(window_key(None, "a/b", None), window_key(Some("a"), "b", None));
+        assert_ne!(window_key(None, "LINK", None), window_key(None, "LINK", Some(LINK.parse().unwrap())));
+    }
+
+    #[test]
+    fn takes_over_old_windows() {
+        let mut state = State::default();
+        state.windows.entry("LINK".to_string()).or_default().push(1, 10.0);
+        state.window(Some("treasury"), "LINK", None).push(2, 20.0);
+        assert!(state.windows.contains_key("LINK"));
+
+        let pool = Some(LINK.parse().unwrap());
+        assert_eq!(state.window(None, "LINK", pool).samples().map(|s| s.1).collect::<Vec<_>>(), [10.0]);
+        assert!(!state.windows.contains_key("LINK"));
+    }
+}
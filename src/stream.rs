@@ -0,0 +1,209 @@
+/*!
+ *
+ * Event mode. `--ws URL` follows the Sync events of the `--pool` route over
+ * a WebSocket instead of polling it: every block that moves either pool is
+ * quoted as soon as the node sends its logs.
+ *
+ * uni-price-polling --pool LINK --ws wss://node.example/ws --state state.json
+ *
+ * A connection that drops is opened again after 1 second, then 2, 4 and
+ * so on up to a minute. Before the stream resumes, the Sync events of the
+ * blocks missed in between are read with eth_getLogs and quoted in order,
+ * up to `backfill::MAX_BLOCKS` back, so the history the sinks write has no
+ * holes. Logs a reorg removed are skipped, the blocks that replace them
+ * bring their own.
+ *
+**/
+
+use ethers::{
+    contract::{parse_log, EthEvent},
+    providers::{Middleware, Provider, StreamExt, Ws},
+    types::{Address, Filter, Log, ValueOrArray},
+};
+use eyre::eyre;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::backfill;
+use crate::source::Quote;
+use crate::uniswap_v2::{route_from_reserves, SyncFilter};
+
+/// Wait before the first reconnect, doubled after every failed one
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+
+/// Longest wait between two reconnects
+const MAX_RETRY: Duration = Duration::from_secs(60);
+
+/// Quiet time after the last log of a block before it is quoted, the
+/// logs of a block come in a burst
+const SETTLE: Duration = Duration::from_millis(250);
+
+/// The route as of the last block quoted
+#[derive(Debug, Clone, Copy)]
+struct Track {
+    block: u64,
+    timestamp: u64,
+    start: (u128, u128),
+    end: (u128, u128),
+}
+
+/**
+ * @gist quotes the --pool route after every block with a Sync event of its
+ * pools, reconnecting and backfilling whenever the WebSocket drops
+ * @param url -- the ws:// or wss:// endpoint
+ * @param start_a -- the TOKEN/ETH pair address
+ * @param end_a -- the ETH/USDT pair address
+ * @param on_block -- gets the quotes of one block, or of the blocks missed
+ * while disconnected, oldest first
+**/
+pub async fn watch<F: AsyncFnMut(Vec<Quote>)>(url: &str, start_a: Address, end_a: Address, mut on_block: F) -> eyre::Result<()> {
+    let mut track: Option<Track> = None;
+    let mut retry = FIRST_RETRY;
+    loop {
+        match follow(url, &start_a, &end_a, &mut track, &mut retry, &mut on_block).await {
+            Ok(()) => eprintln!("the WebSocket stream of {url} ended, reconnecting in {}s", retry.as_secs()),
+            Err(e) => eprintln!("{e}, reconnecting in {}s", retry.as_secs()),
+        }
+        tokio::time::sleep(retry).await;
+        retry = (retry * 2).min(MAX_RETRY);
+    }
+}
+
+/**
+ * @gist one connection: subscribes, quotes the blocks missed since the last
+ * one and then every new block until the stream ends
+ * @param track -- the last block quoted, None before the first connection
+ * @param retry -- set back to FIRST_RETRY once subscribed
+**/
+async fn follow<F: AsyncFnMut(Vec<Quote>)>(url: &str, start_a: &Address, end_a: &Address, track: &mut Option<Track>, retry: &mut Duration, on_block: &mut F) -> eyre::Result<()> {
+    // The reconnects of ethers would resubscribe without the missed blocks
+    let provider = Arc::new(Provider::<Ws>::connect_with_reconnects(url, 0).await.map_err(|e| eyre!("could not connect to {url}: {e}"))?);
+    let filter = Filter::new()
+        .address(ValueOrArray::Array(vec![*start_a, *end_a]))
+        .topic0(SyncFilter::signature());
+    // Subscribed before the missed blocks are read, so none falls in between
+    let mut logs = provider.subscribe_logs(&filter).await.map_err(|e| eyre!("could not subscribe to {url}: {e}"))?;
+    *retry = FIRST_RETRY;
+
+    let head = provider.get_block_number().await?.as_u64();
+    let head_time = timestamp(provider.as_ref(), head).await?;
+    match track {
+        Some(last) if head > last.block => {
+            let quotes = catch_up(provider.clone(), start_a, end_a, last, head, head_time).await?;
+            eprintln!("backfilled {} blocks with Sync events up to block {head}", quotes.len());
+            if !quotes.is_empty() {
+                on_block(quotes).await;
+            }
+        }
+        Some(_) => {}
+        None => {
+            let start = backfill::reserves_at(provider.clone(), start_a, head).await?;
+            let end = backfill::reserves_at(provider.clone(), end_a, head).await?;
+            let first = Track { block: head, timestamp: head_time, start, end };
+            on_block(vec![quote(&first)]).await;
+            *track = Some(first);
+        }
+    }
+    let last = track.as_mut().expect("the track is set once connected");
+
+    // The block whose logs are coming in, applied on top of the last one
+    let mut pending: Option<Track> = None;
+    loop {
+        let next = match pending {
+            None => logs.next().await,
+            Some(block) => match tokio::time::timeout(SETTLE, logs.next()).await {
+                Ok(next) => next,
+                Err(_) => {
+                    *last = settle(provider.as_ref(), block).await?;
+                    on_block(vec![quote(last)]).await;
+                    pending = None;
+                    continue;
+                }
+            },
+        };
+        let Some(log) = next else {
+            return Ok(());
+        };
+        let Some(block) = log.block_number.map(|b| b.as_u64()).filter(|b| *b > last.block && log.removed != Some(true)) else {
+            continue;
+        };
+        let Ok(sync) = parse_log::<SyncFilter>(log.clone()) else {
+            continue;
+        };
+        if let Some(done) = pending.filter(|p| p.block < block) {
+            *last = settle(provider.as_ref(), done).await?;
+            on_block(vec![quote(last)]).await;
+            pending = None;
+        }
+        let block = pending.get_or_insert(Track { block, ..*last });
+        apply(block, &log, start_a, (sync.reserve_0, sync.reserve_1));
+    }
+}
+
+/**
+ * @gist quotes the blocks with Sync events after the last one quoted, the
+ * times of those between the two ends are interpolated
+ * @param last -- moved to the head
+**/
+async fn catch_up<M: Middleware + 'static>(provider: Arc<M>, start_a: &Address, end_a: &Address, last: &mut Track, head: u64, head_time: u64) -> eyre::Result<Vec<Quote>> {
+    // Beyond MAX_BLOCKS the reserves are read again at the start of the gap
+    let from = last.block.max(head.saturating_sub(backfill::MAX_BLOCKS));
+    if from > last.block {
+        last.start = backfill::reserves_at(provider.clone(), start_a, from).await?;
+        last.end = backfill::reserves_at(provider.clone(), end_a, from).await?;
+    }
+    let (from_block, from_time) = (last.block, last.timestamp);
+
+    let mut quotes: Vec<Quote> = Vec::new();
+    for event in backfill::sync_events(provider, start_a, end_a, from, head).await? {
+        match event.is_start {
+            true => last.start = event.reserves,
+            false => last.end = event.reserves,
+        }
+        last.block = event.block;
+        last.timestamp = from_time + head_time.saturating_sub(from_time) * (event.block - from_block) / (head - from_block);
+        match quotes.last_mut() {
+            Some(previous) if previous.block == Some(event.block) => *previous = quote(last),
+            _ => quotes.push(quote(last)),
+        }
+    }
+    last.block = head;
+    last.timestamp = head_time;
+    Ok(quotes)
+}
+
+/**
+ * @gist applies a Sync log to the pool it came from
+**/
+fn apply(track: &mut Track, log: &Log, start_a: &Address, reserves: (u128, u128)) {
+    match log.address == *start_a {
+        true => track.start = reserves,
+        false => track.end = reserves,
+    }
+}
+
+/**
+ * @gist stamps a block whose logs are all in with its time
+**/
+async fn settle<M: Middleware>(provider: &M, mut block: Track) -> eyre::Result<Track> {
+    block.timestamp = timestamp(provider, block.block).await?;
+    Ok(block)
+}
+
+async fn timestamp<M: Middleware>(provider: &M, block: u64) -> eyre::Result<u64> {
+    let header = provider.get_block(block).await
+        .map_err(|e| eyre!("could not read block {block}: {e}"))?
+        .ok_or_else(|| eyre!("the node has no block {block}"))?;
+    Ok(header.timestamp.as_u64())
+}
+
+/**
+ * @gist the quote of the route at a block
+**/
+fn quote(track: &Track) -> Quote {
+    let mut quote = Quote::new("UNI V2", "TOKEN/USDT", route_from_reserves(track.start, track.end).mid_price());
+    quote.block = Some(track.block);
+    quote.timestamp = Some(track.timestamp);
+    quote
+}
@@ -0,0 +1,213 @@
+/*!
+ *
+ * Optional Uniswap subgraph lookups. When a subgraph is configured, quotes of
+ * plain pools are enriched with the 24h volume and fees and `pools discover`
+ * finds pairs with one query per token. Everything here is best effort: if
+ * the subgraph is down the quotes simply come without volume and discovery
+ * falls back to the factories.
+ *
+ * [subgraph]
+ * url = "https://gateway.thegraph.com/api/<key>/subgraphs/id/<id>"
+ * kind = "v2"
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::types::Address;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::amm::V2_FEE;
+use crate::clock::unix_now;
+use crate::source::{Asset, PriceSource, Quote};
+use crate::window::DAY;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    /// Uniswap V2 schema: pairs and pairHourDatas
+    #[default]
+    V2,
+    /// Uniswap V3 schema: pools and poolHourDatas
+    V3,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SubgraphConfig {
+    /// GraphQL endpoint
+    pub url: String,
+    #[serde(default)]
+    pub kind: Kind,
+    /// Seconds a pool's volume is reused before it is queried again
+    #[serde(default = "default_ttl")]
+    pub ttl: u64,
+}
+
+fn default_ttl() -> u64 {
+    300
+}
+
+/// Trading activity of a pool over the last 24 hours, in USD
+#[derive(Debug, Clone, Copy)]
+pub struct Volume {
+    pub volume: f64,
+    pub fees: f64,
+}
+
+pub struct Subgraph {
+    client: Client,
+    config: SubgraphConfig,
+    cache: Mutex<HashMap<Address, (u64, Option<Volume>)>>,
+}
+
+impl Subgraph {
+    /**
+     * @gist creates the client
+     * @param client -- HTTP client used for the queries
+     * @param config -- endpoint and schema
+    **/
+    pub fn new(client: Client, config: SubgraphConfig) -> Self {
+        Self { client, config, cache: Mutex::new(HashMap::new()) }
+    }
+
+    /**
+     * @gist runs a GraphQL query
+     * @output the data object, or the first error the subgraph returned
+    **/
+    async fn query(&self, query: &str, variables: Value) -> eyre::Result<Value> {
+        let body = json!({ "query": query, "variables": variables });
+        let mut res: Value = self.client.post(&self.config.url).json(&body).send().await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| eyre::eyre!("subgraph unavailable: {}", e.without_url()))?
+            .json().await?;
+
+        if let Some(error) = res["errors"].get(0) {
+            eyre::bail!("subgraph error: {}", error["message"].as_str().unwrap_or("unknown"));
+        }
+        Ok(res["data"].take())
+    }
+
+    /**
+     * @gist 24h volume and fees of a pool, answers are cached for the
+     * configured ttl
+     * @param pool -- the pair or pool address
+     * @output None if the subgraph does not know the pool
+    **/
+    pub async fn volume(&self, pool: &Address) -> eyre::Result<Option<Volume>> {
+        let now = unix_now();
+        if let Some((at, volume)) = self.cache.lock().unwrap().get(pool) {
+            if now < at + self.config.ttl {
+                return Ok(*volume);
+            }
+        }
+
+        let id = format!("{pool:?}");
+        let since = now.saturating_sub(DAY);
+        let volume = match self.config.kind {
+            Kind::V2 => {
+                let data = self.query(
+                    "query($pair: String!, $since: Int!) {
+                        pairHourDatas(first: 24, orderBy: hourStartUnix, orderDirection: desc,
+                                      where: { pair: $pair, hourStartUnix_gte: $since }) { hourlyVolumeUSD }
+                    }",
+                    json!({ "pair": id, "since": since }),
+                ).await?;
+                let hours = data["pairHourDatas"].as_array().cloned().unwrap_or_default();
+                let volume = hours.iter().map(|h| decimal(&h["hourlyVolumeUSD"])).sum::<f64>();
+                (!hours.is_empty()).then_some(Volume { volume, fees: volume * V2_FEE })
+            }
+            Kind::V3 => {
+                let data = self.query(
+                    "query($pool: String!, $since: Int!) {
+                        poolHourDatas(first: 24, orderBy: periodStartUnix, orderDirection: desc,
+                                      where: { pool: $pool, periodStartUnix_gte: $since }) { volumeUSD feesUSD }
+                    }",
+                    json!({ "pool": id, "since": since }),
+                ).await?;
+                let hours = data["poolHourDatas"].as_array().cloned().unwrap_or_default();
+                (!hours.is_empty()).then(|| Volume {
+                    volume: hours.iter().map(|h| decimal(&h["volumeUSD"])).sum(),
+                    fees: hours.iter().map(|h| decimal(&h["feesUSD"])).sum(),
+                })
+            }
+        };
+
+        // Unknown pools are cached as well so they are not asked every cycle
+        self.cache.lock().unwrap().insert(*pool, (now, volume));
+        Ok(volume)
+    }
+
+    /**
+     * @gist the V2 pair of two tokens, as the subgraph sees it
+     * @param token -- the token
+     * @param weth -- the WETH address
+     * @output the pair and its WETH reserve in ETH, None if there is no pair
+    **/
+    pub async fn pair(&self, token: &Address, weth: &Address) -> eyre::Result<Option<(Address, f64)>> {
+        if self.config.kind != Kind::V2 {
+            eyre::bail!("pair lookups need a v2 subgraph");
+        }
+        let (token0, token1) = if token < weth { (token, weth) } else { (weth, token) };
+        let data = self.query(
+            "query($token0: String!, $token1: String!) {
+                pairs(first: 1, orderBy: reserveUSD, orderDirection: desc,
+                      where: { token0: $token0, token1: $token1 }) { id reserveETH }
+            }",
+            json!({ "token0": format!("{token0:?}"), "token1": format!("{token1:?}") }),
+        ).await?;
+
+        let Some(pair) = data["pairs"].get(0) else {
+            return Ok(None);
+        };
+        let address = pair["id"].as_str().unwrap_or_default().parse()
+            .map_err(|e| eyre::eyre!("subgraph returned an invalid pair id: {e}"))?;
+        // reserveETH is the value of both sides, the WETH side is half of it
+        Ok(Some((address, decimal(&pair["reserveETH"]) / 2.0)))
+    }
+}
+
+/// BigDecimal values come as strings
+fn decimal(value: &Value) -> f64 {
+    value.as_str().and_then(|s| s.parse().ok()).or(value.as_f64()).unwrap_or_default()
+}
+
+/// Adds the subgraph's 24h volume and fees to the quotes of a pool source
+pub struct WithVolume {
+    inner: Arc<dyn PriceSource>,
+    subgraph: Arc<Subgraph>,
+}
+
+impl WithVolume {
+    /**
+     * @gist wraps a source whose asset addresses are pools
+    **/
+    pub fn new(inner: Arc<dyn PriceSource>, subgraph: Arc<Subgraph>) -> Self {
+        Self { inner, subgraph }
+    }
+}
+
+#[async_trait]
+impl PriceSource for WithVolume {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let mut quote = self.inner.quote(asset).await?;
+        // A failing subgraph only costs the volume, never the quote
+        match self.subgraph.volume(&asset.address).await {
+            Ok(Some(volume)) => {
+                quote.volume_24h = Some(volume.volume);
+                quote.fees_24h = Some(volume.fees);
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("no volume for {}: {e}", asset.name),
+        }
+        Ok(quote)
+    }
+}
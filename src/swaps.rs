@@ -0,0 +1,379 @@
+/*!
+ *
+ * Trades of the watched Uniswap V2 pools, decoded from their Swap events.
+ * Every quote of a pool source reads the swaps since the previous one. It
+ * can carry their buy and sell volume and the volume-weighted average
+ * price of the last `--vwap SECONDS`:
+ *
+ * VWAP = sum(price * amount) / sum(amount)
+ *
+ * which is what the trades in that window actually paid on average, a
+ * better benchmark for an execution than the spot price of a single block.
+ * The ETH side of every swap is valued with the ETH/USDT price of the poll
+ * that found it, and swap times are estimated from the block numbers.
+ *
+ * The Mint and Burn events of liquidity added to or removed from the pools
+ * are read the same way, for the alerts on large LP withdrawals, and the
+ * swaps of every block can be searched for sandwiches.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{
+    contract::LogMeta,
+    providers::Middleware,
+    types::{Address, ValueOrArray, H256, U256},
+    utils::format_units,
+};
+use serde::{Deserialize, Serialize};
+
+use std::collections::{HashMap, VecDeque};
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex};
+
+use crate::backfill;
+use crate::sandwich;
+use crate::source::{Asset, Head, PriceSource, Quote};
+use crate::uniswap_v2::IUniswapV2Pair;
+
+/// Seconds between two mainnet blocks, used to date the swaps
+pub const BLOCK_TIME: u64 = 12;
+
+/// Block range of a single eth_getLogs request
+const CHUNK: u64 = 2000;
+
+/// One swap of a TOKEN/ETH pool
+#[derive(Debug, Clone, Serialize)]
+pub struct Trade {
+    pub block: u64,
+    pub tx: H256,
+    pub log_index: u64,
+    /// Recipient of the swap's output
+    pub to: Address,
+    /// Estimated unix time of the block
+    pub time: u64,
+    /// TOKEN was bought from the pool, false for a sell into it
+    pub buy: bool,
+    /// TOKEN amount traded
+    pub token: f64,
+    /// ETH amount traded
+    pub eth: f64,
+    /// USDT value of the ETH amount
+    pub usd: f64,
+}
+
+impl Trade {
+    /**
+     * @gist price the trade was executed at, in USDT per TOKEN
+    **/
+    pub fn price(&self) -> f64 {
+        self.usd / self.token
+    }
+}
+
+/// Buy and sell volume of the swaps between two polls
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Volume {
+    pub buys: u64,
+    pub sells: u64,
+    /// TOKEN bought from the pool
+    pub buy_token: f64,
+    /// TOKEN sold into the pool
+    pub sell_token: f64,
+    pub buy_usd: f64,
+    pub sell_usd: f64,
+}
+
+impl Volume {
+    /**
+     * @gist sums up the volume of some trades
+    **/
+    pub fn of<'a>(trades: impl IntoIterator<Item = &'a Trade>) -> Self {
+        let mut volume = Volume::default();
+        for t in trades {
+            if t.buy {
+                volume.buys += 1;
+                volume.buy_token += t.token;
+                volume.buy_usd += t.usd;
+            } else {
+                volume.sells += 1;
+                volume.sell_token += t.token;
+                volume.sell_usd += t.usd;
+            }
+        }
+        volume
+    }
+
+    /**
+     * @gist adds the volume of another interval
+    **/
+    pub fn add(&mut self, other: &Volume) {
+        self.buys += other.buys;
+        self.sells += other.sells;
+        self.buy_token += other.buy_token;
+        self.sell_token += other.sell_token;
+        self.buy_usd += other.buy_usd;
+        self.sell_usd += other.sell_usd;
+    }
+}
+
+/**
+ * @gist reads the swaps of a pool in a range of blocks
+ * @param provider -- used for eth_getLogs
+ * @param pool -- the TOKEN/ETH pair address
+ * @param inverted -- WETH is token0 of the pool
+ * @param decimals -- decimals of TOKEN
+ * @param blocks -- the blocks to read, both ends included
+ * @param head -- block the end of the range was read at, to date the swaps
+ * @param eth_usd -- USDT price of ETH the swaps are valued with
+ * @output the trades, oldest first
+**/
+pub async fn trades<M: Middleware + 'static>(provider: Arc<M>, pool: &Address, inverted: bool, decimals: u8, blocks: RangeInclusive<u64>, head: Head, eth_usd: f64) -> eyre::Result<Vec<Trade>> {
+    let pair = IUniswapV2Pair::new(*pool, provider);
+    let scale = |v: U256, decimals: u8| -> eyre::Result<f64> { Ok(format_units(v, decimals as u32)?.parse()?) };
+
+    let mut trades = Vec::new();
+    let (mut lo, to) = blocks.into_inner();
+    while lo <= to {
+        let hi = (lo + CHUNK - 1).min(to);
+        // The generated filter matches the event of every contract, not just this one
+        let filter = pair.swap_filter().address(ValueOrArray::Value(*pool)).from_block(lo).to_block(hi);
+        for (swap, meta) in filter.query_with_meta().await? {
+            let (token_in, eth_in, token_out, eth_out) = if inverted {
+                (swap.amount_1_in, swap.amount_0_in, swap.amount_1_out, swap.amount_0_out)
+            } else {
+                (swap.amount_0_in, swap.amount_1_in, swap.amount_0_out, swap.amount_1_out)
+            };
+            let token = scale(token_out, decimals)? - scale(token_in, decimals)?;
+            let eth = scale(eth_in, 18)? - scale(eth_out, 18)?;
+            // A swap that returns what it took, e.g. a flash loan, moves no price
+            if token == 0.0 {
+                continue;
+            }
+
+            let block = meta.block_number.as_u64();
+            trades.push(Trade {
+                block,
+                tx: meta.transaction_hash,
+                log_index: meta.log_index.as_u64(),
+                to: swap.to,
+                time: head.timestamp.saturating_sub(head.number.saturating_sub(block) * BLOCK_TIME),
+                buy: token > 0.0,
+                token: token.abs(),
+                eth: eth.abs(),
+                usd: eth.abs() * eth_usd,
+            });
+        }
+        lo = hi + 1;
+    }
+    Ok(trades)
+}
+
+/// Liquidity added to or removed from a TOKEN/ETH pool
+#[derive(Debug, Clone, Serialize)]
+pub struct LiquidityEvent {
+    pub block: u64,
+    pub tx: H256,
+    /// A Mint, false for a Burn
+    pub added: bool,
+    /// TOKEN amount deposited or withdrawn
+    pub token: f64,
+    /// ETH amount deposited or withdrawn
+    pub eth: f64,
+    /// USDT value of both sides, twice the ETH side
+    pub usd: f64,
+}
+
+/**
+ * @gist reads the Mint and Burn events of a pool in a range of blocks
+ * @param provider -- used for eth_getLogs
+ * @param pool -- the TOKEN/ETH pair address
+ * @param inverted -- WETH is token0 of the pool
+ * @param decimals -- decimals of TOKEN
+ * @param blocks -- the blocks to read, both ends included
+ * @param eth_usd -- USDT price of ETH the events are valued with
+ * @output the events, oldest first
+**/
+pub async fn liquidity_events<M: Middleware + 'static>(provider: Arc<M>, pool: &Address, inverted: bool, decimals: u8, blocks: RangeInclusive<u64>, eth_usd: f64) -> eyre::Result<Vec<LiquidityEvent>> {
+    let pair = IUniswapV2Pair::new(*pool, provider);
+    let event = |added: bool, amount0: U256, amount1: U256, meta: LogMeta| -> eyre::Result<(u64, u64, LiquidityEvent)> {
+        let (token, eth) = if inverted { (amount1, amount0) } else { (amount0, amount1) };
+        let eth: f64 = format_units(eth, 18)?.parse()?;
+        Ok((meta.block_number.as_u64(), meta.log_index.as_u64(), LiquidityEvent {
+            block: meta.block_number.as_u64(),
+            tx: meta.transaction_hash,
+            added,
+            token: format_units(token, decimals as u32)?.parse()?,
+            eth,
+            usd: 2.0 * eth * eth_usd,
+        }))
+    };
+
+    let mut events = Vec::new();
+    let (mut lo, to) = blocks.into_inner();
+    while lo <= to {
+        let hi = (lo + CHUNK - 1).min(to);
+        let address = ValueOrArray::Value(*pool);
+        for (mint, meta) in pair.mint_filter().address(address.clone()).from_block(lo).to_block(hi).query_with_meta().await? {
+            events.push(event(true, mint.amount_0, mint.amount_1, meta)?);
+        }
+        for (burn, meta) in pair.burn_filter().address(address).from_block(lo).to_block(hi).query_with_meta().await? {
+            events.push(event(false, burn.amount_0, burn.amount_1, meta)?);
+        }
+        lo = hi + 1;
+    }
+    events.sort_by_key(|(block, index, _)| (*block, *index));
+    Ok(events.into_iter().map(|(_, _, e)| e).collect())
+}
+
+/**
+ * @gist volume-weighted average price of some trades, None without any
+**/
+pub fn vwap<'a>(trades: impl IntoIterator<Item = &'a Trade>) -> Option<f64> {
+    let (usd, token) = trades.into_iter().fold((0.0, 0.0), |(usd, token), t| (usd + t.usd, token + t.token));
+    (token > 0.0).then(|| usd / token)
+}
+
+/// The trades of a pool in the window
+#[derive(Debug, Default)]
+struct Tape {
+    /// First block that was not read yet
+    next: Option<u64>,
+    trades: VecDeque<Trade>,
+}
+
+/// Adds the volume and VWAP of the recent swaps to the quotes of a Uniswap
+/// V2 source
+pub struct WithSwaps<M> {
+    inner: Arc<dyn PriceSource>,
+    provider: Arc<M>,
+    inverted: bool,
+    decimals: u8,
+    /// Seconds of trades the VWAP is computed over, 0 for none
+    window: u64,
+    volume: bool,
+    liquidity: bool,
+    sandwiches: bool,
+    tapes: Mutex<HashMap<Address, Tape>>,
+}
+
+impl<M: Middleware + 'static> WithSwaps<M> {
+    /**
+     * @gist wraps a source whose asset addresses are TOKEN/ETH pools
+     * @param inner -- the source, its quotes need the block and raw values
+     * @param provider -- used for eth_getLogs
+     * @param inverted -- WETH is token0 of the pools
+     * @param decimals -- decimals of TOKEN
+    **/
+    pub fn new(inner: Arc<dyn PriceSource>, provider: Arc<M>, inverted: bool, decimals: u8) -> Self {
+        Self { inner, provider, inverted, decimals, window: 0, volume: false, liquidity: false, sandwiches: false, tapes: Mutex::new(HashMap::new()) }
+    }
+
+    /**
+     * @gist adds the VWAP of the trades of the last `window` seconds
+    **/
+    pub fn with_vwap(mut self, window: u64) -> Self {
+        self.window = window;
+        self
+    }
+
+    /**
+     * @gist adds the buy and sell volume since the previous quote, the
+     * first quote of a pool has none
+    **/
+    pub fn with_volume(mut self) -> Self {
+        self.volume = true;
+        self
+    }
+
+    /**
+     * @gist also reads the Mint and Burn events since the previous quote
+    **/
+    pub fn with_liquidity_events(mut self) -> Self {
+        self.liquidity = true;
+        self
+    }
+
+    /**
+     * @gist also searches the swaps since the previous quote for sandwiches
+    **/
+    pub fn with_sandwiches(mut self) -> Self {
+        self.sandwiches = true;
+        self
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> PriceSource for WithSwaps<M> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let mut quote = self.inner.quote(asset).await?;
+        let (Some(number), Some(timestamp)) = (quote.block, quote.timestamp) else {
+            return Ok(quote);
+        };
+        let Some(eth_usd) = quote.raw.as_ref().and_then(|raw| raw.ratios.get(1).copied()) else {
+            return Ok(quote);
+        };
+
+        // The first poll reads the whole window, later ones what is new
+        let oldest = number.saturating_sub(backfill::MAX_BLOCKS);
+        let next = self.tapes.lock().unwrap().get(&asset.address).and_then(|t| t.next);
+        let from = match next {
+            Some(next) => next.max(oldest),
+            None => number.saturating_sub(self.window / BLOCK_TIME).max(oldest),
+        };
+        let head = Head { number, timestamp };
+
+        // A failing log query only costs the swap values, never the quote
+        let new = if from <= number {
+            match trades(self.provider.clone(), &asset.address, self.inverted, self.decimals, from..=number, head, eth_usd).await {
+                Ok(new) => new,
+                Err(e) => {
+                    eprintln!("no swaps for {}: {e}", asset.name);
+                    return Ok(quote);
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        let changes = if self.liquidity && next.is_some() && from <= number {
+            match liquidity_events(self.provider.clone(), &asset.address, self.inverted, self.decimals, from..=number, eth_usd).await {
+                Ok(changes) => changes,
+                Err(e) => {
+                    eprintln!("no liquidity events for {}: {e}", asset.name);
+                    return Ok(quote);
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        // The first read may reach back a whole window, that is no interval
+        if next.is_some() {
+            if self.volume {
+                quote.swaps = Some(Volume::of(&new));
+            }
+            if self.sandwiches {
+                quote.sandwiches = sandwich::detect(&new);
+            }
+            quote.trades = new.clone();
+            quote.liquidity_events = changes;
+        }
+
+        let mut tapes = self.tapes.lock().unwrap();
+        let tape = tapes.entry(asset.address).or_default();
+        tape.next = Some(tape.next.unwrap_or_default().max(number + 1));
+        if self.window > 0 {
+            tape.trades.extend(new);
+            let cutoff = timestamp.saturating_sub(self.window);
+            while tape.trades.front().is_some_and(|t| t.time < cutoff) {
+                tape.trades.pop_front();
+            }
+            quote.vwap = vwap(&tape.trades);
+        }
+        Ok(quote)
+    }
+}
@@ -0,0 +1,88 @@
+/*!
+ *
+ * Minimal sd_notify(3) support. When the poller is started by systemd with
+ * `Type=notify` the service manager hands us a datagram socket through the
+ * NOTIFY_SOCKET environment variable. We report READY=1 once the first quote
+ * has been produced and WATCHDOG=1 after every successful poll, so a poller
+ * stuck on a dead RPC stops pinging and gets restarted.
+ *
+ * Outside of systemd every function in here is a no-op.
+ *
+**/
+
+use std::env;
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::time::Duration;
+
+/**
+ * @gist sends a raw state string (e.g. "READY=1") to the service manager
+ * @param state -- newline separated list of assignments, see sd_notify(3)
+ * @output Ok(true) if the message was delivered, Ok(false) if not running
+ * under systemd
+**/
+pub fn notify(state: &str) -> io::Result<bool> {
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+        return Ok(false);
+    };
+    let path = path.to_string_lossy().into_owned();
+    let socket = UnixDatagram::unbound()?;
+
+    match path.strip_prefix('@') {
+        Some(name) => send_abstract(&socket, name, state)?,
+        None => {
+            socket.send_to(state.as_bytes(), &path)?;
+        }
+    }
+
+    Ok(true)
+}
+
+#[cfg(target_os = "linux")]
+fn send_abstract(socket: &UnixDatagram, name: &str, state: &str) -> io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::SocketAddr;
+
+    let addr = SocketAddr::from_abstract_name(name.as_bytes())?;
+    socket.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn send_abstract(_socket: &UnixDatagram, _name: &str, _state: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "abstract notify sockets are only supported on linux",
+    ))
+}
+
+/**
+ * @gist tells systemd that startup is finished
+**/
+pub fn ready() -> io::Result<bool> {
+    notify("READY=1")
+}
+
+/**
+ * @gist keepalive ping, should be sent after every successful poll
+**/
+pub fn watchdog() -> io::Result<bool> {
+    notify("WATCHDOG=1")
+}
+
+/**
+ * @gist returns the watchdog timeout configured with WatchdogSec= if it is
+ * meant for this process
+ * @output None when the watchdog is disabled
+**/
+pub fn watchdog_timeout() -> Option<Duration> {
+    let usec: u64 = env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+
+    if let Ok(pid) = env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+
+    Some(Duration::from_micros(usec))
+}
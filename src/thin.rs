@@ -0,0 +1,117 @@
+/*!
+ *
+ * A floor on the liquidity of the pools quoted. The reserve ratio of a pool
+ * holding a few thousand dollars moves with every trade and is easily
+ * pushed around, so its price says little. With `--min-liquidity-usd` a
+ * quote whose pool holds less than that fails like a stale one, with exit
+ * code 6, and is priced off-chain when there is a [fallback]:
+ *
+ * uni-price-polling --config watch.toml --interval 60 --min-liquidity-usd 250000
+ *
+ * With `--flag-thin` the quote is kept and marked thin instead. Quotes
+ * from sources that don't know their liquidity pass as they are.
+ *
+**/
+
+use async_trait::async_trait;
+
+use std::sync::Arc;
+
+use crate::exit::{Failure, Kind};
+use crate::source::{Asset, PriceSource, Quote};
+
+/// Refuses or flags the quotes of thin pools
+pub struct MinLiquidity {
+    inner: Arc<dyn PriceSource>,
+    /// USD the pool has to hold
+    min_usd: f64,
+    /// Mark thin quotes instead of failing them
+    flag: bool,
+}
+
+impl MinLiquidity {
+    /**
+     * @gist wraps a source
+     * @param inner -- the source, its liquidity is in USD
+     * @param min_usd -- the floor
+    **/
+    pub fn new(inner: Arc<dyn PriceSource>, min_usd: f64) -> Self {
+        Self { inner, min_usd, flag: false }
+    }
+
+    /**
+     * @gist keeps the quotes of thin pools, marked thin
+    **/
+    pub fn with_flag(mut self) -> Self {
+        self.flag = true;
+        self
+    }
+}
+
+#[async_trait]
+impl PriceSource for MinLiquidity {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let mut quote = self.inner.quote(asset).await?;
+        match quote.liquidity {
+            Some(liquidity) if liquidity < self.min_usd => {
+                if !self.flag {
+                    return Err(Failure::new(Kind::Thin, format!("{} holds ${liquidity:.0}, less than --min-liquidity-usd {}", asset.name, self.min_usd)).into());
+                }
+                quote.thin = true;
+                Ok(quote)
+            }
+            _ => Ok(quote),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Pool(Option<f64>);
+
+    #[async_trait]
+    impl PriceSource for Pool {
+        fn name(&self) -> &str {
+            "POOL"
+        }
+
+        async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+            let mut quote = Quote::new(self.name(), &asset.name, 10.0);
+            quote.liquidity = self.0;
+            Ok(quote)
+        }
+    }
+
+    fn asset() -> Asset {
+        Asset { name: "LINK".to_string(), address: Default::default() }
+    }
+
+    #[tokio::test]
+    async fn refuses_thin_pools() {
+        let e = MinLiquidity::new(Arc::new(Pool(Some(90_000.0))), 250_000.0).quote(&asset()).await.unwrap_err();
+        assert_eq!(e.downcast_ref::<Failure>().map(|f| f.kind), Some(Kind::Thin));
+        assert_eq!(Kind::Thin.code(), 6);
+        assert!(e.to_string().contains("LINK holds $90000"), "{e}");
+    }
+
+    #[tokio::test]
+    async fn passes_deep_and_unknown_pools() {
+        let deep = MinLiquidity::new(Arc::new(Pool(Some(250_000.0))), 250_000.0).quote(&asset()).await.unwrap();
+        assert!(!deep.thin);
+        let unknown = MinLiquidity::new(Arc::new(Pool(None)), 250_000.0).quote(&asset()).await.unwrap();
+        assert!(!unknown.thin);
+    }
+
+    #[tokio::test]
+    async fn flags_thin_pools() {
+        let quote = MinLiquidity::new(Arc::new(Pool(Some(90_000.0))), 250_000.0).with_flag().quote(&asset()).await.unwrap();
+        assert!(quote.thin);
+        assert_eq!(quote.price, 10.0);
+    }
+}
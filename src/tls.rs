@@ -0,0 +1,64 @@
+/*!
+ *
+ * HTTPS for the `--dashboard` server, so a small deployment needs no
+ * reverse proxy in front of it. With a certificate and its private key in
+ * the [server] table the server speaks TLS only:
+ *
+ * [server]
+ * tls_cert = "/etc/uni-price-polling/fullchain.pem"
+ * tls_key = "/etc/uni-price-polling/privkey.pem"
+ *
+ * Both are PEM files, the certificate file may hold the whole chain and
+ * the key may be PKCS#8, PKCS#1 (RSA) or SEC1 (EC).
+ *
+**/
+
+use rustls_pemfile::Item;
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+/**
+ * @gist reads a PEM file
+ * @param path -- the file
+ * @output every item in it
+**/
+fn read_pem(path: &Path) -> eyre::Result<Vec<Item>> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| eyre::eyre!("could not read {}: {e}", path.display()))?;
+    rustls_pemfile::read_all(&mut BufReader::new(file))
+        .map_err(|e| eyre::eyre!("invalid PEM file {}: {e}", path.display()))
+}
+
+/**
+ * @gist builds the acceptor of the server
+ * @param cert -- PEM certificate chain, the server's own certificate first
+ * @param key -- PEM private key of the certificate
+**/
+pub fn acceptor(cert: &Path, key: &Path) -> eyre::Result<TlsAcceptor> {
+    let chain: Vec<Certificate> = read_pem(cert)?.into_iter()
+        .filter_map(|item| match item {
+            Item::X509Certificate(der) => Some(Certificate(der)),
+            _ => None,
+        })
+        .collect();
+    if chain.is_empty() {
+        eyre::bail!("{} holds no certificate", cert.display());
+    }
+    let key = read_pem(key)?.into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(der) | Item::RSAKey(der) | Item::ECKey(der) => Some(PrivateKey(der)),
+            _ => None,
+        })
+        .ok_or_else(|| eyre::eyre!("{} holds no private key", key.display()))?;
+
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .map_err(|e| eyre::eyre!("invalid TLS certificate or key: {e}"))?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
@@ -0,0 +1,291 @@
+/*!
+ *
+ * OpenTelemetry traces of the poll cycles, exported as OTLP/HTTP JSON to a
+ * collector, Jaeger or Tempo with `--otlp http://localhost:4318`. Every
+ * cycle is one trace:
+ *
+ * poll cycle
+ *   quote                 one per feed, with the asset and source
+ *     reserve fetch       the Uniswap V2 reserves
+ *       rpc eth_call      every RPC request
+ *     math
+ *   sink write            one per sink and quote, in the sink's worker
+ *
+ * Spans are buffered and sent every few seconds. Without --otlp nothing is
+ * recorded.
+ *
+**/
+
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Service name of the exported spans
+const SERVICE: &str = "uni-price-polling";
+
+/// Spans kept while the collector is unreachable, newer ones are dropped
+const MAX_BUFFERED: usize = 10_000;
+
+/// Time between two exports
+const EXPORT_EVERY: Duration = Duration::from_secs(5);
+
+/// SPAN_KIND_INTERNAL and SPAN_KIND_CLIENT of the OTLP spec
+const KIND_INTERNAL: u8 = 1;
+const KIND_CLIENT: u8 = 3;
+
+static TRACER: OnceLock<Tracer> = OnceLock::new();
+
+tokio::task_local! {
+    static CURRENT: SpanContext;
+}
+
+/// Identifies a span, children are created below it
+#[derive(Debug, Clone, Copy)]
+pub struct SpanContext {
+    pub trace_id: u128,
+    pub span_id: u64,
+}
+
+#[derive(Debug)]
+struct SpanData {
+    context: SpanContext,
+    parent: Option<u64>,
+    name: String,
+    kind: u8,
+    start: u64,
+    end: u64,
+    attributes: Vec<(String, String)>,
+    error: Option<String>,
+}
+
+struct Tracer {
+    spans: Mutex<Vec<SpanData>>,
+    dropped: AtomicU64,
+}
+
+/**
+ * @gist starts recording spans and sending them to a collector, only the
+ * first call has an effect
+ * @param client -- used for the exports
+ * @param endpoint -- OTLP/HTTP endpoint, e.g. http://localhost:4318
+**/
+pub fn init(client: Client, endpoint: &str) {
+    if TRACER.set(Tracer { spans: Mutex::new(Vec::new()), dropped: AtomicU64::new(0) }).is_err() {
+        return;
+    }
+    let url = if endpoint.ends_with("/v1/traces") {
+        endpoint.to_string()
+    } else {
+        format!("{}/v1/traces", endpoint.trim_end_matches('/'))
+    };
+
+    tokio::spawn(async move {
+        let mut failing = false;
+        let mut ticker = tokio::time::interval(EXPORT_EVERY);
+        loop {
+            ticker.tick().await;
+            let Some(tracer) = TRACER.get() else {
+                return;
+            };
+            let spans = std::mem::take(&mut *tracer.spans.lock().unwrap());
+            let dropped = tracer.dropped.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                eprintln!("{dropped} spans dropped, the trace export is falling behind");
+            }
+            if spans.is_empty() {
+                continue;
+            }
+
+            let result = client.post(&url).json(&export_body(&spans)).send().await
+                .and_then(|response| response.error_for_status());
+            // Report an outage once, not every few seconds
+            match result {
+                Ok(_) if failing => {
+                    eprintln!("trace export to {url} works again");
+                    failing = false;
+                }
+                Ok(_) => {}
+                Err(e) if !failing => {
+                    eprintln!("trace export to {url} failed: {e}");
+                    failing = true;
+                }
+                Err(_) => {}
+            }
+        }
+    });
+}
+
+/**
+ * @gist the span the current task runs in, if any
+**/
+pub fn current() -> Option<SpanContext> {
+    CURRENT.try_with(|c| *c).ok()
+}
+
+/**
+ * @gist runs a future below a span, e.g. one captured before a spawn
+ * @param parent -- the span, None runs the future as is
+ * @param fut -- the work
+**/
+pub async fn within<F: Future>(parent: Option<SpanContext>, fut: F) -> F::Output {
+    match parent {
+        Some(parent) => CURRENT.scope(parent, fut).await,
+        None => fut.await,
+    }
+}
+
+/// A span that is recorded when dropped
+pub struct Span(Option<SpanData>);
+
+impl Span {
+    /**
+     * @gist starts a span below the current one, or a new trace
+    **/
+    pub fn start(name: &str) -> Self {
+        Self::child_of(name, current())
+    }
+
+    /**
+     * @gist starts a span below the current one, outside of any span
+     * nothing is recorded
+    **/
+    pub fn child(name: &str) -> Self {
+        match current() {
+            Some(parent) => Self::child_of(name, Some(parent)),
+            None => Span(None),
+        }
+    }
+
+    /**
+     * @gist starts a span below the given one, or a new trace for None
+    **/
+    pub fn child_of(name: &str, parent: Option<SpanContext>) -> Self {
+        if TRACER.get().is_none() {
+            return Span(None);
+        }
+        let context = SpanContext {
+            trace_id: parent.map_or_else(|| (random() as u128) << 64 | random() as u128, |p| p.trace_id),
+            span_id: random(),
+        };
+        Span(Some(SpanData {
+            context,
+            parent: parent.map(|p| p.span_id),
+            name: name.to_string(),
+            kind: KIND_INTERNAL,
+            start: now_nanos(),
+            end: 0,
+            attributes: Vec::new(),
+            error: None,
+        }))
+    }
+
+    /**
+     * @gist marks the span as a request to a remote service
+    **/
+    pub fn client(mut self) -> Self {
+        if let Some(data) = &mut self.0 {
+            data.kind = KIND_CLIENT;
+        }
+        self
+    }
+
+    /**
+     * @gist adds an attribute
+    **/
+    pub fn set(&mut self, key: &str, value: impl ToString) {
+        if let Some(data) = &mut self.0 {
+            data.attributes.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    /**
+     * @gist marks the span as failed
+    **/
+    pub fn fail(&mut self, error: &impl std::fmt::Display) {
+        if let Some(data) = &mut self.0 {
+            data.error = Some(error.to_string());
+        }
+    }
+
+    /**
+     * @gist the id of the span, None when tracing is off
+    **/
+    pub fn context(&self) -> Option<SpanContext> {
+        self.0.as_ref().map(|data| data.context)
+    }
+
+    /**
+     * @gist runs a future below this span
+    **/
+    pub async fn run<F: Future>(&self, fut: F) -> F::Output {
+        within(self.context(), fut).await
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let (Some(mut data), Some(tracer)) = (self.0.take(), TRACER.get()) else {
+            return;
+        };
+        data.end = now_nanos();
+        let mut spans = tracer.spans.lock().unwrap();
+        if spans.len() < MAX_BUFFERED {
+            spans.push(data);
+        } else {
+            tracer.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+fn random() -> u64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+    hasher.write_u64(now_nanos());
+    hasher.finish()
+}
+
+fn now_nanos() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64)
+}
+
+/**
+ * @gist the OTLP/JSON ExportTraceServiceRequest of a batch of spans
+**/
+fn export_body(spans: &[SpanData]) -> Value {
+    let string = |key: &str, value: &str| json!({ "key": key, "value": { "stringValue": value } });
+    let spans: Vec<Value> = spans.iter()
+        .map(|s| {
+            let mut span = json!({
+                "traceId": format!("{:032x}", s.context.trace_id),
+                "spanId": format!("{:016x}", s.context.span_id),
+                "name": s.name,
+                "kind": s.kind,
+                "startTimeUnixNano": s.start.to_string(),
+                "endTimeUnixNano": s.end.to_string(),
+                "attributes": s.attributes.iter().map(|(k, v)| string(k, v)).collect::<Vec<_>>(),
+                "status": match &s.error {
+                    Some(message) => json!({ "code": 2, "message": message }),
+                    None => json!({ "code": 1 }),
+                },
+            });
+            if let Some(parent) = s.parent {
+                span["parentSpanId"] = json!(format!("{parent:016x}"));
+            }
+            span
+        })
+        .collect();
+
+    json!({
+        "resourceSpans": [{
+            "resource": { "attributes": [string("service.name", SERVICE)] },
+            "scopeSpans": [{ "scope": { "name": SERVICE }, "spans": spans }],
+        }]
+    })
+}
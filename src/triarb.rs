@@ -0,0 +1,133 @@
+/*!
+ *
+ * Triangular arbitrage across three V2 pools forming a cycle, A/B, B/C and
+ * C/A. Every interval the three pools are read at the same block and the
+ * round trip A -> B -> C -> A is priced both ways, fees included:
+ *
+ * uni-price-polling tri-arb --pool 0xa478c2975ab1ea89e8196811f51a7b7ade33eb11 \
+ *     --pool 0xae461ca67b15dc8dc81ce7615e0320da1a9ab8d5 --pool 0xb4e16d0168e52d35cacd2c6185b44281ec28c9dc
+ *
+ * A product of 1.002 means a trade around the cycle returns 0.2% more than
+ * it put in. Without a size it's the product of the marginal prices, with
+ * `--size` the amounts are run through the pools, which includes the price
+ * impact. Gas is not included.
+ *
+**/
+
+use ethers::{providers::Middleware, types::Address};
+use serde::Serialize;
+
+use std::sync::Arc;
+
+use crate::amm;
+use crate::source;
+use crate::uniswap_v2::{get_reserves_at, get_token, IUniswapV2Pair, Token};
+
+/// A pool of the cycle, oriented along it
+struct Hop {
+    pool: Address,
+    /// The cycle enters the pool through token0
+    zero_for_one: bool,
+}
+
+/// Three pools that form a cycle
+pub struct Cycle {
+    /// A, B and C
+    pub tokens: [Token; 3],
+    hops: [Hop; 3],
+    fee: f64,
+}
+
+/// One check of a cycle
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundTrip {
+    /// The tokens in trading order, e.g. WETH/DAI/USDC/WETH
+    pub path: String,
+    /// What one A returns after going around
+    pub product: f64,
+    /// Distance of the product from 1.0 in basis points
+    pub bps: f64,
+    pub block: u64,
+}
+
+impl Cycle {
+    /**
+     * @gist reads the tokens of three pools and orders them into a cycle
+     * @param provider -- any ethers middleware
+     * @param pools -- the pools, in any order
+     * @param fee -- the fee of every pool, 0.003 for Uniswap V2
+    **/
+    pub async fn new<M: Middleware + 'static>(provider: Arc<M>, pools: [Address; 3], fee: f64) -> eyre::Result<Self> {
+        let mut pairs = Vec::with_capacity(3);
+        for pool in pools {
+            let pair = IUniswapV2Pair::new(pool, provider.clone());
+            let token0 = pair.token_0().call().await.map_err(|e| eyre::eyre!("{pool:?} is not a V2 pair: {e}"))?;
+            let token1 = pair.token_1().call().await.map_err(|e| eyre::eyre!("{pool:?} is not a V2 pair: {e}"))?;
+            pairs.push((pool, token0, token1));
+        }
+
+        // A and B come from the first pool, in a cycle B is in exactly one
+        // of the others, which trades it for C
+        let (_, a, b) = pairs[0];
+        let (second, third) = match (pairs[1], pairs[2]) {
+            (p, q) if p.1 == b || p.2 == b => (p, q),
+            (p, q) if q.1 == b || q.2 == b => (q, p),
+            _ => eyre::bail!("the pools don't form a cycle A/B, B/C, C/A"),
+        };
+        let c = if second.1 == b { second.2 } else { second.1 };
+        if !((third.1 == c && third.2 == a) || (third.1 == a && third.2 == c)) || c == a {
+            eyre::bail!("the pools don't form a cycle A/B, B/C, C/A");
+        }
+
+        let hop = |(pool, token0, _): (Address, Address, Address), from: Address| Hop { pool, zero_for_one: token0 == from };
+        let tokens = [
+            get_token(provider.clone(), a).await?,
+            get_token(provider.clone(), b).await?,
+            get_token(provider.clone(), c).await?,
+        ];
+        Ok(Self { tokens, hops: [hop(pairs[0], a), hop(second, b), hop(third, c)], fee })
+    }
+
+    /**
+     * @gist the cycle as a path of symbols, e.g. WETH/DAI/USDC/WETH
+     * @param reverse -- A -> C -> B -> A instead
+    **/
+    pub fn path(&self, reverse: bool) -> String {
+        let symbol = |t: &Token| t.symbol.clone().unwrap_or_else(|| format!("{:?}", t.address));
+        let [a, b, c] = &self.tokens;
+        let order = if reverse { [a, c, b, a] } else { [a, b, c, a] };
+        order.map(symbol).join("/")
+    }
+
+    /**
+     * @gist prices the round trip both ways at the current block
+     * @param provider -- any ethers middleware
+     * @param size -- amount of A to trade around, None for the marginal
+     * prices
+     * @output the trip A -> B -> C -> A, then A -> C -> B -> A
+    **/
+    pub async fn check<M: Middleware + 'static>(&self, provider: Arc<M>, size: Option<f64>) -> eyre::Result<[RoundTrip; 2]> {
+        let head = source::head(provider.as_ref()).await?;
+        let mut reserves = Vec::with_capacity(3);
+        for hop in &self.hops {
+            let (reserve0, reserve1, _) = get_reserves_at(provider.clone(), &hop.pool, Some(head.number.into())).await?;
+            if reserve0 == 0 || reserve1 == 0 {
+                eyre::bail!("{:?} is empty", hop.pool);
+            }
+            // (in, out) along the cycle, raw amounts as decimals cancel out
+            // around it
+            reserves.push(if hop.zero_for_one { (reserve0 as f64, reserve1 as f64) } else { (reserve1 as f64, reserve0 as f64) });
+        }
+        let backward: Vec<(f64, f64)> = reserves.iter().rev().map(|(r_in, r_out)| (*r_out, *r_in)).collect();
+
+        let raw_size = size.map(|s| s * 10_f64.powi(self.tokens[0].decimals as i32));
+        let trip = |hops: &[(f64, f64)], reverse: bool| {
+            let product = match raw_size {
+                Some(amount) => hops.iter().fold(amount, |amount, (r_in, r_out)| amm::amount_out(amount, *r_in, *r_out, self.fee)) / amount,
+                None => hops.iter().map(|(r_in, r_out)| (1.0 - self.fee) * r_out / r_in).product(),
+            };
+            RoundTrip { path: self.path(reverse), product, bps: (product - 1.0) * 10_000.0, block: head.number }
+        };
+        Ok([trip(&reserves, false), trip(&backward, true)])
+    }
+}
@@ -0,0 +1,437 @@
+/*!
+ *
+ * Uniswap V2 2-hop pricing:
+ *
+ * TOKEN / ETH * ETH / USDT = TOKEN / USDT
+ *
+ * The asset address is the TOKEN / ETH pool, the ETH / USDT pool is fixed
+ * for the source.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{
+    abi,
+    prelude::{abigen, ContractError, Multicall},
+    providers::Middleware,
+    types::{Address, BlockId},
+};
+
+use serde::Serialize;
+
+use std::sync::Arc;
+
+use crate::batch::ReserveBatch;
+use crate::cache::ReserveCache;
+use crate::curve::Route;
+use crate::source::{self, Asset, PriceSource, Quote, Raw};
+use crate::trace::Span;
+
+/// The canonical ETH/USDT pair on mainnet
+pub const ETH_USDT_POOL: &str = "0x0d4a11d5EEaaC28EC3F61d100daF4d40471f1852";
+
+/// Wrapped ether on mainnet, the ETH side of every TOKEN/ETH pool
+pub const WETH: &str = "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2";
+
+/// Tether USD on mainnet, the USDT side of the ETH/USDT pool
+pub const USDT: &str = "0xdAC17F958D2ee523a2206206994597C13D831ec7";
+
+/// The Uniswap V2 factory on mainnet
+pub const UNISWAP_V2_FACTORY: &str = "0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f";
+
+/// The SushiSwap factory on mainnet, same pair layout as Uniswap V2
+pub const SUSHISWAP_FACTORY: &str = "0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac";
+
+abigen!(
+    IUniswapV2Pair,
+    r#"[
+        function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)
+        function token0() external view returns (address)
+        function token1() external view returns (address)
+        function factory() external view returns (address)
+        event Sync(uint112 reserve0, uint112 reserve1)
+        event Swap(address indexed sender, uint256 amount0In, uint256 amount1In, uint256 amount0Out, uint256 amount1Out, address indexed to)
+        event Mint(address indexed sender, uint256 amount0, uint256 amount1)
+        event Burn(address indexed sender, uint256 amount0, uint256 amount1, address indexed to)
+    ]"#;
+
+    IERC20,
+    r#"[
+        function symbol() external view returns (string)
+        function decimals() external view returns (uint8)
+        function balanceOf(address owner) external view returns (uint256)
+        function totalSupply() external view returns (uint256)
+        function transfer(address to, uint256 value) external returns (bool)
+        event Transfer(address indexed from, address indexed to, uint256 value)
+    ]"#;
+
+    IUniswapV2Factory,
+    r#"[
+        function getPair(address tokenA, address tokenB) external view returns (address pair)
+        event PairCreated(address indexed token0, address indexed token1, address pair, uint256 allPairsLength)
+    ]"#
+);
+
+/// Prices TOKEN/ETH pools in USDT through a second ETH/USDT hop
+pub struct UniswapV2<M> {
+    provider: Arc<M>,
+    end_a: Address,
+    inverted: bool,
+    decimals: u8,
+    batch: Option<Arc<ReserveBatch<M>>>,
+    cache: Option<Arc<ReserveCache>>,
+}
+
+impl<M: Middleware + 'static> UniswapV2<M> {
+    /**
+     * @gist creates the source
+     * @param provider -- this is used to send request to the UniswapV2Pair SC
+     * @param end_a -- the ETH/USDT pair address
+    **/
+    pub fn new(provider: Arc<M>, end_a: Address) -> Self {
+        Self { provider, end_a, inverted: false, decimals: 18, batch: None, cache: None }
+    }
+
+    /**
+     * @gist describes pools that do not follow the default layout of an 18
+     * decimals TOKEN as token0 and WETH as token1
+     * @param inverted -- WETH is token0, which is the case whenever the TOKEN
+     * address sorts after WETH
+     * @param decimals -- decimals of TOKEN
+    **/
+    pub fn with_layout(mut self, inverted: bool, decimals: u8) -> Self {
+        self.inverted = inverted;
+        self.decimals = decimals;
+        self
+    }
+
+    /**
+     * @gist reads the reserves through a batch shared with the other pools
+     * of the watchlist instead of one call per pool
+     * @param batch -- the shared batch
+    **/
+    pub fn with_batch(mut self, batch: Arc<ReserveBatch<M>>) -> Self {
+        self.batch = Some(batch);
+        self
+    }
+
+    /**
+     * @gist records the reserves of both pools for --cache-rpc
+     * @param cache -- served to other tools
+    **/
+    pub fn with_cache(mut self, cache: Arc<ReserveCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> PriceSource for UniswapV2<M> {
+    fn name(&self) -> &str {
+        "UNI V2"
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        // Both pools are read at the same block so the two hops are consistent
+        let fetch = Span::start("reserve fetch");
+        let (head, (reserve0, reserve1, updated), end) = fetch.run(async {
+            match &self.batch {
+                Some(batch) => batch.reserves(&asset.address, &self.end_a).await,
+                None => {
+                    let head = source::head(self.provider.as_ref()).await?;
+                    let pool = get_reserves_at(self.provider.clone(), &asset.address, Some(head.number.into())).await?;
+                    let end = get_reserves_at(self.provider.clone(), &self.end_a, Some(head.number.into())).await?;
+                    eyre::Ok((head, pool, end))
+                }
+            }
+        }).await?;
+        drop(fetch);
+        if let Some(cache) = &self.cache {
+            cache.store(asset.address, head.number, (reserve0, reserve1, updated));
+            cache.store(self.end_a, head.number, end);
+        }
+
+        let _math = Span::start("math");
+
+        let (eth_2, usdt_1, _) = end;
+        let (token_1, eth_1) = if self.inverted { (reserve1, reserve0) } else { (reserve0, reserve1) };
+        let mut route = route_from_reserves((token_1, eth_1), (eth_2, usdt_1));
+        route.token *= 10_f64.powi(18 - self.decimals as i32);
+
+        let mut quote = Quote::new(self.name(), &asset.name, route.mid_price());
+        quote.liquidity = Some(route.liquidity());
+        quote.block = Some(head.number);
+        quote.timestamp = Some(head.timestamp);
+        quote.updated = Some(updated as u64);
+        quote.raw = Some(Raw {
+            reserves: vec![(token_1, eth_1), (eth_2, usdt_1)],
+            decimals: vec![(self.decimals, 18), (18, 6)],
+            ratios: vec![route.eth / route.token, route.usdt / route.eth2],
+        });
+        Ok(quote)
+    }
+}
+
+/**
+ * @gist computes the USDT price of TOKEN by hopping through the TOKEN/ETH and
+ * ETH/USDT pools
+ * @param provider -- this is used to send request to the UniswapV2Pair SC
+ * @param start_a -- the TOKEN/ETH pair address
+ * @param end_a -- the ETH/USDT pair address
+ * @output the TOKEN price in USDT
+**/
+pub async fn get_price<M: Middleware>(provider: Arc<M>, start_a: &Address, end_a: &Address) -> Result<f64, ContractError<M>> {
+    Ok(get_price_and_liquidity(provider, start_a, end_a).await?.0)
+}
+
+/**
+ * @gist like get_price but also returns the USDT value of the TOKEN/ETH
+ * pool. Both sides of a V2 pool hold the same value, so that is twice the
+ * value of its ETH reserve.
+ * @param provider -- this is used to send request to the UniswapV2Pair SC
+ * @param start_a -- the TOKEN/ETH pair address
+ * @param end_a -- the ETH/USDT pair address
+ * @output the TOKEN price in USDT and the pool liquidity in USDT
+**/
+pub async fn get_price_and_liquidity<M: Middleware>(provider: Arc<M>, start_a: &Address, end_a: &Address) -> Result<(f64, f64), ContractError<M>> {
+    let route = get_route(provider, start_a, end_a).await?;
+    Ok((route.mid_price(), route.liquidity()))
+}
+
+/**
+ * @gist reads both pools of the route and converts the reserves into decimals
+ * @param provider -- this is used to send request to the UniswapV2Pair SC
+ * @param start_a -- the TOKEN/ETH pair address
+ * @param end_a -- the ETH/USDT pair address
+ * @output the reserves of the route
+**/
+pub async fn get_route<M: Middleware>(provider: Arc<M>, start_a: &Address, end_a: &Address) -> Result<Route, ContractError<M>> {
+    get_route_at(provider, start_a, end_a, None).await
+}
+
+/**
+ * @gist like get_route but reads the reserves at a given block
+ * @param provider -- this is used to send request to the UniswapV2Pair SC
+ * @param start_a -- the TOKEN/ETH pair address
+ * @param end_a -- the ETH/USDT pair address
+ * @param block -- the block to read at, None for latest
+ * @output the reserves of the route
+**/
+pub async fn get_route_at<M: Middleware>(provider: Arc<M>, start_a: &Address, end_a: &Address, block: Option<BlockId>) -> Result<Route, ContractError<M>> {
+    let (token_1, eth_1, _) = get_reserves_at(provider.clone(), start_a, block).await?;
+    let (eth_2, usdt_1, _) = get_reserves_at(provider, end_a, block).await?;
+
+    Ok(route_from_reserves((token_1, eth_1), (eth_2, usdt_1)))
+}
+
+/**
+ * @gist converts raw reserves of both pools into a route
+ * @param start -- (TOKEN, ETH) reserves of the first pool
+ * @param end -- (ETH, USDT) reserves of the second pool
+ * @output the route in decimals
+**/
+pub fn route_from_reserves(start: (u128, u128), end: (u128, u128)) -> Route {
+    Route {
+        token: reformat_wei(start.0),
+        eth: reformat_wei(start.1),
+        eth2: reformat_wei(end.0),
+        usdt: reformat_usd(end.1),
+    }
+}
+
+/**
+ * @gist converts wei into eth values, 1 ETH = 10**18 wei
+ * @param wei_int -- this is the wei value to be converted into eth
+ * @output -- ETH value
+**/
+pub fn reformat_wei(wei_int: u128) -> f64 {
+    wei_int as f64 / 10_f64.powf(18.0)
+}
+
+/**
+ * @gist converts the USDT values in UNI pools into decimals
+ * @param usd_int -- this is the usdt value returned by a UNI pool
+ * @output USDT value
+**/
+pub fn reformat_usd(usd_int:u128) -> f64 {
+    usd_int as f64 / 10_f64.powf(6.0)
+}
+
+/**
+ * @gist get_reserves returns the reserves of tokens in a given uniswap pool.
+ * @param provider -- this is used to send request to the UniswapV2Pair SC
+ * @param pair_address -- the pair address you want the reserves from
+ * @output the values of the reserves and a timestamp.
+**/
+pub async fn get_reserves<M: Middleware>(provider: Arc<M>, pair_address: &Address) -> Result<(u128, u128, u32), ContractError<M>> {
+    get_reserves_at(provider, pair_address, None).await
+}
+
+/**
+ * @gist like get_reserves but at a given block
+ * @param provider -- this is used to send request to the UniswapV2Pair SC
+ * @param pair_address -- the pair address you want the reserves from
+ * @param block -- the block to read at, None for latest
+ * @output the values of the reserves and a timestamp.
+**/
+pub async fn get_reserves_at<M: Middleware>(provider: Arc<M>, pair_address: &Address, block: Option<BlockId>) -> Result<(u128, u128, u32), ContractError<M>> {
+    let uniswap_v2_pair = IUniswapV2Pair::new(*pair_address, provider);
+    let call = uniswap_v2_pair.get_reserves();
+    match block {
+        Some(block) => call.block(block).call().await,
+        None => call.call().await,
+    }
+}
+
+/**
+ * @gist looks up the pair of two tokens on a V2 factory
+ * @param provider -- this is used to send request to the factory SC
+ * @param factory -- the factory address
+ * @param token_a -- one token of the pair, order does not matter
+ * @param token_b -- the other token
+ * @output the pair address, None if the factory has no such pair
+**/
+pub async fn get_pair<M: Middleware>(provider: Arc<M>, factory: &Address, token_a: &Address, token_b: &Address) -> Result<Option<Address>, ContractError<M>> {
+    let factory = IUniswapV2Factory::new(*factory, provider);
+    let pair = factory.get_pair(*token_a, *token_b).call().await?;
+    Ok((!pair.is_zero()).then_some(pair))
+}
+
+/// One side of a pair
+#[derive(Debug, Clone, Serialize)]
+pub struct Token {
+    pub address: Address,
+    /// None if the token has no string symbol()
+    pub symbol: Option<String>,
+    pub decimals: u8,
+}
+
+/// What `info` prints about a pair
+#[derive(Debug, Clone, Serialize)]
+pub struct PairInfo {
+    pub address: Address,
+    pub factory: Address,
+    pub token0: Token,
+    pub token1: Token,
+    /// Unscaled reserves
+    pub reserve0: u128,
+    pub reserve1: u128,
+    /// Unix time of the last reserve update
+    pub last_update: u32,
+    /// token1 per token0
+    pub price0: f64,
+    /// token0 per token1
+    pub price1: f64,
+}
+
+impl PairInfo {
+    /**
+     * @gist the side of the pair that isn't WETH
+     * @output whether WETH is token0, the way `with_layout` takes it, and
+     * the other token, None when neither token is WETH
+    **/
+    pub fn token(&self) -> Option<(bool, &Token)> {
+        let weth: Address = WETH.parse().expect("WETH is a valid address");
+        match (self.token0.address == weth, self.token1.address == weth) {
+            (true, _) => Some((true, &self.token1)),
+            (_, true) => Some((false, &self.token0)),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * @gist reads the tokens, factory and reserves of a pair
+ * @param provider -- this is used to send request to the UniswapV2Pair SC
+ * @param pair_address -- the pair to inspect
+ * @output the pair metadata and the implied price both ways
+**/
+pub async fn get_pair_info<M: Middleware + 'static>(provider: Arc<M>, pair_address: &Address) -> eyre::Result<PairInfo> {
+    let pair = IUniswapV2Pair::new(*pair_address, provider.clone());
+    let not_a_pair = |e: ContractError<M>| eyre::eyre!("{pair_address:?} is not a V2 pair: {e}");
+
+    let factory = pair.factory().call().await.map_err(not_a_pair)?;
+    let address0 = pair.token_0().call().await.map_err(not_a_pair)?;
+    let address1 = pair.token_1().call().await.map_err(not_a_pair)?;
+    let (reserve0, reserve1, last_update) = pair.get_reserves().call().await.map_err(not_a_pair)?;
+    let token0 = get_token(provider.clone(), address0).await?;
+    let token1 = get_token(provider, address1).await?;
+
+    let amount0 = reserve0 as f64 / 10_f64.powi(token0.decimals as i32);
+    let amount1 = reserve1 as f64 / 10_f64.powi(token1.decimals as i32);
+
+    Ok(PairInfo {
+        address: *pair_address,
+        factory,
+        token0,
+        token1,
+        reserve0,
+        reserve1,
+        last_update,
+        price0: amount1 / amount0,
+        price1: amount0 / amount1,
+    })
+}
+
+/**
+ * @gist reads the layouts of many TOKEN/WETH pairs with two multicalls, the
+ * tokens of every pair first and then the decimals of the TOKENs
+ * @param provider -- this is used to send request to the multicall SC
+ * @param pools -- the pairs
+ * @output whether WETH is token0 and the decimals of TOKEN, the way
+ * `with_layout` takes them, one result per pool in the same order
+**/
+pub async fn pair_layouts<M: Middleware + 'static>(provider: Arc<M>, pools: &[Address]) -> eyre::Result<Vec<eyre::Result<(bool, u8)>>> {
+    if pools.is_empty() {
+        return Ok(Vec::new());
+    }
+    let weth: Address = WETH.parse().expect("WETH is a valid address");
+    let mut multicall = Multicall::new(provider.clone(), None).await
+        .map_err(|e| eyre::eyre!("could not set up the multicall: {e}"))?;
+    // A bad pool must not fail the others
+    for pool in pools {
+        let pair = IUniswapV2Pair::new(*pool, provider.clone());
+        multicall.add_call(pair.token_0(), true);
+        multicall.add_call(pair.token_1(), true);
+    }
+    let tokens = multicall.call_raw().await.map_err(|e| eyre::eyre!("could not read the pool tokens: {e}"))?;
+    let sides: Vec<eyre::Result<(bool, Address)>> = pools.iter().zip(tokens.chunks(2))
+        .map(|(pool, pair)| match pair {
+            [Ok(abi::Token::Address(token0)), Ok(abi::Token::Address(token1))] => match (*token0 == weth, *token1 == weth) {
+                (true, _) => Ok((true, *token1)),
+                (_, true) => Ok((false, *token0)),
+                _ => Err(eyre::eyre!("{pool:?} is no TOKEN/WETH pair")),
+            },
+            _ => Err(eyre::eyre!("{pool:?} is not a V2 pair")),
+        })
+        .collect();
+
+    multicall.clear_calls();
+    for (_, token) in sides.iter().flatten() {
+        multicall.add_call(IERC20::new(*token, provider.clone()).decimals(), true);
+    }
+    let mut decimals = multicall.call_raw().await.map_err(|e| eyre::eyre!("could not read the token decimals: {e}"))?.into_iter();
+    Ok(sides.into_iter()
+        .map(|side| {
+            let (inverted, token) = side?;
+            match decimals.next() {
+                Some(Ok(abi::Token::Uint(d))) if d <= 255.into() => Ok((inverted, d.as_u32() as u8)),
+                _ => Err(eyre::eyre!("{token:?} has no decimals()")),
+            }
+        })
+        .collect())
+}
+
+/**
+ * @gist reads the symbol and decimals of an ERC20 token. Some old tokens
+ * return their symbol as bytes32, those are left without a symbol.
+ * @param provider -- this is used to send request to the token SC
+ * @param address -- the token
+**/
+pub async fn get_token<M: Middleware + 'static>(provider: Arc<M>, address: Address) -> eyre::Result<Token> {
+    let token = IERC20::new(address, provider);
+    let decimals = token.decimals().call().await
+        .map_err(|e| eyre::eyre!("{address:?} has no decimals(): {e}"))?;
+    let symbol = token.symbol().call().await.ok();
+    Ok(Token { address, symbol, decimals })
+}
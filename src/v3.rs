@@ -0,0 +1,235 @@
+/*!
+ *
+ * Uniswap V3 paths. A token is priced along a path of V3 pools, each hop
+ * given by the next token and the fee tier of the pool:
+ *
+ * [[v3]]
+ * name = "LINK/USDT"
+ * path = ["0x514910771af9ca656af840dff83e8264ecf986ca", "0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", "0xdAC17F958D2ee523a2206206994597C13D831ec7"]
+ * fees = [3000, 500]
+ * amount = 1000   # of the first token, for --v3-quoter
+ *
+ * The price is in the last token of the path, in dollars when that is USDT,
+ * USDC or DAI and marked with the token's symbol otherwise. By default it's the product of
+ * the slot0 prices of the pools, the marginal price. With `--v3-quoter` the
+ * path is quoted by QuoterV2 `quoteExactInput` for `amount`, which includes
+ * the fees and the ticks a trade of that size crosses.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{
+    prelude::abigen,
+    providers::Middleware,
+    types::{Address, Bytes, U256},
+    utils::{format_units, parse_units},
+};
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+
+use std::sync::Arc;
+
+use crate::pairs;
+use crate::source::{self, Asset, PriceSource, Quote};
+use crate::uniswap_v2::get_token;
+
+abigen!(
+    IUniswapV3Factory,
+    r#"[
+        function getPool(address tokenA, address tokenB, uint24 fee) external view returns (address pool)
+    ]"#;
+
+    IUniswapV3Pool,
+    r#"[
+        function slot0() external view returns (uint160 sqrtPriceX96, int24 tick, uint16 observationIndex, uint16 observationCardinality, uint16 observationCardinalityNext, uint8 feeProtocol, bool unlocked)
+    ]"#;
+
+    IQuoterV2,
+    r#"[
+        function quoteExactInput(bytes path, uint256 amountIn) external returns (uint256 amountOut, uint160[] sqrtPriceX96AfterList, uint32[] initializedTicksCrossedList, uint256 gasEstimate)
+    ]"#
+);
+
+/// The Uniswap V3 factory on mainnet
+pub const V3_FACTORY: &str = "0x1F98431c8aD98523631AE4a59f267346ea31F984";
+
+/// QuoterV2 on mainnet
+pub const QUOTER_V2: &str = "0x61fFE014bA17989E743c5F6cB21bF9697530B21e";
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct V3Config {
+    /// Label used in the output
+    pub name: String,
+    /// The tokens of the path, the priced token first
+    pub path: Vec<Address>,
+    /// Fee tier of every hop in hundredths of a bip, 3000 for 0.3%
+    pub fees: Vec<u32>,
+    /// Amount of the first token the quoter trades
+    #[serde(default = "default_amount")]
+    pub amount: f64,
+}
+
+fn default_amount() -> f64 {
+    1.0
+}
+
+impl V3Config {
+    /**
+     * @gist the asset the path prices
+    **/
+    pub fn asset(&self) -> Asset {
+        Asset { name: self.name.clone(), address: self.path.first().copied().unwrap_or_default() }
+    }
+
+    /**
+     * @gist checks that every hop has a fee
+    **/
+    pub fn validate(&self) -> eyre::Result<()> {
+        if self.path.len() < 2 || self.fees.len() != self.path.len() - 1 {
+            eyre::bail!("[[v3]] {}: a path of n tokens needs n - 1 fees", self.name);
+        }
+        Ok(())
+    }
+
+    /**
+     * @gist the path as QuoterV2 expects it, token, fee, token, ... packed
+    **/
+    fn encoded(&self) -> Bytes {
+        let mut path = Vec::with_capacity(self.path.len() * 23);
+        for (i, token) in self.path.iter().enumerate() {
+            path.extend_from_slice(token.as_bytes());
+            if let Some(fee) = self.fees.get(i) {
+                path.extend_from_slice(&fee.to_be_bytes()[1..]);
+            }
+        }
+        path.into()
+    }
+}
+
+/// A pool of the path and its orientation
+struct Hop {
+    pool: Address,
+    /// The path goes from token0 to token1
+    zero_for_one: bool,
+    /// Decimals of token0 and token1
+    decimals: (u8, u8),
+}
+
+/// Prices a token along a path of V3 pools
+pub struct UniswapV3<M> {
+    provider: Arc<M>,
+    config: V3Config,
+    /// QuoterV2 to quote the path with, the slot0 prices are used without
+    quoter: Option<Address>,
+    /// Pools and decimals of the path, looked up on the first quote
+    hops: OnceCell<Vec<Hop>>,
+    /// Symbol of the last token, None for a dollar stablecoin
+    currency: OnceCell<Option<String>>,
+}
+
+impl<M: Middleware + 'static> UniswapV3<M> {
+    /**
+     * @gist creates the source
+     * @param provider -- used to read the pools or call the quoter
+     * @param config -- the path
+    **/
+    pub fn new(provider: Arc<M>, config: V3Config) -> Self {
+        Self { provider, config, quoter: None, hops: OnceCell::new(), currency: OnceCell::new() }
+    }
+
+    /**
+     * @gist quotes the path with QuoterV2 for the configured amount
+     * @param quoter -- the QuoterV2 contract
+    **/
+    pub fn with_quoter(mut self, quoter: Address) -> Self {
+        self.quoter = Some(quoter);
+        self
+    }
+
+    async fn hops(&self) -> eyre::Result<&Vec<Hop>> {
+        self.hops.get_or_try_init(|| async {
+            let factory = IUniswapV3Factory::new(V3_FACTORY.parse::<Address>()?, self.provider.clone());
+            let mut hops = Vec::with_capacity(self.config.fees.len());
+            for (pair, fee) in self.config.path.windows(2).zip(&self.config.fees) {
+                let (from, to) = (pair[0], pair[1]);
+                let pool = factory.get_pool(from, to, *fee).call().await?;
+                if pool.is_zero() {
+                    eyre::bail!("no V3 pool for {from:?}/{to:?} at fee {fee}");
+                }
+                let from_decimals = get_token(self.provider.clone(), from).await?.decimals;
+                let to_decimals = get_token(self.provider.clone(), to).await?.decimals;
+                let zero_for_one = from < to;
+                let decimals = if zero_for_one { (from_decimals, to_decimals) } else { (to_decimals, from_decimals) };
+                hops.push(Hop { pool, zero_for_one, decimals });
+            }
+            Ok(hops)
+        }).await
+    }
+
+    async fn currency(&self) -> eyre::Result<Option<String>> {
+        self.currency.get_or_try_init(|| async {
+            let last = self.config.path[self.config.path.len() - 1];
+            if pairs::is_stable(&last) {
+                return Ok(None);
+            }
+            let symbol = get_token(self.provider.clone(), last).await?.symbol;
+            Ok(Some(symbol.unwrap_or_else(|| format!("{last:?}"))))
+        }).await.cloned()
+    }
+
+    /**
+     * @gist the product of the marginal prices of the pools
+    **/
+    async fn spot(&self, block: u64) -> eyre::Result<f64> {
+        let mut price = 1.0;
+        for hop in self.hops().await? {
+            let (sqrt_price, ..) = IUniswapV3Pool::new(hop.pool, self.provider.clone()).slot_0().block(block).call().await
+                .map_err(|e| eyre::eyre!("{:?} is not a V3 pool: {e}", hop.pool))?;
+            let sqrt_price: f64 = sqrt_price.to_string().parse()?;
+            // token1 per token0, in whole tokens
+            let one_in_zero = (sqrt_price / 2_f64.powi(96)).powi(2) * 10_f64.powi(hop.decimals.0 as i32 - hop.decimals.1 as i32);
+            price *= if hop.zero_for_one { one_in_zero } else { 1.0 / one_in_zero };
+        }
+        Ok(price)
+    }
+
+    /**
+     * @gist the average price of a trade of the configured amount
+    **/
+    async fn quoted(&self, quoter: Address, block: u64) -> eyre::Result<f64> {
+        let hops = self.hops().await?;
+        let (first, last) = (&hops[0], &hops[hops.len() - 1]);
+        let in_decimals = if first.zero_for_one { first.decimals.0 } else { first.decimals.1 };
+        let out_decimals = if last.zero_for_one { last.decimals.1 } else { last.decimals.0 };
+
+        let amount_in: U256 = parse_units(self.config.amount, in_decimals as u32)?.into();
+        let (amount_out, ..) = IQuoterV2::new(quoter, self.provider.clone())
+            .quote_exact_input(self.config.encoded(), amount_in)
+            .block(block)
+            .call().await
+            .map_err(|e| eyre::eyre!("QuoterV2 could not quote {}: {e}", self.config.name))?;
+        let amount_out: f64 = format_units(amount_out, out_decimals as u32)?.parse()?;
+        Ok(amount_out / self.config.amount)
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> PriceSource for UniswapV3<M> {
+    fn name(&self) -> &str {
+        "UNI V3"
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let head = source::head(self.provider.as_ref()).await?;
+        let price = match self.quoter {
+            Some(quoter) => self.quoted(quoter, head.number).await?,
+            None => self.spot(head.number).await?,
+        };
+        let mut quote = Quote::new(self.name(), &asset.name, price);
+        quote.block = Some(head.number);
+        quote.timestamp = Some(head.timestamp);
+        quote.currency = self.currency().await?;
+        Ok(quote)
+    }
+}
@@ -0,0 +1,157 @@
+/*!
+ *
+ * Uniswap V4 pools. All V4 pools live in the PoolManager singleton and are
+ * known by their id, the hash of their pool key (currencies, fee, tick
+ * spacing and hooks):
+ *
+ * uni-price-polling --v4 --pool-id $POOL_ID
+ *
+ * The key is looked up in the PositionManager on the first quote, the
+ * sqrtPriceX96 is read from the pool's slot0 in the PoolManager's storage
+ * with extsload at every quote. Like `--pool` the pool pairs TOKEN with ETH
+ * (native or WETH) and the TOKEN/ETH price is taken to USDT through the
+ * ETH/USDT V2 pool.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{
+    abi::{self, Token as AbiToken},
+    prelude::abigen,
+    providers::Middleware,
+    types::{Address, H256, I256, U256},
+    utils::keccak256,
+};
+use tokio::sync::OnceCell;
+
+use std::sync::Arc;
+
+use crate::source::{self, Asset, PriceSource, Quote};
+use crate::uniswap_v2::{self, get_token};
+
+abigen!(
+    IPoolManager,
+    r#"[
+        function extsload(bytes32 slot) external view returns (bytes32 value)
+    ]"#;
+
+    IPositionManager,
+    r#"[
+        function poolKeys(bytes25 poolId) external view returns (address currency0, address currency1, uint24 fee, int24 tickSpacing, address hooks)
+    ]"#
+);
+
+/// The V4 PoolManager on mainnet
+pub const POOL_MANAGER: &str = "0x000000000004444c5dc75cB358380D2e3dE08A90";
+
+/// The V4 PositionManager on mainnet, knows the keys of the pools it holds
+/// positions in
+pub const POSITION_MANAGER: &str = "0xbD216513d74C8cf14cf4747E6AaA6420FF64ee9e";
+
+/// Storage slot of the pools mapping of the PoolManager
+const POOLS_SLOT: u64 = 6;
+
+/// The pool key and what the price needs of it
+struct Key {
+    /// TOKEN is currency0, ETH currency1
+    token_is_zero: bool,
+    /// Decimals of currency0 and currency1
+    decimals: (u8, u8),
+}
+
+/// Prices a V4 TOKEN/ETH pool in USDT through a second ETH/USDT hop
+pub struct UniswapV4<M> {
+    provider: Arc<M>,
+    id: H256,
+    /// The ETH/USDT V2 pool
+    end_a: Address,
+    key: OnceCell<Key>,
+}
+
+impl<M: Middleware + 'static> UniswapV4<M> {
+    /**
+     * @gist creates the source
+     * @param provider -- used to read the PoolManager
+     * @param id -- the pool id
+     * @param end_a -- the ETH/USDT pool
+    **/
+    pub fn new(provider: Arc<M>, id: H256, end_a: Address) -> Self {
+        Self { provider, id, end_a, key: OnceCell::new() }
+    }
+
+    async fn key(&self) -> eyre::Result<&Key> {
+        self.key.get_or_try_init(|| async {
+            let manager = IPositionManager::new(POSITION_MANAGER.parse::<Address>()?, self.provider.clone());
+            let mut truncated = [0u8; 25];
+            truncated.copy_from_slice(&self.id.as_bytes()[..25]);
+            let (currency0, currency1, fee, tick_spacing, hooks) = manager.pool_keys(truncated).call().await?;
+
+            // The PositionManager only keys the first 25 bytes and returns
+            // zeros for unknown pools, the full hash tells both apart
+            let encoded = abi::encode(&[
+                AbiToken::Address(currency0),
+                AbiToken::Address(currency1),
+                AbiToken::Uint(U256::from(fee)),
+                AbiToken::Int(I256::from(tick_spacing).into_raw()),
+                AbiToken::Address(hooks),
+            ]);
+            if H256(keccak256(encoded)) != self.id {
+                eyre::bail!("the PositionManager knows no V4 pool {:?}", self.id);
+            }
+
+            let weth: Address = uniswap_v2::WETH.parse()?;
+            let is_eth = |c: Address| c.is_zero() || c == weth;
+            let token_is_zero = match (is_eth(currency0), is_eth(currency1)) {
+                (false, true) => true,
+                (true, false) => false,
+                _ => eyre::bail!("the V4 pool {:?} does not pair a token with ETH", self.id),
+            };
+            let decimals = |c: Address| async move {
+                if c.is_zero() {
+                    return Ok(18);
+                }
+                get_token(self.provider.clone(), c).await.map(|t| t.decimals)
+            };
+            Ok(Key { token_is_zero, decimals: (decimals(currency0).await?, decimals(currency1).await?) })
+        }).await
+    }
+
+    /**
+     * @gist reads sqrtPriceX96, the low 160 bits of slot0
+    **/
+    async fn sqrt_price(&self, block: u64) -> eyre::Result<U256> {
+        let mut slot = self.id.as_bytes().to_vec();
+        slot.extend_from_slice(&H256::from_low_u64_be(POOLS_SLOT).0);
+        let manager = IPoolManager::new(POOL_MANAGER.parse::<Address>()?, self.provider.clone());
+        let slot0 = manager.extsload(keccak256(slot)).block(block).call().await?;
+        let sqrt_price = U256::from_big_endian(&slot0) & ((U256::one() << 160) - 1);
+        if sqrt_price.is_zero() {
+            eyre::bail!("the V4 pool {:?} is not initialized", self.id);
+        }
+        Ok(sqrt_price)
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> PriceSource for UniswapV4<M> {
+    fn name(&self) -> &str {
+        "UNI V4"
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let head = source::head(self.provider.as_ref()).await?;
+        let key = self.key().await?;
+        let sqrt_price: f64 = self.sqrt_price(head.number).await?.to_string().parse()?;
+        // currency1 per currency0, in whole tokens
+        let one_in_zero = (sqrt_price / 2_f64.powi(96)).powi(2) * 10_f64.powi(key.decimals.0 as i32 - key.decimals.1 as i32);
+        let token_eth = if key.token_is_zero { one_in_zero } else { 1.0 / one_in_zero };
+
+        let (eth, usdt, _) = uniswap_v2::get_reserves_at(self.provider.clone(), &self.end_a, Some(head.number.into())).await?;
+        let eth_usdt = uniswap_v2::reformat_usd(usdt) / uniswap_v2::reformat_wei(eth);
+
+        let mut quote = Quote::new(self.name(), &asset.name, token_eth * eth_usdt);
+        quote.block = Some(head.number);
+        quote.timestamp = Some(head.timestamp);
+        Ok(quote)
+    }
+}
@@ -0,0 +1,136 @@
+/*!
+ *
+ * ERC-4626 vault shares. A share is worth `convertToAssets` of the
+ * underlying, which is priced through its TOKEN/ETH pool like --pool:
+ *
+ * [[vault]]
+ * name = "sDAI"
+ * address = "0x83F20F44975D03b1b09e64809B757c47f942BEeA"
+ * pool = "0xA478c2975Ab1Ea89e8196811F51A7B7Ade33eB11"   # DAI/ETH
+ * inverted = false                                       # layout of the pool
+ * decimals = 18                                          # of the underlying
+ *
+ * The rate is read at the block the underlying was priced at.
+ *
+**/
+
+use async_trait::async_trait;
+use ethers::{
+    prelude::abigen,
+    providers::Middleware,
+    types::{Address, U256},
+    utils::format_units,
+};
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+
+use std::sync::Arc;
+
+use crate::poller::Feed;
+use crate::source::{Asset, PriceSource, Quote};
+
+abigen!(
+    IERC4626,
+    r#"[
+        function convertToAssets(uint256 shares) external view returns (uint256 assets)
+        function decimals() external view returns (uint8)
+    ]"#
+);
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct VaultConfig {
+    /// Label used in the output
+    pub name: String,
+    /// The vault, its shares are the priced token
+    pub address: Address,
+    /// TOKEN/ETH pool of the underlying asset
+    pub pool: Address,
+    /// WETH is token0 of the pool
+    #[serde(default)]
+    pub inverted: bool,
+    /// Decimals of the underlying asset
+    #[serde(default = "default_decimals")]
+    pub decimals: u8,
+}
+
+fn default_decimals() -> u8 {
+    18
+}
+
+impl VaultConfig {
+    /**
+     * @gist the asset the vault prices, its shares
+    **/
+    pub fn asset(&self) -> Asset {
+        Asset { name: self.name.clone(), address: self.address }
+    }
+
+    /**
+     * @gist the asset the underlying is priced as
+    **/
+    pub fn underlying(&self) -> Asset {
+        Asset { name: format!("{} underlying", self.name), address: self.pool }
+    }
+}
+
+/// Prices vault shares as their redeemable amount of the underlying
+pub struct Vault<M> {
+    provider: Arc<M>,
+    underlying: Feed,
+    /// Asset decimals of the underlying
+    decimals: u8,
+    /// Decimals of one share, read on the first quote
+    share_decimals: OnceCell<u8>,
+}
+
+impl<M: Middleware + 'static> Vault<M> {
+    /**
+     * @gist creates the source
+     * @param provider -- this is used to send request to the vault SC
+     * @param underlying -- prices the underlying asset
+     * @param decimals -- decimals of the underlying asset
+    **/
+    pub fn new(provider: Arc<M>, underlying: Feed, decimals: u8) -> Self {
+        Self { provider, underlying, decimals, share_decimals: OnceCell::new() }
+    }
+
+    /**
+     * @gist underlying assets per share at a block
+     * @param vault -- the vault address
+     * @param block -- the block to read at, None for latest
+    **/
+    pub async fn rate(&self, vault: &Address, block: Option<u64>) -> eyre::Result<f64> {
+        let contract = IERC4626::new(*vault, self.provider.clone());
+        let share_decimals = *self.share_decimals.get_or_try_init(|| async {
+            contract.decimals().call().await.map_err(|e| eyre::eyre!("{vault:?} has no decimals(): {e}"))
+        }).await?;
+
+        let call = contract.convert_to_assets(U256::exp10(share_decimals as usize));
+        let call = match block {
+            Some(block) => call.block(block),
+            None => call,
+        };
+        let assets = call.call().await.map_err(|e| eyre::eyre!("{vault:?} is not an ERC-4626 vault: {e}"))?;
+        Ok(format_units(assets, self.decimals as u32)?.parse()?)
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> PriceSource for Vault<M> {
+    fn name(&self) -> &str {
+        "4626"
+    }
+
+    async fn quote(&self, asset: &Asset) -> eyre::Result<Quote> {
+        let underlying = self.underlying.source.quote(&self.underlying.asset).await
+            .map_err(|e| eyre::eyre!("{}: {e}", self.underlying.asset.name))?;
+        let rate = self.rate(&asset.address, underlying.block).await?;
+
+        let mut quote = Quote::new(self.name(), &asset.name, underlying.price * rate);
+        quote.block = underlying.block;
+        quote.timestamp = underlying.timestamp;
+        quote.components = vec![underlying];
+        Ok(quote)
+    }
+}
@@ -0,0 +1,169 @@
+/*!
+ *
+ * Rolling price windows. Every asset keeps the samples of the last 24 hours
+ * plus one sample from before that, which is the reference for the 24h
+ * change.
+ *
+**/
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+use crate::indicators::{Bands, Rsi};
+
+/// Length of the rolling window in seconds
+pub const DAY: u64 = 24 * 60 * 60;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Window {
+    /// (unix time, price) pairs, oldest first
+    samples: VecDeque<(u64, f64)>,
+    /// Running averages of the RSI, over every sample since the first
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rsi: Option<Rsi>,
+}
+
+impl Window {
+    /**
+     * @gist records a price and drops samples that fell out of the window
+     * @param time -- unix time of the sample, samples older than the newest
+     * one are ignored
+     * @param price -- the price
+    **/
+    pub fn push(&mut self, time: u64, price: f64) {
+        if self.samples.back().is_some_and(|(t, _)| time < *t) {
+            return;
+        }
+        self.samples.push_back((time, price));
+        if let Some(rsi) = &mut self.rsi {
+            rsi.push(price);
+        }
+
+        let cutoff = time.saturating_sub(DAY);
+        while self.samples.len() > 1 && self.samples[1].0 <= cutoff {
+            self.samples.pop_front();
+        }
+    }
+
+    /**
+     * @gist change in percent over the last 24 hours
+     * @output None until the window covers a full day
+    **/
+    pub fn change(&self) -> Option<f64> {
+        let (first_t, first) = *self.samples.front()?;
+        let (last_t, last) = *self.samples.back()?;
+        if last_t.saturating_sub(first_t) < DAY {
+            return None;
+        }
+        Some((last / first - 1.0) * 100.0)
+    }
+
+    /**
+     * @gist first and second derivative of the price, in price units per
+     * minute and per minute squared. They are taken from the newest sample
+     * and the latest samples at least `lookback` and twice as long before it.
+     * @param lookback -- seconds between the points, longer is smoother
+     * @output None until the window spans twice the lookback
+    **/
+    pub fn derivatives(&self, lookback: u64) -> Option<(f64, f64)> {
+        let at_or_before = |time: u64| self.samples.iter().rev().find(|(t, _)| *t <= time).copied();
+        let (t0, p0) = *self.samples.back()?;
+        let (t1, p1) = at_or_before(t0.checked_sub(lookback.max(1))?)?;
+        let (t2, p2) = at_or_before(t1.checked_sub(lookback.max(1))?)?;
+
+        let minutes = |from: u64, to: u64| (to - from) as f64 / 60.0;
+        let rate = (p0 - p1) / minutes(t1, t0);
+        let previous = (p1 - p2) / minutes(t2, t1);
+        // The two rates are measured at the middle of their intervals
+        let accel = (rate - previous) / (minutes(t2, t0) / 2.0);
+        Some((rate, accel))
+    }
+
+    /**
+     * @gist Bollinger bands over the newest samples
+     * @param period -- number of samples, the newest included
+     * @param k -- width of the bands in standard deviations
+     * @output None until the window holds `period` samples
+    **/
+    pub fn bollinger(&self, period: usize, k: f64) -> Option<Bands> {
+        if period == 0 || self.samples.len() < period {
+            return None;
+        }
+        let prices: Vec<f64> = self.samples.iter().skip(self.samples.len() - period).map(|(_, p)| *p).collect();
+        Bands::of(&prices, k)
+    }
+
+    /**
+     * @gist relative strength index, kept up to date by push. The first call,
+     * or one with another period, starts it over the samples in the window.
+     * @param period -- smoothing period in samples
+     * @output None until `period` + 1 samples were seen
+    **/
+    pub fn rsi(&mut self, period: usize) -> Option<f64> {
+        if self.rsi.as_ref().is_none_or(|rsi| rsi.period() != period) {
+            let mut rsi = Rsi::new(period);
+            for (_, price) in &self.samples {
+                rsi.push(*price);
+            }
+            self.rsi = Some(rsi);
+        }
+        self.rsi.as_ref().and_then(Rsi::value)
+    }
+
+    /**
+     * @gist the samples in the window, oldest first
+    **/
+    pub fn samples(&self) -> impl Iterator<Item = &(u64, f64)> {
+        self.samples.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicators;
+
+    fn window(prices: &[f64]) -> Window {
+        let mut window = Window::default();
+        for (i, price) in prices.iter().enumerate() {
+            window.push(60 * i as u64, *price);
+        }
+        window
+    }
+
+    #[test]
+    fn bollinger_over_the_newest_samples() {
+        let window = window(&[100.0, 2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(window.bollinger(8, 2.0), Some(Bands { lower: 1.0, middle: 5.0, upper: 9.0 }));
+        assert_eq!(window.bollinger(10, 2.0), None);
+        assert_eq!(window.bollinger(0, 2.0), None);
+    }
+
+    #[test]
+    fn rsi_keeps_running_past_the_window() {
+        let prices = [10.0, 11.0, 10.5, 12.0, 11.0, 13.0, 12.5];
+        let mut window = Window::default();
+        window.push(0, prices[0]);
+        assert_eq!(window.rsi(3), None);
+        // A day apart, each push drops the samples before the previous one
+        for (i, price) in prices.iter().enumerate().skip(1) {
+            window.push(DAY * i as u64, *price);
+        }
+        assert_eq!(window.samples().count(), 2);
+        assert_eq!(window.rsi(3), indicators::rsi(&prices, 3));
+    }
+
+    #[test]
+    fn rsi_of_another_period_starts_over_the_window() {
+        let prices = [10.0, 11.0, 10.5, 12.0, 11.0, 13.0, 12.5];
+        let mut window = window(&prices);
+        assert_eq!(window.rsi(3), indicators::rsi(&prices, 3));
+        assert_eq!(window.rsi(2), indicators::rsi(&prices, 2));
+        window.push(60 * 7, 12.0);
+        assert_eq!(window.rsi(2), indicators::rsi(&[10.0, 11.0, 10.5, 12.0, 11.0, 13.0, 12.5, 12.0], 2));
+    }
+}